@@ -0,0 +1,53 @@
+//! Each allocator method through a `Geiger` beside the same method of the
+//! inner allocator, which `tests/off.rs` compiles to assembly to check that
+//! they're the same code with the `off` feature.
+
+// the probes are only compiled, never called
+#![allow(clippy::missing_safety_doc)]
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+static GEIGER: alloc_geiger::System = alloc_geiger::SYSTEM;
+static INNER: System = System;
+
+#[no_mangle]
+pub unsafe fn geiger_alloc(layout: Layout) -> *mut u8 {
+    GEIGER.alloc(layout)
+}
+
+#[no_mangle]
+pub unsafe fn system_alloc(layout: Layout) -> *mut u8 {
+    INNER.alloc(layout)
+}
+
+#[no_mangle]
+pub unsafe fn geiger_alloc_zeroed(layout: Layout) -> *mut u8 {
+    GEIGER.alloc_zeroed(layout)
+}
+
+#[no_mangle]
+pub unsafe fn system_alloc_zeroed(layout: Layout) -> *mut u8 {
+    INNER.alloc_zeroed(layout)
+}
+
+#[no_mangle]
+pub unsafe fn geiger_dealloc(ptr: *mut u8, layout: Layout) {
+    GEIGER.dealloc(ptr, layout)
+}
+
+#[no_mangle]
+pub unsafe fn system_dealloc(ptr: *mut u8, layout: Layout) {
+    INNER.dealloc(ptr, layout)
+}
+
+#[no_mangle]
+pub unsafe fn geiger_realloc(ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    GEIGER.realloc(ptr, layout, new_size)
+}
+
+#[no_mangle]
+pub unsafe fn system_realloc(ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+    INNER.realloc(ptr, layout, new_size)
+}
+
+fn main() {}
@@ -0,0 +1,632 @@
+//! An `LD_PRELOAD` library for non-Rust programs on Linux.
+//!
+//! This exports the C allocator functions, passing each to the next library
+//! that defines them, usually the C library, through a `Geiger` that's
+//! configured from the `ALLOC_GEIGER_*` environment variables as usual.  It's
+//! a package of its own, only built as a `cdylib`, since it replaces the
+//! allocator of the whole process:
+//!
+//! ```sh
+//! cargo build --release -p alloc_geiger_preload
+//! LD_PRELOAD=target/release/liballoc_geiger_preload.so some-program
+//! ```
+//!
+//! Sizes are counted with `malloc_usable_size`, so that allocations and
+//! frees always agree even though `free` isn't told the size.  Until the
+//! library's constructor runs, allocations pass through without a sound,
+//! keeping the audio output from opening in the middle of the dynamic
+//! loader's own setup.  Up to 1024 of those blocks are remembered so that
+//! their frees aren't counted either, and any more are counted as freed
+//! without having been allocated, which lowers the live bytes that `cap`
+//! sees.
+
+#![cfg(target_os = "linux")]
+
+use alloc_geiger::{Geiger, GeigerLayer};
+use std::alloc::{GlobalAlloc, Layout};
+use std::cell::Cell;
+use std::ffi::{c_char, c_int, c_void};
+use std::mem::{self, MaybeUninit};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+/// The sound engine around the real allocator.
+static GEIGER: Geiger<Real> = Geiger::new(Real);
+
+/// Set by the library's constructor, after which events are counted.
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// The real allocator functions, once they're found.
+static FUNCTIONS: AtomicPtr<Functions> = AtomicPtr::new(ptr::null_mut());
+/// Set while finding the real functions, or after that failed.
+static RESOLVING: AtomicBool = AtomicBool::new(false);
+/// Where the real functions are kept, written once before `FUNCTIONS`.
+static mut STORAGE: MaybeUninit<Functions> = MaybeUninit::uninit();
+
+/// The size of the arena for allocations made while `dlsym` looks up the
+/// real functions.
+const ARENA_SIZE: usize = 16 * 1024;
+/// The alignment of `malloc`, and of each arena allocation.
+const MIN_ALIGN: usize = 16;
+
+#[repr(C, align(16))]
+struct Arena([u8; ARENA_SIZE]);
+
+static mut ARENA: Arena = Arena([0; ARENA_SIZE]);
+/// The used length of the arena.
+static ARENA_USED: AtomicUsize = AtomicUsize::new(0);
+
+/// The most blocks that can be remembered as uncounted.
+const UNCOUNTED_SLOTS: usize = 1024;
+/// A slot whose block was freed, which lookups probe past.
+const TOMBSTONE: usize = 1;
+
+#[allow(clippy::declare_interior_mutable_const)]
+const EMPTY: AtomicUsize = AtomicUsize::new(0);
+/// The addresses of live blocks that were never counted, like those from
+/// before `READY`, as an open-addressed set with `0` for an empty slot.
+static UNCOUNTED: [AtomicUsize; UNCOUNTED_SLOTS] = [EMPTY; UNCOUNTED_SLOTS];
+/// The number of addresses in `UNCOUNTED`, so frees only look when there
+/// are any.
+static UNCOUNTED_LEN: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The result of a real allocation that the `Geiger` is about to count,
+    /// which `Real` hands over instead of allocating again.
+    static PREPARED: Cell<Option<*mut u8>> = const { Cell::new(None) };
+
+    /// A block that the real `realloc` already freed, which the `Geiger` is
+    /// about to count as freed, and `Real` must not free again.
+    static FREED: Cell<*mut u8> = const { Cell::new(ptr::null_mut()) };
+}
+
+#[used]
+#[link_section = ".init_array"]
+static CONSTRUCTOR: extern "C" fn() = {
+    extern "C" fn ready() {
+        READY.store(true, Ordering::Release);
+    }
+    ready
+};
+
+/// The C library's allocator functions.
+struct Functions {
+    malloc: unsafe extern "C" fn(usize) -> *mut c_void,
+    calloc: unsafe extern "C" fn(usize, usize) -> *mut c_void,
+    realloc: unsafe extern "C" fn(*mut c_void, usize) -> *mut c_void,
+    free: unsafe extern "C" fn(*mut c_void),
+    memalign: unsafe extern "C" fn(usize, usize) -> *mut c_void,
+}
+
+/// Returns the real functions, or `None` while they're being found.
+fn functions() -> Option<&'static Functions> {
+    let functions = FUNCTIONS.load(Ordering::Acquire);
+    if !functions.is_null() {
+        // SAFETY: `FUNCTIONS` only ever points to the initialized `STORAGE`.
+        return Some(unsafe { &*functions });
+    }
+    if RESOLVING.swap(true, Ordering::AcqRel) {
+        return None;
+    }
+    // SAFETY: the symbols are looked up by their C names and have those
+    // signatures, and only this thread writes `STORAGE`, once.
+    unsafe {
+        let found = Functions {
+            malloc: next(b"malloc\0")?,
+            calloc: next(b"calloc\0")?,
+            realloc: next(b"realloc\0")?,
+            free: next(b"free\0")?,
+            memalign: next(b"memalign\0")?,
+        };
+        let storage = ptr::addr_of_mut!(STORAGE);
+        (*storage).write(found);
+        FUNCTIONS.store((*storage).as_mut_ptr(), Ordering::Release);
+        Some((*storage).assume_init_ref())
+    }
+}
+
+/// Finds the next definition of a function after this library, as the
+/// function pointer type `F`.
+unsafe fn next<F>(name: &[u8]) -> Option<F> {
+    let symbol = libc::dlsym(libc::RTLD_NEXT, name.as_ptr().cast::<c_char>());
+    (!symbol.is_null()).then(|| mem::transmute_copy::<*mut c_void, F>(&symbol))
+}
+
+/// Allocates from the arena, for `dlsym` before the real functions are known.
+fn arena_alloc(size: usize) -> *mut c_void {
+    let size = (size.max(1) + MIN_ALIGN - 1) & !(MIN_ALIGN - 1);
+    let start = ARENA_USED.fetch_add(size, Ordering::Relaxed);
+    if start.saturating_add(size) > ARENA_SIZE {
+        return ptr::null_mut();
+    }
+    // SAFETY: the range is within the arena, and no one else was given it.
+    unsafe { ptr::addr_of_mut!(ARENA).cast::<u8>().add(start).cast() }
+}
+
+fn in_arena(ptr: *mut c_void) -> bool {
+    let start = ptr::addr_of!(ARENA) as usize;
+    (start..start + ARENA_SIZE).contains(&(ptr as usize))
+}
+
+/// The slots of `UNCOUNTED` to probe for an address, in order.
+fn probes(ptr: *mut c_void) -> impl Iterator<Item = &'static AtomicUsize> {
+    let start = (ptr as usize >> 4).wrapping_mul(0x9e37_79b9) % UNCOUNTED_SLOTS;
+    (0..UNCOUNTED_SLOTS).map(move |i| &UNCOUNTED[(start + i) % UNCOUNTED_SLOTS])
+}
+
+/// Remembers a block that wasn't counted, so that its free isn't either,
+/// unless there's no room left.
+fn mark_uncounted(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+    for slot in probes(ptr) {
+        let old = slot.load(Ordering::Relaxed);
+        if (old == 0 || old == TOMBSTONE)
+            && slot
+                .compare_exchange(old, ptr as usize, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            UNCOUNTED_LEN.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+    }
+}
+
+/// Forgets a block if it was never counted, returning whether it was.
+fn take_uncounted(ptr: *mut c_void) -> bool {
+    if UNCOUNTED_LEN.load(Ordering::Relaxed) == 0 {
+        return false;
+    }
+    for slot in probes(ptr) {
+        match slot.load(Ordering::Relaxed) {
+            0 => return false,
+            old if old == ptr as usize => {
+                slot.store(TOMBSTONE, Ordering::Relaxed);
+                UNCOUNTED_LEN.fetch_sub(1, Ordering::Relaxed);
+                return true;
+            }
+            _ => {}
+        }
+    }
+    false
+}
+
+/// The bytes that are actually usable at an allocated address.
+unsafe fn usable_size(ptr: *mut c_void) -> usize {
+    libc::malloc_usable_size(ptr)
+}
+
+/// Counts an allocation that was already made by the real function, unless
+/// `cap_enforce` refuses it, returning the result for the caller.
+unsafe fn count_alloc(result: *mut c_void, size: usize, align: usize, zeroed: bool) -> *mut c_void {
+    if !READY.load(Ordering::Acquire) {
+        mark_uncounted(result);
+        return result;
+    }
+    let size = if result.is_null() {
+        size
+    } else {
+        usable_size(result)
+    };
+    // A failure for a size too large for any layout is just passed on.
+    let Ok(layout) = Layout::from_size_align(size, align) else {
+        return result;
+    };
+    let (counted, refused) = with_prepared(result, || {
+        if zeroed {
+            GEIGER.alloc_zeroed(layout)
+        } else {
+            GEIGER.alloc(layout)
+        }
+    });
+    // The allocation wasn't handed over, so it was refused and isn't counted.
+    if let (Some(refused), Some(f)) = (refused, functions()) {
+        (f.free)(refused.cast());
+    }
+    counted.cast()
+}
+
+/// Prepares a real allocation for `Real` to hand over during `call`,
+/// returning what `call` returns and the allocation if it wasn't taken.
+///
+/// An allocation prepared by an outer call is set aside meanwhile, as the
+/// failure hook and alarm of a refusal may allocate again.
+fn with_prepared<R>(result: *mut c_void, call: impl FnOnce() -> R) -> (R, Option<*mut u8>) {
+    let outer = PREPARED.with(|prepared| prepared.replace(Some(result.cast())));
+    let returned = call();
+    (returned, PREPARED.with(|prepared| prepared.replace(outer)))
+}
+
+/// The real allocator, which gives a `PREPARED` allocation if there is one.
+///
+/// The C library's own alignment is enough for the layouts made here, which
+/// only have larger alignments from `memalign` and its relatives.
+struct Real;
+
+unsafe impl GlobalAlloc for Real {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(prepared) = PREPARED.with(Cell::take) {
+            return prepared;
+        }
+        match functions() {
+            Some(f) if layout.align() <= MIN_ALIGN => (f.malloc)(layout.size()).cast(),
+            Some(f) => (f.memalign)(layout.align(), layout.size()).cast(),
+            None => arena_alloc(layout.size()).cast(),
+        }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if let Some(prepared) = PREPARED.with(Cell::take) {
+            return prepared;
+        }
+        match functions() {
+            Some(f) if layout.align() <= MIN_ALIGN => (f.calloc)(1, layout.size()).cast(),
+            _ => {
+                let ptr = self.alloc(layout);
+                if !ptr.is_null() {
+                    ptr.write_bytes(0, layout.size());
+                }
+                ptr
+            }
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
+        if FREED.with(|freed| freed.get() == ptr) {
+            FREED.with(|freed| freed.set(ptr::null_mut()));
+            return;
+        }
+        if let Some(f) = functions() {
+            if !in_arena(ptr.cast()) {
+                (f.free)(ptr.cast());
+            }
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, _layout: Layout, new_size: usize) -> *mut u8 {
+        if let Some(prepared) = PREPARED.with(Cell::take) {
+            return prepared;
+        }
+        match functions() {
+            Some(f) => (f.realloc)(ptr.cast(), new_size).cast(),
+            None => ptr::null_mut(),
+        }
+    }
+}
+
+/// Allocates like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `malloc`.
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    let Some(f) = functions() else {
+        return arena_alloc(size);
+    };
+    count_alloc((f.malloc)(size), size, 1, false)
+}
+
+/// Allocates zeroed memory like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `calloc`.
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(f) = functions() else {
+        // the arena starts zeroed and is never reused
+        return match count.checked_mul(size) {
+            Some(total) => arena_alloc(total),
+            None => ptr::null_mut(),
+        };
+    };
+    let result = (f.calloc)(count, size);
+    count_alloc(result, count.saturating_mul(size), 1, true)
+}
+
+/// Frees memory like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `free`.
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() || in_arena(ptr) {
+        return;
+    }
+    match functions() {
+        Some(f) if take_uncounted(ptr) => (f.free)(ptr),
+        Some(_) if READY.load(Ordering::Acquire) => {
+            let layout = Layout::from_size_align_unchecked(usable_size(ptr), 1);
+            GEIGER.dealloc(ptr.cast(), layout);
+        }
+        Some(f) => (f.free)(ptr),
+        None => {}
+    }
+}
+
+/// Reallocates like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `realloc`.
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+    if in_arena(ptr) {
+        // the old size isn't known, so copy as much as the arena could hold
+        let new = malloc(size);
+        if !new.is_null() {
+            let available = ptr::addr_of!(ARENA) as usize + ARENA_SIZE - ptr as usize;
+            ptr::copy_nonoverlapping(ptr.cast::<u8>(), new.cast(), size.min(available));
+        }
+        return new;
+    }
+    let Some(f) = functions() else {
+        return ptr::null_mut();
+    };
+    if size == 0 {
+        free(ptr);
+        return ptr::null_mut();
+    }
+    let uncounted = take_uncounted(ptr);
+    if !READY.load(Ordering::Acquire) {
+        let result = (f.realloc)(ptr, size);
+        mark_uncounted(if result.is_null() { ptr } else { result });
+        return result;
+    }
+    if uncounted {
+        // the old block was never counted, so the new one is counted as an
+        // allocation of its own
+        let result = match GEIGER.allow(size) {
+            true => (f.realloc)(ptr, size),
+            false => ptr::null_mut(),
+        };
+        if result.is_null() {
+            mark_uncounted(ptr);
+            return result;
+        }
+        let layout = Layout::from_size_align_unchecked(usable_size(result), 1);
+        return match with_prepared(result, || GEIGER.alloc(layout)) {
+            // only refused if the cap was reached meanwhile, so it stays uncounted
+            (_, Some(_)) => {
+                mark_uncounted(result);
+                result
+            }
+            (counted, None) => counted.cast(),
+        };
+    }
+    let layout = Layout::from_size_align_unchecked(usable_size(ptr), 1);
+    // `cap_enforce` is asked first, since the real function may move the
+    // allocation, which can't be undone.  The `Geiger` asks again and plays
+    // the failure of a refusal, with nothing handed over.
+    let result = match GEIGER.allow(size.saturating_sub(layout.size())) {
+        true => (f.realloc)(ptr, size),
+        false => ptr::null_mut(),
+    };
+    let new_size = if result.is_null() {
+        // A failure for a size too large for any layout is just passed on.
+        if Layout::from_size_align(size, 1).is_err() {
+            return result;
+        }
+        size
+    } else {
+        usable_size(result)
+    };
+    match with_prepared(result, || GEIGER.realloc(ptr.cast(), layout, new_size)) {
+        // Only refused if the cap was reached meanwhile, too late to undo, so
+        // the old block is counted as freed, and the new one left uncounted.
+        (_, Some(_)) if result.is_null() => result,
+        (_, Some(_)) => {
+            FREED.with(|freed| freed.set(ptr.cast()));
+            GEIGER.dealloc(ptr.cast(), layout);
+            mark_uncounted(result);
+            result
+        }
+        (counted, None) => counted.cast(),
+    }
+}
+
+/// Reallocates an array like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `reallocarray`.
+#[no_mangle]
+pub unsafe extern "C" fn reallocarray(ptr: *mut c_void, count: usize, size: usize) -> *mut c_void {
+    match count.checked_mul(size) {
+        Some(total) => realloc(ptr, total),
+        None => {
+            *libc::__errno_location() = libc::ENOMEM;
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Allocates aligned memory like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `memalign`.
+#[no_mangle]
+pub unsafe extern "C" fn memalign(align: usize, size: usize) -> *mut c_void {
+    let Some(f) = functions() else {
+        return ptr::null_mut();
+    };
+    let align = align.max(1);
+    if !align.is_power_of_two() {
+        *libc::__errno_location() = libc::EINVAL;
+        return ptr::null_mut();
+    }
+    count_alloc((f.memalign)(align, size), size, align, false)
+}
+
+/// Allocates aligned memory like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `aligned_alloc`.
+#[no_mangle]
+pub unsafe extern "C" fn aligned_alloc(align: usize, size: usize) -> *mut c_void {
+    memalign(align, size)
+}
+
+/// Allocates aligned memory like the C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `posix_memalign`, with a
+/// valid `out` pointer.
+#[no_mangle]
+pub unsafe extern "C" fn posix_memalign(out: *mut *mut c_void, align: usize, size: usize) -> c_int {
+    if !align.is_power_of_two() || align % mem::size_of::<usize>() != 0 {
+        return libc::EINVAL;
+    }
+    let result = memalign(align, size);
+    if result.is_null() {
+        return libc::ENOMEM;
+    }
+    *out = result;
+    0
+}
+
+/// Allocates page-aligned memory like the obsolete C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `valloc`.
+#[no_mangle]
+pub unsafe extern "C" fn valloc(size: usize) -> *mut c_void {
+    memalign(page_size(), size)
+}
+
+/// Allocates whole pages like the obsolete C function.
+///
+/// # Safety
+///
+/// This is only called by C code as the process's `pvalloc`.
+#[no_mangle]
+pub unsafe extern "C" fn pvalloc(size: usize) -> *mut c_void {
+    let page = page_size();
+    match size.max(1).checked_add(page - 1) {
+        Some(end) => memalign(page, end & !(page - 1)),
+        None => {
+            *libc::__errno_location() = libc::ENOMEM;
+            ptr::null_mut()
+        }
+    }
+}
+
+fn page_size() -> usize {
+    // SAFETY: `sysconf` has no preconditions.
+    match unsafe { libc::sysconf(libc::_SC_PAGESIZE) } {
+        size if size > 0 => size as usize,
+        _ => 4096,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc_geiger::{AllocOp, EventReceiver, RecentEvent};
+
+    /// The events of an address, skipping the others.
+    fn events_of(events: &mut EventReceiver, ptr: *mut c_void) -> Vec<RecentEvent> {
+        let all: Vec<_> = events.try_iter().collect();
+        all.into_iter()
+            .filter(|event| event.address == ptr as usize)
+            .collect()
+    }
+
+    /// Checks that an allocation is counted with the same size as its free.
+    unsafe fn check_balanced(allocate: impl FnOnce() -> *mut c_void) {
+        let mut events = GEIGER.subscribe();
+        let ptr = allocate();
+        assert!(!ptr.is_null());
+        let size = usable_size(ptr);
+        free(ptr);
+        let ops: Vec<_> = (events_of(&mut events, ptr).iter())
+            .map(|event| (event.op, event.size))
+            .collect();
+        assert_eq!(ops, [(AllocOp::Alloc, size), (AllocOp::Dealloc, size)]);
+    }
+
+    #[test]
+    fn counts_every_allocator() {
+        unsafe {
+            check_balanced(|| malloc(100));
+            check_balanced(|| memalign(64, 100));
+            check_balanced(|| valloc(100));
+            check_balanced(|| pvalloc(100));
+            check_balanced(|| reallocarray(ptr::null_mut(), 10, 10));
+            assert_eq!(pvalloc(1) as usize % page_size(), 0);
+            assert!(usable_size(valloc(1)) >= 1);
+        }
+    }
+
+    #[test]
+    fn reallocates_arrays() {
+        unsafe {
+            let ptr = reallocarray(ptr::null_mut(), 10, 10);
+            ptr.cast::<u8>().write_bytes(7, 100);
+            assert!(reallocarray(ptr, usize::MAX, 2).is_null());
+            assert_eq!(*libc::__errno_location(), libc::ENOMEM);
+            let mut events = GEIGER.subscribe();
+            let new = reallocarray(ptr, 100, 10);
+            assert!(!new.is_null());
+            assert_eq!(*new.cast::<u8>().add(99), 7);
+            let realloc = events_of(&mut events, new);
+            assert_eq!(realloc[0].op, AllocOp::Realloc);
+            assert_eq!(realloc[0].size, usable_size(new));
+            free(new);
+        }
+    }
+
+    #[test]
+    fn leaves_blocks_from_before_ready_uncounted() {
+        unsafe {
+            // like the blocks `count_alloc` marks before the constructor runs
+            let f = functions().unwrap();
+            let early = (f.malloc)(100);
+            let moved = (f.malloc)(100);
+            mark_uncounted(early);
+            mark_uncounted(moved);
+            let mut events = GEIGER.subscribe();
+            free(early);
+            assert!(events_of(&mut events, early).is_empty());
+            // a reallocation is counted as a new allocation
+            moved.cast::<u8>().write_bytes(7, 100);
+            let grown = realloc(moved, 1000);
+            assert_eq!(*grown.cast::<u8>().add(99), 7);
+            let size = usable_size(grown);
+            free(grown);
+            let ops: Vec<_> = (events_of(&mut events, grown).iter())
+                .map(|event| (event.op, event.size))
+                .collect();
+            assert_eq!(ops, [(AllocOp::Alloc, size), (AllocOp::Dealloc, size)]);
+        }
+    }
+
+    #[test]
+    fn refuses_reallocs_before_moving() {
+        unsafe {
+            let ptr = malloc(1024);
+            ptr.cast::<u8>().write_bytes(7, 1024);
+            let cap = GEIGER.stats().live_bytes() + (64 << 20);
+            GEIGER.set("cap", &cap.to_string()).unwrap();
+            GEIGER.set("cap_enforce", "true").unwrap();
+            let refused = realloc(ptr, 256 << 20);
+            GEIGER.set("cap", "off").unwrap();
+            assert!(refused.is_null());
+            // the original is untouched, and still the caller's to free
+            assert_eq!(*ptr.cast::<u8>().add(1023), 7);
+            let mut events = GEIGER.subscribe();
+            free(ptr);
+            assert_eq!(events_of(&mut events, ptr)[0].op, AllocOp::Dealloc);
+        }
+    }
+}
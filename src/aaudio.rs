@@ -0,0 +1,75 @@
+//! Low-latency output with Oboe on Android, enabled by the `android` feature.
+//!
+//! Oboe uses AAudio where it's available, and OpenSL ES on older devices.
+//! The stream doesn't request audio focus, so it never pauses other media,
+//! and it's marked as sonification so the system mixes it like UI sounds.
+//! When the stream is disconnected, as when the device changes or another
+//! app takes exclusive use of it, the output is opened again at the next
+//! event.
+
+use crate::config::Config;
+use crate::mixer::{Grid, Mixer, Shared, DEFAULT_SAMPLE_RATE};
+use crate::pulse::Pulse;
+use oboe::{
+    AudioOutputCallback, AudioOutputStreamSafe, AudioStream, AudioStreamBuilder, ContentType,
+    DataCallbackResult, Error, PerformanceMode, SampleRateConversionQuality, SharingMode, Stereo,
+    Usage,
+};
+use std::sync::Arc;
+
+/// Fills Oboe's buffers from our `Mixer`, on Oboe's callback thread.
+struct Callback(Mixer);
+
+impl AudioOutputCallback for Callback {
+    type FrameType = (f32, Stereo);
+
+    fn on_error_after_close(&mut self, _stream: &mut dyn AudioOutputStreamSafe, _error: Error) {
+        self.0.lost();
+    }
+
+    fn on_audio_ready(
+        &mut self,
+        _stream: &mut dyn AudioOutputStreamSafe,
+        frames: &mut [(f32, f32)],
+    ) -> DataCallbackResult {
+        for frame in frames {
+            let left = self.0.next().unwrap_or(0.0);
+            let right = self.0.next().unwrap_or(0.0);
+            *frame = (left, right);
+        }
+        DataCallbackResult::Continue
+    }
+}
+
+/// Opens and starts a low-latency Oboe stream, returning its mixer once the
+/// first buffer was requested.
+pub(crate) fn open(config: &Config) -> Result<Arc<Shared>, String> {
+    // Oboe converts from our rate if the device uses another, which still
+    // allows a low-latency stream.
+    let sample_rate = config.sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+    let (mixer, started, shared) = Mixer::new(
+        sample_rate,
+        Grid::of(config),
+        config.audio_priority,
+        config.mix_budget,
+    );
+    let mut stream = AudioStreamBuilder::default()
+        .set_performance_mode(PerformanceMode::LowLatency)
+        .set_sharing_mode(SharingMode::Shared)
+        .set_usage(Usage::AssistanceSonification)
+        .set_content_type(ContentType::Sonification)
+        .set_sample_rate(sample_rate as i32)
+        .set_sample_rate_conversion_quality(SampleRateConversionQuality::Fastest)
+        .set_output()
+        .set_f32()
+        .set_stereo()
+        .set_callback(Callback(mixer))
+        .open_stream()
+        .map_err(|e| e.to_string())?;
+    stream.request_start().map_err(|e| e.to_string())?;
+    started.wait(config.init_timeout)?;
+    // As with `rodio`, the stream isn't `Send`, so we can't keep it in a
+    // static, but we also mustn't drop it or playback will end.
+    std::mem::forget(stream);
+    Ok(shared)
+}
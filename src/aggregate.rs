@@ -0,0 +1,168 @@
+//! Mixing the events of many processes in one, enabled by the `aggregate`
+//! feature on Unix.
+
+use crate::clock::now;
+use crate::event::{AllocEvent, AllocOp};
+use crate::{Geiger, RecentEvent, BUSY};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::net::UnixDatagram;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, PoisonError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// The size of each event's datagram.
+const RECORD: usize = 32;
+
+/// How long to wait before connecting again when there's no aggregator.
+const RETRY: Duration = Duration::from_secs(1);
+
+/// The process running the aggregator, which plays its own events instead
+/// of forwarding them, even if its children inherited the setting.
+static AGGREGATOR: AtomicU32 = AtomicU32::new(0);
+
+/// The socket events are forwarded to, by its path, or when to try again.
+static SOCKET: Mutex<Option<(String, Result<UnixDatagram, Instant>)>> = Mutex::new(None);
+
+/// Sends an event to the aggregator at `path`, returning `false` if this is
+/// the aggregator's own process, which should play it instead.
+///
+/// Events are dropped while there's no aggregator, or when it's too busy to
+/// keep up, without blocking the allocation.
+pub(crate) fn forward(path: &str, event: &AllocEvent) -> bool {
+    if AGGREGATOR.load(Ordering::Relaxed) == std::process::id() {
+        return false;
+    }
+    let mut socket = SOCKET.lock().unwrap_or_else(PoisonError::into_inner);
+    let stale = match &*socket {
+        Some((connected, Ok(_))) => connected != path,
+        Some((connected, Err(retry_at))) => connected != path || Instant::now() >= *retry_at,
+        None => true,
+    };
+    if stale {
+        *socket = Some((path.to_owned(), connect(path)));
+    }
+    if let Some((_, Ok(datagram))) = &*socket {
+        if let Err(e) = datagram.send(&encode(event)) {
+            if e.kind() != io::ErrorKind::WouldBlock {
+                // the aggregator went away, so look for a new one later
+                *socket = Some((path.to_owned(), Err(Instant::now() + RETRY)));
+            }
+        }
+    }
+    true
+}
+
+fn connect(path: &str) -> Result<UnixDatagram, Instant> {
+    let connect = || {
+        let datagram = UnixDatagram::unbound()?;
+        datagram.connect(path)?;
+        datagram.set_nonblocking(true)?;
+        Ok::<_, io::Error>(datagram)
+    };
+    connect().map_err(|_| Instant::now() + RETRY)
+}
+
+/// Encodes an event with the process and thread it came from, in little
+/// endian: the method, 3 bytes of padding, the thread index, the process ID,
+/// 4 more bytes of padding, the size, and the address.
+fn encode(event: &AllocEvent) -> [u8; RECORD] {
+    let op = match event.op {
+        AllocOp::Alloc => 0,
+        AllocOp::AllocZeroed => 1,
+        AllocOp::Dealloc => 2,
+        AllocOp::Realloc => 3,
+    };
+    let mut record = [0; RECORD];
+    record[0] = op;
+    record[4..8].copy_from_slice(&event.thread().to_le_bytes());
+    record[8..12].copy_from_slice(&std::process::id().to_le_bytes());
+    record[16..24].copy_from_slice(&(event.new_size as u64).to_le_bytes());
+    record[24..32].copy_from_slice(&(event.address as u64).to_le_bytes());
+    record
+}
+
+/// Decodes an event, returning its process ID and the thread index within
+/// that process.
+fn decode(record: &[u8; RECORD]) -> Option<(u32, RecentEvent)> {
+    let u32_at = |i: usize| u32::from_le_bytes(record[i..i + 4].try_into().unwrap());
+    let u64_at = |i: usize| u64::from_le_bytes(record[i..i + 8].try_into().unwrap());
+    let op = match record[0] {
+        0 => AllocOp::Alloc,
+        1 => AllocOp::AllocZeroed,
+        2 => AllocOp::Dealloc,
+        3 => AllocOp::Realloc,
+        _ => return None,
+    };
+    let event = RecentEvent {
+        op,
+        size: u64_at(16).try_into().ok()?,
+        address: u64_at(24).try_into().ok()?,
+        time: now(),
+        thread: u32_at(4),
+    };
+    Some((u32_at(8), event))
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread listening on a Unix socket for the events of other
+    /// processes, and playing them with this process's sound, so a
+    /// multi-process application, like a forking server, makes one coherent
+    /// soundscape instead of racing audio streams.
+    ///
+    /// Processes with [`Config::forward`](crate::Config::forward) set to the
+    /// same path, as by `ALLOC_GEIGER_FORWARD` in their environment, send
+    /// their events here instead of playing them.  This process still plays
+    /// its own events, even if its children inherit the setting.  Each thread
+    /// of each process is given its own index for the pitch and pan, in
+    /// order of their first events here.
+    ///
+    /// A stale socket left at `path` is removed first.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_aggregator("/tmp/alloc_geiger.sock").unwrap();
+    ///     std::process::Command::new("worker")
+    ///         .env("ALLOC_GEIGER_FORWARD", "/tmp/alloc_geiger.sock")
+    ///         .spawn()
+    ///         .unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_aggregator<P: AsRef<Path>>(&'static self, path: P) -> io::Result<JoinHandle<()>> {
+        let path = path.as_ref();
+        if fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            fs::remove_file(path)?;
+        }
+        let socket = UnixDatagram::bind(path)?;
+        AGGREGATOR.store(std::process::id(), Ordering::Relaxed);
+        thread::Builder::new()
+            .name("alloc_geiger-aggregate".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let mut threads = HashMap::new();
+                let mut record = [0; RECORD];
+                loop {
+                    match socket.recv(&mut record) {
+                        Ok(RECORD) => {}
+                        Ok(_) => continue,
+                        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                        Err(_) => return,
+                    }
+                    let Some((pid, mut event)) = decode(&record) else {
+                        continue;
+                    };
+                    let next = threads.len() as u32;
+                    event.thread = *threads.entry((pid, event.thread)).or_insert(next);
+                    self.replay(&event);
+                }
+            })
+    }
+}
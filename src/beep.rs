@@ -0,0 +1,32 @@
+//! The Windows `Beep` function, enabled by the `beep` feature.
+
+use crate::BUSY;
+use std::io;
+use std::thread::{self, Thread};
+
+/// The fixed pitch of each beep, in Hz.
+const FREQUENCY: u32 = 1000;
+/// The length of each beep in milliseconds, which `Beep` blocks for.
+const DURATION: u32 = 10;
+
+#[link(name = "kernel32")]
+extern "system" {
+    fn Beep(frequency: u32, duration: u32) -> i32;
+}
+
+/// Spawns a thread that beeps each time it's unparked, so the allocator
+/// never waits for `Beep` to return.
+pub(crate) fn spawn() -> io::Result<Thread> {
+    let handle = thread::Builder::new()
+        .name("alloc_geiger-beep".into())
+        .spawn(|| {
+            BUSY.with(|busy| busy.set(true));
+            loop {
+                thread::park();
+                // SAFETY: `Beep` has no preconditions, and a failure is
+                // just silence.
+                unsafe { Beep(FREQUENCY, DURATION) };
+            }
+        })?;
+    Ok(handle.thread().clone())
+}
@@ -0,0 +1,131 @@
+//! Allocation measurements for `criterion` benchmarks, enabled by the `bench`
+//! feature.
+//!
+//! These count the activity on the benchmark's thread through a
+//! [`Geiger`](crate::Geiger) global allocator, using the same counters as
+//! [`count`](crate::count).  Criterion measures one thing per group, so a
+//! benchmark can be run in one group timed as usual and in another group with
+//! [`Allocations`] to catch allocation regressions alongside time.
+//!
+//! ```no_run
+//! use alloc_geiger::bench::Allocations;
+//! use criterion::Criterion;
+//!
+//! #[global_allocator]
+//! static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+//!
+//! fn format(c: &mut Criterion<Allocations>) {
+//!     c.bench_function("format", |b| b.iter(|| format!("{}", 42)));
+//! }
+//!
+//! fn main() {
+//!     let mut c = Criterion::default().with_measurement(Allocations);
+//!     format(&mut c);
+//!     c.final_summary();
+//! }
+//! ```
+
+use crate::count::Counter;
+use criterion::measurement::{Measurement, ValueFormatter};
+use criterion::Throughput;
+
+/// Measures the number of `alloc`, `alloc_zeroed`, and `realloc` calls.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Allocations;
+
+impl Measurement for Allocations {
+    type Intermediate = Counter;
+    type Value = u64;
+
+    fn start(&self) -> Self::Intermediate {
+        Counter::start()
+    }
+
+    fn end(&self, counter: Self::Intermediate) -> Self::Value {
+        let counts = counter.finish();
+        counts.allocs + counts.reallocs
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &Formatter("allocs")
+    }
+}
+
+/// Measures the bytes requested by `alloc`, `alloc_zeroed`, and `realloc`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AllocatedBytes;
+
+impl Measurement for AllocatedBytes {
+    type Intermediate = Counter;
+    type Value = u64;
+
+    fn start(&self) -> Self::Intermediate {
+        Counter::start()
+    }
+
+    fn end(&self, counter: Self::Intermediate) -> Self::Value {
+        counter.finish().bytes_allocated
+    }
+
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        v1 + v2
+    }
+
+    fn zero(&self) -> Self::Value {
+        0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value as f64
+    }
+
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &Formatter("bytes")
+    }
+}
+
+/// Formats plain counts of the given unit, without scaling.
+struct Formatter(&'static str);
+
+impl ValueFormatter for Formatter {
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        self.0
+    }
+
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        throughput: &Throughput,
+        values: &mut [f64],
+    ) -> &'static str {
+        let (n, unit) = match *throughput {
+            Throughput::Bytes(n) | Throughput::BytesDecimal(n) => (n, "byte"),
+            Throughput::Elements(n) => (n, "elem"),
+        };
+        for value in values {
+            *value /= n as f64;
+        }
+        match (self.0, unit) {
+            ("allocs", "byte") => "allocs/byte",
+            ("allocs", _) => "allocs/elem",
+            (_, "byte") => "bytes/byte",
+            _ => "bytes/elem",
+        }
+    }
+
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        self.0
+    }
+}
@@ -0,0 +1,107 @@
+//! Runs a program with the Geiger counter attached through `LD_PRELOAD`,
+//! without adding `alloc_geiger` to its build at all.
+//!
+//! ```text
+//! alloc-geiger-run [--preload LIBRARY] [--set KEY=VALUE]... PROGRAM [ARGS]...
+//! ```
+//!
+//! The library is the one built by the `alloc_geiger_preload` package, found
+//! from `--preload`, then `ALLOC_GEIGER_PRELOAD`, then
+//! `liballoc_geiger_preload.so` next to this executable.  Each `--set` passes a setting to the program as its
+//! `ALLOC_GEIGER_*` environment variable, like `--set pitch=size` for
+//! `ALLOC_GEIGER_PITCH=size`, after checking it as in `Config::set`.
+
+use alloc_geiger::Config;
+use std::env;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::process::{Command, ExitCode};
+
+const USAGE: &str =
+    "usage: alloc-geiger-run [--preload LIBRARY] [--set KEY=VALUE]... PROGRAM [ARGS]...";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("alloc-geiger-run: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<ExitCode, String> {
+    let mut preload = env::var_os("ALLOC_GEIGER_PRELOAD").map(PathBuf::from);
+    let mut settings = Vec::new();
+    let mut args = env::args_os().skip(1);
+    let program = loop {
+        let arg = args.next().ok_or(USAGE)?;
+        match arg.to_str() {
+            Some("--preload") => preload = Some(args.next().ok_or(USAGE)?.into()),
+            Some("--set") => {
+                let setting = args.next().ok_or(USAGE)?;
+                let setting = setting.to_str().ok_or(USAGE)?;
+                let (key, value) = (setting.split_once('='))
+                    .ok_or_else(|| format!("invalid setting, not KEY=VALUE: {setting}"))?;
+                Config::new().set(key, value).map_err(|e| e.to_string())?;
+                settings.push((key.to_ascii_uppercase(), value.to_owned()));
+            }
+            Some("-h" | "--help") => {
+                println!("{USAGE}");
+                return Ok(ExitCode::SUCCESS);
+            }
+            Some("--") => break args.next().ok_or(USAGE)?,
+            Some(s) if s.starts_with('-') => return Err(USAGE.into()),
+            _ => break arg,
+        }
+    };
+
+    let preload = match preload {
+        Some(path) => path,
+        None => env::current_exe()
+            .map_err(|e| format!("can't find the preload library: {e}"))?
+            .with_file_name("liballoc_geiger_preload.so"),
+    };
+    if !preload.is_file() {
+        return Err(format!(
+            "missing preload library {}, built with \
+             `cargo build --release -p alloc_geiger_preload`",
+            preload.display()
+        ));
+    }
+    let preload = preload
+        .canonicalize()
+        .map_err(|e| format!("{}: {e}", preload.display()))?;
+
+    // Keep any other preloaded libraries, after ours.
+    let mut ld_preload = OsString::from(preload);
+    if let Some(others) = env::var_os("LD_PRELOAD").filter(|others| !others.is_empty()) {
+        ld_preload.push(" ");
+        ld_preload.push(others);
+    }
+    let mut command = Command::new(&program);
+    command.args(args).env("LD_PRELOAD", ld_preload);
+    for (key, value) in settings {
+        command.env(format!("ALLOC_GEIGER_{key}"), value);
+    }
+    let program = program.to_string_lossy();
+    exec(&mut command).map_err(|e| format!("{program}: {e}"))
+}
+
+/// Replaces this process with the program, so it keeps the same process ID
+/// and signals.
+#[cfg(unix)]
+fn exec(command: &mut Command) -> Result<ExitCode, std::io::Error> {
+    use std::os::unix::process::CommandExt;
+    Err(command.exec())
+}
+
+/// Runs the program to completion, passing on its exit code.
+#[cfg(not(unix))]
+fn exec(command: &mut Command) -> Result<ExitCode, std::io::Error> {
+    let status = command.status()?;
+    Ok(match status.code() {
+        Some(code) => ExitCode::from(code as u8),
+        None => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,114 @@
+//! Replays a trace from `Geiger::spawn_trace_recorder`, or a binary one from
+//! `Geiger::spawn_binary_trace_recorder`, slowed down or sped up, so bursts
+//! that take microseconds can be heard one click at a time.
+//!
+//! ```text
+//! geiger-replay [--speed FACTOR] [--baseline TRACE] [--set KEY=VALUE]... TRACE
+//! ```
+//!
+//! The speed is a factor from 0.1 to 10, 1 by default.  With a baseline
+//! trace, only the events in size classes allocating faster than in the
+//! baseline are played, as in `Config::baseline`, to hear what a change
+//! added.  Each `--set` changes
+//! a sound setting as in `Config::set`, like `--set pitch=size` or
+//! `--set pan=thread`, and the `ALLOC_GEIGER_*` environment variables apply
+//! too, taking precedence as usual.
+
+use alloc_geiger::{read_trace, Geiger, RecentEvent};
+use std::alloc::System;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// The replaying `Geiger`, which isn't the global allocator so the tool's
+/// own allocations stay quiet.
+static GEIGER: Geiger<System> = Geiger::new(System);
+
+/// How long to wait after the last event for its sound to finish.
+const TAIL: Duration = Duration::from_millis(500);
+
+const USAGE: &str =
+    "usage: geiger-replay [--speed FACTOR] [--baseline TRACE] [--set KEY=VALUE]... TRACE";
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("geiger-replay: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run() -> Result<(), String> {
+    let mut speed = 1.0;
+    let mut path = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--speed" => {
+                let value = args.next().ok_or(USAGE)?;
+                speed = value
+                    .parse()
+                    .ok()
+                    .filter(|speed| (0.1..=10.0).contains(speed))
+                    .ok_or_else(|| format!("invalid speed, not from 0.1 to 10: {value}"))?;
+            }
+            "--baseline" => {
+                let baseline = args.next().ok_or(USAGE)?;
+                GEIGER
+                    .set("baseline", &baseline)
+                    .map_err(|e| e.to_string())?;
+            }
+            "--set" => {
+                let setting = args.next().ok_or(USAGE)?;
+                let (key, value) = (setting.split_once('='))
+                    .ok_or_else(|| format!("invalid setting, not KEY=VALUE: {setting}"))?;
+                GEIGER.set(key, value).map_err(|e| e.to_string())?;
+            }
+            "-h" | "--help" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ if path.is_none() && !arg.starts_with('-') => path = Some(arg),
+            _ => return Err(USAGE.into()),
+        }
+    }
+    let path = path.ok_or(USAGE)?;
+    let file = File::open(&path).map_err(|e| format!("{path}: {e}"))?;
+    let events = read_trace(BufReader::new(file)).map_err(|e| format!("{path}: {e}"))?;
+    if !GEIGER.init() {
+        let error = GEIGER.init_error().map(|e| e.to_string());
+        return Err(error.unwrap_or_else(|| "no backend is available".into()));
+    }
+    replay(&events, speed);
+    Ok(())
+}
+
+/// Plays the events at their recorded times, scaled by `speed`.
+fn replay(events: &[RecentEvent], speed: f64) {
+    let Some(first) = events.first() else {
+        return;
+    };
+    let start = Instant::now();
+    for event in events {
+        let offset = (event.time.saturating_sub(first.time)).div_f64(speed);
+        if let Some(wait) = offset.checked_sub(start.elapsed()) {
+            thread::sleep(wait);
+        }
+        GEIGER.replay(event);
+    }
+    thread::sleep(TAIL);
+    let span = events
+        .last()
+        .map_or(Duration::ZERO, |last| last.time.saturating_sub(first.time));
+    eprintln!(
+        "geiger-replay: {} events from {:?}, over {:?}",
+        events.len(),
+        span,
+        start.elapsed() - TAIL
+    );
+}
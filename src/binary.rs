@@ -0,0 +1,738 @@
+//! A compact binary trace of allocator events, with metadata about the
+//! process that recorded it.
+//!
+//! A trace starts with the magic bytes `AGTRACE\0`, then the format version
+//! and flags as little-endian `u16`s.  The rest is a series of records, each
+//! a tag byte and its fields, compressed as one zstd frame if flag bit 0 is
+//! set.  Integers are LEB128 varints, with times and addresses as zigzag
+//! deltas from the previous event, and strings are a length and UTF-8 bytes.
+//!
+//! - `1` metadata: a count of key and value string pairs.
+//! - `2` thread name: the thread index and its name.
+//! - `3` event: the time delta in nanoseconds, the method, the size, the
+//!   address delta, and the thread index.
+//! - `4` dropped: the total count of events dropped so far.
+
+use crate::clock::clock_calibration;
+use crate::config::Config;
+use crate::{pitch, quietly, AllocOp, Geiger, RecentEvent, BUSY};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Read, Write};
+use std::path::Path;
+use std::process;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The first bytes of a binary trace.
+pub(crate) const MAGIC: &[u8; 8] = b"AGTRACE\0";
+/// The format version written, and the newest one read.
+const VERSION: u16 = 1;
+/// The flag for a zstd-compressed body.
+const COMPRESSED: u16 = 1;
+
+const METADATA: u8 = 1;
+const THREAD_NAME: u8 = 2;
+const EVENT: u8 = 3;
+const DROPPED: u8 = 4;
+
+/// How long the recorder sleeps when there are no new events.
+const IDLE: Duration = Duration::from_millis(1);
+
+/// About the process that recorded a binary trace.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct TraceMetadata {
+    /// The process ID.
+    pub pid: u32,
+    /// The program and its arguments.
+    pub command: Vec<String>,
+    /// The wall-clock time of the zero of the event times.
+    pub start: SystemTime,
+    /// The `CLOCK_MONOTONIC` time of the zero of the event times, on Unix.
+    pub monotonic: Option<Duration>,
+    /// How long it took to read the clocks for `start` and `monotonic`,
+    /// bounding their error, as in [`ClockCalibration`].
+    ///
+    /// [`ClockCalibration`]: crate::ClockCalibration
+    pub clock_uncertainty: Duration,
+    /// The settings in use, as listed by [`Config`]'s `Display`.
+    pub config: String,
+    /// The version of `alloc_geiger` that wrote the trace.
+    pub version: String,
+}
+
+impl TraceMetadata {
+    /// Describes the current process, with its settings and the calibration
+    /// of its event times from [`clock_calibration`].
+    ///
+    /// [`clock_calibration`]: crate::clock_calibration
+    pub fn current(config: &Config) -> Self {
+        let calibration = clock_calibration();
+        TraceMetadata {
+            pid: process::id(),
+            command: env::args().collect(),
+            start: calibration.wall,
+            monotonic: calibration.monotonic,
+            clock_uncertainty: calibration.uncertainty,
+            config: config.to_string(),
+            version: env!("CARGO_PKG_VERSION").into(),
+        }
+    }
+
+    fn pairs(&self) -> Vec<(&str, String)> {
+        let start = self.start.duration_since(UNIX_EPOCH).unwrap_or_default();
+        let mut pairs = vec![
+            ("pid", self.pid.to_string()),
+            ("start", start.as_nanos().to_string()),
+            (
+                "clock_uncertainty",
+                self.clock_uncertainty.as_nanos().to_string(),
+            ),
+            ("config", self.config.clone()),
+            ("version", self.version.clone()),
+        ];
+        if let Some(monotonic) = self.monotonic {
+            pairs.push(("monotonic", monotonic.as_nanos().to_string()));
+        }
+        pairs.extend(self.command.iter().map(|arg| ("arg", arg.clone())));
+        pairs
+    }
+
+    /// Fills in the fields from pairs, ignoring any keys from newer versions.
+    fn set(&mut self, key: &str, value: String) -> io::Result<()> {
+        match key {
+            "pid" => self.pid = value.parse().map_err(|_| invalid("invalid pid"))?,
+            "start" => {
+                let nanos: u64 = value.parse().map_err(|_| invalid("invalid start"))?;
+                self.start = UNIX_EPOCH + Duration::from_nanos(nanos);
+            }
+            "monotonic" => {
+                let nanos = value.parse().map_err(|_| invalid("invalid monotonic"))?;
+                self.monotonic = Some(Duration::from_nanos(nanos));
+            }
+            "clock_uncertainty" => {
+                let nanos = value
+                    .parse()
+                    .map_err(|_| invalid("invalid clock_uncertainty"))?;
+                self.clock_uncertainty = Duration::from_nanos(nanos);
+            }
+            "config" => self.config = value,
+            "version" => self.version = value,
+            "arg" => self.command.push(value),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Where a [`TraceWriter`] writes the body of a trace.
+enum Sink<W: Write> {
+    Plain(W),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::stream::write::Encoder<'static, W>),
+}
+
+impl<W: Write> Write for Sink<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Sink::Plain(w) => w.write(buf),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Sink::Plain(w) => w.flush(),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w.flush(),
+        }
+    }
+}
+
+/// Writes allocator events in the binary trace format, which is smaller and
+/// faster to read than the text of [`Geiger::spawn_trace_recorder`], and
+/// records thread names and the settings in use too.
+///
+/// ```
+/// use alloc_geiger::{Config, TraceMetadata, TraceReader, TraceWriter};
+///
+/// let text = "1000 alloc 64 4096 0\n2500 dealloc 64 4096 0\n";
+/// let events = alloc_geiger::read_trace(text.as_bytes()).unwrap();
+///
+/// let metadata = TraceMetadata::current(&Config::new());
+/// let mut writer = TraceWriter::new(Vec::new(), &metadata).unwrap();
+/// writer.write_thread_name(0, "main").unwrap();
+/// for event in &events {
+///     writer.write_event(event).unwrap();
+/// }
+/// let trace = writer.finish().unwrap();
+///
+/// let mut reader = TraceReader::new(&trace[..]).unwrap();
+/// assert_eq!(reader.metadata(), &metadata);
+/// let read: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+/// assert_eq!(read, events);
+/// assert_eq!(reader.thread_name(0), Some("main"));
+/// ```
+pub struct TraceWriter<W: Write> {
+    sink: Sink<W>,
+    /// the time of the last event, in nanoseconds
+    time: u64,
+    address: u64,
+}
+
+impl<W: Write> TraceWriter<W> {
+    /// Starts an uncompressed trace, writing its header and metadata.
+    pub fn new(mut writer: W, metadata: &TraceMetadata) -> io::Result<Self> {
+        write_header(&mut writer, 0)?;
+        TraceWriter::start(Sink::Plain(writer), metadata)
+    }
+
+    /// Starts a trace compressed with zstd at the given level, from 1 to 19,
+    /// writing its header and metadata.
+    #[cfg(feature = "zstd")]
+    pub fn compressed(mut writer: W, metadata: &TraceMetadata, level: i32) -> io::Result<Self> {
+        write_header(&mut writer, COMPRESSED)?;
+        let encoder = zstd::stream::write::Encoder::new(writer, level)?;
+        TraceWriter::start(Sink::Zstd(encoder), metadata)
+    }
+
+    fn start(sink: Sink<W>, metadata: &TraceMetadata) -> io::Result<Self> {
+        let mut trace = TraceWriter {
+            sink,
+            time: 0,
+            address: 0,
+        };
+        let pairs = metadata.pairs();
+        trace.sink.write_all(&[METADATA])?;
+        write_varint(&mut trace.sink, pairs.len() as u64)?;
+        for (key, value) in pairs {
+            write_str(&mut trace.sink, key)?;
+            write_str(&mut trace.sink, &value)?;
+        }
+        Ok(trace)
+    }
+
+    /// Writes an event, which should be no earlier than the last.
+    pub fn write_event(&mut self, event: &RecentEvent) -> io::Result<()> {
+        let time = event.time.as_nanos() as u64;
+        let address = event.address as u64;
+        let op = match event.op {
+            AllocOp::Alloc => 0,
+            AllocOp::AllocZeroed => 1,
+            AllocOp::Dealloc => 2,
+            AllocOp::Realloc => 3,
+        };
+        let w = &mut self.sink;
+        w.write_all(&[EVENT])?;
+        write_varint(w, zigzag(time.wrapping_sub(self.time) as i64))?;
+        w.write_all(&[op])?;
+        write_varint(w, event.size as u64)?;
+        write_varint(w, zigzag(address.wrapping_sub(self.address) as i64))?;
+        write_varint(w, event.thread.into())?;
+        self.time = time;
+        self.address = address;
+        Ok(())
+    }
+
+    /// Names the thread with an index, before or after its events.
+    pub fn write_thread_name(&mut self, thread: u32, name: &str) -> io::Result<()> {
+        self.sink.write_all(&[THREAD_NAME])?;
+        write_varint(&mut self.sink, thread.into())?;
+        write_str(&mut self.sink, name)
+    }
+
+    /// Notes the total count of events dropped so far, when the recorder
+    /// fell behind.
+    pub fn write_dropped(&mut self, total: u64) -> io::Result<()> {
+        self.sink.write_all(&[DROPPED])?;
+        write_varint(&mut self.sink, total)
+    }
+
+    /// Flushes everything written so far, which a reader can then read even
+    /// before the trace is finished.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.sink.flush()
+    }
+
+    /// Ends the trace, returning the writer.
+    pub fn finish(self) -> io::Result<W> {
+        match self.sink {
+            Sink::Plain(mut w) => w.flush().map(|()| w),
+            #[cfg(feature = "zstd")]
+            Sink::Zstd(w) => w.finish(),
+        }
+    }
+}
+
+/// Where a [`TraceReader`] reads the body of a trace.
+enum Source<R: BufRead> {
+    Plain(R),
+    #[cfg(feature = "zstd")]
+    Zstd(io::BufReader<zstd::stream::read::Decoder<'static, R>>),
+}
+
+impl<R: BufRead> Read for Source<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Source::Plain(r) => r.read(buf),
+            // an unfinished frame reads as far as it goes, like a trace
+            // that's still being written
+            #[cfg(feature = "zstd")]
+            Source::Zstd(r) => match r.read(buf) {
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(0),
+                result => result,
+            },
+        }
+    }
+}
+
+/// Reads the events of a binary trace from [`TraceWriter`] or
+/// [`Geiger::spawn_binary_trace_recorder`], in order.
+///
+/// Thread names and the count of dropped events are recorded as they're
+/// found, so they're complete once all the events have been read.
+pub struct TraceReader<R: BufRead> {
+    source: Source<R>,
+    metadata: TraceMetadata,
+    thread_names: BTreeMap<u32, String>,
+    dropped: u64,
+    time: u64,
+    address: u64,
+}
+
+impl<R: BufRead> TraceReader<R> {
+    /// Starts reading a trace, checking its header and reading its metadata.
+    ///
+    /// Reading a compressed trace needs the `zstd` feature, or else this
+    /// returns an error of kind `Unsupported`.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0; 12];
+        reader.read_exact(&mut header)?;
+        if header[..8] != MAGIC[..] {
+            return Err(invalid("not a binary alloc_geiger trace"));
+        }
+        let version = u16::from_le_bytes([header[8], header[9]]);
+        let flags = u16::from_le_bytes([header[10], header[11]]);
+        if version == 0 || version > VERSION {
+            return Err(invalid(&format!("unsupported trace version {version}")));
+        }
+        let source = if flags & COMPRESSED == 0 {
+            Source::Plain(reader)
+        } else {
+            compressed(reader)?
+        };
+        let mut trace = TraceReader {
+            source,
+            metadata: TraceMetadata {
+                pid: 0,
+                command: Vec::new(),
+                start: UNIX_EPOCH,
+                monotonic: None,
+                clock_uncertainty: Duration::ZERO,
+                config: String::new(),
+                version: String::new(),
+            },
+            thread_names: BTreeMap::new(),
+            dropped: 0,
+            time: 0,
+            address: 0,
+        };
+        if trace.read_tag()? != Some(METADATA) {
+            return Err(invalid("missing trace metadata"));
+        }
+        for _ in 0..read_varint(&mut trace.source)? {
+            let key = read_string(&mut trace.source)?;
+            let value = read_string(&mut trace.source)?;
+            trace.metadata.set(&key, value)?;
+        }
+        Ok(trace)
+    }
+
+    /// About the process that recorded the trace.
+    pub fn metadata(&self) -> &TraceMetadata {
+        &self.metadata
+    }
+
+    /// The name of the thread with an index, if it's been read.
+    pub fn thread_name(&self, thread: u32) -> Option<&str> {
+        self.thread_names.get(&thread).map(String::as_str)
+    }
+
+    /// The count of events dropped while recording, as far as it's been read.
+    pub fn dropped(&self) -> u64 {
+        self.dropped
+    }
+
+    /// Reads the next tag, or `None` at the end of the trace.
+    fn read_tag(&mut self) -> io::Result<Option<u8>> {
+        let mut tag = [0];
+        loop {
+            match self.source.read(&mut tag) {
+                Ok(0) => return Ok(None),
+                Ok(_) => return Ok(Some(tag[0])),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn read_event(&mut self) -> io::Result<RecentEvent> {
+        let r = &mut self.source;
+        let time = self.time.wrapping_add(unzigzag(read_varint(r)?) as u64);
+        let mut op = [0];
+        r.read_exact(&mut op)?;
+        let op = match op[0] {
+            0 => AllocOp::Alloc,
+            1 => AllocOp::AllocZeroed,
+            2 => AllocOp::Dealloc,
+            3 => AllocOp::Realloc,
+            _ => return Err(invalid("invalid event method")),
+        };
+        let size = read_varint(r)?;
+        let address = self.address.wrapping_add(unzigzag(read_varint(r)?) as u64);
+        let thread = read_varint(r)?;
+        self.time = time;
+        self.address = address;
+        Ok(RecentEvent {
+            op,
+            size: size.try_into().map_err(|_| invalid("invalid event size"))?,
+            address: address as usize,
+            time: Duration::from_nanos(time),
+            thread: thread.try_into().map_err(|_| invalid("invalid thread"))?,
+        })
+    }
+}
+
+impl<R: BufRead> Iterator for TraceReader<R> {
+    type Item = io::Result<RecentEvent>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = (|| loop {
+            match self.read_tag()? {
+                None => return Ok(None),
+                Some(EVENT) => return self.read_event().map(Some),
+                Some(THREAD_NAME) => {
+                    let thread = read_varint(&mut self.source)?;
+                    let name = read_string(&mut self.source)?;
+                    let thread = thread.try_into().map_err(|_| invalid("invalid thread"))?;
+                    self.thread_names.insert(thread, name);
+                }
+                Some(DROPPED) => self.dropped = read_varint(&mut self.source)?,
+                Some(tag) => return Err(invalid(&format!("invalid record tag {tag}"))),
+            }
+        })();
+        result.transpose()
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn compressed<R: BufRead>(reader: R) -> io::Result<Source<R>> {
+    let decoder = zstd::stream::read::Decoder::with_buffer(reader)?;
+    Ok(Source::Zstd(io::BufReader::new(decoder)))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compressed<R: BufRead>(_reader: R) -> io::Result<Source<R>> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "reading a compressed trace needs the `zstd` feature",
+    ))
+}
+
+fn write_header(w: &mut impl Write, flags: u16) -> io::Result<()> {
+    w.write_all(MAGIC)?;
+    w.write_all(&VERSION.to_le_bytes())?;
+    w.write_all(&flags.to_le_bytes())
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn zigzag(n: i64) -> u64 {
+    ((n << 1) ^ (n >> 63)) as u64
+}
+
+fn unzigzag(n: u64) -> i64 {
+    (n >> 1) as i64 ^ -((n & 1) as i64)
+}
+
+fn write_varint(w: &mut impl Write, mut n: u64) -> io::Result<()> {
+    let mut buf = [0; 10];
+    let mut len = 0;
+    loop {
+        let byte = (n & 0x7f) as u8;
+        n >>= 7;
+        if n == 0 {
+            buf[len] = byte;
+            len += 1;
+            return w.write_all(&buf[..len]);
+        }
+        buf[len] = byte | 0x80;
+        len += 1;
+    }
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<u64> {
+    let mut n = 0;
+    for shift in (0..64).step_by(7) {
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+        n |= u64::from(byte[0] & 0x7f) << shift;
+        if byte[0] & 0x80 == 0 {
+            return Ok(n);
+        }
+    }
+    Err(invalid("invalid varint"))
+}
+
+fn write_str(w: &mut impl Write, s: &str) -> io::Result<()> {
+    write_varint(w, s.len() as u64)?;
+    w.write_all(s.as_bytes())
+}
+
+fn read_string(r: &mut impl Read) -> io::Result<String> {
+    let len = read_varint(r)?;
+    let mut bytes = Vec::new();
+    r.take(len).read_to_end(&mut bytes)?;
+    if bytes.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("invalid string"))
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread recording each allocator event to a file in the
+    /// binary trace format, for [`TraceReader`], [`read_trace`], or
+    /// `geiger-replay` to play back later.
+    ///
+    /// This is like [`spawn_trace_recorder`](Self::spawn_trace_recorder), but
+    /// smaller, and it records the process ID, command line, start time, and
+    /// settings, along with the names of threads as they first allocate.
+    /// With the `zstd` feature, the file is compressed.
+    ///
+    /// [`read_trace`]: crate::read_trace
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_binary_trace_recorder("alloc.agtrace").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_binary_trace_recorder<P: AsRef<Path>>(
+        &'static self,
+        path: P,
+    ) -> io::Result<JoinHandle<()>> {
+        let mut trace = quietly(|| {
+            let file = BufWriter::new(File::create(path)?);
+            let metadata = TraceMetadata::current(&self.config());
+            #[cfg(feature = "zstd")]
+            return TraceWriter::compressed(file, &metadata, zstd::DEFAULT_COMPRESSION_LEVEL);
+            #[cfg(not(feature = "zstd"))]
+            TraceWriter::new(file, &metadata)
+        })?;
+        let mut events = (self.core).subscribe_regions(&self.read_config().record_regions);
+        thread::Builder::new()
+            .name("alloc_geiger-binary-trace".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let mut named = 0;
+                let mut dropped = 0;
+                let mut record = || -> io::Result<()> {
+                    loop {
+                        let names = pitch::thread_names(named);
+                        named += names.len();
+                        for (thread, name) in names {
+                            trace.write_thread_name(thread, &name)?;
+                        }
+                        let mut idle = true;
+                        for event in events.try_iter() {
+                            trace.write_event(&event)?;
+                            idle = false;
+                        }
+                        if events.dropped() != dropped {
+                            dropped = events.dropped();
+                            trace.write_dropped(dropped)?;
+                        }
+                        if idle {
+                            trace.flush()?;
+                            thread::sleep(IDLE);
+                        }
+                    }
+                };
+                let _ = record();
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> Vec<RecentEvent> {
+        let event = |op, size, address, micros, thread| RecentEvent {
+            op,
+            size,
+            address,
+            time: Duration::from_micros(micros),
+            thread,
+        };
+        vec![
+            event(AllocOp::Alloc, 64, 0x7f00_0000_1000, 10, 0),
+            event(AllocOp::AllocZeroed, 1 << 20, 0x5500_0000, 11, 1),
+            event(AllocOp::Realloc, 128, 0x7f00_0000_1000, 11, 0),
+            event(AllocOp::Dealloc, 64, 0x10, 5_000_000, 2),
+            event(AllocOp::Alloc, 8, 0, 5_000_001, 0),
+        ]
+    }
+
+    fn write(metadata: &TraceMetadata) -> Vec<u8> {
+        let mut writer = TraceWriter::new(Vec::new(), metadata).unwrap();
+        writer.write_thread_name(0, "main").unwrap();
+        for (i, event) in events().iter().enumerate() {
+            writer.write_event(event).unwrap();
+            if i == 1 {
+                writer.write_thread_name(1, "worker ünïcode").unwrap();
+                writer.write_dropped(3).unwrap();
+            }
+        }
+        writer.write_dropped(7).unwrap();
+        writer.finish().unwrap()
+    }
+
+    fn read(trace: &[u8]) -> io::Result<Vec<RecentEvent>> {
+        TraceReader::new(trace)?.collect()
+    }
+
+    #[test]
+    fn round_trips() {
+        let metadata = TraceMetadata::current(&Config::new());
+        let trace = write(&metadata);
+        let mut reader = TraceReader::new(&trace[..]).unwrap();
+        assert_eq!(reader.metadata(), &metadata);
+        let read: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, events());
+        assert_eq!(reader.thread_name(0), Some("main"));
+        assert_eq!(reader.thread_name(1), Some("worker ünïcode"));
+        assert_eq!(reader.thread_name(2), None);
+        assert_eq!(reader.dropped(), 7);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let mut trace = write(&TraceMetadata::current(&Config::new()));
+        for version in [0, VERSION + 1] {
+            trace[8..10].copy_from_slice(&version.to_le_bytes());
+            let error = TraceReader::new(&trace[..]).err().unwrap();
+            assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+            assert!(error.to_string().contains("version"), "{error}");
+        }
+        trace[0] = b'X';
+        let error = TraceReader::new(&trace[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn rejects_truncated_input() {
+        let trace = write(&TraceMetadata::current(&Config::new()));
+        for len in 0..trace.len() {
+            // a cut between records can't be told from the end of a trace
+            // that's still being written, but never reads anything wrong
+            match read(&trace[..len]) {
+                Ok(read) => assert!(events().starts_with(&read), "{len}"),
+                Err(e) => assert!(
+                    matches!(
+                        e.kind(),
+                        io::ErrorKind::UnexpectedEof | io::ErrorKind::InvalidData
+                    ),
+                    "{len}: {e}"
+                ),
+            }
+        }
+        assert!(read(&trace[..12]).is_err());
+        assert!(read(&trace[..trace.len() - 1]).is_err());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn round_trips_compressed() {
+        let metadata = TraceMetadata::current(&Config::new());
+        let mut writer = TraceWriter::compressed(Vec::new(), &metadata, 3).unwrap();
+        for event in &events() {
+            writer.write_event(event).unwrap();
+        }
+        let trace = writer.finish().unwrap();
+        assert_eq!(u16::from_le_bytes([trace[10], trace[11]]), COMPRESSED);
+        let mut reader = TraceReader::new(&trace[..]).unwrap();
+        assert_eq!(reader.metadata(), &metadata);
+        let read: Vec<_> = reader.by_ref().collect::<Result<_, _>>().unwrap();
+        assert_eq!(read, events());
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn reads_a_prefix_of_a_truncated_compressed_trace() {
+        let metadata = TraceMetadata::current(&Config::new());
+        let mut writer = TraceWriter::compressed(Vec::new(), &metadata, 3).unwrap();
+        // more than one block, and not all of it compressible
+        let many: Vec<_> = (0..50_000u64)
+            .map(|i| RecentEvent {
+                op: AllocOp::Alloc,
+                size: (i.wrapping_mul(2_654_435_761) >> 16) as usize,
+                address: (i.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> 20) as usize,
+                time: Duration::from_micros(i),
+                thread: 0,
+            })
+            .collect();
+        for event in &many {
+            writer.write_event(event).unwrap();
+        }
+        let trace = writer.finish().unwrap();
+        // an unfinished frame reads as far as it goes, like a trace that's
+        // still being written, though the last event may be cut
+        let read: Vec<_> = TraceReader::new(&trace[..trace.len() / 2])
+            .unwrap()
+            .map_while(Result::ok)
+            .collect();
+        assert!(!read.is_empty() && read.len() < many.len());
+        assert!(many.starts_with(&read));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn rejects_corrupt_compressed_traces() {
+        let mut trace = Vec::new();
+        write_header(&mut trace, COMPRESSED).unwrap();
+        trace.extend_from_slice(b"not a zstd frame at all");
+        assert!(TraceReader::new(&trace[..]).is_err());
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn needs_zstd_for_compressed_traces() {
+        let mut trace = Vec::new();
+        write_header(&mut trace, COMPRESSED).unwrap();
+        let error = TraceReader::new(&trace[..]).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn round_trips_varints() {
+        for n in [0, 1, 127, 128, 300, u64::from(u32::MAX), u64::MAX] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, n).unwrap();
+            assert_eq!(read_varint(&mut &buf[..]).unwrap(), n);
+        }
+        for n in [0, 1, -1, i64::MIN, i64::MAX] {
+            assert_eq!(unzigzag(zigzag(n)), n);
+        }
+        let overlong = [0x80; 11];
+        assert!(read_varint(&mut &overlong[..]).is_err());
+    }
+}
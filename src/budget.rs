@@ -0,0 +1,239 @@
+use crate::event::{AllocEvent, AllocOp};
+use crate::quietly;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// The `Budget` scopes on this thread, outermost first.
+    static SCOPES: RefCell<Vec<Scope>> = const { RefCell::new(Vec::new()) };
+}
+
+#[derive(Clone, Copy)]
+struct Scope {
+    limit: usize,
+    bytes: usize,
+}
+
+/// Gives the current thread a budget of bytes to allocate until the returned
+/// guard is dropped, playing the alarm as soon as it's exceeded.
+///
+/// Each allocation counts its size, and each reallocation its growth, while
+/// deallocations give nothing back, so this bounds the allocator traffic of
+/// something like a request handler.  A nested budget's bytes count toward
+/// the budgets around it too, which play the alarm as soon as they're
+/// exceeded as well.  Call [`Budget::strict`] to also panic in debug builds,
+/// for tests.
+///
+/// This only sees allocations through a [`Geiger`](crate::Geiger) that is the
+/// global allocator.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn handle(request: &str) -> Vec<String> {
+///     let _budget = alloc_geiger::budget(64 * 1024).strict();
+///     request.split(' ').map(String::from).collect()
+/// }
+///
+/// fn main() {
+///     handle("GET /index.html");
+/// }
+/// ```
+pub fn budget(bytes: usize) -> Budget {
+    let scope = Scope {
+        limit: bytes,
+        bytes: 0,
+    };
+    // quietly, since growing the scopes must not be checked against them
+    let depth = quietly(|| {
+        SCOPES.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            scopes.push(scope);
+            scopes.len() - 1
+        })
+    });
+    Budget {
+        depth,
+        strict: false,
+        _marker: PhantomData,
+    }
+}
+
+/// A scope with a budget of bytes to allocate, from [`budget`].
+#[must_use = "the budget only lasts until the guard is dropped"]
+pub struct Budget {
+    /// the index of this budget's scope in `SCOPES`
+    depth: usize,
+    strict: bool,
+    /// `SCOPES` is thread-local, so the guard must stay on its thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Budget {
+    /// Panics when the guard is dropped if the budget was exceeded, in debug
+    /// builds only, to enforce it in tests.
+    ///
+    /// ```should_panic
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let _budget = alloc_geiger::budget(1024).strict();
+    ///     let _vec = vec![0u8; 4096];
+    /// #   assert!(cfg!(debug_assertions));
+    /// }
+    /// ```
+    pub fn strict(mut self) -> Self {
+        self.strict = true;
+        self
+    }
+}
+
+impl Drop for Budget {
+    fn drop(&mut self) {
+        // any scopes inside this one end with it, if they're still open
+        let scope = SCOPES.with(|scopes| {
+            let mut scopes = scopes.borrow_mut();
+            let scope = scopes.get(self.depth).copied();
+            scopes.truncate(self.depth);
+            scope
+        });
+        let Some(scope) = scope else {
+            return;
+        };
+        if self.strict
+            && cfg!(debug_assertions)
+            && scope.bytes > scope.limit
+            && !std::thread::panicking()
+        {
+            panic!(
+                "{} bytes allocated in a scope with a budget of {}",
+                scope.bytes, scope.limit
+            );
+        }
+    }
+}
+
+/// Counts an event against every current budget, returning the innermost
+/// budget that this event exceeded, if any.
+pub(crate) fn check(event: &AllocEvent) -> Option<usize> {
+    let growth = match event.op {
+        _ if event.address == 0 => return None,
+        AllocOp::Alloc | AllocOp::AllocZeroed => event.new_size,
+        AllocOp::Realloc => event.new_size.saturating_sub(event.layout.size()),
+        AllocOp::Dealloc => return None,
+    };
+    // not during the thread's exit, after the scopes are gone
+    let scopes = SCOPES.try_with(|scopes| {
+        let mut exceeded = None;
+        for scope in scopes.borrow_mut().iter_mut() {
+            let was_within = scope.bytes <= scope.limit;
+            scope.bytes = scope.bytes.saturating_add(growth);
+            if was_within && scope.bytes > scope.limit {
+                exceeded = Some(scope.limit);
+            }
+        }
+        exceeded
+    });
+    scopes.ok().flatten()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+
+    fn alloc(size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(AllocOp::Alloc, layout, 0x1000 as *mut u8)
+    }
+
+    fn realloc(size: usize, new_size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::realloc(layout, new_size, 0x1000 as *mut u8, 0x2000 as *mut u8)
+    }
+
+    fn dealloc(size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(AllocOp::Dealloc, layout, 0x1000 as *mut u8)
+    }
+
+    #[test]
+    fn alarms_once_when_exceeded() {
+        assert_eq!(check(&alloc(100)), None);
+        let _budget = budget(100);
+        assert_eq!(check(&alloc(60)), None);
+        assert_eq!(check(&alloc(40)), None);
+        assert_eq!(check(&alloc(1)), Some(100));
+        assert_eq!(check(&alloc(1)), None);
+    }
+
+    #[test]
+    fn counts_realloc_growth_but_not_shrinking() {
+        let _budget = budget(100);
+        assert_eq!(check(&realloc(10, 60)), None);
+        assert_eq!(check(&realloc(60, 20)), None);
+        assert_eq!(check(&realloc(20, 69)), None);
+        assert_eq!(check(&realloc(69, 71)), Some(100));
+    }
+
+    #[test]
+    fn frees_give_nothing_back() {
+        let _budget = budget(100);
+        assert_eq!(check(&alloc(80)), None);
+        assert_eq!(check(&dealloc(80)), None);
+        assert_eq!(check(&alloc(30)), Some(100));
+    }
+
+    #[test]
+    fn skips_failed_allocations() {
+        let _budget = budget(100);
+        let layout = Layout::from_size_align(1000, 1).unwrap();
+        let failed = AllocEvent::new(AllocOp::Alloc, layout, std::ptr::null_mut());
+        assert_eq!(check(&failed), None);
+        assert_eq!(check(&alloc(100)), None);
+    }
+
+    #[test]
+    fn nested_budgets_count_toward_the_outer_ones() {
+        let outer = budget(100);
+        assert_eq!(check(&alloc(50)), None);
+        {
+            let _inner = budget(1000);
+            // the outer budget is exceeded within the inner one
+            assert_eq!(check(&alloc(60)), Some(100));
+            assert_eq!(check(&alloc(950)), Some(1000));
+            assert_eq!(check(&alloc(1)), None);
+        }
+        assert_eq!(check(&alloc(1)), None);
+        drop(outer);
+
+        let _outer = budget(1000);
+        {
+            let _inner = budget(10);
+            assert_eq!(check(&alloc(20)), Some(10));
+        }
+        assert_eq!(check(&alloc(980)), None);
+        assert_eq!(check(&alloc(1)), Some(1000));
+    }
+
+    #[test]
+    #[should_panic = "1100 bytes allocated in a scope with a budget of 1000"]
+    fn strict_budgets_panic_when_dropped() {
+        let _outer = budget(1000).strict();
+        let _inner = budget(10000);
+        check(&alloc(1100));
+    }
+
+    #[test]
+    fn guards_dropped_out_of_order_end_the_inner_scopes() {
+        let outer = budget(100);
+        let inner = budget(10);
+        drop(outer);
+        assert_eq!(check(&alloc(200)), None);
+        drop(inner);
+        let _budget = budget(100);
+        assert_eq!(check(&alloc(200)), Some(100));
+    }
+}
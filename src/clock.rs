@@ -0,0 +1,106 @@
+//! The monotonic clock that times events, and its calibration against the
+//! system's other clocks for merging exports with other timelines.
+
+use std::sync::OnceLock;
+use std::time::{Duration, Instant, SystemTime};
+
+/// How many times to read the clocks together, keeping the closest reading.
+const CALIBRATION_TRIES: usize = 5;
+
+/// The time zero of every event, with the other clocks read at that moment.
+struct Epoch {
+    instant: Instant,
+    calibration: ClockCalibration,
+}
+
+static EPOCH: OnceLock<Epoch> = OnceLock::new();
+
+/// The wall-clock and `CLOCK_MONOTONIC` times of the zero of the event times,
+/// like [`RecentEvent::time`](crate::RecentEvent::time), from
+/// [`clock_calibration`].
+///
+/// Adding an event's time to these places it on another profiler's timeline,
+/// like `perf record -k CLOCK_MONOTONIC` or a `tracing` subscriber's wall
+/// clock.  Binary traces record these in their [`TraceMetadata`], and text
+/// traces in a comment.
+///
+/// [`TraceMetadata`]: crate::TraceMetadata
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct ClockCalibration {
+    /// The wall-clock time of the zero.
+    pub wall: SystemTime,
+    /// The `CLOCK_MONOTONIC` time of the zero, on Unix.
+    pub monotonic: Option<Duration>,
+    /// How long it took to read the clocks together, bounding the error of
+    /// the others relative to the events' clock.
+    pub uncertainty: Duration,
+}
+
+/// Returns the calibration of the event times against the system's other
+/// clocks, which are read together when the first event is timed.
+///
+/// Events are timed by a monotonic clock that's read without allocating,
+/// so the calibration is what makes their times absolute.
+///
+/// ```
+/// let calibration = alloc_geiger::clock_calibration();
+/// assert!(calibration.wall > std::time::UNIX_EPOCH);
+/// assert_eq!(calibration.monotonic.is_some(), cfg!(unix));
+/// ```
+pub fn clock_calibration() -> ClockCalibration {
+    epoch().calibration
+}
+
+fn epoch() -> &'static Epoch {
+    EPOCH.get_or_init(|| {
+        // the closest of a few readings, in case one was preempted
+        (0..CALIBRATION_TRIES)
+            .map(|_| {
+                let instant = Instant::now();
+                let wall = SystemTime::now();
+                let monotonic = monotonic();
+                let calibration = ClockCalibration {
+                    wall,
+                    monotonic,
+                    uncertainty: instant.elapsed(),
+                };
+                Epoch {
+                    instant,
+                    calibration,
+                }
+            })
+            .min_by_key(|epoch| epoch.calibration.uncertainty)
+            .expect("at least one calibration")
+    })
+}
+
+/// Reads `CLOCK_MONOTONIC`, which `perf` can use too.
+#[cfg(unix)]
+fn monotonic() -> Option<Duration> {
+    let mut time = libc::timespec {
+        tv_sec: 0,
+        tv_nsec: 0,
+    };
+    // SAFETY: `time` is a valid place for the result.
+    if unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut time) } != 0 {
+        return None;
+    }
+    let secs = u64::try_from(time.tv_sec).ok()?;
+    Some(Duration::new(secs, time.tv_nsec as u32))
+}
+
+#[cfg(not(unix))]
+fn monotonic() -> Option<Duration> {
+    None
+}
+
+/// Milliseconds since the first call to this or `now`.
+pub(crate) fn now_millis() -> u64 {
+    now().as_millis() as u64
+}
+
+/// The time since the first call to this or `now_millis`.
+pub(crate) fn now() -> Duration {
+    epoch().instant.elapsed()
+}
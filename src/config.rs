@@ -0,0 +1,1480 @@
+use crate::clock::now_millis;
+use crate::event::Ops;
+use crate::output::Backend;
+use crate::pitch::{Bend, Pitch, Scale};
+use crate::pulse::{Envelope, Loudness, Pan, Waveform};
+use std::borrow::Cow;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The prefix of environment variables read by [`Config::apply_env`].
+const ENV_PREFIX: &str = "ALLOC_GEIGER_";
+
+/// The backends tried by default, starting with Oboe where it's available,
+/// and falling back to `Beep` where it's available.
+const DEFAULT_BACKENDS: &[Backend] = if cfg!(all(feature = "android", target_os = "android")) {
+    &[
+        Backend::Oboe,
+        Backend::Rodio,
+        Backend::Bell,
+        Backend::Silent,
+    ]
+} else if cfg!(all(feature = "beep", windows)) {
+    &[
+        Backend::Rodio,
+        Backend::Beep,
+        Backend::Bell,
+        Backend::Silent,
+    ]
+} else {
+    &[Backend::Rodio, Backend::Bell, Backend::Silent]
+};
+
+/// Settings for the sound made by a [`Geiger`](crate::Geiger).
+///
+/// Each setting may also be given as a `key=value` pair, and in the
+/// environment as `ALLOC_GEIGER_KEY=value` when the sound is initialized.
+///
+/// ```
+/// use alloc_geiger::{Config, Geiger, Waveform};
+///
+/// #[global_allocator]
+/// static ALLOC: Geiger<std::alloc::System> =
+///     Geiger::with_config(std::alloc::System, Config::new().waveform(Waveform::Sine));
+///
+/// fn main() {
+///     // ...
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    pub(crate) waveform: Waveform,
+    pub(crate) duration: Option<Duration>,
+    pub(crate) envelope: Envelope,
+    pub(crate) sample_rate: Option<u32>,
+    pub(crate) pan: Pan,
+    pub(crate) pitch: Pitch,
+    pub(crate) scale: Scale,
+    pub(crate) bend: Bend,
+    pub(crate) echo: Option<Duration>,
+    pub(crate) loudness: Loudness,
+    pub(crate) align_timbre: bool,
+    pub(crate) voices: usize,
+    pub(crate) steal: bool,
+    pub(crate) tick: Option<Duration>,
+    pub(crate) tempo: Option<u32>,
+    pub(crate) coalesce: bool,
+    pub(crate) ops: Ops,
+    pub(crate) warn_above: Option<usize>,
+    pub(crate) cap: Option<usize>,
+    pub(crate) cap_enforce: bool,
+    pub(crate) heatmap: Option<Duration>,
+    pub(crate) sweep: Option<Duration>,
+    pub(crate) watermark: Option<Duration>,
+    pub(crate) quantile_tone: Option<Duration>,
+    pub(crate) thread_cues: bool,
+    pub(crate) pool_threads: bool,
+    pub(crate) unwind_cue: bool,
+    pub(crate) recycle_cue: bool,
+    pub(crate) stacks: Option<usize>,
+    pub(crate) flamegraph: Option<String>,
+    pub(crate) record_regions: Vec<String>,
+    pub(crate) dhat: Option<usize>,
+    pub(crate) silence: Vec<String>,
+    pub(crate) theme: Option<String>,
+    pub(crate) baseline: Option<String>,
+    pub(crate) baseline_classes: bool,
+    pub(crate) spans: Vec<String>,
+    pub(crate) tags: Vec<u32>,
+    pub(crate) quiet_threads: Option<Vec<String>>,
+    pub(crate) rate_window: Duration,
+    pub(crate) grains: Option<u32>,
+    pub(crate) target_rate: Option<u32>,
+    pub(crate) dead_time: Option<Duration>,
+    pub(crate) sonify_from: Duration,
+    pub(crate) sonify_until: Option<Duration>,
+    pub(crate) warmup: u64,
+    pub(crate) device: Option<String>,
+    pub(crate) tone_device: Option<String>,
+    pub(crate) buffer_frames: Option<u32>,
+    pub(crate) audio_priority: bool,
+    pub(crate) mix_budget: Option<f32>,
+    pub(crate) forward: Option<String>,
+    pub(crate) socket: Option<String>,
+    pub(crate) backends: Cow<'static, [Backend]>,
+    pub(crate) init_warning: bool,
+    pub(crate) detect_headless: bool,
+    pub(crate) init_timeout: Option<Duration>,
+    pub(crate) retry: Option<Duration>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config::new()
+    }
+}
+
+impl Config {
+    /// Creates the default configuration.
+    pub const fn new() -> Self {
+        Config {
+            waveform: Waveform::Sinc,
+            duration: None,
+            envelope: Envelope::FLAT,
+            sample_rate: None,
+            pan: Pan::Center,
+            pitch: Pitch::Fixed,
+            scale: Scale::Continuous,
+            bend: Bend::Off,
+            echo: None,
+            loudness: Loudness::Fixed,
+            align_timbre: false,
+            voices: 64,
+            steal: true,
+            tick: None,
+            tempo: None,
+            coalesce: false,
+            ops: Ops::ALL,
+            warn_above: None,
+            cap: None,
+            cap_enforce: false,
+            heatmap: None,
+            sweep: None,
+            watermark: None,
+            quantile_tone: None,
+            thread_cues: false,
+            pool_threads: true,
+            unwind_cue: false,
+            recycle_cue: false,
+            stacks: None,
+            flamegraph: None,
+            record_regions: Vec::new(),
+            dhat: None,
+            silence: Vec::new(),
+            theme: None,
+            baseline: None,
+            baseline_classes: false,
+            spans: Vec::new(),
+            tags: Vec::new(),
+            quiet_threads: None,
+            rate_window: Duration::from_secs(1),
+            grains: None,
+            target_rate: None,
+            dead_time: None,
+            sonify_from: Duration::ZERO,
+            sonify_until: None,
+            warmup: 0,
+            device: None,
+            tone_device: None,
+            buffer_frames: None,
+            audio_priority: false,
+            mix_budget: None,
+            forward: None,
+            socket: None,
+            backends: Cow::Borrowed(DEFAULT_BACKENDS),
+            init_warning: false,
+            detect_headless: true,
+            init_timeout: Some(Duration::from_secs(3)),
+            retry: None,
+        }
+    }
+
+    /// Applies a preset of the sound settings, key `profile`, replacing the
+    /// waveform, duration, envelope, pitch, scale, bend, pan, voices, and
+    /// steal.
+    ///
+    /// When read from the environment, `ALLOC_GEIGER_PROFILE` is applied
+    /// first, so other variables can still adjust it.
+    pub const fn profile(mut self, profile: Profile) -> Self {
+        let (waveform, envelope, pitch, scale, bend, voices, steal) = match profile {
+            Profile::Classic => (
+                Waveform::Sinc,
+                Envelope::FLAT,
+                Pitch::Fixed,
+                Scale::Continuous,
+                Bend::Off,
+                64,
+                true,
+            ),
+            Profile::Geiger => (
+                Waveform::Authentic,
+                Envelope::FLAT,
+                Pitch::Fixed,
+                Scale::Continuous,
+                Bend::Off,
+                64,
+                true,
+            ),
+            Profile::Musical => (
+                Waveform::Sine,
+                Envelope::FLAT,
+                Pitch::Size,
+                Scale::Pentatonic,
+                Bend::Direction,
+                64,
+                true,
+            ),
+            Profile::Minimal => (
+                Waveform::Sine,
+                Envelope {
+                    attack: Duration::from_millis(1),
+                    decay: Duration::ZERO,
+                },
+                Pitch::Fixed,
+                Scale::Continuous,
+                Bend::Off,
+                1,
+                false,
+            ),
+        };
+        self.waveform = waveform;
+        self.duration = None;
+        self.envelope = envelope;
+        self.pitch = pitch;
+        self.scale = scale;
+        self.bend = bend;
+        self.pan = Pan::Center;
+        self.voices = voices;
+        self.steal = steal;
+        self
+    }
+
+    /// Sets the waveform played for each event, key `waveform`.
+    pub const fn waveform(mut self, waveform: Waveform) -> Self {
+        self.waveform = waveform;
+        self
+    }
+
+    /// Sets the length of each pulse, key `duration` in milliseconds.
+    ///
+    /// By default, each waveform has its own length of a few milliseconds,
+    /// shown as `auto`.
+    pub const fn duration(mut self, duration: Duration) -> Self {
+        self.duration = Some(duration);
+        self
+    }
+
+    /// Sets the amplitude envelope of each pulse.
+    pub const fn envelope(mut self, envelope: Envelope) -> Self {
+        self.envelope = envelope;
+        self
+    }
+
+    /// Sets the sample rate of each pulse in Hz, key `sample_rate`.
+    ///
+    /// By default, pulses are synthesized at the native rate of the output
+    /// device, shown as `auto`, so they don't need to be resampled.  The rate
+    /// only changes when the output is opened.
+    pub const fn sample_rate(mut self, sample_rate: u32) -> Self {
+        self.sample_rate = Some(sample_rate);
+        self
+    }
+
+    /// Sets the stereo placement of each pulse, key `pan`.
+    pub const fn pan(mut self, pan: Pan) -> Self {
+        self.pan = pan;
+        self
+    }
+
+    /// Sets what varies the pitch of each pulse, key `pitch`.
+    pub const fn pitch(mut self, pitch: Pitch) -> Self {
+        self.pitch = pitch;
+        self
+    }
+
+    /// Sets the musical scale that varied pitches are rounded to, key `scale`.
+    pub const fn scale(mut self, scale: Scale) -> Self {
+        self.scale = scale;
+        self
+    }
+
+    /// Sets how the pitch of a reallocation's pulse glides, key `bend`.
+    pub const fn bend(mut self, bend: Bend) -> Self {
+        self.bend = bend;
+        self
+    }
+
+    /// Echoes the pulses of reallocation chains, key `echo` in milliseconds,
+    /// as when a `Vec` grows element by element and each reallocation resizes
+    /// the buffer from the last one.
+    ///
+    /// Each reallocation of the previous one's address within this window
+    /// adds another echo to its pulse, up to 6, each a little closer than the
+    /// last, so a long chain becomes a cascade instead of identical clicks.
+    /// By default, there's no echo, shown as `off`.
+    pub const fn echo(mut self, window: Duration) -> Self {
+        self.echo = Some(window);
+        self
+    }
+
+    /// Sets what varies the loudness of each pulse, key `loudness`.
+    pub const fn loudness(mut self, loudness: Loudness) -> Self {
+        self.loudness = loudness;
+        self
+    }
+
+    /// Brightens the timbre of over-aligned allocations, key `align_timbre`,
+    /// like SIMD buffers and page-aligned blocks, which otherwise sound like
+    /// any other allocation of their size.
+    ///
+    /// Pulses with an alignment above 16 bytes are driven harder into a soft
+    /// saturation for each doubling, up to 4096, adding the harmonics that
+    /// turn a click into a buzz.  By default, alignment makes no difference,
+    /// shown as `false`.
+    pub const fn align_timbre(mut self, enabled: bool) -> Self {
+        self.align_timbre = enabled;
+        self
+    }
+
+    /// Sets the most pulses that may play at once, key `voices`.
+    ///
+    /// This bounds the mixing work and latency of the output during bursts of
+    /// allocator activity.
+    pub const fn voices(mut self, voices: usize) -> Self {
+        self.voices = voices;
+        self
+    }
+
+    /// Sets whether new pulses cut off the oldest ones when too many are
+    /// playing, key `steal`.  Otherwise, the new pulses are dropped.
+    pub const fn steal(mut self, steal: bool) -> Self {
+        self.steal = steal;
+        self
+    }
+
+    /// Clusters clicks into ticks of this length, key `tick` in milliseconds.
+    ///
+    /// Each tick plays up to 8 clicks, spread evenly, for the events counted
+    /// since the last one, all from a single long-lived mixer.  This bounds
+    /// the work of the output however fast the allocator goes, but each tick
+    /// reuses one recent click, so its pitch and pan are shared.  The tick
+    /// only changes when the output is opened.
+    ///
+    /// By default, every event plays its own pulse, shown as `off`.
+    pub const fn tick(mut self, tick: Duration) -> Self {
+        self.tick = Some(tick);
+        self
+    }
+
+    /// Quantizes clicks to sixteenth notes at this many beats per minute, key
+    /// `tempo`, so periodic allocation patterns become rhythms.
+    ///
+    /// Each step plays one click if there were any events in it, from a
+    /// quarter of the volume for one event up to full for 64 or more.  Like
+    /// the [`tick`](Self::tick), which this replaces, the click is shared and
+    /// the tempo only changes when the output is opened.
+    ///
+    /// By default, there's no tempo, shown as `off`.
+    pub const fn tempo(mut self, bpm: u32) -> Self {
+        self.tempo = Some(bpm);
+        self
+    }
+
+    /// Sets which allocator methods make sound, key `ops` as a comma-separated
+    /// list, or `all` or `none`.
+    ///
+    /// The others are still counted.  By default, that's `all`.
+    pub const fn ops(mut self, ops: Ops) -> Self {
+        self.ops = ops;
+        self
+    }
+
+    /// Coalesces bursts of events with the same method and power-of-two size
+    /// class into one louder pulse, key `coalesce`, `false` by default.
+    ///
+    /// Events are coalesced while they wait for the mixer, about a
+    /// millisecond, rather than mixing many identical overlapping pulses.
+    /// The first event decides the pitch and pan for all of them.
+    pub const fn coalesce(mut self, coalesce: bool) -> Self {
+        self.coalesce = coalesce;
+        self
+    }
+
+    /// Sets a size in bytes above which allocations play a loud warning clunk,
+    /// key `warn_above`, even while muted.
+    ///
+    /// By default, there's no warning, shown as `off`.
+    pub const fn warn_above(mut self, bytes: usize) -> Self {
+        self.warn_above = Some(bytes);
+        self
+    }
+
+    /// Sets a budget of live bytes, key `cap`, playing a rising siren as usage
+    /// first reaches 90% of it, even while muted.
+    ///
+    /// The siren plays again after usage drops back under 80%.  By default,
+    /// there's no budget, shown as `off`.
+    pub const fn cap(mut self, bytes: usize) -> Self {
+        self.cap = Some(bytes);
+        self
+    }
+
+    /// Fails allocations that would grow the live bytes beyond the
+    /// [`cap`](Self::cap), key `cap_enforce`, `false` by default.
+    ///
+    /// A refused allocation is reported like any other failure, with the
+    /// failure hook and alarm, and isn't counted.  The live bytes are only
+    /// as exact as the stats, so concurrent allocations may overshoot a bit.
+    pub const fn cap_enforce(mut self, enforce: bool) -> Self {
+        self.cap_enforce = enforce;
+        self
+    }
+
+    /// Records a [`Heatmap`](crate::Heatmap) of event counts in time buckets
+    /// of this length, key `heatmap` in milliseconds.
+    ///
+    /// By default, nothing is recorded, shown as `off`.  Changing the interval
+    /// starts the recording over.  Only the last 4096 buckets are kept, and
+    /// [`Geiger::write_heatmap_at_exit`](crate::Geiger::write_heatmap_at_exit)
+    /// writes them as the process exits.
+    pub const fn heatmap(mut self, interval: Duration) -> Self {
+        self.heatmap = Some(interval);
+        self
+    }
+
+    /// Plays a summary sweep at this interval, key `sweep` in milliseconds,
+    /// rising for net growth of the live bytes since the last one and falling
+    /// for a net shrink, further for larger changes.
+    ///
+    /// This still tells the trend when clicks are dropped by a policy or
+    /// limited by the voices.  Each sweep plays at the first event after its
+    /// interval, unless the live bytes didn't change.  By default, there are
+    /// no sweeps, shown as `off`.
+    pub const fn sweep(mut self, interval: Duration) -> Self {
+        self.sweep = Some(interval);
+        self
+    }
+
+    /// Plays a soft chime when the live bytes pass their highest mark yet, at
+    /// most once in this interval, key `watermark` in milliseconds.
+    ///
+    /// A program that keeps chiming is still climbing, and one that's gone
+    /// quiet has plateaued.  The mark is kept either way, as
+    /// [`Geiger::peak_live_bytes`](crate::Geiger::peak_live_bytes).  By
+    /// default, there's no chime, shown as `off`.
+    pub const fn watermark(mut self, interval: Duration) -> Self {
+        self.watermark = Some(interval);
+        self
+    }
+
+    /// Plays a reference tone for the 99th percentile of the recent
+    /// allocation sizes at this interval, key `quantile_tone` in
+    /// milliseconds, higher for larger sizes, a whole tone for each doubling.
+    ///
+    /// Like a VU meter, this tells when a change shifts the tail of the size
+    /// distribution, which the clicks of the common sizes drown out.  Each
+    /// tone plays at the first allocation after its interval.  The estimates
+    /// are kept either way, as
+    /// [`Geiger::size_quantiles`](crate::Geiger::size_quantiles).  By
+    /// default, there's no tone, shown as `off`.
+    pub const fn quantile_tone(mut self, interval: Duration) -> Self {
+        self.quantile_tone = Some(interval);
+        self
+    }
+
+    /// Plays a shrill blip for each allocation while the thread is panicking,
+    /// key `unwind_cue`, `false` by default, since allocating in `Drop` during
+    /// unwinding can fail again or abort.
+    ///
+    /// The panic machinery itself allocates, so every panic blips, even one
+    /// that's caught or expected by a `#[should_panic]` test.
+    ///
+    /// The allocations are counted by
+    /// [`Geiger::unwind_allocs`](crate::Geiger::unwind_allocs) either way.
+    pub const fn unwind_cue(mut self, enabled: bool) -> Self {
+        self.unwind_cue = enabled;
+        self
+    }
+
+    /// Tracks the addresses just freed and plays a muted, softer click for an
+    /// allocation that reuses one of them within about 100 ms, key
+    /// `recycle_cue`, `false` by default.
+    ///
+    /// Mostly soft clicks mean the allocator is recycling its memory well,
+    /// while loud ones at fresh addresses mean the heap is growing.
+    pub const fn recycle_cue(mut self, enabled: bool) -> Self {
+        self.recycle_cue = enabled;
+        self
+    }
+
+    /// Plays a rising cue when a thread has its first event and a falling
+    /// one after it exits, key `thread_cues`, `false` by default, to reveal
+    /// unexpected churn in thread pools.
+    ///
+    /// Each exit is heard at the next event on any thread, since the thread
+    /// itself has none left.  The threads are counted by
+    /// [`Geiger::live_threads`](crate::Geiger::live_threads) either way.
+    pub const fn thread_cues(mut self, enabled: bool) -> Self {
+        self.thread_cues = enabled;
+        self
+    }
+
+    /// Gives the workers of each thread pool one voice, key `pool_threads`,
+    /// `true` by default, so the dozens of `rayon` workers sound like one
+    /// pitch and position, with [`Pitch::Thread`] and [`Pan::Thread`],
+    /// rather than a cacophony of near-identical ones.
+    ///
+    /// The pools are found by the names of their threads, as for
+    /// [`Geiger::stats_per_pool`](crate::Geiger::stats_per_pool).
+    pub const fn pool_threads(mut self, pooled: bool) -> Self {
+        self.pool_threads = pooled;
+        self
+    }
+
+    /// Samples the stacks of allocations about once per this many bytes on
+    /// each thread, key `stacks`, for [`write_folded_stacks`] to export.
+    ///
+    /// Each sample captures a backtrace, which is slow, so the interval
+    /// should be much larger than most allocations.  By default, nothing is
+    /// sampled, shown as `off`, unless there's a [`flamegraph`] path.
+    ///
+    /// [`write_folded_stacks`]: crate::write_folded_stacks
+    /// [`flamegraph`]: Self::flamegraph
+    pub const fn stacks(mut self, bytes: usize) -> Self {
+        self.stacks = Some(bytes);
+        self
+    }
+
+    /// Writes the sampled allocation [`stacks`](Self::stacks) to this path at
+    /// exit, key `flamegraph`, as folded stacks for `flamegraph.pl` or
+    /// `inferno-flamegraph` to draw.
+    ///
+    /// Stacks are sampled every 512 KiB if no other interval is set.  The
+    /// file is only written if any were sampled, and only when the process
+    /// exits normally.  By default, nothing is written, shown as `off`.
+    pub fn flamegraph(mut self, path: impl Into<String>) -> Self {
+        self.flamegraph = Some(path.into());
+        self
+    }
+
+    /// Records only the events in [`region`](crate::region)s with these
+    /// names, key `record_regions` as a comma-separated list, keeping traces
+    /// and stacks focused on the code at hand.
+    ///
+    /// This applies to the trace recorders, like
+    /// [`Geiger::spawn_trace_recorder`](crate::Geiger::spawn_trace_recorder),
+    /// when they're spawned, and to the sampled [`stacks`](Self::stacks).  An
+    /// event is in the innermost region on its thread.  By default,
+    /// everything is recorded, shown as `off`.
+    pub fn record_regions<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.record_regions = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Totals the bytes, blocks, and lifetimes of allocations by their call
+    /// site, key `dhat`, and reports this many of the largest sites on
+    /// stderr at exit, like `dhat`, or by [`write_dhat_report`].
+    ///
+    /// Every allocation captures a backtrace, which is much slower than
+    /// sampling [`stacks`](Self::stacks), so this is for finding the source
+    /// of a noisy run rather than leaving on.  By default, nothing is
+    /// recorded, shown as `off`.
+    ///
+    /// [`write_dhat_report`]: crate::write_dhat_report
+    pub const fn dhat(mut self, top: usize) -> Self {
+        self.dhat = Some(top);
+        self
+    }
+
+    /// Never plays events allocated from code under these crate or module
+    /// paths, like `tracing_subscriber` or `rustls::conn`, key `silence` as
+    /// a comma-separated list, though they're still counted.
+    ///
+    /// This quiets noisy dependencies that can't be changed.  An event is
+    /// silenced if any frame of its stack is under one of the paths, so each
+    /// one that would be heard captures a backtrace, which is slow.  By
+    /// default, nothing is silenced, shown as `off`.
+    pub fn silence<S: Into<String>>(mut self, paths: impl IntoIterator<Item = S>) -> Self {
+        self.silence = paths.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Plays the recordings in this directory instead of the synthesized
+    /// pulses, key `theme`: `alloc.wav` for allocations, `dealloc.wav`,
+    /// `realloc.wav`, and `alarm.wav` for failures.
+    ///
+    /// Each file may be missing, falling back to the synthesized sound, and
+    /// may be 8, 16, 24, or 32-bit PCM or 32-bit float, in any number of
+    /// channels, which are mixed to mono.  The recordings are shifted by the
+    /// [`pitch`](Self::pitch) like the waveform would be, and still panned
+    /// and echoed.  They're loaded when the output is opened, as by
+    /// [`Geiger::init`](crate::Geiger::init).  By default, every sound is
+    /// synthesized, shown as `off`.
+    pub fn theme(mut self, dir: impl Into<String>) -> Self {
+        self.theme = Some(dir.into());
+        self
+    }
+
+    /// Plays only the events in size classes that allocate faster than in
+    /// this baseline trace, key `baseline`, to hear just what a change added
+    /// to the allocation behavior.
+    ///
+    /// The baseline is a trace of an earlier run, text or binary as for
+    /// [`read_trace`](crate::read_trace), whose allocations and reallocations
+    /// in each power-of-two size class are averaged over its span.  Those are
+    /// compared with the current rates over the
+    /// [`rate_window`](Self::rate_window), and deallocations play along with
+    /// their class.  It's loaded when the output is opened, as by
+    /// [`Geiger::init`](crate::Geiger::init), and also applies to
+    /// [`Geiger::replay`](crate::Geiger::replay), as in `geiger-replay
+    /// --baseline`.  By default, every event plays, shown as `off`.
+    pub fn baseline(mut self, path: impl Into<String>) -> Self {
+        self.baseline = Some(path.into());
+        self
+    }
+
+    /// Mutes every size class that the [`baseline`](Self::baseline)
+    /// allocated in at all, key `baseline_classes`, rather than only up to
+    /// its rate there, `false` by default.
+    ///
+    /// The baseline is then a whitelist of sizes learned from a calibration
+    /// run, so only allocations of novel sizes make noise, however often the
+    /// known ones come.
+    pub const fn baseline_classes(mut self, enabled: bool) -> Self {
+        self.baseline_classes = enabled;
+        self
+    }
+
+    /// Plays only the events inside `tracing` spans with one of these names,
+    /// key `spans` as a comma-separated list, like `render` to hear only the
+    /// rendering, while still counting the others.
+    ///
+    /// An event is inside every span entered on its thread, as tracked by
+    /// `SpanLayer`, so this needs the `tracing` feature
+    /// and is ignored without it.  By default, events play in or out of any
+    /// span, shown as `off`.
+    pub fn spans<S: Into<String>>(mut self, names: impl IntoIterator<Item = S>) -> Self {
+        self.spans = names.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Plays only the events inside a [`tag`](crate::tag) with one of these
+    /// numbers, key `tags` as a comma-separated list, while still counting
+    /// the others.
+    ///
+    /// Only the innermost tag on the thread counts.  By default, events play
+    /// with or without a tag, shown as `off`.
+    pub fn tags(mut self, tags: impl IntoIterator<Item = u32>) -> Self {
+        self.tags = tags.into_iter().collect();
+        self
+    }
+
+    /// Ignores every event on threads whose names start with one of these
+    /// prefixes, key `quiet_threads` as a comma-separated list, as if they
+    /// were the crate's own threads, not even counting them.
+    ///
+    /// Our own threads never count their events, but the threads that audio
+    /// libraries start for us don't know that, so their allocations can click
+    /// in a feedback loop.  By default, shown as `auto`, this is our threads,
+    /// `cpal`'s, and PulseAudio's threaded main loop, which are
+    /// [`QUIET_THREADS`](Self::QUIET_THREADS).  The names are read with
+    /// `pthread_getname_np`, so this has no effect on other systems.
+    pub fn quiet_threads<S: Into<String>>(mut self, prefixes: impl IntoIterator<Item = S>) -> Self {
+        self.quiet_threads = Some(prefixes.into_iter().map(Into::into).collect());
+        self
+    }
+
+    /// The thread name prefixes quiet by default, as for
+    /// [`quiet_threads`](Self::quiet_threads).
+    pub const QUIET_THREADS: &'static [&'static str] = &["alloc_geiger", "cpal_", "threaded-ml"];
+
+    /// Returns `true` if events on a thread with this name are ignored.
+    pub(crate) fn is_quiet_thread(&self, name: &str) -> bool {
+        match &self.quiet_threads {
+            Some(prefixes) => prefixes.iter().any(|prefix| name.starts_with(&**prefix)),
+            None => Self::QUIET_THREADS
+                .iter()
+                .any(|prefix| name.starts_with(prefix)),
+        }
+    }
+
+    /// Sets the length of the sliding window for [`Geiger::rate`], key
+    /// `rate_window` in milliseconds, 1 second by default.
+    ///
+    /// [`Geiger::rate`]: crate::Geiger::rate
+    pub const fn rate_window(mut self, window: Duration) -> Self {
+        self.rate_window = window;
+        self
+    }
+
+    /// Above this many allocations per second, over the
+    /// [`rate_window`](Self::rate_window), plays a granular hiss instead of
+    /// clicks, key `grains`.
+    ///
+    /// Clicks in a huge burst are lost to the voices and blur together, so
+    /// instead each tenth of a second of events is played as a burst of tiny
+    /// noise grains, denser the more events there were, which still tells a
+    /// million events from a thousand.  By default, every event clicks,
+    /// shown as `off`.
+    pub const fn grains(mut self, per_second: u32) -> Self {
+        self.grains = Some(per_second);
+        self
+    }
+
+    /// Aims for this many clicks per second, key `target_rate`, sampling the
+    /// events that would click as the rate of them changes.
+    ///
+    /// A quiet program plays every event, while a busy one plays a fraction
+    /// of them, measured again four times a second, or sooner in a burst.
+    /// The fraction drops at once as the events speed up, and eases back as
+    /// they slow down, so it stays listenable without tuning a policy by
+    /// hand.  Other
+    /// cues, like warnings and the [`grains`](Self::grains), aren't sampled.
+    /// By default, every event clicks, shown as `off`.
+    pub const fn target_rate(mut self, per_second: u32) -> Self {
+        self.target_rate = Some(per_second);
+        self
+    }
+
+    /// Sets a dead time after each click, key `dead_time` in microseconds,
+    /// when the later events of the same thread are counted but don't click,
+    /// like the recovery of a real Geiger-Müller tube.
+    ///
+    /// This is a simpler, more physical limit than a
+    /// [`target_rate`](Self::target_rate) or a policy, which makes a tight
+    /// cluster of allocations on one thread sound like a single thud, while
+    /// each thread still clicks on its own.  By default, there's no dead
+    /// time, shown as `off`.
+    pub const fn dead_time(mut self, dead_time: Duration) -> Self {
+        self.dead_time = Some(dead_time);
+        self
+    }
+
+    /// Keeps quiet until this long after the first allocation, key
+    /// `sonify_from` in milliseconds, while still counting stats.
+    pub const fn sonify_from(mut self, start: Duration) -> Self {
+        self.sonify_from = start;
+        self
+    }
+
+    /// Goes quiet for good this long after the first allocation, key
+    /// `sonify_until` in milliseconds, while still counting stats.
+    ///
+    /// By default, the sound never stops, shown as `never`.
+    pub const fn sonify_until(mut self, end: Duration) -> Self {
+        self.sonify_until = Some(end);
+        self
+    }
+
+    /// Keeps quiet for this many events at first, key `warmup`, skipping the
+    /// burst from runtime initialization while still counting stats.
+    ///
+    /// To skip a length of time instead, see [`sonify_from`](Self::sonify_from).
+    pub const fn warmup(mut self, events: u64) -> Self {
+        self.warmup = events;
+        self
+    }
+
+    /// Sets the name of the audio output device, key `device`, as listed by
+    /// `output_devices`.
+    ///
+    /// By default, the system's default device is used, shown as `default`.
+    /// If the named device isn't found, there's no sound at all.
+    pub fn device(mut self, name: impl Into<String>) -> Self {
+        self.device = Some(name.into());
+        self
+    }
+
+    /// Plays the tones on a second audio output device, key `tone_device`,
+    /// while the clicks stay on the [`device`](Self::device), like alarms on
+    /// a USB buzzer and allocations on the speakers.
+    ///
+    /// The tones are the alarms, warnings, sirens, and every other cue that
+    /// isn't an event's click.  The device is opened along with the main
+    /// output, with the first `rodio` or `alsa` backend configured, and
+    /// until it opens, or if it's lost, the tones play on the main output.
+    /// By default, there's no second device, shown as `off`.
+    pub fn tone_device(mut self, name: impl Into<String>) -> Self {
+        self.tone_device = Some(name.into());
+        self
+    }
+
+    /// Asks the output device for a buffer of this many frames, key
+    /// `buffer_frames`, so clicks follow the code that caused them more
+    /// closely in interactive debugging.
+    ///
+    /// This sets the WASAPI, CoreAudio, or other `cpal` host's buffer size,
+    /// clamped to the range the device supports, and the period size of the
+    /// `alsa` backend.  Smaller buffers lower the latency but risk underruns
+    /// on a busy system.  By default, the host chooses, shown as `auto`, which
+    /// can lag noticeably.  The size only changes when the output is opened.
+    pub const fn buffer_frames(mut self, frames: u32) -> Self {
+        self.buffer_frames = Some(frames);
+        self
+    }
+
+    /// Raises the priority of the thread that mixes the output, key
+    /// `audio_priority`, so clicks don't stutter when the program keeps every
+    /// core busy.
+    ///
+    /// The thread asks for real-time scheduling, or failing that, a higher
+    /// nice value on Unix, and time-critical priority on Windows.  Without
+    /// the permission for either, as for most users on Linux without
+    /// `rtkit`, it warns on stderr and keeps the normal priority, which is
+    /// the default.  The priority only changes when the output is opened.
+    pub const fn audio_priority(mut self, boost: bool) -> Self {
+        self.audio_priority = boost;
+        self
+    }
+
+    /// Limits the time spent mixing the output to this percentage of the
+    /// audio thread's time, key `mix_budget`, or `off` by default, so the
+    /// sound never becomes the bottleneck of the program it's studying.
+    /// Something like `10` leaves plenty for the program.
+    ///
+    /// The mixer measures its own load four times a second, and while it's
+    /// over the budget, the clicks degrade a level at a time, each playing
+    /// half as many as the last and coalescing their bursts, then recover a
+    /// level at a time once it's well under.  The load and the level are in
+    /// [`Geiger::overhead`](crate::Geiger::overhead).  With `off`, the load
+    /// is still measured, but the clicks never degrade, as with a percentage
+    /// outside `0` to `100`, which the key rejects.  The budget only changes
+    /// when the output is opened.
+    pub const fn mix_budget(mut self, percent: f32) -> Self {
+        self.mix_budget = Some(percent);
+        self
+    }
+
+    /// Sends each event to the Unix socket at this path instead of playing
+    /// it, key `forward`, for `Geiger::spawn_aggregator` to play in another
+    /// process.  This needs the `aggregate` feature on Unix.
+    ///
+    /// Events are dropped while nothing is listening, and the process
+    /// running the aggregator plays its own as usual.  By default, events
+    /// are played here, shown as `off`.
+    pub fn forward(mut self, path: impl Into<String>) -> Self {
+        self.forward = Some(path.into());
+        self
+    }
+
+    /// Sets the path of the Unix datagram socket for the
+    /// [`Backend::Socket`](crate::Backend::Socket) output, key `socket`,
+    /// which sends it a frame for each event played.
+    ///
+    /// That backend is only used when it's also in the
+    /// [`backends`](Self::backends), like `socket,silent`.  By default, there's
+    /// no socket, shown as `off`.
+    pub fn socket(mut self, path: impl Into<String>) -> Self {
+        self.socket = Some(path.into());
+        self
+    }
+
+    /// Sets the backends to try in order until one works, key `backend` as a
+    /// comma-separated list.
+    ///
+    /// The default is `rodio,bell,silent`, or `oboe,rodio,bell,silent` with
+    /// the `android` feature on Android, or `rodio,beep,bell,silent` with the
+    /// `beep` feature on Windows.  If none of them work, there's no
+    /// sound at all, just as with `silent`.
+    pub fn backends(mut self, backends: &[Backend]) -> Self {
+        self.backends = Cow::Owned(backends.to_vec());
+        self
+    }
+
+    /// Prints a warning to stderr the first time any backend fails to open,
+    /// key `init_warning`, `false` by default.
+    ///
+    /// The failures are also available from
+    /// [`Geiger::init_error`](crate::Geiger::init_error).
+    pub const fn init_warning(mut self, warn: bool) -> Self {
+        self.init_warning = warn;
+        self
+    }
+
+    /// Skips the audio backends when the system looks headless, key
+    /// `detect_headless`, `true` by default.
+    ///
+    /// On Linux and the BSDs, that's when none of `DISPLAY`,
+    /// `WAYLAND_DISPLAY`, `PULSE_SERVER`, or `XDG_RUNTIME_DIR` is set, as for
+    /// a system service.  The next configured backend is used instead, like
+    /// `bell` or `log`, and [`Geiger::status`](crate::Geiger::status) shows
+    /// which.
+    pub const fn detect_headless(mut self, detect: bool) -> Self {
+        self.detect_headless = detect;
+        self
+    }
+
+    /// Gives up on an audio device that hasn't opened and started playing
+    /// after this long, key `init_timeout` in milliseconds, 3 seconds by
+    /// default.
+    ///
+    /// Opening the default device can hang rather than fail, as with
+    /// CoreAudio in some sandboxed macOS test runners, and a broken sound
+    /// server or a suspended device may never run the audio callback, while
+    /// the first allocation to make a sound waits for both.  After this long,
+    /// the next backend is used instead, as reported by
+    /// [`GeigerError::timed_out`](crate::GeigerError::timed_out), and a
+    /// `rodio` device isn't tried again until the stalled attempt gives up on
+    /// its own.  With `off`, the wait is unbounded.
+    pub const fn init_timeout(mut self, timeout: Duration) -> Self {
+        self.init_timeout = Some(timeout);
+        self
+    }
+
+    /// Tries the backends again this long after any of them failed, key
+    /// `retry` in milliseconds, as for a sound server that isn't up yet.
+    ///
+    /// This retries whenever the output failed entirely, or when it fell back
+    /// to a later backend, until the first one opens.  By default, a failed
+    /// backend isn't tried again, shown as `off`.
+    pub const fn retry(mut self, interval: Duration) -> Self {
+        self.retry = Some(interval);
+        self
+    }
+
+    /// Changes a single setting by its `key`, parsing the `value`.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ParseConfigError> {
+        match key {
+            "profile" => {
+                // parsed first, so a bad value leaves the other settings be
+                let profile = value.parse()?;
+                *self = mem::take(self).profile(profile);
+            }
+            "waveform" => self.waveform = value.parse()?,
+            "duration" if value == "auto" => self.duration = None,
+            "duration" => self.duration = Some(parse_millis(key, value)?),
+            "attack" => self.envelope.attack = parse_millis(key, value)?,
+            "decay" => self.envelope.decay = parse_millis(key, value)?,
+            "pan" => self.pan = value.parse()?,
+            "pitch" => self.pitch = value.parse()?,
+            "scale" => self.scale = value.parse()?,
+            "bend" => self.bend = value.parse()?,
+            "echo" if value == "off" => self.echo = None,
+            "echo" => self.echo = Some(parse_millis(key, value)?),
+            "loudness" => self.loudness = value.parse()?,
+            "align_timbre" => self.align_timbre = parse(key, value)?,
+            "voices" => self.voices = parse(key, value)?,
+            "steal" => self.steal = parse(key, value)?,
+            "tick" if value == "off" => self.tick = None,
+            "tick" => self.tick = Some(parse_millis(key, value)?),
+            "coalesce" => self.coalesce = parse(key, value)?,
+            "ops" => self.ops = value.parse()?,
+            "tempo" if value == "off" => self.tempo = None,
+            "tempo" => match parse(key, value)? {
+                0 => return Err(ParseConfigError::invalid(key, value)),
+                bpm => self.tempo = Some(bpm),
+            },
+            "warn_above" if value == "off" => self.warn_above = None,
+            "warn_above" => self.warn_above = Some(parse(key, value)?),
+            "cap" if value == "off" => self.cap = None,
+            "cap" => self.cap = Some(parse(key, value)?),
+            "cap_enforce" => self.cap_enforce = parse(key, value)?,
+            "heatmap" if value == "off" => self.heatmap = None,
+            "heatmap" => self.heatmap = Some(parse_millis(key, value)?),
+            "sweep" if value == "off" => self.sweep = None,
+            "sweep" => self.sweep = Some(parse_millis(key, value)?),
+            "watermark" if value == "off" => self.watermark = None,
+            "watermark" => self.watermark = Some(parse_millis(key, value)?),
+            "quantile_tone" if value == "off" => self.quantile_tone = None,
+            "quantile_tone" => self.quantile_tone = Some(parse_millis(key, value)?),
+            "thread_cues" => self.thread_cues = parse(key, value)?,
+            "pool_threads" => self.pool_threads = parse(key, value)?,
+            "unwind_cue" => self.unwind_cue = parse(key, value)?,
+            "recycle_cue" => self.recycle_cue = parse(key, value)?,
+            "stacks" if value == "off" => self.stacks = None,
+            "stacks" => match parse(key, value)? {
+                0 => return Err(ParseConfigError::invalid(key, value)),
+                bytes => self.stacks = Some(bytes),
+            },
+            "flamegraph" if value == "off" => self.flamegraph = None,
+            "flamegraph" => self.flamegraph = Some(value.to_owned()),
+            "record_regions" if value == "off" => self.record_regions.clear(),
+            "record_regions" => {
+                let names = value.split(',').map(str::trim).filter(|n| !n.is_empty());
+                self.record_regions = names.map(str::to_owned).collect();
+            }
+            "silence" if value == "off" => self.silence.clear(),
+            "silence" => {
+                let prefixes = value.split(',').map(str::trim).filter(|p| !p.is_empty());
+                self.silence = prefixes.map(str::to_owned).collect();
+            }
+            "theme" if value == "off" => self.theme = None,
+            "theme" => self.theme = Some(value.to_owned()),
+            "baseline" if value == "off" => self.baseline = None,
+            "baseline" => self.baseline = Some(value.to_owned()),
+            "baseline_classes" => self.baseline_classes = parse(key, value)?,
+            "spans" if value == "off" => self.spans = Vec::new(),
+            "spans" => {
+                let names = value.split(',').map(str::trim).filter(|n| !n.is_empty());
+                self.spans = names.map(str::to_owned).collect();
+            }
+            "tags" if value == "off" => self.tags = Vec::new(),
+            "tags" => {
+                let tags = value.split(',').map(str::trim).filter(|t| !t.is_empty());
+                self.tags = tags.map(|tag| parse(key, tag)).collect::<Result<_, _>>()?;
+            }
+            "quiet_threads" if value == "auto" => self.quiet_threads = None,
+            "quiet_threads" if value == "off" => self.quiet_threads = Some(Vec::new()),
+            "quiet_threads" => {
+                let prefixes = value.split(',').map(str::trim).filter(|p| !p.is_empty());
+                self.quiet_threads = Some(prefixes.map(str::to_owned).collect());
+            }
+            "dhat" if value == "off" => self.dhat = None,
+            "dhat" => match parse(key, value)? {
+                0 => return Err(ParseConfigError::invalid(key, value)),
+                top => self.dhat = Some(top),
+            },
+            "rate_window" => self.rate_window = parse_millis(key, value)?,
+            "grains" if value == "off" => self.grains = None,
+            "grains" => self.grains = Some(parse(key, value)?),
+            "target_rate" if value == "off" => self.target_rate = None,
+            "target_rate" => self.target_rate = Some(parse(key, value)?),
+            "dead_time" if value == "off" => self.dead_time = None,
+            "dead_time" => self.dead_time = Some(parse_micros(key, value)?),
+            "sonify_from" => self.sonify_from = parse_millis(key, value)?,
+            "sonify_until" if value == "never" => self.sonify_until = None,
+            "sonify_until" => self.sonify_until = Some(parse_millis(key, value)?),
+            "warmup" => self.warmup = parse(key, value)?,
+            "device" if value == "default" => self.device = None,
+            "device" => self.device = Some(value.to_owned()),
+            "tone_device" if value == "off" => self.tone_device = None,
+            "tone_device" => self.tone_device = Some(value.to_owned()),
+            "forward" if value == "off" => self.forward = None,
+            "forward" => self.forward = Some(value.to_owned()),
+            "socket" if value == "off" => self.socket = None,
+            "socket" => self.socket = Some(value.to_owned()),
+            "buffer_frames" if value == "auto" => self.buffer_frames = None,
+            "buffer_frames" => match parse(key, value)? {
+                0 => return Err(ParseConfigError::invalid(key, value)),
+                frames => self.buffer_frames = Some(frames),
+            },
+            "audio_priority" => self.audio_priority = parse(key, value)?,
+            "mix_budget" if value == "off" => self.mix_budget = None,
+            "mix_budget" => match parse::<f32>(key, value)? {
+                percent if percent > 0.0 && percent <= 100.0 => self.mix_budget = Some(percent),
+                _ => return Err(ParseConfigError::invalid(key, value)),
+            },
+            "backend" => {
+                let backends: Result<Vec<_>, _> =
+                    value.split(',').map(str::trim).map(str::parse).collect();
+                self.backends = Cow::Owned(backends?);
+            }
+            "init_warning" => self.init_warning = parse(key, value)?,
+            "detect_headless" => self.detect_headless = parse(key, value)?,
+            "init_timeout" if value == "off" => self.init_timeout = None,
+            "init_timeout" => self.init_timeout = Some(parse_millis(key, value)?),
+            "retry" if value == "off" => self.retry = None,
+            "retry" => self.retry = Some(parse_millis(key, value)?),
+            "sample_rate" if value == "auto" => self.sample_rate = None,
+            "sample_rate" => match parse(key, value)? {
+                0 => return Err(ParseConfigError::invalid(key, value)),
+                rate => self.sample_rate = Some(rate),
+            },
+            _ => return Err(ParseConfigError::unknown(key)),
+        }
+        Ok(())
+    }
+
+    /// The interval in bytes to sample stacks, if they're wanted at all.
+    pub(crate) fn stack_interval(&self) -> Option<usize> {
+        const DEFAULT: usize = 512 * 1024;
+        (self.stacks).or_else(|| self.flamegraph.as_ref().map(|_| DEFAULT))
+    }
+
+    /// Returns `true` if pulses should play now, within the time since the
+    /// first allocation that's set to make sound.
+    pub(crate) fn is_sonifying(&self) -> bool {
+        if self.sonify_from.is_zero() && self.sonify_until.is_none() {
+            return true;
+        }
+        let elapsed = Duration::from_millis(now_millis());
+        elapsed >= self.sonify_from && self.sonify_until.map_or(true, |end| elapsed < end)
+    }
+
+    /// Changes settings from all `ALLOC_GEIGER_*` environment variables.
+    ///
+    /// Unknown keys and invalid values are ignored.
+    pub fn apply_env(&mut self) {
+        if let Ok(profile) = env::var("ALLOC_GEIGER_PROFILE") {
+            let _ = self.set("profile", &profile);
+        }
+        for (key, value) in env::vars_os() {
+            let (Some(key), Some(value)) = (key.to_str(), value.to_str()) else {
+                continue;
+            };
+            if let Some(key) = key.strip_prefix(ENV_PREFIX).filter(|&key| key != "PROFILE") {
+                let _ = self.set(&key.to_ascii_lowercase(), value);
+            }
+        }
+    }
+}
+
+/// A preset of sound settings, for [`Config::profile`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Profile {
+    /// The default sinc pulses, named `classic`.
+    #[default]
+    Classic,
+    /// The crackle of a real Geiger counter, named `geiger`.
+    Geiger,
+    /// Sine tones pitched by size on a pentatonic scale, with reallocations
+    /// gliding up or down, named `musical`.
+    Musical,
+    /// Soft sine ticks, only one at a time, named `minimal`.
+    Minimal,
+}
+
+impl Profile {
+    const ALL: [Profile; 4] = [
+        Profile::Classic,
+        Profile::Geiger,
+        Profile::Musical,
+        Profile::Minimal,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Profile::Classic => "classic",
+            Profile::Geiger => "geiger",
+            Profile::Musical => "musical",
+            Profile::Minimal => "minimal",
+        }
+    }
+}
+
+impl fmt::Display for Profile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Profile {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Profile::ALL
+            .into_iter()
+            .find(|profile| profile.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("profile", s))
+    }
+}
+
+/// Lists each setting as a `key value` line.
+impl fmt::Display for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "waveform {}", self.waveform)?;
+        match self.duration {
+            Some(duration) => writeln!(f, "duration {}", millis(duration))?,
+            None => writeln!(f, "duration auto")?,
+        }
+        writeln!(f, "attack {}", millis(self.envelope.attack))?;
+        writeln!(f, "decay {}", millis(self.envelope.decay))?;
+        match self.sample_rate {
+            Some(rate) => writeln!(f, "sample_rate {rate}")?,
+            None => writeln!(f, "sample_rate auto")?,
+        }
+        writeln!(f, "pan {}", self.pan)?;
+        writeln!(f, "pitch {}", self.pitch)?;
+        writeln!(f, "scale {}", self.scale)?;
+        writeln!(f, "bend {}", self.bend)?;
+        match self.echo {
+            Some(window) => writeln!(f, "echo {}", millis(window))?,
+            None => writeln!(f, "echo off")?,
+        }
+        writeln!(f, "loudness {}", self.loudness)?;
+        writeln!(f, "align_timbre {}", self.align_timbre)?;
+        writeln!(f, "voices {}", self.voices)?;
+        writeln!(f, "steal {}", self.steal)?;
+        match self.tick {
+            Some(tick) => writeln!(f, "tick {}", millis(tick))?,
+            None => writeln!(f, "tick off")?,
+        }
+        match self.tempo {
+            Some(bpm) => writeln!(f, "tempo {bpm}")?,
+            None => writeln!(f, "tempo off")?,
+        }
+        writeln!(f, "coalesce {}", self.coalesce)?;
+        writeln!(f, "ops {}", self.ops)?;
+        match self.warn_above {
+            Some(bytes) => writeln!(f, "warn_above {bytes}")?,
+            None => writeln!(f, "warn_above off")?,
+        }
+        match self.cap {
+            Some(bytes) => writeln!(f, "cap {bytes}")?,
+            None => writeln!(f, "cap off")?,
+        }
+        writeln!(f, "cap_enforce {}", self.cap_enforce)?;
+        match self.heatmap {
+            Some(interval) => writeln!(f, "heatmap {}", millis(interval))?,
+            None => writeln!(f, "heatmap off")?,
+        }
+        match self.sweep {
+            Some(interval) => writeln!(f, "sweep {}", millis(interval))?,
+            None => writeln!(f, "sweep off")?,
+        }
+        match self.watermark {
+            Some(interval) => writeln!(f, "watermark {}", millis(interval))?,
+            None => writeln!(f, "watermark off")?,
+        }
+        match self.quantile_tone {
+            Some(interval) => writeln!(f, "quantile_tone {}", millis(interval))?,
+            None => writeln!(f, "quantile_tone off")?,
+        }
+        writeln!(f, "thread_cues {}", self.thread_cues)?;
+        writeln!(f, "pool_threads {}", self.pool_threads)?;
+        writeln!(f, "unwind_cue {}", self.unwind_cue)?;
+        writeln!(f, "recycle_cue {}", self.recycle_cue)?;
+        match self.stacks {
+            Some(bytes) => writeln!(f, "stacks {bytes}")?,
+            None => writeln!(f, "stacks off")?,
+        }
+        match &self.flamegraph {
+            Some(path) => writeln!(f, "flamegraph {path}")?,
+            None => writeln!(f, "flamegraph off")?,
+        }
+        match self.record_regions.as_slice() {
+            [] => writeln!(f, "record_regions off")?,
+            names => writeln!(f, "record_regions {}", names.join(","))?,
+        }
+        match self.dhat {
+            Some(top) => writeln!(f, "dhat {top}")?,
+            None => writeln!(f, "dhat off")?,
+        }
+        match &*self.silence {
+            [] => writeln!(f, "silence off")?,
+            prefixes => writeln!(f, "silence {}", prefixes.join(","))?,
+        }
+        match &self.theme {
+            Some(dir) => writeln!(f, "theme {dir}")?,
+            None => writeln!(f, "theme off")?,
+        }
+        match &self.baseline {
+            Some(path) => writeln!(f, "baseline {path}")?,
+            None => writeln!(f, "baseline off")?,
+        }
+        writeln!(f, "baseline_classes {}", self.baseline_classes)?;
+        match self.spans.as_slice() {
+            [] => writeln!(f, "spans off")?,
+            names => writeln!(f, "spans {}", names.join(","))?,
+        }
+        match self.tags.as_slice() {
+            [] => writeln!(f, "tags off")?,
+            tags => {
+                write!(f, "tags ")?;
+                for (i, tag) in tags.iter().enumerate() {
+                    let sep = if i > 0 { "," } else { "" };
+                    write!(f, "{sep}{tag}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        match self.quiet_threads.as_deref() {
+            None => writeln!(f, "quiet_threads auto")?,
+            Some([]) => writeln!(f, "quiet_threads off")?,
+            Some(prefixes) => writeln!(f, "quiet_threads {}", prefixes.join(","))?,
+        }
+        writeln!(f, "rate_window {}", millis(self.rate_window))?;
+        match self.grains {
+            Some(per_second) => writeln!(f, "grains {per_second}")?,
+            None => writeln!(f, "grains off")?,
+        }
+        match self.target_rate {
+            Some(per_second) => writeln!(f, "target_rate {per_second}")?,
+            None => writeln!(f, "target_rate off")?,
+        }
+        match self.dead_time {
+            Some(dead_time) => writeln!(f, "dead_time {}", dead_time.as_micros())?,
+            None => writeln!(f, "dead_time off")?,
+        }
+        writeln!(f, "sonify_from {}", millis(self.sonify_from))?;
+        match self.sonify_until {
+            Some(end) => writeln!(f, "sonify_until {}", millis(end))?,
+            None => writeln!(f, "sonify_until never")?,
+        }
+        writeln!(f, "warmup {}", self.warmup)?;
+        match &self.device {
+            Some(name) => writeln!(f, "device {name}")?,
+            None => writeln!(f, "device default")?,
+        }
+        match &self.tone_device {
+            Some(name) => writeln!(f, "tone_device {name}")?,
+            None => writeln!(f, "tone_device off")?,
+        }
+        match self.buffer_frames {
+            Some(frames) => writeln!(f, "buffer_frames {frames}")?,
+            None => writeln!(f, "buffer_frames auto")?,
+        }
+        writeln!(f, "audio_priority {}", self.audio_priority)?;
+        match self.mix_budget {
+            Some(percent) => writeln!(f, "mix_budget {percent}")?,
+            None => writeln!(f, "mix_budget off")?,
+        }
+        match &self.forward {
+            Some(path) => writeln!(f, "forward {path}")?,
+            None => writeln!(f, "forward off")?,
+        }
+        match &self.socket {
+            Some(path) => writeln!(f, "socket {path}")?,
+            None => writeln!(f, "socket off")?,
+        }
+        write!(f, "backend ")?;
+        for (i, backend) in self.backends.iter().enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(f, "{sep}{backend}")?;
+        }
+        writeln!(f)?;
+        writeln!(f, "init_warning {}", self.init_warning)?;
+        writeln!(f, "detect_headless {}", self.detect_headless)?;
+        match self.init_timeout {
+            Some(timeout) => writeln!(f, "init_timeout {}", millis(timeout))?,
+            None => writeln!(f, "init_timeout off")?,
+        }
+        match self.retry {
+            Some(interval) => writeln!(f, "retry {}", millis(interval)),
+            None => writeln!(f, "retry off"),
+        }
+    }
+}
+
+/// Parses a plain value with its `FromStr`.
+fn parse<T: FromStr>(key: &str, value: &str) -> Result<T, ParseConfigError> {
+    value
+        .parse()
+        .map_err(|_| ParseConfigError::invalid(key, value))
+}
+
+/// Parses a non-negative number of milliseconds.
+fn parse_millis(key: &str, value: &str) -> Result<Duration, ParseConfigError> {
+    value
+        .parse::<f64>()
+        .ok()
+        .and_then(|ms| Duration::try_from_secs_f64(ms / 1000.0).ok())
+        .ok_or_else(|| ParseConfigError::invalid(key, value))
+}
+
+fn parse_micros(key: &str, value: &str) -> Result<Duration, ParseConfigError> {
+    value
+        .parse()
+        .map(Duration::from_micros)
+        .map_err(|_| ParseConfigError::invalid(key, value))
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// An error from [`Config::set`] or parsing a setting's value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseConfigError {
+    key: String,
+    value: Option<String>,
+}
+
+impl ParseConfigError {
+    pub(crate) fn invalid(key: &str, value: &str) -> Self {
+        ParseConfigError {
+            key: key.to_owned(),
+            value: Some(value.to_owned()),
+        }
+    }
+
+    pub(crate) fn unknown(key: &str) -> Self {
+        ParseConfigError {
+            key: key.to_owned(),
+            value: None,
+        }
+    }
+}
+
+impl fmt::Display for ParseConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            Some(value) => write!(f, "invalid {}: {value}", self.key),
+            None => write!(f, "unknown setting: {}", self.key),
+        }
+    }
+}
+
+impl Error for ParseConfigError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_mix_budgets_out_of_range() {
+        let mut config = Config::new();
+        assert_eq!(config.mix_budget, None);
+        for value in ["0", "-5", "100.5", "NaN", "inf", "-inf"] {
+            assert!(config.set("mix_budget", value).is_err(), "{value}");
+        }
+        assert_eq!(config.mix_budget, None);
+        config.set("mix_budget", "100").unwrap();
+        assert_eq!(config.mix_budget, Some(100.0));
+        config.set("mix_budget", "off").unwrap();
+        assert_eq!(config.mix_budget, None);
+    }
+
+    /// Sets each `key value` line of a config's `Display` on a new one.
+    fn reparse(config: &Config) -> Config {
+        let mut parsed = Config::new();
+        for line in config.to_string().lines() {
+            let (key, value) = line.split_once(' ').unwrap();
+            parsed
+                .set(key, value)
+                .unwrap_or_else(|e| panic!("{line}: {e}"));
+        }
+        parsed
+    }
+
+    #[test]
+    fn display_round_trips() {
+        let config = Config::new();
+        assert_eq!(reparse(&config), config);
+
+        let mut config = Config::new();
+        for (key, value) in [
+            ("profile", "musical"),
+            ("duration", "12.5"),
+            ("echo", "30"),
+            ("tempo", "120"),
+            ("coalesce", "true"),
+            ("ops", "alloc,realloc"),
+            ("cap", "1048576"),
+            ("record_regions", "parse, render"),
+            ("silence", "std::,core::"),
+            ("tags", "1,2"),
+            ("quiet_threads", "off"),
+            ("dead_time", "250"),
+            ("sonify_until", "5000"),
+            ("device", "USB Audio"),
+            ("buffer_frames", "256"),
+            ("mix_budget", "12.5"),
+            ("backend", "bell, silent"),
+        ] {
+            config.set(key, value).unwrap();
+        }
+        assert_eq!(reparse(&config), config);
+        assert!(config.to_string().contains("\nbackend bell,silent\n"));
+    }
+
+    #[test]
+    fn keeps_settings_on_a_bad_profile() {
+        let mut config = Config::new();
+        for (key, value) in [
+            ("cap", "1048576"),
+            ("cap_enforce", "true"),
+            ("waveform", "sine"),
+        ] {
+            config.set(key, value).unwrap();
+        }
+        let before = config.clone();
+        assert!(config.set("profile", "musicall").is_err());
+        assert_eq!(config, before);
+        config.set("profile", "musical").unwrap();
+        assert_eq!(config.cap, before.cap);
+    }
+
+    #[test]
+    fn trims_backends() {
+        let mut config = Config::new();
+        config.set("backend", "bell , silent").unwrap();
+        assert_eq!(&*config.backends, [Backend::Bell, Backend::Silent]);
+        assert!(config.set("backend", "bell,,silent").is_err());
+    }
+}
@@ -0,0 +1,214 @@
+use crate::event::{AllocEvent, AllocOp};
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// How many `count` calls are running on this thread.
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+
+    /// Running totals while `DEPTH` is non-zero.
+    static COUNTS: Cell<AllocCounts> = const { Cell::new(AllocCounts::ZERO) };
+}
+
+/// Exact allocator activity on one thread, from [`count`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct AllocCounts {
+    /// Number of `alloc` and `alloc_zeroed` calls.
+    pub allocs: u64,
+    /// Number of `dealloc` calls.
+    pub deallocs: u64,
+    /// Number of `realloc` calls.
+    pub reallocs: u64,
+    /// Total bytes requested, including the new size of each `realloc`.
+    pub bytes_allocated: u64,
+    /// Total bytes released, including the old size of each `realloc`.
+    pub bytes_freed: u64,
+}
+
+impl AllocCounts {
+    const ZERO: AllocCounts = AllocCounts {
+        allocs: 0,
+        deallocs: 0,
+        reallocs: 0,
+        bytes_allocated: 0,
+        bytes_freed: 0,
+    };
+
+    fn add(&mut self, event: &AllocEvent) {
+        if event.address == 0 {
+            return;
+        }
+        let size = event.layout.size() as u64;
+        match event.op {
+            AllocOp::Alloc | AllocOp::AllocZeroed => {
+                self.allocs += 1;
+                self.bytes_allocated += size;
+            }
+            AllocOp::Dealloc => {
+                self.deallocs += 1;
+                self.bytes_freed += size;
+            }
+            AllocOp::Realloc => {
+                self.reallocs += 1;
+                self.bytes_allocated += event.new_size as u64;
+                self.bytes_freed += size;
+            }
+        }
+    }
+
+    fn since(self, start: AllocCounts) -> AllocCounts {
+        AllocCounts {
+            allocs: self.allocs - start.allocs,
+            deallocs: self.deallocs - start.deallocs,
+            reallocs: self.reallocs - start.reallocs,
+            bytes_allocated: self.bytes_allocated - start.bytes_allocated,
+            bytes_freed: self.bytes_freed - start.bytes_freed,
+        }
+    }
+}
+
+/// Runs `f` and returns the allocator activity it caused on the current
+/// thread.
+///
+/// This only sees allocations through a [`Geiger`](crate::Geiger) that is the
+/// global allocator, not counting its own activity for making sound.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     let counts = alloc_geiger::count(|| {
+///         let vec = vec![0u8; 100];
+///         drop(vec);
+///     });
+///     assert_eq!(counts.allocs, 1);
+///     assert_eq!(counts.deallocs, 1);
+///     assert_eq!(counts.bytes_allocated, 100);
+/// }
+/// ```
+pub fn count(f: impl FnOnce()) -> AllocCounts {
+    let counter = Counter::start();
+    f();
+    counter.finish()
+}
+
+/// Counts the allocator activity on the current thread from `start` until
+/// `finish`, like [`count`] without a closure.
+#[must_use = "counting stops when the counter is dropped"]
+pub struct Counter {
+    start: AllocCounts,
+    /// The counts are thread-local, so the counter must stay on its thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Counter {
+    /// Starts counting.
+    pub fn start() -> Self {
+        DEPTH.with(|depth| depth.set(depth.get() + 1));
+        Counter {
+            start: COUNTS.with(Cell::get),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Stops counting, returning the activity since the start.
+    pub fn finish(self) -> AllocCounts {
+        COUNTS.with(Cell::get).since(self.start)
+    }
+}
+
+impl Drop for Counter {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Adds an event to the running totals, if anything is counting.
+pub(crate) fn add(event: &AllocEvent) {
+    if DEPTH.with(Cell::get) > 0 {
+        COUNTS.with(|cell| {
+            let mut counts = cell.get();
+            counts.add(event);
+            cell.set(counts);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+    use std::ptr;
+
+    fn event(op: AllocOp, size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(op, layout, 0x1000 as *mut u8)
+    }
+
+    fn realloc(size: usize, new_size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::realloc(layout, new_size, 0x1000 as *mut u8, 0x2000 as *mut u8)
+    }
+
+    #[test]
+    fn nested_counts_add_up() {
+        let mut inner = AllocCounts::ZERO;
+        let outer = count(|| {
+            add(&event(AllocOp::Alloc, 100));
+            inner = count(|| {
+                add(&event(AllocOp::AllocZeroed, 10));
+                add(&event(AllocOp::Dealloc, 10));
+            });
+            add(&event(AllocOp::Alloc, 1));
+        });
+        let expected = AllocCounts {
+            allocs: 1,
+            deallocs: 1,
+            bytes_allocated: 10,
+            bytes_freed: 10,
+            ..AllocCounts::ZERO
+        };
+        assert_eq!(inner, expected);
+        let expected = AllocCounts {
+            allocs: 3,
+            deallocs: 1,
+            bytes_allocated: 111,
+            bytes_freed: 10,
+            ..AllocCounts::ZERO
+        };
+        assert_eq!(outer, expected);
+
+        // nothing is counted outside
+        add(&event(AllocOp::Alloc, 100));
+        assert_eq!(count(|| {}), AllocCounts::ZERO);
+    }
+
+    #[test]
+    fn counts_both_sizes_of_reallocs() {
+        let counts = count(|| {
+            add(&realloc(100, 300));
+            add(&realloc(300, 50));
+        });
+        let expected = AllocCounts {
+            reallocs: 2,
+            bytes_allocated: 350,
+            bytes_freed: 400,
+            ..AllocCounts::ZERO
+        };
+        assert_eq!(counts, expected);
+    }
+
+    #[test]
+    fn skips_failed_calls() {
+        let layout = Layout::from_size_align(100, 1).unwrap();
+        let counts = count(|| {
+            add(&AllocEvent::new(AllocOp::Alloc, layout, ptr::null_mut()));
+            let ptr = 0x1000 as *mut u8;
+            add(&AllocEvent::realloc(layout, 200, ptr, ptr::null_mut()));
+        });
+        assert_eq!(counts, AllocCounts::ZERO);
+    }
+}
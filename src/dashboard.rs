@@ -0,0 +1,334 @@
+//! A live terminal dashboard, enabled by the `tui` feature.
+
+use crate::{quietly, Geiger, GeigerStats, ThreadStats, BUSY};
+use ratatui::backend::CrosstermBackend;
+use ratatui::buffer::Buffer;
+use ratatui::layout::{Constraint, Layout, Rect};
+use ratatui::style::{Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, Sparkline, Widget};
+use ratatui::{Terminal, TerminalOptions, Viewport};
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::io::{self, IsTerminal};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// How often the dashboard is redrawn.
+const FRAME: Duration = Duration::from_millis(100);
+
+/// How many frames of history are kept, more than any sparkline shows.
+const HISTORY: usize = 256;
+
+/// The most threads given a pane, the busiest ones.
+const PANES: usize = 4;
+
+/// The height of the dashboard: a summary line, the sparkline of the whole
+/// process, and the thread panes.
+const HEIGHT: u16 = 1 + 3 + 5;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread drawing a live dashboard with `ratatui` in a few lines
+    /// of stderr, with the current allocations per second, the live bytes,
+    /// a scrolling sparkline of the rate, and a pane for each of the busiest
+    /// threads with its own rate.
+    ///
+    /// This fails if stderr isn't a terminal.  The dashboard's own
+    /// allocations are not counted or sonified.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_dashboard().unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_dashboard(&'static self) -> io::Result<JoinHandle<()>> {
+        if !io::stderr().is_terminal() {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "stderr is not a terminal",
+            ));
+        }
+        let mut terminal = quietly(|| {
+            let options = TerminalOptions {
+                viewport: Viewport::Inline(HEIGHT),
+            };
+            Terminal::with_options(CrosstermBackend::new(io::stderr()), options)
+        })?;
+        thread::Builder::new()
+            .name("alloc_geiger-tui".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let mut dashboard = Dashboard::new(self.stats(), &self.stats_per_thread());
+                let mut last = Instant::now();
+                loop {
+                    thread::sleep(FRAME);
+                    let now = Instant::now();
+                    let secs = now.duration_since(last).as_secs_f64();
+                    last = now;
+                    dashboard.update(secs, self.stats(), &self.stats_per_thread());
+                    let drawn =
+                        terminal.draw(|frame| frame.render_widget(&dashboard, frame.area()));
+                    if drawn.is_err() {
+                        break;
+                    }
+                }
+            })
+    }
+}
+
+/// The dashboard's state between frames.
+struct Dashboard {
+    last: GeigerStats,
+    /// allocations per second in recent frames, oldest first
+    rates: VecDeque<u64>,
+    /// the history of each thread, by its index
+    threads: BTreeMap<u32, Pane>,
+}
+
+/// The history of one thread.
+struct Pane {
+    name: String,
+    allocs: u64,
+    /// allocations per second in recent frames, oldest first
+    rates: VecDeque<u64>,
+}
+
+/// Counts both allocations and reallocations, as the sound does.
+fn allocs(stats: &GeigerStats) -> u64 {
+    stats.allocs + stats.reallocs
+}
+
+/// Adds a rate to a history, dropping the oldest past [`HISTORY`].
+fn push(rates: &mut VecDeque<u64>, rate: u64) {
+    if rates.len() == HISTORY {
+        rates.pop_front();
+    }
+    rates.push_back(rate);
+}
+
+impl Dashboard {
+    fn new(stats: GeigerStats, threads: &[ThreadStats]) -> Self {
+        let mut dashboard = Dashboard {
+            last: stats,
+            rates: VecDeque::with_capacity(HISTORY),
+            threads: BTreeMap::new(),
+        };
+        for thread in threads {
+            dashboard.pane(thread);
+        }
+        dashboard
+    }
+
+    /// Returns the pane of a thread, starting one from its current counts.
+    fn pane(&mut self, thread: &ThreadStats) -> &mut Pane {
+        self.threads.entry(thread.thread).or_insert_with(|| Pane {
+            name: match &thread.name {
+                Some(name) => name.clone(),
+                None => format!("thread {}", thread.thread),
+            },
+            allocs: allocs(&thread.stats),
+            rates: VecDeque::new(),
+        })
+    }
+
+    /// Adds a frame of `secs` seconds since the last one.
+    fn update(&mut self, secs: f64, stats: GeigerStats, threads: &[ThreadStats]) {
+        let rate =
+            |now: u64, before: u64| (now.saturating_sub(before) as f64 / secs).round() as u64;
+        push(&mut self.rates, rate(allocs(&stats), allocs(&self.last)));
+        self.last = stats;
+        for thread in threads {
+            let pane = self.pane(thread);
+            let now = allocs(&thread.stats);
+            push(&mut pane.rates, rate(now, pane.allocs));
+            pane.allocs = now;
+        }
+    }
+
+    /// Returns the threads with any allocations in the history, busiest
+    /// first, up to [`PANES`].
+    fn busiest(&self) -> Vec<&Pane> {
+        let mut panes: Vec<(u64, &Pane)> = (self.threads.values())
+            .map(|pane| (pane.rates.iter().sum(), pane))
+            .filter(|&(total, _)| total > 0)
+            .collect();
+        // stable, so ties stay in the order the threads started
+        panes.sort_by_key(|&(total, _)| std::cmp::Reverse(total));
+        panes
+            .into_iter()
+            .take(PANES)
+            .map(|(_, pane)| pane)
+            .collect()
+    }
+}
+
+/// The latest rates that fit in a width, oldest first.
+fn tail(rates: &VecDeque<u64>, width: u16) -> Vec<u64> {
+    let skip = rates.len().saturating_sub(width.into());
+    rates.iter().skip(skip).copied().collect()
+}
+
+impl Widget for &Dashboard {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let [summary, history, threads] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Length(3),
+            Constraint::Min(0),
+        ])
+        .areas(area);
+
+        let rate = self.rates.back().copied().unwrap_or(0);
+        Line::from(vec![
+            "alloc_geiger ".bold(),
+            format!("{rate} allocs/s").yellow(),
+            format!("  live {}", Bytes(self.last.live_bytes())).into(),
+        ])
+        .render(summary, buf);
+
+        Sparkline::default()
+            .data(tail(&self.rates, history.width))
+            .style(Style::new().yellow())
+            .render(history, buf);
+
+        let panes = self.busiest();
+        if panes.is_empty() {
+            return;
+        }
+        let columns = Layout::horizontal(vec![Constraint::Fill(1); panes.len()]).split(threads);
+        for (pane, &area) in panes.iter().zip(columns.iter()) {
+            let rate = pane.rates.back().copied().unwrap_or(0);
+            let block = Block::bordered()
+                .title(pane.name.as_str())
+                .title_bottom(format!("{rate}/s"));
+            let inner = block.inner(area);
+            block.render(area, buf);
+            Sparkline::default()
+                .data(tail(&pane.rates, inner.width))
+                .render(inner, buf);
+        }
+    }
+}
+
+/// A byte count in binary units.
+struct Bytes(u64);
+
+impl fmt::Display for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+        let mut value = self.0 as f64;
+        let mut unit = 0;
+        while value >= 1024.0 && unit + 1 < UNITS.len() {
+            value /= 1024.0;
+            unit += 1;
+        }
+        if unit == 0 {
+            write!(f, "{} {}", self.0, UNITS[0])
+        } else {
+            write!(f, "{value:.1} {}", UNITS[unit])
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(allocs: u64, bytes_allocated: u64) -> GeigerStats {
+        GeigerStats {
+            allocs,
+            bytes_allocated,
+            ..GeigerStats::default()
+        }
+    }
+
+    fn thread(thread: u32, name: Option<&str>, allocs: u64) -> ThreadStats {
+        ThreadStats {
+            thread,
+            name: name.map(Into::into),
+            stats: stats(allocs, 0),
+        }
+    }
+
+    /// Renders the dashboard into lines of text.
+    fn render(dashboard: &Dashboard, width: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, HEIGHT);
+        let mut buf = Buffer::empty(area);
+        dashboard.render(area, &mut buf);
+        (0..area.height)
+            .map(|y| {
+                (0..area.width)
+                    .map(|x| buf[(x, y)].symbol())
+                    .collect::<String>()
+                    .trim_end()
+                    .to_owned()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn measures_rates() {
+        let mut dashboard = Dashboard::new(stats(100, 0), &[thread(0, Some("main"), 100)]);
+        let threads = [thread(0, Some("main"), 150), thread(1, None, 20)];
+        dashboard.update(0.5, stats(170, 4096), &threads);
+        assert_eq!(dashboard.rates, [140]);
+        assert_eq!(dashboard.threads[&0].rates, [100]);
+        // a new thread starts from its counts when it's first seen
+        assert_eq!(dashboard.threads[&1].rates, [0]);
+        assert_eq!(dashboard.threads[&1].name, "thread 1");
+
+        for _ in 0..HISTORY {
+            dashboard.update(1.0, stats(170, 4096), &threads);
+        }
+        assert_eq!(dashboard.rates.len(), HISTORY);
+        assert!(dashboard.rates.iter().all(|&rate| rate == 0));
+    }
+
+    #[test]
+    fn picks_the_busiest_threads() {
+        let threads: Vec<_> = (0..6).map(|i| thread(i, None, 0)).collect();
+        let mut dashboard = Dashboard::new(stats(0, 0), &threads);
+        let busy: Vec<_> = (0..6)
+            .map(|i| thread(i, None, [5, 0, 9, 1, 5, 7][i as usize]))
+            .collect();
+        dashboard.update(1.0, stats(0, 0), &busy);
+        let names: Vec<_> = dashboard
+            .busiest()
+            .iter()
+            .map(|pane| &pane.name[..])
+            .collect();
+        assert_eq!(names, ["thread 2", "thread 5", "thread 0", "thread 4"]);
+    }
+
+    #[test]
+    fn draws_the_summary_and_panes() {
+        let idle = [thread(0, Some("main"), 0), thread(1, Some("worker"), 0)];
+        let mut dashboard = Dashboard::new(stats(0, 0), &idle);
+        assert_eq!(
+            render(&dashboard, 60)[0],
+            "alloc_geiger 0 allocs/s  live 0 B"
+        );
+
+        let threads = [thread(0, Some("main"), 30), thread(1, Some("worker"), 10)];
+        dashboard.update(1.0, stats(40, 3 << 20), &threads);
+        let lines = render(&dashboard, 60);
+        assert_eq!(lines[0], "alloc_geiger 40 allocs/s  live 3.0 MiB");
+        // the latest rate is the highest, drawn as a full bar
+        assert!(lines[3].starts_with('█'), "{lines:?}");
+        assert!(lines[4].starts_with("┌main"), "{lines:?}");
+        assert!(lines[4].contains("┌worker"), "{lines:?}");
+        assert!(lines[8].starts_with("└30/s"), "{lines:?}");
+        assert!(lines[8].contains("└10/s"), "{lines:?}");
+    }
+
+    #[test]
+    fn shows_bytes_in_binary_units() {
+        assert_eq!(Bytes(0).to_string(), "0 B");
+        assert_eq!(Bytes(1023).to_string(), "1023 B");
+        assert_eq!(Bytes(1536).to_string(), "1.5 KiB");
+        assert_eq!(Bytes(u64::MAX).to_string(), "16777216.0 TiB");
+    }
+}
@@ -0,0 +1,951 @@
+//! A D-Bus control interface, enabled by the `dbus` feature on Unix.
+//!
+//! This speaks just enough of the D-Bus wire protocol to own a name on the
+//! session bus and answer method calls, without any bindings to `libdbus`.
+
+use crate::{Geiger, BUSY};
+use std::env;
+use std::io::{self, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::thread::{self, JoinHandle};
+
+/// The interface of the control object, and the prefix of its bus name.
+const INTERFACE: &str = "io.github.cuviper.AllocGeiger";
+/// The path of the control object.
+const PATH: &str = "/io/github/cuviper/AllocGeiger";
+
+const INTROSPECTION: &str = r#"<!DOCTYPE node PUBLIC
+ "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node>
+  <interface name="io.github.cuviper.AllocGeiger">
+    <method name="Mute"/>
+    <method name="Unmute"/>
+    <method name="ToggleMute"/>
+    <method name="IsMuted"><arg type="b" direction="out"/></method>
+    <method name="Volume"><arg type="d" direction="out"/></method>
+    <method name="SetVolume"><arg name="volume" type="d" direction="in"/></method>
+    <method name="Stats"><arg type="a{st}" direction="out"/></method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect"><arg type="s" direction="out"/></method>
+  </interface>
+  <interface name="org.freedesktop.DBus.Peer">
+    <method name="Ping"/>
+  </interface>
+</node>
+"#;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Connects to the session bus and spawns a thread serving a control
+    /// object, for desktop media keys or scripts to control the sound.
+    ///
+    /// The bus name is `io.github.cuviper.AllocGeiger.p<PID>` with the process
+    /// ID, and the object at `/io/github/cuviper/AllocGeiger` has these
+    /// methods in the `io.github.cuviper.AllocGeiger` interface:
+    ///
+    /// - `Mute`, `Unmute`, and `ToggleMute` change the sound, and `IsMuted`
+    ///   returns whether it's muted.
+    /// - `Volume` and `SetVolume` get and set the pulse volume as a double.
+    /// - `Stats` returns the current [`GeigerStats`](crate::GeigerStats) as a
+    ///   dictionary of counters, including `live_bytes`.
+    ///
+    /// The server's own allocations are not counted or sonified.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_dbus_control().unwrap();
+    ///     // e.g. busctl --user call io.github.cuviper.AllocGeiger.p$PID \
+    ///     //     /io/github/cuviper/AllocGeiger io.github.cuviper.AllocGeiger ToggleMute
+    /// }
+    /// ```
+    pub fn spawn_dbus_control(&'static self) -> io::Result<JoinHandle<()>> {
+        let mut bus = Bus::session()?;
+        let name = format!("{INTERFACE}.p{}", std::process::id());
+        bus.request_name(&name)?;
+        thread::Builder::new()
+            .name("alloc_geiger-dbus".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                while let Ok(message) = bus.read() {
+                    if message.kind != METHOD_CALL {
+                        continue;
+                    }
+                    let reply = self.handle_dbus(&message);
+                    if message.flags & NO_REPLY_EXPECTED == 0 && bus.send(reply).is_err() {
+                        return;
+                    }
+                }
+            })
+    }
+
+    fn handle_dbus(&self, call: &Message) -> Message {
+        let path = call.path.as_deref().unwrap_or("");
+        let interface = call.interface.as_deref();
+        let member = call.member.as_deref().unwrap_or("");
+        let mut body = Writer::default();
+        let signature = match (interface, member) {
+            (Some("org.freedesktop.DBus.Introspectable") | None, "Introspect") => {
+                match introspect(path) {
+                    Some(xml) => body.str(&xml),
+                    None => return call.error("org.freedesktop.DBus.Error.UnknownObject", path),
+                }
+                "s"
+            }
+            (Some("org.freedesktop.DBus.Peer") | None, "Ping") => "",
+            _ if path != PATH => {
+                return call.error("org.freedesktop.DBus.Error.UnknownObject", path);
+            }
+            (Some(INTERFACE) | None, "Mute") => {
+                self.mute();
+                ""
+            }
+            (Some(INTERFACE) | None, "Unmute") => {
+                self.unmute();
+                ""
+            }
+            (Some(INTERFACE) | None, "ToggleMute") => {
+                if self.is_muted() {
+                    self.unmute();
+                } else {
+                    self.mute();
+                }
+                ""
+            }
+            (Some(INTERFACE) | None, "IsMuted") => {
+                body.u32(self.is_muted().into());
+                "b"
+            }
+            (Some(INTERFACE) | None, "Volume") => {
+                body.u64(f64::from(self.volume()).to_bits());
+                "d"
+            }
+            (Some(INTERFACE) | None, "SetVolume") => {
+                match call.reader().filter(|_| call.signature == "d") {
+                    Some(mut args) => match args.u64() {
+                        Ok(bits) => self.set_volume(f64::from_bits(bits) as f32),
+                        Err(e) => {
+                            let message = e.to_string();
+                            return call.error("org.freedesktop.DBus.Error.InvalidArgs", &message);
+                        }
+                    },
+                    None => {
+                        let message = format!("expected a double, not {:?}", call.signature);
+                        return call.error("org.freedesktop.DBus.Error.InvalidArgs", &message);
+                    }
+                }
+                ""
+            }
+            (Some(INTERFACE) | None, "Stats") => {
+                let stats = self.stats();
+                body.dict(&[
+                    ("allocs", stats.allocs),
+                    ("deallocs", stats.deallocs),
+                    ("reallocs", stats.reallocs),
+                    ("bytes_allocated", stats.bytes_allocated),
+                    ("bytes_freed", stats.bytes_freed),
+                    ("live_bytes", stats.live_bytes()),
+                ]);
+                "a{st}"
+            }
+            _ => {
+                let message = format!("no method {member} on {}", interface.unwrap_or(INTERFACE));
+                return call.error("org.freedesktop.DBus.Error.UnknownMethod", &message);
+            }
+        };
+        call.reply(signature, body.buf)
+    }
+}
+
+/// Returns the introspection XML for `path`, which lists the next node on
+/// the way to the control object.
+fn introspect(path: &str) -> Option<String> {
+    if path == PATH {
+        return Some(INTROSPECTION.to_owned());
+    }
+    let rest = PATH.strip_prefix(path.trim_end_matches('/'))?;
+    let child = rest.strip_prefix('/')?.split('/').next()?;
+    Some(format!("<node>\n  <node name=\"{child}\"/>\n</node>\n"))
+}
+
+/// The message types.
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+
+/// The message flag for calls that don't want a reply.
+const NO_REPLY_EXPECTED: u8 = 1;
+
+/// The header field codes.
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// The endianness marker of the messages we send, in native byte order.
+const ENDIAN: u8 = if cfg!(target_endian = "big") {
+    b'B'
+} else {
+    b'l'
+};
+
+/// A D-Bus message, with the header fields that we use.
+#[derive(Default)]
+struct Message {
+    kind: u8,
+    flags: u8,
+    serial: u32,
+    path: Option<String>,
+    interface: Option<String>,
+    member: Option<String>,
+    error_name: Option<String>,
+    reply_serial: Option<u32>,
+    destination: Option<String>,
+    sender: Option<String>,
+    signature: String,
+    body: Vec<u8>,
+    /// whether the body is in big-endian order
+    big_endian: bool,
+}
+
+impl Message {
+    fn call(destination: &str, path: &str, interface: &str, member: &str) -> Self {
+        Message {
+            kind: METHOD_CALL,
+            path: Some(path.to_owned()),
+            interface: Some(interface.to_owned()),
+            member: Some(member.to_owned()),
+            destination: Some(destination.to_owned()),
+            ..Message::default()
+        }
+    }
+
+    fn reply(&self, signature: &str, body: Vec<u8>) -> Self {
+        Message {
+            kind: METHOD_RETURN,
+            reply_serial: Some(self.serial),
+            destination: self.sender.clone(),
+            signature: signature.to_owned(),
+            body,
+            ..Message::default()
+        }
+    }
+
+    fn error(&self, name: &str, message: &str) -> Self {
+        let mut body = Writer::default();
+        body.str(message);
+        Message {
+            kind: ERROR,
+            error_name: Some(name.to_owned()),
+            ..self.reply("s", body.buf)
+        }
+    }
+
+    /// Returns a reader of the body, if there is one.
+    fn reader(&self) -> Option<Reader<'_>> {
+        (!self.body.is_empty()).then(|| Reader::new(&self.body, self.big_endian))
+    }
+
+    fn encode(&self, serial: u32) -> Vec<u8> {
+        let mut w = Writer::default();
+        w.byte(ENDIAN);
+        w.byte(self.kind);
+        w.byte(self.flags);
+        w.byte(1);
+        w.u32(self.body.len() as u32);
+        w.u32(serial);
+        let fields = w.array_start();
+        let strings = [
+            (FIELD_PATH, "o", &self.path),
+            (FIELD_INTERFACE, "s", &self.interface),
+            (FIELD_MEMBER, "s", &self.member),
+            (FIELD_ERROR_NAME, "s", &self.error_name),
+            (FIELD_DESTINATION, "s", &self.destination),
+        ];
+        for (code, signature, value) in strings {
+            if let Some(value) = value {
+                w.align(8);
+                w.byte(code);
+                w.signature(signature);
+                w.str(value);
+            }
+        }
+        if let Some(reply_serial) = self.reply_serial {
+            w.align(8);
+            w.byte(FIELD_REPLY_SERIAL);
+            w.signature("u");
+            w.u32(reply_serial);
+        }
+        if !self.signature.is_empty() {
+            w.align(8);
+            w.byte(FIELD_SIGNATURE);
+            w.signature("g");
+            w.signature(&self.signature);
+        }
+        w.array_end(fields);
+        w.align(8);
+        w.buf.extend_from_slice(&self.body);
+        w.buf
+    }
+
+    fn decode(header: &[u8], body: Vec<u8>) -> io::Result<Self> {
+        let big_endian = header[0] == b'B';
+        let mut r = Reader::new(header, big_endian);
+        r.pos = 1;
+        let mut message = Message {
+            kind: r.byte()?,
+            flags: r.byte()?,
+            big_endian,
+            body,
+            ..Message::default()
+        };
+        r.pos = 8;
+        message.serial = r.u32()?;
+        let end = r.u32()? as usize + r.pos;
+        while r.pos < end {
+            r.align(8);
+            let code = r.byte()?;
+            let signature = r.signature()?;
+            match signature.as_str() {
+                "o" | "s" => {
+                    let value = Some(r.str()?);
+                    match code {
+                        FIELD_PATH => message.path = value,
+                        FIELD_INTERFACE => message.interface = value,
+                        FIELD_MEMBER => message.member = value,
+                        FIELD_ERROR_NAME => message.error_name = value,
+                        FIELD_DESTINATION => message.destination = value,
+                        FIELD_SENDER => message.sender = value,
+                        _ => {}
+                    }
+                }
+                "u" => {
+                    let value = r.u32()?;
+                    if code == FIELD_REPLY_SERIAL {
+                        message.reply_serial = Some(value);
+                    }
+                }
+                "g" => {
+                    let value = r.signature()?;
+                    if code == FIELD_SIGNATURE {
+                        message.signature = value;
+                    }
+                }
+                _ => return Err(invalid("unsupported header field type")),
+            }
+        }
+        Ok(message)
+    }
+}
+
+/// Writes values in the D-Bus wire format, in native byte order.
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        self.buf.resize(padded(self.buf.len(), n), 0);
+    }
+
+    fn byte(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.align(8);
+        self.buf.extend_from_slice(&value.to_ne_bytes());
+    }
+
+    fn str(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.buf.push(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    /// Starts an array of 8-aligned elements, returning the position of its
+    /// length.
+    fn array_start(&mut self) -> usize {
+        self.u32(0);
+        let pos = self.buf.len() - 4;
+        self.align(8);
+        pos
+    }
+
+    /// Fills in the length of the array started at `pos`.
+    fn array_end(&mut self, pos: usize) {
+        let start = padded(pos + 4, 8);
+        let len = (self.buf.len() - start) as u32;
+        self.buf[pos..pos + 4].copy_from_slice(&len.to_ne_bytes());
+    }
+
+    /// Writes an `a{st}` dictionary.
+    fn dict(&mut self, entries: &[(&str, u64)]) {
+        let array = self.array_start();
+        for &(key, value) in entries {
+            self.align(8);
+            self.str(key);
+            self.u64(value);
+        }
+        self.array_end(array);
+    }
+}
+
+/// Reads values in the D-Bus wire format.
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8], big_endian: bool) -> Self {
+        Reader {
+            buf,
+            pos: 0,
+            big_endian,
+        }
+    }
+
+    fn align(&mut self, n: usize) {
+        self.pos = padded(self.pos, n);
+    }
+
+    fn take<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let bytes =
+            (self.buf.get(self.pos..self.pos + N)).ok_or_else(|| invalid("truncated message"))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn byte(&mut self) -> io::Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u32(&mut self) -> io::Result<u32> {
+        self.align(4);
+        let bytes = self.take()?;
+        Ok(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn u64(&mut self) -> io::Result<u64> {
+        self.align(8);
+        let bytes = self.take()?;
+        Ok(if self.big_endian {
+            u64::from_be_bytes(bytes)
+        } else {
+            u64::from_le_bytes(bytes)
+        })
+    }
+
+    fn bytes(&mut self, len: usize) -> io::Result<String> {
+        let bytes =
+            (self.buf.get(self.pos..self.pos + len)).ok_or_else(|| invalid("truncated message"))?;
+        // skip the trailing nul too
+        self.pos += len + 1;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid("invalid string"))
+    }
+
+    fn str(&mut self) -> io::Result<String> {
+        let len = self.u32()? as usize;
+        self.bytes(len)
+    }
+
+    fn signature(&mut self) -> io::Result<String> {
+        let len = self.byte()? as usize;
+        self.bytes(len)
+    }
+}
+
+/// Rounds `len` up to a multiple of the power of two `n`.
+fn padded(len: usize, n: usize) -> usize {
+    (len + n - 1) & !(n - 1)
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// A connection to a message bus.
+struct Bus {
+    stream: UnixStream,
+    serial: u32,
+}
+
+impl Bus {
+    /// Connects to the session bus, from `DBUS_SESSION_BUS_ADDRESS` or else
+    /// the usual socket in `XDG_RUNTIME_DIR`.
+    fn session() -> io::Result<Self> {
+        let stream = match env::var("DBUS_SESSION_BUS_ADDRESS") {
+            Ok(addresses) => addresses
+                .split(';')
+                .find_map(|address| connect(address).transpose())
+                .unwrap_or_else(|| Err(invalid("no usable session bus address")))?,
+            Err(_) => {
+                let dir = env::var_os("XDG_RUNTIME_DIR")
+                    .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no session bus"))?;
+                UnixStream::connect(std::path::Path::new(&dir).join("bus"))?
+            }
+        };
+        let mut bus = Bus { stream, serial: 0 };
+        bus.authenticate()?;
+        let hello = Message::call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "Hello",
+        );
+        bus.call(hello)?;
+        Ok(bus)
+    }
+
+    /// Authenticates as our user ID, by the credentials of the socket.
+    fn authenticate(&mut self) -> io::Result<()> {
+        // SAFETY: `getuid` is always successful.
+        let uid = unsafe { libc::getuid() }.to_string();
+        let hex: String = uid.bytes().map(|b| format!("{b:02x}")).collect();
+        write!(self.stream, "\0AUTH EXTERNAL {hex}\r\n")?;
+        let mut line = Vec::new();
+        while !line.ends_with(b"\r\n") {
+            let mut byte = [0];
+            self.stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        if !line.starts_with(b"OK ") {
+            let line = String::from_utf8_lossy(&line);
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                format!("D-Bus authentication failed: {}", line.trim_end()),
+            ));
+        }
+        self.stream.write_all(b"BEGIN\r\n")
+    }
+
+    /// Requests a well-known name, failing if another connection has it.
+    fn request_name(&mut self, name: &str) -> io::Result<()> {
+        let mut request = Message::call(
+            "org.freedesktop.DBus",
+            "/org/freedesktop/DBus",
+            "org.freedesktop.DBus",
+            "RequestName",
+        );
+        let mut body = Writer::default();
+        body.str(name);
+        // DBUS_NAME_FLAG_DO_NOT_QUEUE
+        body.u32(4);
+        request.signature = "su".to_owned();
+        request.body = body.buf;
+        let reply = self.call(request)?;
+        // DBUS_REQUEST_NAME_REPLY_PRIMARY_OWNER
+        match reply.reader().map(|mut r| r.u32()).transpose()? {
+            Some(1) => Ok(()),
+            _ => Err(io::Error::new(
+                io::ErrorKind::AddrInUse,
+                format!("the D-Bus name {name} is taken"),
+            )),
+        }
+    }
+
+    fn send(&mut self, message: Message) -> io::Result<u32> {
+        self.serial += 1;
+        self.stream.write_all(&message.encode(self.serial))?;
+        Ok(self.serial)
+    }
+
+    /// Sends a method call and waits for its reply, skipping other messages
+    /// like the `NameAcquired` signal.
+    fn call(&mut self, message: Message) -> io::Result<Message> {
+        let serial = self.send(message)?;
+        loop {
+            let reply = self.read()?;
+            if reply.reply_serial != Some(serial) {
+                continue;
+            }
+            if reply.kind == ERROR {
+                let name = reply.error_name.unwrap_or_default();
+                return Err(io::Error::new(io::ErrorKind::Other, name));
+            }
+            return Ok(reply);
+        }
+    }
+
+    fn read(&mut self) -> io::Result<Message> {
+        let mut fixed = [0; 16];
+        self.stream.read_exact(&mut fixed)?;
+        let mut r = Reader::new(&fixed, fixed[0] == b'B');
+        r.pos = 4;
+        let body_len = r.u32()? as usize;
+        r.pos = 12;
+        let fields_len = r.u32()? as usize;
+        let mut header = fixed.to_vec();
+        header.resize(16 + padded(fields_len, 8), 0);
+        self.stream.read_exact(&mut header[16..])?;
+        let mut body = vec![0; body_len];
+        self.stream.read_exact(&mut body)?;
+        Message::decode(&header, body)
+    }
+}
+
+/// Connects to a `unix:` bus address with a `path` or `abstract` key, or
+/// returns `None` for other kinds of address.
+fn connect(address: &str) -> io::Result<Option<UnixStream>> {
+    let Some(keys) = address.strip_prefix("unix:") else {
+        return Ok(None);
+    };
+    for (key, value) in keys.split(',').filter_map(|kv| kv.split_once('=')) {
+        let value = unescape(value);
+        match key {
+            "path" => return UnixStream::connect(value).map(Some),
+            #[cfg(target_os = "linux")]
+            "abstract" => {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(value)?;
+                return UnixStream::connect_addr(&addr).map(Some);
+            }
+            _ => {}
+        }
+    }
+    Ok(None)
+}
+
+/// Decodes the `%xx` escapes of an address value.
+fn unescape(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|hex| std::str::from_utf8(hex).ok());
+        match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+            Some(byte) if b == b'%' => {
+                bytes.push(byte);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    /// Splits an encoded message into its header and body, as `Bus::read`.
+    fn split(bytes: &[u8]) -> (&[u8], Vec<u8>) {
+        let fields_len = u32::from_ne_bytes(bytes[12..16].try_into().unwrap()) as usize;
+        let (header, body) = bytes.split_at(16 + padded(fields_len, 8));
+        (header, body.to_vec())
+    }
+
+    fn round_trip(message: &Message) -> Message {
+        let encoded = message.encode(7);
+        let (header, body) = split(&encoded);
+        Message::decode(header, body).unwrap()
+    }
+
+    fn call(member: &str, signature: &str, body: Vec<u8>) -> Message {
+        Message {
+            serial: 3,
+            sender: Some(":1.42".into()),
+            signature: signature.into(),
+            body,
+            ..Message::call("unused", PATH, INTERFACE, member)
+        }
+    }
+
+    #[test]
+    fn pads_to_alignments() {
+        assert_eq!(padded(0, 8), 0);
+        assert_eq!(padded(1, 4), 4);
+        assert_eq!(padded(8, 8), 8);
+        assert_eq!(padded(9, 8), 16);
+
+        let mut w = Writer::default();
+        w.byte(1);
+        w.u32(2);
+        assert_eq!(w.buf.len(), 8);
+        w.byte(3);
+        w.u64(4);
+        assert_eq!(w.buf.len(), 24);
+        assert_eq!(w.buf[1..4], [0; 3]);
+        assert_eq!(w.buf[9..16], [0; 7]);
+
+        let mut r = Reader::new(&w.buf, cfg!(target_endian = "big"));
+        assert_eq!(r.byte().unwrap(), 1);
+        assert_eq!(r.u32().unwrap(), 2);
+        assert_eq!(r.byte().unwrap(), 3);
+        assert_eq!(r.u64().unwrap(), 4);
+        assert!(r.byte().is_err());
+    }
+
+    #[test]
+    fn encodes_strings_and_signatures() {
+        let mut w = Writer::default();
+        w.signature("a{st}");
+        assert_eq!(w.buf, b"\x05a{st}\0");
+        let mut w = Writer::default();
+        w.str("hi");
+        let mut expected = 2u32.to_ne_bytes().to_vec();
+        expected.extend_from_slice(b"hi\0");
+        assert_eq!(w.buf, expected);
+
+        let mut r = Reader::new(&w.buf, cfg!(target_endian = "big"));
+        assert_eq!(r.str().unwrap(), "hi");
+        let truncated = &w.buf[..5];
+        assert!(Reader::new(truncated, cfg!(target_endian = "big"))
+            .str()
+            .is_err());
+    }
+
+    #[test]
+    fn encodes_dicts() {
+        let mut w = Writer::default();
+        w.byte(0);
+        w.dict(&[("a", 1), ("bcd", 2)]);
+        let mut r = Reader::new(&w.buf, cfg!(target_endian = "big"));
+        r.pos = 1;
+        let len = r.u32().unwrap() as usize;
+        r.align(8);
+        // the length leaves out the padding before the first entry
+        assert_eq!(r.pos, 8);
+        assert_eq!(r.pos + len, w.buf.len());
+        let mut entries = Vec::new();
+        while r.pos < w.buf.len() {
+            r.align(8);
+            entries.push((r.str().unwrap(), r.u64().unwrap()));
+        }
+        assert_eq!(entries, [("a".into(), 1), ("bcd".into(), 2)]);
+    }
+
+    #[test]
+    fn round_trips_messages() {
+        let mut body = Writer::default();
+        body.str("name");
+        body.u32(4);
+        let message = Message {
+            flags: NO_REPLY_EXPECTED,
+            ..call("RequestName", "su", body.buf)
+        };
+        let encoded = message.encode(7);
+        let (header, body) = split(&encoded);
+        assert_eq!(header.len() % 8, 0);
+        assert_eq!(encoded[0], ENDIAN);
+        let decoded = Message::decode(header, body).unwrap();
+        assert_eq!(decoded.kind, METHOD_CALL);
+        assert_eq!(decoded.flags, NO_REPLY_EXPECTED);
+        assert_eq!(decoded.serial, 7);
+        assert_eq!(decoded.path.as_deref(), Some(PATH));
+        assert_eq!(decoded.interface.as_deref(), Some(INTERFACE));
+        assert_eq!(decoded.member.as_deref(), Some("RequestName"));
+        assert_eq!(decoded.destination.as_deref(), Some("unused"));
+        assert_eq!(decoded.signature, "su");
+        let mut args = decoded.reader().unwrap();
+        assert_eq!(args.str().unwrap(), "name");
+        assert_eq!(args.u32().unwrap(), 4);
+
+        let reply = round_trip(&message.error("org.example.Error", "oops"));
+        assert_eq!(reply.kind, ERROR);
+        assert_eq!(reply.reply_serial, Some(message.serial));
+        assert_eq!(reply.error_name.as_deref(), Some("org.example.Error"));
+        assert_eq!(reply.reader().unwrap().str().unwrap(), "oops");
+    }
+
+    #[test]
+    fn decodes_big_endian_messages() {
+        let mut header = vec![b'B', METHOD_CALL, 0, 1];
+        header.extend_from_slice(&0u32.to_be_bytes());
+        header.extend_from_slice(&9u32.to_be_bytes());
+        let mut fields = vec![FIELD_PATH, 1, b'o', 0];
+        fields.extend_from_slice(&2u32.to_be_bytes());
+        fields.extend_from_slice(b"/a\0");
+        header.extend_from_slice(&(fields.len() as u32).to_be_bytes());
+        header.extend_from_slice(&fields);
+        header.resize(padded(header.len(), 8), 0);
+        let message = Message::decode(&header, Vec::new()).unwrap();
+        assert_eq!(message.serial, 9);
+        assert_eq!(message.path.as_deref(), Some("/a"));
+        assert!(message.big_endian);
+
+        // a field of an unsupported type, signature "o\0"
+        header[16 + 1] = 2;
+        assert!(Message::decode(&header, Vec::new()).is_err());
+    }
+
+    #[test]
+    fn introspects_the_path_to_the_object() {
+        assert_eq!(introspect(PATH).as_deref(), Some(INTROSPECTION));
+        let child = |name: &str| format!("<node>\n  <node name=\"{name}\"/>\n</node>\n");
+        assert_eq!(introspect("/"), Some(child("io")));
+        assert_eq!(introspect("/io/github"), Some(child("cuviper")));
+        assert_eq!(introspect("/io/github/"), Some(child("cuviper")));
+        assert_eq!(introspect("/org"), None);
+        assert_eq!(introspect("/io/git"), None);
+        assert_eq!(introspect(&format!("{PATH}/more")), None);
+        for method in [
+            "Mute",
+            "Unmute",
+            "ToggleMute",
+            "IsMuted",
+            "SetVolume",
+            "Stats",
+        ] {
+            assert!(INTROSPECTION.contains(&format!("<method name=\"{method}\"")));
+        }
+    }
+
+    #[test]
+    fn authenticates_as_our_user() {
+        let (stream, mut server) = UnixStream::pair().unwrap();
+        let bus = thread::spawn(move || Bus { stream, serial: 0 }.authenticate());
+        let mut line = Vec::new();
+        while !line.ends_with(b"\r\n") {
+            let mut byte = [0];
+            server.read_exact(&mut byte).unwrap();
+            line.push(byte[0]);
+        }
+        let line = String::from_utf8(line).unwrap();
+        let hex = line.strip_prefix("\0AUTH EXTERNAL ").unwrap().trim_end();
+        let uid = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap() as char)
+            .collect::<String>();
+        // SAFETY: `getuid` is always successful.
+        assert_eq!(uid, unsafe { libc::getuid() }.to_string());
+        server.write_all(b"OK 0123456789abcdef\r\n").unwrap();
+        bus.join().unwrap().unwrap();
+        let mut begin = [0; 7];
+        server.read_exact(&mut begin).unwrap();
+        assert_eq!(&begin, b"BEGIN\r\n");
+
+        let (stream, mut server) = UnixStream::pair().unwrap();
+        let bus = thread::spawn(move || Bus { stream, serial: 0 }.authenticate());
+        server.write_all(b"REJECTED EXTERNAL\r\n").unwrap();
+        let error = bus.join().unwrap().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::PermissionDenied);
+        assert!(error.to_string().ends_with("REJECTED EXTERNAL"), "{error}");
+    }
+
+    #[test]
+    fn sends_and_reads_framed_messages() {
+        let (stream, server) = UnixStream::pair().unwrap();
+        let (mut bus, mut server) = (
+            Bus { stream, serial: 0 },
+            Bus {
+                stream: server,
+                serial: 10,
+            },
+        );
+        let mut body = Writer::default();
+        body.dict(&[("allocs", 5)]);
+        assert_eq!(
+            bus.send(call("Stats", "a{st}", body.buf.clone())).unwrap(),
+            1
+        );
+        assert_eq!(bus.send(call("Ping", "", Vec::new())).unwrap(), 2);
+        let stats = server.read().unwrap();
+        assert_eq!(stats.serial, 1);
+        assert_eq!(stats.member.as_deref(), Some("Stats"));
+        assert_eq!(stats.body, body.buf);
+        let ping = server.read().unwrap();
+        assert_eq!((ping.serial, ping.body.len()), (2, 0));
+
+        // a reply is found past the other messages
+        server.send(call("Signal", "", Vec::new())).unwrap();
+        server.send(ping.reply("", Vec::new())).unwrap();
+        server.send(stats.error("org.example.Error", "no")).unwrap();
+        let reply = thread::spawn(move || {
+            let reply = bus.call(call("Ping", "", Vec::new()));
+            (bus, reply)
+        });
+        let call = server.read().unwrap();
+        assert_eq!(call.serial, 3);
+        server.send(call.reply("", Vec::new())).unwrap();
+        let (_bus, reply) = reply.join().unwrap();
+        assert_eq!(reply.unwrap().reply_serial, Some(3));
+    }
+
+    #[test]
+    fn handles_method_calls() {
+        let geiger: Geiger<System> = Geiger::metrics_only(System);
+        let reply = geiger.handle_dbus(&call("Mute", "", Vec::new()));
+        assert_eq!((reply.kind, reply.reply_serial), (METHOD_RETURN, Some(3)));
+        assert_eq!(reply.destination.as_deref(), Some(":1.42"));
+        assert!(geiger.is_muted());
+        geiger.handle_dbus(&call("ToggleMute", "", Vec::new()));
+        assert!(!geiger.is_muted());
+        let reply = geiger.handle_dbus(&call("IsMuted", "", Vec::new()));
+        assert_eq!(reply.signature, "b");
+        assert_eq!(reply.reader().unwrap().u32().unwrap(), 0);
+
+        let mut body = Writer::default();
+        body.u64(0.25f64.to_bits());
+        geiger.handle_dbus(&call("SetVolume", "d", body.buf));
+        assert_eq!(geiger.volume(), 0.25);
+        let reply = geiger.handle_dbus(&call("Volume", "", Vec::new()));
+        assert_eq!(reply.signature, "d");
+        let volume = reply.reader().unwrap().u64().unwrap();
+        assert_eq!(f64::from_bits(volume), 0.25);
+
+        let reply = geiger.handle_dbus(&call("Stats", "", Vec::new()));
+        assert_eq!(reply.signature, "a{st}");
+
+        let reply = geiger.handle_dbus(&call("SetVolume", "s", Vec::new()));
+        assert_eq!(reply.kind, ERROR);
+        let error = reply.error_name.as_deref();
+        assert_eq!(error, Some("org.freedesktop.DBus.Error.InvalidArgs"));
+        let reply = geiger.handle_dbus(&call("Explode", "", Vec::new()));
+        let error = reply.error_name.as_deref();
+        assert_eq!(error, Some("org.freedesktop.DBus.Error.UnknownMethod"));
+        let elsewhere = Message {
+            path: Some("/elsewhere".into()),
+            ..call("Mute", "", Vec::new())
+        };
+        let reply = geiger.handle_dbus(&elsewhere);
+        let error = reply.error_name.as_deref();
+        assert_eq!(error, Some("org.freedesktop.DBus.Error.UnknownObject"));
+
+        let introspect = Message {
+            path: Some("/".into()),
+            interface: None,
+            ..call("Introspect", "", Vec::new())
+        };
+        let reply = geiger.handle_dbus(&introspect);
+        assert_eq!(reply.signature, "s");
+        assert!(reply.reader().unwrap().str().unwrap().contains("\"io\""));
+    }
+
+    #[test]
+    fn unescapes_addresses() {
+        assert_eq!(unescape("/run/user/1000/bus"), "/run/user/1000/bus");
+        assert_eq!(unescape("%2ftmp%2Fdbus-x"), "/tmp/dbus-x");
+        assert_eq!(unescape("100%"), "100%");
+        assert_eq!(unescape("%zz"), "%zz");
+        assert!(connect("tcp:host=localhost,port=1").unwrap().is_none());
+    }
+}
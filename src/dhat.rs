@@ -0,0 +1,204 @@
+//! Per-call-site totals of every allocation, reported like dhat at exit.
+
+use crate::event::{AllocEvent, AllocOp};
+use crate::quietly;
+use crate::stacks::{self, atexit, Frame};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Mutex, Once, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The caller frames shown for each site in the report.
+const SHOWN_FRAMES: usize = 4;
+
+/// The totals of every site, and the site of each live allocation.
+static SITES: Mutex<Option<Sites>> = Mutex::new(None);
+
+/// How many sites to report at exit, from [`Config::dhat`].
+///
+/// [`Config::dhat`]: crate::Config::dhat
+static TOP: Mutex<usize> = Mutex::new(0);
+
+#[derive(Default)]
+struct Sites {
+    /// the totals of each site, by its displayed caller frames
+    totals: HashMap<String, Totals>,
+    /// the site, size, and time of each live allocation
+    live: HashMap<usize, (String, usize, Instant)>,
+}
+
+/// What was allocated at one call site.
+#[derive(Clone, Default)]
+struct Totals {
+    blocks: u64,
+    bytes: u64,
+    /// the blocks that have been freed, with their total lifetime
+    freed: u64,
+    lifetimes: Duration,
+    /// the bytes still live, and the most that ever were at once
+    live: u64,
+    peak: u64,
+}
+
+/// Records an allocation at its call site, or the lifetime of a freed one,
+/// and reports the `top` sites at exit.
+///
+/// Every allocation captures a backtrace, which is very slow.
+pub(crate) fn record(top: usize, event: &AllocEvent) {
+    let (freed, allocated) = match event.op {
+        AllocOp::Alloc | AllocOp::AllocZeroed => (0, event.address),
+        AllocOp::Dealloc => (event.address, 0),
+        AllocOp::Realloc => (event.old_address, event.address),
+    };
+    if freed == 0 && allocated == 0 {
+        return;
+    }
+    let now = Instant::now();
+    quietly(|| {
+        let site = (allocated != 0).then(site);
+        let mut sites = SITES.lock().unwrap_or_else(PoisonError::into_inner);
+        let sites = sites.get_or_insert_with(Sites::default);
+        if freed != 0 {
+            sites.free(freed, now);
+        }
+        if let Some(site) = site {
+            sites.alloc(site, allocated, event.new_size, now);
+        }
+        report_at_exit(top);
+    });
+}
+
+impl Sites {
+    fn alloc(&mut self, site: String, address: usize, size: usize, now: Instant) {
+        let totals = self.totals.entry(site.clone()).or_default();
+        totals.blocks += 1;
+        totals.bytes += size as u64;
+        totals.live += size as u64;
+        totals.peak = totals.peak.max(totals.live);
+        self.live.insert(address, (site, size, now));
+    }
+
+    fn free(&mut self, address: usize, now: Instant) {
+        if let Some((site, size, start)) = self.live.remove(&address) {
+            if let Some(totals) = self.totals.get_mut(&site) {
+                totals.freed += 1;
+                totals.lifetimes += now - start;
+                totals.live -= size as u64;
+            }
+        }
+    }
+}
+
+/// The first few caller frames of the current allocation, one per line,
+/// starting from the first outside the standard library if there is one.
+fn site() -> String {
+    let backtrace = stacks::capture();
+    let frames = stacks::caller_frames(&backtrace);
+    let start = frames.iter().position(|frame| !is_std(frame)).unwrap_or(0);
+    let mut site = String::new();
+    for frame in frames[start..].iter().take(SHOWN_FRAMES) {
+        let _ = match frame.location {
+            Some((file, line)) => writeln!(site, "{} ({file}:{line})", frame.name),
+            None => writeln!(site, "{}", frame.name),
+        };
+    }
+    if site.is_empty() {
+        site.push_str("[unknown]\n");
+    }
+    site
+}
+
+/// Returns `true` for frames in the standard library, which are the same for
+/// every `Vec` and `String`, so they don't tell sites apart.
+fn is_std(frame: &Frame<'_>) -> bool {
+    const PREFIXES: [&str; 3] = ["alloc::", "core::", "std::"];
+    match frame.location {
+        Some((file, _)) => file.starts_with("/rustc/"),
+        None => {
+            let name = frame.name.trim_start_matches('<');
+            PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+        }
+    }
+}
+
+/// Sets how many sites to report at exit, registering the exit handler once.
+fn report_at_exit(top: usize) {
+    static REGISTER: Once = Once::new();
+    *TOP.lock().unwrap_or_else(PoisonError::into_inner) = top;
+    REGISTER.call_once(|| {
+        // SAFETY: registering a plain function that doesn't unwind.
+        unsafe { atexit(exit_handler) };
+    });
+}
+
+extern "C" fn exit_handler() {
+    quietly(|| {
+        let top = *TOP.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Err(e) = write_dhat_report(io::stderr(), top) {
+            eprintln!("alloc_geiger: couldn't write the allocation site report: {e}");
+        }
+    });
+}
+
+/// Writes the `top` allocation sites by total bytes, as recorded when
+/// [`Config::dhat`](crate::Config::dhat) is set, in the manner of `dhat`.
+///
+/// Each site is listed with the bytes and blocks allocated there, how many
+/// are still live and the most bytes that were at once, and the average
+/// lifetime of the blocks freed so far, then the innermost frames of its
+/// stack.  The allocator's own frames are left out.
+///
+/// ```
+/// use alloc_geiger::Config;
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System =
+///     alloc_geiger::Geiger::with_config(std::alloc::System, Config::new().dhat(10));
+///
+/// fn main() {
+///     drop(vec![0u8; 10000]);
+///     let mut report = Vec::new();
+///     alloc_geiger::write_dhat_report(&mut report, 10).unwrap();
+///     assert!(String::from_utf8(report).unwrap().contains("#1:"));
+/// }
+/// ```
+pub fn write_dhat_report<W: Write>(writer: W, top: usize) -> io::Result<()> {
+    quietly(|| {
+        let mut totals: Vec<(String, Totals)> =
+            match &*SITES.lock().unwrap_or_else(PoisonError::into_inner) {
+                Some(sites) => sites.totals.clone().into_iter().collect(),
+                None => Vec::new(),
+            };
+        totals.sort_by(|a, b| b.1.bytes.cmp(&a.1.bytes).then_with(|| a.0.cmp(&b.0)));
+        let mut writer = BufWriter::new(writer);
+        writeln!(
+            writer,
+            "alloc_geiger: the top {} of {} allocation sites, by bytes",
+            top.min(totals.len()),
+            totals.len(),
+        )?;
+        for (rank, (site, t)) in totals.iter().take(top).enumerate() {
+            write!(
+                writer,
+                "#{}: {} bytes in {} blocks, {} blocks live, peak {} bytes live",
+                rank + 1,
+                t.bytes,
+                t.blocks,
+                t.blocks - t.freed,
+                t.peak,
+            )?;
+            match u32::try_from(t.freed) {
+                Ok(freed) if freed > 0 => {
+                    let average = t.lifetimes / freed;
+                    writeln!(writer, ", average lifetime {average:.3?}")?;
+                }
+                _ => writeln!(writer)?,
+            }
+            for frame in site.lines() {
+                writeln!(writer, "    at {frame}")?;
+            }
+        }
+        writer.flush()
+    })
+}
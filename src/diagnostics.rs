@@ -0,0 +1,166 @@
+//! Checks for anomalies in how the allocator is reentered, with the
+//! `diagnostics` feature, to help debug combinations with other instrumenting
+//! allocators.
+
+use crate::clock::now_millis;
+use std::cell::Cell;
+use std::error::Error;
+use std::fmt;
+use std::io::Write;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+
+/// The deepest the inner allocator may reenter the global allocator before
+/// the process is aborted, well short of overflowing the stack.
+const MAX_DEPTH: u32 = 64;
+
+/// How long an event may keep a thread busy before a reentered event reports
+/// it, much longer than even opening an output takes.
+const BUSY_LIMIT_MILLIS: u64 = 10_000;
+
+thread_local! {
+    /// The calls into the inner allocator on this thread.
+    static DEPTH: Cell<u32> = const { Cell::new(0) };
+    /// Set on the audio threads of the mixer.
+    static AUDIO: Cell<bool> = const { Cell::new(false) };
+    /// When this thread started recording its current event, or `0`.
+    static BUSY_SINCE: Cell<u64> = const { Cell::new(0) };
+}
+
+static DEEPEST: AtomicU32 = AtomicU32::new(0);
+static AUDIO_EVENTS: AtomicU64 = AtomicU64::new(0);
+static LONGEST_BUSY: AtomicU64 = AtomicU64::new(0);
+
+/// A call into the inner allocator, until it's dropped.
+pub(crate) struct Depth(());
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        DEPTH.with(|depth| depth.set(depth.get() - 1));
+    }
+}
+
+/// Enters the inner allocator, aborting with a message if it keeps
+/// reentering the global allocator, rather than overflowing the stack.
+pub(crate) fn enter_inner() -> Depth {
+    let depth = DEPTH.with(|depth| {
+        depth.set(depth.get() + 1);
+        depth.get()
+    });
+    DEEPEST.fetch_max(depth, Ordering::Relaxed);
+    if depth > MAX_DEPTH {
+        // without formatting, which could allocate again
+        let message =
+            b"alloc_geiger: the inner allocator keeps reentering the global allocator, aborting\n";
+        let _ = std::io::stderr().write_all(message);
+        std::process::abort();
+    }
+    Depth(())
+}
+
+/// The recording of an event, until it's dropped.
+pub(crate) struct Busy(());
+
+impl Drop for Busy {
+    fn drop(&mut self) {
+        BUSY_SINCE.with(|since| since.set(0));
+    }
+}
+
+/// Starts recording an event on this thread.
+pub(crate) fn busy() -> Busy {
+    BUSY_SINCE.with(|since| since.set(now_millis().max(1)));
+    Busy(())
+}
+
+/// Checks an event that was dropped because its thread was already busy.
+pub(crate) fn reentered() {
+    if AUDIO.with(Cell::get) {
+        AUDIO_EVENTS.fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+    let since = BUSY_SINCE.with(Cell::get);
+    if since != 0 {
+        let millis = now_millis().saturating_sub(since);
+        if millis > BUSY_LIMIT_MILLIS {
+            LONGEST_BUSY.fetch_max(millis, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Marks this thread as the audio thread of a mixer.
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+pub(crate) fn mark_audio_thread() {
+    AUDIO.with(|audio| audio.set(true));
+}
+
+/// Lists the anomalies seen so far.
+pub(crate) fn anomalies() -> Vec<Anomaly> {
+    let mut anomalies = Vec::new();
+    let depth = DEEPEST.load(Ordering::Relaxed);
+    if depth > 1 {
+        anomalies.push(Anomaly::Reentered { depth });
+    }
+    let events = AUDIO_EVENTS.load(Ordering::Relaxed);
+    if events > 0 {
+        anomalies.push(Anomaly::AudioThread { events });
+    }
+    let millis = LONGEST_BUSY.load(Ordering::Relaxed);
+    if millis > 0 {
+        anomalies.push(Anomaly::LongBusy { millis });
+    }
+    anomalies
+}
+
+/// An anomaly in how the allocator was reentered, from
+/// [`GeigerCore::anomalies`](crate::GeigerCore::anomalies), with the
+/// `diagnostics` feature.
+///
+/// These are signs of a bad interaction with another instrumenting allocator
+/// or a hook, which the recursion guard otherwise hides by dropping the events
+/// it reenters with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Anomaly {
+    /// The inner allocator called back into the global allocator, nesting
+    /// this deep at most.  Past 64, the process is aborted with a message,
+    /// rather than overflowing the stack.
+    Reentered {
+        /// The most calls into the inner allocator on one thread at once.
+        depth: u32,
+    },
+    /// The audio thread of an output made allocator events, which were
+    /// dropped, and which can make the sound glitch.
+    AudioThread {
+        /// The events dropped on the audio thread.
+        events: u64,
+    },
+    /// A thread was busy recording one event for longer than 10 seconds, as
+    /// with a hook that blocks, and the events it made meanwhile were dropped.
+    LongBusy {
+        /// The longest that a thread was found busy, in milliseconds.
+        millis: u64,
+    },
+}
+
+impl fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Anomaly::Reentered { depth } => write!(
+                f,
+                "the inner allocator reentered the global allocator, {depth} calls deep"
+            ),
+            Anomaly::AudioThread { events } => {
+                write!(f, "{events} allocator events on the audio thread")
+            }
+            Anomaly::LongBusy { millis } => {
+                write!(f, "a thread was busy with one event for {millis} ms")
+            }
+        }
+    }
+}
+
+impl Error for Anomaly {}
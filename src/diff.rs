@@ -0,0 +1,96 @@
+//! The audible diff against a baseline trace, playing only the events in size
+//! classes that allocate faster than they did in the baseline.
+
+use crate::event::{size_class, AllocEvent, AllocOp, SIZE_CLASSES};
+use crate::rate::Meter;
+use crate::read_trace;
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::sync::{PoisonError, RwLock};
+use std::time::Duration;
+
+/// The allocation rates of each size class in a baseline, and the current
+/// rates to compare with them.
+pub(crate) struct Diff {
+    baseline: RwLock<Option<Vec<f64>>>,
+    meters: [Meter; SIZE_CLASSES],
+}
+
+impl Diff {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const METER: Meter = Meter::new();
+
+    pub(crate) const fn new() -> Self {
+        Diff {
+            baseline: RwLock::new(None),
+            meters: [Self::METER; SIZE_CLASSES],
+        }
+    }
+
+    /// Loads the baseline trace at `path`, or clears it for `None`, warning
+    /// on stderr if it can't be read.
+    pub(crate) fn load(&self, path: Option<&str>) {
+        let Some(path) = path else {
+            return self.set(None);
+        };
+        if let Err(e) = self.try_load(path) {
+            eprintln!("alloc_geiger: couldn't load baseline {path}: {e}");
+            self.set(None);
+        }
+    }
+
+    /// Loads the baseline trace at `path`, leaving the old one if it can't
+    /// be read.
+    pub(crate) fn try_load(&self, path: &str) -> io::Result<()> {
+        self.set(Some(baseline_rates(path)?));
+        Ok(())
+    }
+
+    fn set(&self, rates: Option<Vec<f64>>) {
+        *self
+            .baseline
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = rates;
+    }
+
+    /// Counts an event in its size class, returning `false` if there's a
+    /// baseline that the class's rate doesn't exceed, or with `classes`, that
+    /// allocated in the class at all.
+    ///
+    /// Deallocations aren't counted, but play along with their class.
+    pub(crate) fn exceeds(&self, window: Duration, classes: bool, event: &AllocEvent) -> bool {
+        let baseline = self.baseline.read().unwrap_or_else(PoisonError::into_inner);
+        let Some(rates) = baseline.as_deref() else {
+            return true;
+        };
+        let rate = rates[event.size_class()];
+        if classes {
+            return rate == 0.0;
+        }
+        let meter = &self.meters[event.size_class()];
+        if event.op != AllocOp::Dealloc {
+            meter.record(window, event);
+        }
+        meter.rate(window).allocs > rate
+    }
+}
+
+/// Reads a trace, averaging the allocations and reallocations of each size
+/// class over its span.
+fn baseline_rates(path: &str) -> io::Result<Vec<f64>> {
+    let events = read_trace(BufReader::new(File::open(path)?))?;
+    let mut counts = vec![0u64; SIZE_CLASSES];
+    for event in events.iter().filter(|event| event.op != AllocOp::Dealloc) {
+        counts[size_class(event.size)] += 1;
+    }
+    let span = match (events.first(), events.last()) {
+        (Some(first), Some(last)) => last.time.saturating_sub(first.time),
+        _ => Duration::ZERO,
+    };
+    // a trace of an instant still counts as taking a millisecond
+    let secs = span.max(Duration::from_millis(1)).as_secs_f64();
+    Ok(counts
+        .into_iter()
+        .map(|count| count as f64 / secs)
+        .collect())
+}
@@ -0,0 +1,54 @@
+//! Chains of reallocations, for an echo that builds as they continue.
+
+use crate::clock::now;
+use crate::event::{AllocEvent, AllocOp};
+use std::cell::Cell;
+use std::time::Duration;
+
+/// The most echoes of a pulse, however long the chain.
+pub(crate) const MAX_ECHOES: u32 = 6;
+
+/// The latest reallocation on a thread.
+#[derive(Clone, Copy)]
+struct Chain {
+    /// the address it returned, which the next in the chain reallocates
+    address: usize,
+    /// how many reallocations the chain has had
+    len: u32,
+    time: Duration,
+}
+
+thread_local! {
+    static CHAIN: Cell<Chain> = const {
+        Cell::new(Chain {
+            address: 0,
+            len: 0,
+            time: Duration::ZERO,
+        })
+    };
+}
+
+/// Follows a reallocation of the last one's address within `window`, as a
+/// `Vec` growing element by element does, returning the number of echoes
+/// for its pulse: none for the first of a chain, then one more for each
+/// that follows, up to [`MAX_ECHOES`].
+pub(crate) fn echoes(event: &AllocEvent, window: Duration) -> u32 {
+    if event.op != AllocOp::Realloc || event.address == 0 {
+        return 0;
+    }
+    CHAIN.with(|cell| {
+        let chain = cell.get();
+        let time = now();
+        let len = if chain.address == event.old_address && time - chain.time <= window {
+            chain.len + 1
+        } else {
+            1
+        };
+        cell.set(Chain {
+            address: event.address,
+            len,
+            time,
+        });
+        (len - 1).min(MAX_ECHOES)
+    })
+}
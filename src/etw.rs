@@ -0,0 +1,45 @@
+//! ETW TraceLogging events on Windows, enabled by the `windows-etw` feature,
+//! so the allocator events show up in Windows Performance Analyzer next to
+//! the CPU samples.
+//!
+//! Each event is written from the allocating thread as it happens, by the
+//! `AllocGeiger` provider, whose GUID is derived from that name as usual for
+//! TraceLogging.  They're only written while a session is listening, like:
+//!
+//! ```text
+//! tracelog -start AllocGeiger -f alloc.etl -guid *AllocGeiger -level 5
+//! <run the program>
+//! tracelog -stop AllocGeiger
+//! ```
+//!
+//! The events are named `Event`, with the fields `Op`, the method as in a
+//! trace, `Size`, the new size, `OldSize`, the old size of a reallocation,
+//! `Address`, and `OldAddress`, the address a reallocation moved from.
+
+use crate::event::AllocEvent;
+use std::sync::Once;
+use tracelogging as tlg;
+
+tlg::define_provider!(PROVIDER, "AllocGeiger");
+
+static REGISTER: Once = Once::new();
+
+/// Writes an event, registering the provider with the first one.
+pub(crate) fn write(event: &AllocEvent) {
+    // SAFETY: the provider is never unregistered, which is only a problem for
+    // a DLL that's unloaded, and the `preload` library isn't on Windows.
+    REGISTER.call_once(|| unsafe {
+        PROVIDER.register();
+    });
+    tlg::write_event!(
+        PROVIDER,
+        "Event",
+        level(Verbose),
+        keyword(0x1),
+        str8("Op", event.op.name()),
+        usize("Size", &event.new_size),
+        usize("OldSize", &event.layout.size()),
+        pointer("Address", &event.address),
+        pointer("OldAddress", &event.old_address),
+    );
+}
@@ -0,0 +1,347 @@
+use crate::config::ParseConfigError;
+use crate::pitch::{pool_index, thread_index};
+use crate::recent::RecentEvent;
+use std::alloc::Layout;
+use std::fmt;
+use std::ops::{BitAnd, BitOr, BitOrAssign, Not};
+use std::str::FromStr;
+
+/// The allocator method behind an event.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AllocOp {
+    /// `GlobalAlloc::alloc`
+    Alloc,
+    /// `GlobalAlloc::alloc_zeroed`
+    AllocZeroed,
+    /// `GlobalAlloc::dealloc`
+    Dealloc,
+    /// `GlobalAlloc::realloc`
+    Realloc,
+}
+
+impl AllocOp {
+    const ALL: [AllocOp; 4] = [
+        AllocOp::Alloc,
+        AllocOp::AllocZeroed,
+        AllocOp::Dealloc,
+        AllocOp::Realloc,
+    ];
+
+    pub(crate) const fn name(self) -> &'static str {
+        match self {
+            AllocOp::Alloc => "alloc",
+            AllocOp::AllocZeroed => "alloc_zeroed",
+            AllocOp::Dealloc => "dealloc",
+            AllocOp::Realloc => "realloc",
+        }
+    }
+}
+
+impl fmt::Display for AllocOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for AllocOp {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AllocOp::ALL
+            .into_iter()
+            .find(|op| op.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("op", s))
+    }
+}
+
+/// The number of power-of-two size classes, including zero.
+pub(crate) const SIZE_CLASSES: usize = usize::BITS as usize + 1;
+
+/// The power-of-two class of a size: 0 for zero, otherwise `k` for sizes in
+/// `2^(k-1)..2^k`.
+pub(crate) fn size_class(size: usize) -> usize {
+    (usize::BITS - size.leading_zeros()) as usize
+}
+
+/// A single call to the allocator, as passed to an event hook.
+#[derive(Clone, Copy, Debug)]
+pub struct AllocEvent {
+    pub(crate) op: AllocOp,
+    pub(crate) layout: Layout,
+    /// The new size of a `Realloc`, otherwise the same as the layout.
+    pub(crate) new_size: usize,
+    /// The address returned, or freed by a `Dealloc`, or `0` on failure.
+    pub(crate) address: usize,
+    /// The address a `Realloc` moved from, otherwise `0`.
+    pub(crate) old_address: usize,
+    /// The index of a replayed event's thread, or `None` for the current one.
+    pub(crate) thread: Option<u32>,
+}
+
+impl AllocEvent {
+    pub(crate) fn new(op: AllocOp, layout: Layout, ptr: *mut u8) -> Self {
+        AllocEvent {
+            op,
+            layout,
+            new_size: layout.size(),
+            address: ptr as usize,
+            old_address: 0,
+            thread: None,
+        }
+    }
+
+    pub(crate) fn realloc(layout: Layout, new_size: usize, ptr: *mut u8, new_ptr: *mut u8) -> Self {
+        AllocEvent {
+            op: AllocOp::Realloc,
+            layout,
+            new_size,
+            address: new_ptr as usize,
+            old_address: ptr as usize,
+            thread: None,
+        }
+    }
+
+    /// Recreates a recorded event, with the new size as its layout.
+    pub(crate) fn replay(event: &RecentEvent) -> Self {
+        AllocEvent {
+            op: event.op,
+            layout: Layout::from_size_align(event.size, 1).unwrap_or(Layout::new::<u8>()),
+            new_size: event.size,
+            address: event.address,
+            old_address: 0,
+            thread: Some(event.thread),
+        }
+    }
+
+    /// The power-of-two class of the new size, as in [`size_class`].
+    pub(crate) fn size_class(&self) -> usize {
+        size_class(self.new_size)
+    }
+
+    /// A key shared by events of the same method and size class, which may
+    /// be coalesced into one sound.
+    pub(crate) fn burst(&self) -> u32 {
+        (self.op as u32) << 8 | self.size_class() as u32
+    }
+
+    /// Returns a small index of the thread that made this call, in order of
+    /// their first events.
+    pub fn thread(&self) -> u32 {
+        self.thread.unwrap_or_else(thread_index)
+    }
+
+    /// Returns the index of the thread's voice, which is that of the first
+    /// thread in its pool when `pools` is set, as for
+    /// [`Config::pool_threads`](crate::Config::pool_threads).
+    pub(crate) fn voice(&self, pools: bool) -> u32 {
+        match self.thread {
+            Some(thread) => thread,
+            None if pools => pool_index(),
+            None => thread_index(),
+        }
+    }
+
+    /// Returns the innermost `tracing` span entered on the thread that made
+    /// this call, as tracked by [`SpanLayer`](crate::SpanLayer), or `None`
+    /// outside of spans and for replayed events.
+    ///
+    /// This needs the `tracing` feature.
+    #[cfg(feature = "tracing")]
+    pub fn span(&self) -> Option<tracing_core::span::Id> {
+        match self.thread {
+            Some(_) => None,
+            None => crate::spans::current(),
+        }
+    }
+
+    /// Returns the innermost [`tag`](crate::tag) on the thread that made this
+    /// call, or `None` outside of tags and for replayed events.
+    pub fn tag(&self) -> Option<u32> {
+        match self.thread {
+            Some(_) => None,
+            None => crate::tag::current(),
+        }
+    }
+
+    /// Returns the allocator method that was called.
+    pub fn op(&self) -> AllocOp {
+        self.op
+    }
+
+    /// Returns the layout passed to the allocator, which for `Realloc` is the
+    /// old layout.
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// Returns the new size of a `Realloc`, otherwise the layout's size.
+    pub fn new_size(&self) -> usize {
+        self.new_size
+    }
+
+    /// Returns the address that was allocated, or the one freed by a
+    /// `Dealloc`, or `0` if the allocation failed.
+    pub fn address(&self) -> usize {
+        self.address
+    }
+}
+
+/// A set of allocator methods, for [`Config::ops`](crate::Config::ops).
+///
+/// ```
+/// use alloc_geiger::Ops;
+///
+/// let ops = Ops::ALLOC | Ops::REALLOC;
+/// assert!(ops.contains(Ops::ALLOC));
+/// assert!(!ops.contains(Ops::DEALLOC));
+/// assert_eq!(ops.to_string(), "alloc,realloc");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Ops(u8);
+
+impl Ops {
+    /// No methods, named `none`.
+    pub const NONE: Ops = Ops(0);
+    /// `GlobalAlloc::alloc`, named `alloc`.
+    pub const ALLOC: Ops = Ops(1);
+    /// `GlobalAlloc::alloc_zeroed`, named `alloc_zeroed`.
+    pub const ALLOC_ZEROED: Ops = Ops(2);
+    /// `GlobalAlloc::dealloc`, named `dealloc`.
+    pub const DEALLOC: Ops = Ops(4);
+    /// `GlobalAlloc::realloc`, named `realloc`.
+    pub const REALLOC: Ops = Ops(8);
+    /// All of the methods, named `all`.
+    pub const ALL: Ops = Ops(15);
+
+    const NAMED: [(Ops, &'static str); 4] = [
+        (Ops::ALLOC, "alloc"),
+        (Ops::ALLOC_ZEROED, "alloc_zeroed"),
+        (Ops::DEALLOC, "dealloc"),
+        (Ops::REALLOC, "realloc"),
+    ];
+
+    /// Returns the bits of the set, one for each method.
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    /// Returns the set of the given bits, ignoring any unknown ones.
+    pub const fn from_bits_truncate(bits: u8) -> Self {
+        Ops(bits & Ops::ALL.0)
+    }
+
+    /// Returns `true` if all of `other` is in this set.
+    pub const fn contains(self, other: Ops) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Returns `true` if the set has no methods.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns the union of both sets, as with `|` in a `const` context.
+    pub const fn union(self, other: Ops) -> Self {
+        Ops(self.0 | other.0)
+    }
+}
+
+impl From<AllocOp> for Ops {
+    fn from(op: AllocOp) -> Self {
+        match op {
+            AllocOp::Alloc => Ops::ALLOC,
+            AllocOp::AllocZeroed => Ops::ALLOC_ZEROED,
+            AllocOp::Dealloc => Ops::DEALLOC,
+            AllocOp::Realloc => Ops::REALLOC,
+        }
+    }
+}
+
+impl BitOr for Ops {
+    type Output = Ops;
+
+    fn bitor(self, other: Ops) -> Ops {
+        self.union(other)
+    }
+}
+
+impl BitOrAssign for Ops {
+    fn bitor_assign(&mut self, other: Ops) {
+        *self = self.union(other);
+    }
+}
+
+impl BitAnd for Ops {
+    type Output = Ops;
+
+    fn bitand(self, other: Ops) -> Ops {
+        Ops(self.0 & other.0)
+    }
+}
+
+impl Not for Ops {
+    type Output = Ops;
+
+    fn not(self) -> Ops {
+        Ops::from_bits_truncate(!self.0)
+    }
+}
+
+/// Lists the methods separated by commas, or `all` or `none`.
+impl fmt::Display for Ops {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Ops::ALL => return f.write_str("all"),
+            Ops::NONE => return f.write_str("none"),
+            _ => {}
+        }
+        let names = Ops::NAMED.iter().filter(|&&(ops, _)| self.contains(ops));
+        for (i, (_, name)) in names.enumerate() {
+            let sep = if i > 0 { "," } else { "" };
+            write!(f, "{sep}{name}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Ops {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("all") {
+            return Ok(Ops::ALL);
+        } else if s.eq_ignore_ascii_case("none") {
+            return Ok(Ops::NONE);
+        }
+        s.split(',').try_fold(Ops::NONE, |ops, name| {
+            let (op, _) = (Ops::NAMED.iter())
+                .find(|(_, n)| n.eq_ignore_ascii_case(name.trim()))
+                .ok_or_else(|| ParseConfigError::invalid("ops", s))?;
+            Ok(ops | *op)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_ops() {
+        assert_eq!("all".parse(), Ok(Ops::ALL));
+        assert_eq!("NONE".parse(), Ok(Ops::NONE));
+        assert_eq!("dealloc".parse(), Ok(Ops::DEALLOC));
+        assert_eq!(
+            " Alloc , alloc_zeroed".parse(),
+            Ok(Ops::ALLOC | Ops::ALLOC_ZEROED)
+        );
+        for invalid in ["", "alloc,", "alloc,free", "zeroed"] {
+            assert!(invalid.parse::<Ops>().is_err(), "{invalid}");
+        }
+        for bits in 0..=Ops::ALL.bits() {
+            let ops = Ops::from_bits_truncate(bits);
+            assert_eq!(ops.to_string().parse(), Ok(ops));
+        }
+    }
+}
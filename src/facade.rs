@@ -0,0 +1,192 @@
+//! Counters and gauges for the `metrics` facade, enabled by the `metrics`
+//! feature.
+
+use crate::{AllocRate, Geiger, GeigerStats, BUSY};
+use metrics::{counter, describe_counter, describe_gauge, gauge, Unit};
+use std::io;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread publishing the allocation counters through the
+    /// `metrics` facade at every `interval`, so whatever recorder the
+    /// application installs picks them up.
+    ///
+    /// The counters `alloc_geiger.allocs`, `deallocs`, `reallocs`,
+    /// `bytes_allocated`, and `bytes_freed` are set to their totals, and the
+    /// gauges `alloc_geiger.live_bytes`, `allocs_per_sec`, and
+    /// `bytes_per_sec` to their current values.  They are looked up from the
+    /// recorder each time, so it can be installed before or after this, but
+    /// their descriptions are only sent to the recorder installed when the
+    /// thread starts.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     // install a `metrics` recorder first, then:
+    ///     ALLOC.spawn_metrics(Duration::from_secs(10)).unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_metrics(&'static self, interval: Duration) -> io::Result<JoinHandle<()>> {
+        thread::Builder::new()
+            .name("alloc_geiger-metrics".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                describe();
+                loop {
+                    publish(&self.stats(), &self.rate());
+                    thread::sleep(interval);
+                }
+            })
+    }
+}
+
+/// Describes the metrics to the current recorder.
+fn describe() {
+    describe_counter!(
+        "alloc_geiger.allocs",
+        Unit::Count,
+        "alloc and alloc_zeroed calls"
+    );
+    describe_counter!("alloc_geiger.deallocs", Unit::Count, "dealloc calls");
+    describe_counter!("alloc_geiger.reallocs", Unit::Count, "realloc calls");
+    describe_counter!(
+        "alloc_geiger.bytes_allocated",
+        Unit::Bytes,
+        "bytes allocated, including the new size of each realloc"
+    );
+    describe_counter!(
+        "alloc_geiger.bytes_freed",
+        Unit::Bytes,
+        "bytes freed, including the old size of each realloc"
+    );
+    describe_gauge!(
+        "alloc_geiger.live_bytes",
+        Unit::Bytes,
+        "bytes currently allocated"
+    );
+    describe_gauge!(
+        "alloc_geiger.allocs_per_sec",
+        Unit::CountPerSecond,
+        "alloc, alloc_zeroed, and realloc calls per second"
+    );
+    // `Unit` has bits per second, but no bytes
+    describe_gauge!("alloc_geiger.bytes_per_sec", "bytes requested per second");
+}
+
+/// Sets the metrics in the current recorder.
+fn publish(stats: &GeigerStats, rate: &AllocRate) {
+    counter!("alloc_geiger.allocs").absolute(stats.allocs);
+    counter!("alloc_geiger.deallocs").absolute(stats.deallocs);
+    counter!("alloc_geiger.reallocs").absolute(stats.reallocs);
+    counter!("alloc_geiger.bytes_allocated").absolute(stats.bytes_allocated);
+    counter!("alloc_geiger.bytes_freed").absolute(stats.bytes_freed);
+    gauge!("alloc_geiger.live_bytes").set(stats.live_bytes() as f64);
+    gauge!("alloc_geiger.allocs_per_sec").set(rate.allocs);
+    gauge!("alloc_geiger.bytes_per_sec").set(rate.bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString};
+    use std::collections::BTreeMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    /// Keeps the value of each counter and gauge, with gauges as `f64` bits.
+    #[derive(Default)]
+    struct Values {
+        metrics: Mutex<BTreeMap<String, Arc<AtomicU64>>>,
+        units: Mutex<BTreeMap<String, Option<Unit>>>,
+    }
+
+    impl Values {
+        fn value(&self, name: &str) -> Arc<AtomicU64> {
+            let mut metrics = self.metrics.lock().unwrap();
+            metrics.entry(name.into()).or_default().clone()
+        }
+
+        fn counter(&self, name: &str) -> u64 {
+            self.value(name).load(Ordering::Relaxed)
+        }
+
+        fn gauge(&self, name: &str) -> f64 {
+            f64::from_bits(self.value(name).load(Ordering::Relaxed))
+        }
+
+        fn describe(&self, key: KeyName, unit: Option<Unit>) {
+            let mut units = self.units.lock().unwrap();
+            units.insert(key.as_str().into(), unit);
+        }
+    }
+
+    impl Recorder for Values {
+        fn describe_counter(&self, key: KeyName, unit: Option<Unit>, _: SharedString) {
+            self.describe(key, unit);
+        }
+
+        fn describe_gauge(&self, key: KeyName, unit: Option<Unit>, _: SharedString) {
+            self.describe(key, unit);
+        }
+
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {
+            unreachable!();
+        }
+
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            Counter::from_arc(self.value(key.name()))
+        }
+
+        fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::from_arc(self.value(key.name()))
+        }
+
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            unreachable!();
+        }
+    }
+
+    #[test]
+    fn publishes_totals_and_gauges() {
+        let values = Values::default();
+        let stats = GeigerStats {
+            allocs: 10,
+            deallocs: 4,
+            reallocs: 2,
+            bytes_allocated: 4096,
+            bytes_freed: 1024,
+            ..GeigerStats::default()
+        };
+        let rate = AllocRate {
+            allocs: 12.5,
+            bytes: 2048.0,
+        };
+        metrics::with_local_recorder(&values, || {
+            describe();
+            publish(&stats, &rate);
+        });
+        assert_eq!(values.counter("alloc_geiger.allocs"), 10);
+        assert_eq!(values.counter("alloc_geiger.deallocs"), 4);
+        assert_eq!(values.counter("alloc_geiger.reallocs"), 2);
+        assert_eq!(values.counter("alloc_geiger.bytes_allocated"), 4096);
+        assert_eq!(values.counter("alloc_geiger.bytes_freed"), 1024);
+        assert_eq!(values.gauge("alloc_geiger.live_bytes"), 3072.0);
+        assert_eq!(values.gauge("alloc_geiger.allocs_per_sec"), 12.5);
+        assert_eq!(values.gauge("alloc_geiger.bytes_per_sec"), 2048.0);
+
+        let units = values.units.lock().unwrap();
+        assert_eq!(units.len(), 8);
+        assert_eq!(units["alloc_geiger.live_bytes"], Some(Unit::Bytes));
+        assert_eq!(
+            units["alloc_geiger.allocs_per_sec"],
+            Some(Unit::CountPerSecond)
+        );
+        assert_eq!(units["alloc_geiger.bytes_per_sec"], None);
+    }
+}
@@ -0,0 +1,215 @@
+//! Settings from a TOML file, enabled by the `config-file` feature.
+
+use crate::{quietly, Geiger, BUSY};
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Applies settings from a TOML file of `key = value` pairs.
+    ///
+    /// The keys are those of [`Config::set`](crate::Config::set), plus
+    /// `volume` and `muted`.  A `profile` is applied before the rest, and a
+    /// `backend` may be given as an array.  Only this flat subset of TOML is
+    /// understood, without tables.
+    ///
+    /// The path in `ALLOC_GEIGER_CONFIG` is loaded automatically when the
+    /// sound is initialized, before the other environment variables.
+    ///
+    /// ```toml
+    /// # alloc_geiger.toml
+    /// profile = "musical"
+    /// volume = 0.25
+    /// backend = ["rodio", "silent"]
+    /// ```
+    pub fn load_config_file(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        quietly(|| {
+            let text = fs::read_to_string(path)?;
+            let mut pairs = Vec::new();
+            for (i, line) in text.lines().enumerate() {
+                let invalid = |error: String| {
+                    let message = format!("line {}: {error}", i + 1);
+                    io::Error::new(io::ErrorKind::InvalidData, message)
+                };
+                if let Some(pair) = parse_line(line).map_err(invalid)? {
+                    pairs.push(pair);
+                }
+            }
+            // like `ALLOC_GEIGER_PROFILE`, a profile is the base for the rest
+            pairs.sort_by_key(|(key, _)| *key != "profile");
+            for (key, value) in pairs {
+                self.set_setting(key, &value)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Loads the file named by `ALLOC_GEIGER_CONFIG`, if any, reporting
+    /// errors on stderr since there's no caller to return them to.
+    pub(crate) fn load_env_config_file(&self) {
+        if let Some(path) = env::var_os("ALLOC_GEIGER_CONFIG") {
+            if let Err(e) = self.load_config_file(&path) {
+                eprintln!("alloc_geiger: {}: {e}", Path::new(&path).display());
+            }
+        }
+    }
+
+    fn set_setting(&self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "volume" => match value.parse() {
+                Ok(volume) => self.set_volume(volume),
+                Err(_) => return Err(format!("invalid volume: {value}")),
+            },
+            "muted" => match value {
+                "true" => self.mute(),
+                "false" => self.unmute(),
+                _ => return Err(format!("invalid muted: {value}")),
+            },
+            _ => self.set(key, value).map_err(|e| e.to_string())?,
+        }
+        Ok(())
+    }
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Loads a TOML settings file as with [`load_config_file`], then spawns a
+    /// thread that checks its modification time at every `interval` and loads
+    /// it again when it changes.
+    ///
+    /// This makes it possible to tune a long-running program without
+    /// restarting it.  Settings that are removed from the file keep their
+    /// current values, and the `device` and `backend` only take effect if the
+    /// output is opened again.  Errors in later loads are reported on stderr.
+    ///
+    /// [`load_config_file`]: Self::load_config_file
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC
+    ///         .watch_config_file("alloc_geiger.toml", Duration::from_secs(1))
+    ///         .unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn watch_config_file(
+        &'static self,
+        path: impl Into<PathBuf>,
+        interval: Duration,
+    ) -> io::Result<JoinHandle<()>> {
+        let path = path.into();
+        let mut modified = fs::metadata(&path)?.modified()?;
+        self.load_config_file(&path)?;
+        thread::Builder::new()
+            .name("alloc_geiger-config".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                loop {
+                    thread::sleep(interval);
+                    let Ok(time) = fs::metadata(&path).and_then(|m| m.modified()) else {
+                        continue;
+                    };
+                    if time != modified {
+                        modified = time;
+                        if let Err(e) = self.load_config_file(&path) {
+                            eprintln!("alloc_geiger: {}: {e}", path.display());
+                        }
+                    }
+                }
+            })
+    }
+}
+
+/// Parses a `key = value` line into a setting, or `None` for a blank line or
+/// comment.  Strings are unquoted, and arrays are joined with commas.
+fn parse_line(line: &str) -> Result<Option<(&str, String)>, String> {
+    let line = strip_comment(line).trim();
+    if line.is_empty() {
+        return Ok(None);
+    }
+    let (key, value) = line
+        .split_once('=')
+        .ok_or_else(|| format!("expected `key = value`: {line}"))?;
+    let (key, value) = (key.trim(), value.trim());
+    let value = match value.strip_prefix('[') {
+        Some(items) => {
+            let items = items
+                .strip_suffix(']')
+                .ok_or_else(|| format!("unterminated array: {value}"))?;
+            let items: Vec<_> = items
+                .split(',')
+                .map(|item| unquote(item.trim()))
+                .filter(|item| !item.is_empty())
+                .collect();
+            items.join(",")
+        }
+        None => unquote(value).to_owned(),
+    };
+    Ok(Some((key, value)))
+}
+
+/// Removes a `#` comment that isn't within a string.
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn unquote(value: &str) -> &str {
+    (value.strip_prefix('"'))
+        .and_then(|value| value.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(line: &str) -> Option<(&str, String)> {
+        parse_line(line).unwrap()
+    }
+
+    #[test]
+    fn parses_lines() {
+        assert_eq!(pair(""), None);
+        assert_eq!(pair("  # a comment"), None);
+        assert_eq!(pair("volume = 0.25"), Some(("volume", "0.25".into())));
+        assert_eq!(
+            pair("profile=\"musical\""),
+            Some(("profile", "musical".into()))
+        );
+        assert_eq!(
+            pair("muted = true # for now"),
+            Some(("muted", "true".into()))
+        );
+        assert_eq!(
+            pair("flamegraph = \"out#1.svg\" # kept"),
+            Some(("flamegraph", "out#1.svg".into()))
+        );
+        assert_eq!(
+            pair("backend = [\"rodio\", \"silent\",]"),
+            Some(("backend", "rodio,silent".into()))
+        );
+        assert_eq!(pair("spans = []"), Some(("spans", String::new())));
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        assert!(parse_line("volume").is_err());
+        assert!(parse_line("backend = [\"rodio\"").is_err());
+    }
+}
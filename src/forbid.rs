@@ -0,0 +1,210 @@
+use crate::event::{AllocEvent, AllocOp};
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// The innermost `Forbidden` scope on this thread.
+    static SCOPE: Cell<Scope> = const { Cell::new(Scope::ALLOWED) };
+}
+
+#[derive(Clone, Copy)]
+struct Scope {
+    forbidden: bool,
+    abort: bool,
+    allocs: usize,
+    bytes: usize,
+}
+
+impl Scope {
+    const ALLOWED: Scope = Scope {
+        forbidden: false,
+        abort: false,
+        allocs: 0,
+        bytes: 0,
+    };
+}
+
+/// Forbids allocation on the current thread until the returned guard is
+/// dropped, which then panics if there were any.
+///
+/// This only sees allocations through a [`Geiger`](crate::Geiger) that is the
+/// global allocator.  Deallocations are still allowed.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     let mut vec = Vec::with_capacity(16);
+///     {
+///         let _guard = alloc_geiger::forbid();
+///         // pushing within the capacity doesn't allocate
+///         vec.push(1);
+///     }
+/// }
+/// ```
+pub fn forbid() -> Forbidden {
+    Forbidden::new(false)
+}
+
+/// Forbids allocation on the current thread until the returned guard is
+/// dropped, aborting the process right away at the first one.
+///
+/// Allocators must not unwind, so this is the way to stop at the offending
+/// allocation itself, as when running under a debugger.
+pub fn forbid_abort() -> Forbidden {
+    Forbidden::new(true)
+}
+
+/// A scope where allocation is forbidden, from [`forbid`] or [`forbid_abort`].
+#[must_use = "allocation is only forbidden until the guard is dropped"]
+pub struct Forbidden {
+    outer: Scope,
+    /// `SCOPE` is thread-local, so the guard must stay on its thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Forbidden {
+    fn new(abort: bool) -> Self {
+        let scope = Scope {
+            forbidden: true,
+            abort,
+            ..Scope::ALLOWED
+        };
+        Forbidden {
+            outer: SCOPE.with(|cell| cell.replace(scope)),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Drop for Forbidden {
+    fn drop(&mut self) {
+        let scope = SCOPE.with(|cell| cell.replace(self.outer));
+        if scope.allocs > 0 && !std::thread::panicking() {
+            panic!(
+                "{} allocation(s) of {} bytes in a forbidden scope",
+                scope.allocs, scope.bytes
+            );
+        }
+    }
+}
+
+/// Notes an event against the current scope, aborting if required.
+pub(crate) fn check(event: &AllocEvent) {
+    if event.op == AllocOp::Dealloc {
+        return;
+    }
+    SCOPE.with(|cell| {
+        let mut scope = cell.get();
+        if scope.forbidden {
+            if scope.abort {
+                eprintln!(
+                    "alloc_geiger: allocation of {} bytes in a forbidden scope",
+                    event.new_size
+                );
+                std::process::abort();
+            }
+            scope.allocs += 1;
+            scope.bytes += event.new_size;
+            cell.set(scope);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+    use std::panic::{self, AssertUnwindSafe};
+
+    fn event(op: AllocOp, size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(op, layout, 0x1000 as *mut u8)
+    }
+
+    /// Drops a guard, returning the message it panicked with, if any.
+    fn drop_guard(guard: Forbidden) -> Option<String> {
+        let dropped = panic::catch_unwind(AssertUnwindSafe(|| drop(guard)));
+        dropped
+            .err()
+            .map(|panic| *panic.downcast::<String>().unwrap())
+    }
+
+    #[test]
+    fn panics_when_dropped_after_allocating() {
+        let guard = forbid();
+        check(&event(AllocOp::Alloc, 100));
+        check(&event(AllocOp::AllocZeroed, 20));
+        assert_eq!(
+            drop_guard(guard).as_deref(),
+            Some("2 allocation(s) of 120 bytes in a forbidden scope")
+        );
+
+        let guard = forbid();
+        let layout = Layout::from_size_align(10, 1).unwrap();
+        let ptr = 0x1000 as *mut u8;
+        check(&AllocEvent::realloc(layout, 30, ptr, ptr));
+        assert_eq!(
+            drop_guard(guard).as_deref(),
+            Some("1 allocation(s) of 30 bytes in a forbidden scope")
+        );
+
+        // allocation is allowed again after the guard
+        check(&event(AllocOp::Alloc, 100));
+        assert_eq!(drop_guard(forbid()), None);
+    }
+
+    #[test]
+    fn allows_frees() {
+        let guard = forbid();
+        check(&event(AllocOp::Dealloc, 100));
+        assert_eq!(drop_guard(guard), None);
+    }
+
+    #[test]
+    fn nested_scopes_panic_for_their_own_allocations() {
+        let outer = forbid();
+        let inner = forbid();
+        check(&event(AllocOp::Alloc, 8));
+        assert_eq!(
+            drop_guard(inner).as_deref(),
+            Some("1 allocation(s) of 8 bytes in a forbidden scope")
+        );
+        // the outer scope is still forbidden
+        check(&event(AllocOp::Alloc, 16));
+        assert_eq!(
+            drop_guard(outer).as_deref(),
+            Some("1 allocation(s) of 16 bytes in a forbidden scope")
+        );
+    }
+
+    /// Aborts instead of unwinding out of the allocator, which is checked by
+    /// running this test again in a child process.
+    #[cfg(unix)]
+    #[test]
+    fn aborts_without_unwinding() {
+        use std::os::unix::process::ExitStatusExt;
+        use std::process::Command;
+
+        const CHILD: &str = "FORBID_ABORT_CHILD";
+        if std::env::var_os(CHILD).is_some() {
+            let _guard = forbid_abort();
+            check(&event(AllocOp::Alloc, 100));
+            unreachable!();
+        }
+        let output = Command::new(std::env::current_exe().unwrap())
+            .args([
+                "--exact",
+                "forbid::tests::aborts_without_unwinding",
+                "--nocapture",
+            ])
+            .env(CHILD, "1")
+            .output()
+            .unwrap();
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert_eq!(output.status.signal(), Some(6), "{stderr}");
+        assert!(stderr.contains("alloc_geiger: allocation of 100 bytes in a forbidden scope"));
+        assert!(!stderr.contains("panicked"), "{stderr}");
+    }
+}
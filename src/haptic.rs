@@ -0,0 +1,146 @@
+//! Rumble on a game controller, enabled by the `haptic` feature on Linux.
+
+use crate::{Geiger, GeigerStats, BUSY};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How often the rumble is updated, which is also the length of each.
+const TICK: Duration = Duration::from_millis(50);
+
+/// The events and bytes in a tick that rumble at full strength.
+const FULL_EVENTS: f32 = 4096.0;
+const FULL_BYTES: f32 = 64.0 * 1024.0 * 1024.0;
+
+/// From `linux/input-event-codes.h`.
+const EV_FF: u16 = 0x15;
+const FF_RUMBLE: u16 = 0x50;
+
+/// `EVIOCSFF`, to upload an effect, from `linux/input.h`.
+const EVIOCSFF: u32 =
+    (1 << 30) | ((mem::size_of::<libc::ff_effect>() as u32) << 16) | (b'E' as u32) << 8 | 0x80;
+
+/// An evdev device with force feedback, with one rumble effect uploaded.
+struct Rumble {
+    device: File,
+    /// the effect's ID, assigned by the first upload
+    id: i16,
+}
+
+impl Rumble {
+    fn open(path: &Path) -> io::Result<Self> {
+        let device = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut rumble = Rumble { device, id: -1 };
+        rumble.upload(0, 0)?;
+        Ok(rumble)
+    }
+
+    /// Sets the strengths of the effect's two motors.
+    fn upload(&mut self, strong: u16, weak: u16) -> io::Result<()> {
+        // SAFETY: `ff_effect` is plain data, and its union starts with the
+        // rumble fields for `FF_RUMBLE`.
+        unsafe {
+            let mut effect: libc::ff_effect = mem::zeroed();
+            effect.type_ = FF_RUMBLE;
+            effect.id = self.id;
+            effect.replay.length = TICK.as_millis() as u16;
+            let rumble = libc::ff_rumble_effect {
+                strong_magnitude: strong,
+                weak_magnitude: weak,
+            };
+            std::ptr::write_unaligned(effect.u.as_mut_ptr().cast(), rumble);
+            if libc::ioctl(self.device.as_raw_fd(), EVIOCSFF as _, &mut effect) < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            self.id = effect.id;
+        }
+        Ok(())
+    }
+
+    /// Plays the effect once.
+    fn play(&mut self) -> io::Result<()> {
+        // SAFETY: `input_event` is plain data.
+        let mut event: libc::input_event = unsafe { mem::zeroed() };
+        event.type_ = EV_FF;
+        event.code = self.id as u16;
+        event.value = 1;
+        // SAFETY: the event is plain data of this size.
+        let bytes = unsafe {
+            std::slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.device.write_all(bytes)
+    }
+}
+
+/// The strength of a motor for an amount, on a log scale up to `full`.
+fn magnitude(amount: u64, full: f32) -> u16 {
+    if amount == 0 {
+        return 0;
+    }
+    let level = (1.0 + amount as f32).log2() / (1.0 + full).log2();
+    (level.min(1.0) * f32::from(u16::MAX)) as u16
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread rumbling a game controller with the allocator
+    /// activity, alongside or instead of the sound, for demos or when the
+    /// headphones are busy with music.
+    ///
+    /// The device is a Linux event device with force feedback, like
+    /// `/dev/input/by-id/usb-...-event-joystick`, which needs permission to
+    /// write.  Every 50 milliseconds, the weak motor rumbles with the number
+    /// of events since the last update and the strong motor with the bytes
+    /// allocated, so bursts are felt as pulses and large allocations as a
+    /// heavier thud.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_haptic("/dev/input/event20").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_haptic<P: AsRef<Path>>(&'static self, device: P) -> io::Result<JoinHandle<()>> {
+        let mut rumble = Rumble::open(device.as_ref())?;
+        thread::Builder::new()
+            .name("alloc_geiger-haptic".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let mut last = self.stats();
+                loop {
+                    thread::sleep(TICK);
+                    let stats = self.stats();
+                    let (events, bytes) = activity(&stats, &last);
+                    last = stats;
+                    if events == 0 || self.is_muted() || self.is_paused() {
+                        continue;
+                    }
+                    let strong = magnitude(bytes, FULL_BYTES);
+                    let weak = magnitude(events, FULL_EVENTS);
+                    if rumble
+                        .upload(strong, weak)
+                        .and_then(|()| rumble.play())
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            })
+    }
+}
+
+/// The events and bytes allocated between two snapshots.
+fn activity(stats: &GeigerStats, last: &GeigerStats) -> (u64, u64) {
+    let events = (stats.allocs + stats.deallocs + stats.reallocs)
+        - (last.allocs + last.deallocs + last.reallocs);
+    (events, stats.bytes_allocated - last.bytes_allocated)
+}
@@ -0,0 +1,296 @@
+//! A recording of allocations with their stacks in heaptrack's data format.
+
+use crate::clock::now_millis;
+use crate::event::{AllocEvent, AllocOp};
+use crate::stacks::{self, Frame};
+use crate::{quietly, Geiger};
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// The heaptrack version claimed in the header, as `0xMMmmpp`.
+const HEAPTRACK_VERSION: u32 = 0x01_02_00;
+/// The data format version, with allocation infos and plain strings.
+const FILE_FORMAT_VERSION: u32 = 2;
+
+/// The current heaptrack recording of a `Geiger`, if any.
+pub(crate) struct Recorder {
+    active: AtomicBool,
+    session: Mutex<Option<Session>>,
+}
+
+impl Recorder {
+    pub(crate) const fn new() -> Self {
+        Recorder {
+            active: AtomicBool::new(false),
+            session: Mutex::new(None),
+        }
+    }
+
+    /// Writes an event to the recording, if one was started.
+    pub(crate) fn record(&self, event: &AllocEvent) {
+        if self.active.load(Ordering::Relaxed) {
+            let mut session = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(s) = session.as_mut() {
+                if s.record(event).is_err() {
+                    self.active.store(false, Ordering::Relaxed);
+                    *session = None;
+                }
+            }
+        }
+    }
+
+    fn replace(&self, session: Option<Session>) -> io::Result<()> {
+        let mut current = self.session.lock().unwrap_or_else(PoisonError::into_inner);
+        self.active.store(session.is_some(), Ordering::Relaxed);
+        match mem::replace(&mut *current, session) {
+            Some(mut old) => old.out.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// An open recording, with the indices of everything written so far.
+///
+/// Strings, instruction pointers, and trace nodes are numbered from 1, with
+/// 0 for none, and allocation infos are numbered from 0.
+struct Session {
+    out: BufWriter<File>,
+    /// `now_millis` when recording started
+    start: u64,
+    /// the last time written, in milliseconds since `start`
+    time: u64,
+    module: u32,
+    strings: HashMap<String, u32>,
+    /// a made-up instruction pointer for each distinct frame
+    ips: HashMap<(String, Option<(String, u32)>), u32>,
+    /// each node of the trace tree by its frame and parent
+    traces: HashMap<(u32, u32), u32>,
+    /// each allocation info by its size and trace
+    infos: HashMap<(usize, u32), u32>,
+    /// the allocation info of each live address
+    live: HashMap<usize, u32>,
+}
+
+impl Session {
+    fn create(path: &Path) -> io::Result<Self> {
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "v {HEAPTRACK_VERSION:x} {FILE_FORMAT_VERSION:x}")?;
+        let command: Vec<String> = env::args().collect();
+        writeln!(out, "X {}", command.join(" "))?;
+        let mut session = Session {
+            out,
+            start: now_millis(),
+            time: 0,
+            module: 0,
+            strings: HashMap::new(),
+            ips: HashMap::new(),
+            traces: HashMap::new(),
+            infos: HashMap::new(),
+            live: HashMap::new(),
+        };
+        let exe = env::current_exe().map(|exe| exe.display().to_string());
+        session.module = session.string(&exe.unwrap_or_else(|_| "<unknown>".into()))?;
+        Ok(session)
+    }
+
+    fn record(&mut self, event: &AllocEvent) -> io::Result<()> {
+        if event.address == 0 {
+            return Ok(());
+        }
+        let time = now_millis().saturating_sub(self.start);
+        if time != self.time {
+            self.time = time;
+            writeln!(self.out, "c {time:x}")?;
+            self.out.flush()?;
+        }
+        match event.op {
+            AllocOp::Dealloc => self.free(event.address),
+            AllocOp::Realloc => {
+                self.free(event.old_address)?;
+                self.alloc(event)
+            }
+            AllocOp::Alloc | AllocOp::AllocZeroed => self.alloc(event),
+        }
+    }
+
+    fn alloc(&mut self, event: &AllocEvent) -> io::Result<()> {
+        let backtrace = stacks::capture();
+        let mut trace = 0;
+        for frame in stacks::caller_frames(&backtrace).iter().rev() {
+            let ip = self.ip(frame)?;
+            trace = match self.traces.get(&(ip, trace)) {
+                Some(&index) => index,
+                None => {
+                    let index = self.traces.len() as u32 + 1;
+                    writeln!(self.out, "t {ip:x} {trace:x}")?;
+                    self.traces.insert((ip, trace), index);
+                    index
+                }
+            };
+        }
+        let key = (event.new_size, trace);
+        let info = match self.infos.get(&key) {
+            Some(&index) => index,
+            None => {
+                let index = self.infos.len() as u32;
+                writeln!(self.out, "a {:x} {trace:x}", event.new_size)?;
+                self.infos.insert(key, index);
+                index
+            }
+        };
+        self.live.insert(event.address, info);
+        writeln!(self.out, "+ {info:x}")
+    }
+
+    /// Writes the free of a live address, ignoring those allocated before
+    /// recording started.
+    fn free(&mut self, address: usize) -> io::Result<()> {
+        match self.live.remove(&address) {
+            Some(info) => writeln!(self.out, "- {info:x}"),
+            None => Ok(()),
+        }
+    }
+
+    fn ip(&mut self, frame: &Frame<'_>) -> io::Result<u32> {
+        let location = frame.location.map(|(file, line)| (file.to_owned(), line));
+        let key = (frame.name.to_owned(), location);
+        if let Some(&index) = self.ips.get(&key) {
+            return Ok(index);
+        }
+        let function = self.string(frame.name)?;
+        let index = self.ips.len() as u32 + 1;
+        match frame.location {
+            Some((file, line)) => {
+                let file = self.string(file)?;
+                let module = self.module;
+                writeln!(
+                    self.out,
+                    "i {index:x} {module:x} {function:x} {file:x} {line:x}"
+                )?;
+            }
+            None => writeln!(self.out, "i {index:x} {:x} {function:x}", self.module)?,
+        }
+        self.ips.insert(key, index);
+        Ok(index)
+    }
+
+    fn string(&mut self, s: &str) -> io::Result<u32> {
+        if let Some(&index) = self.strings.get(s) {
+            return Ok(index);
+        }
+        let index = self.strings.len() as u32 + 1;
+        writeln!(self.out, "s {s}")?;
+        self.strings.insert(s.to_owned(), index);
+        Ok(index)
+    }
+}
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Starts recording every allocation and its stack to a file in
+    /// heaptrack's data format, replacing any recording already started.
+    ///
+    /// The file can be opened in `heaptrack_gui` or summarized with
+    /// `heaptrack_print`, to look deeper into a session after hearing it.
+    /// Only allocations made while recording are included.  Capturing a
+    /// backtrace for every allocation is slow, so this suits short sessions;
+    /// the instruction pointers it shows are made up for each distinct
+    /// function and line.
+    ///
+    /// The file is flushed as time passes, and completely by
+    /// [`stop_heaptrack`](Self::stop_heaptrack).
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.start_heaptrack("alloc.heaptrack").unwrap();
+    ///     // ...
+    ///     ALLOC.stop_heaptrack().unwrap();
+    /// }
+    /// ```
+    pub fn start_heaptrack<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        quietly(|| {
+            let session = Session::create(path.as_ref())?;
+            self.heaptrack.replace(Some(session))
+        })
+    }
+
+    /// Stops the heaptrack recording, flushing the rest of its file.
+    pub fn stop_heaptrack(&self) -> io::Result<()> {
+        quietly(|| self.heaptrack.replace(None))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+    use std::fs;
+
+    fn event(op: AllocOp, size: usize, address: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(op, layout, address as *mut u8)
+    }
+
+    #[test]
+    fn writes_allocations_and_frees() {
+        let path = env::temp_dir().join(format!("alloc_geiger.{}.heaptrack", std::process::id()));
+        let mut session = Session::create(&path).unwrap();
+        let layout = Layout::from_size_align(16, 1).unwrap();
+        let realloc = AllocEvent::realloc(layout, 64, 0x2000 as *mut u8, 0x3000 as *mut u8);
+        let events = [
+            event(AllocOp::Alloc, 16, 0x1000),
+            event(AllocOp::AllocZeroed, 16, 0x2000),
+            event(AllocOp::Alloc, 16, 0),
+            event(AllocOp::Dealloc, 16, 0x1000),
+            event(AllocOp::Dealloc, 16, 0x1000),
+            event(AllocOp::Dealloc, 16, 0x9000),
+            realloc,
+        ];
+        // from the same line, so they share a stack
+        for event in &events {
+            session.record(event).unwrap();
+        }
+        session.out.flush().unwrap();
+        drop(session);
+        let data = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines[0], "v 10200 2");
+        assert!(lines[1].starts_with("X "), "{data}");
+        assert!(lines[2].starts_with("s "), "{data}");
+        // one allocation info for each size, both from the same stack
+        let infos: Vec<_> = (lines.iter())
+            .filter_map(|line| line.strip_prefix("a "))
+            .map(|info| info.split_once(' ').unwrap())
+            .collect();
+        assert_eq!(infos.len(), 2, "{data}");
+        assert_eq!((infos[0].0, infos[1].0), ("10", "40"));
+        assert_eq!(infos[0].1, infos[1].1);
+        let allocs: Vec<_> = (lines.iter())
+            .filter(|line| line.starts_with(['+', '-']))
+            .copied()
+            .collect();
+        assert_eq!(allocs, ["+ 0", "+ 0", "- 0", "- 0", "+ 1"], "{data}");
+
+        // every trace names an instruction pointer written before it
+        for line in &lines {
+            if let Some(trace) = line.strip_prefix("t ") {
+                let ip = trace.split(' ').next().unwrap();
+                let defined = format!("i {ip} ");
+                assert!(
+                    data.split(line).next().unwrap().contains(&defined),
+                    "{data}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,296 @@
+use crate::clock::now_millis;
+use crate::event::{AllocEvent, SIZE_CLASSES};
+use crate::stacks::atexit;
+use crate::{quietly, Geiger};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::sync::{Mutex, Once, PoisonError};
+use std::time::Duration;
+
+/// The most time buckets kept, dropping the oldest beyond them, which is
+/// about 2 MiB of counts.
+const MAX_ROWS: usize = 4096;
+
+/// Event counts by time and size, recorded as configured by
+/// [`Config::heatmap`](crate::Config::heatmap).
+pub(crate) struct Recorder {
+    heatmap: Mutex<Heatmap>,
+}
+
+impl Recorder {
+    pub(crate) const fn new() -> Self {
+        Recorder {
+            heatmap: Mutex::new(Heatmap {
+                interval: Duration::ZERO,
+                start: 0,
+                dropped: 0,
+                rows: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Counts an event in the current time bucket, starting over if the
+    /// interval has changed.
+    pub(crate) fn record(&self, interval: Duration, event: &AllocEvent) {
+        let mut heatmap = self.heatmap.lock().unwrap_or_else(PoisonError::into_inner);
+        // under the lock, so another thread can't start over after this
+        let now = now_millis();
+        if heatmap.interval != interval {
+            *heatmap = Heatmap {
+                interval,
+                start: now,
+                dropped: 0,
+                rows: VecDeque::new(),
+            };
+        }
+        heatmap.count(now, event.size_class());
+    }
+
+    pub(crate) fn snapshot(&self) -> Heatmap {
+        self.heatmap
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// A snapshot of allocator event counts by time and size, from
+/// [`Geiger::heatmap`](crate::Geiger::heatmap).
+///
+/// This displays as a CSV matrix, with a row for each time bucket and a
+/// column for each power-of-two size class, headed by the milliseconds since
+/// recording started and the smallest size in each class.  Only the last
+/// 4096 time buckets are kept.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Heatmap {
+    interval: Duration,
+    /// `now_millis` when recording started
+    start: u64,
+    /// the time buckets before the first of the `rows`
+    dropped: u64,
+    rows: VecDeque<[u64; SIZE_CLASSES]>,
+}
+
+impl Heatmap {
+    /// Returns the length of each time bucket.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    fn interval_millis(&self) -> u64 {
+        (self.interval.as_millis() as u64).max(1)
+    }
+
+    /// Counts an event of a size class at `now`, dropping the oldest rows
+    /// past [`MAX_ROWS`].
+    fn count(&mut self, now: u64, class: usize) {
+        let bucket = now.saturating_sub(self.start) / self.interval_millis();
+        // a clock read before the oldest row was dropped counts in it
+        let row = bucket.saturating_sub(self.dropped) as usize;
+        if row >= MAX_ROWS {
+            let excess = (row + 1 - MAX_ROWS).min(self.rows.len());
+            self.rows.drain(..excess);
+            self.dropped = bucket + 1 - MAX_ROWS as u64;
+        }
+        let row = bucket.saturating_sub(self.dropped) as usize;
+        if self.rows.len() <= row {
+            self.rows.resize(row + 1, [0; SIZE_CLASSES]);
+        }
+        self.rows[row][class] += 1;
+    }
+}
+
+impl Heatmap {
+    /// The number of size classes up to the largest that saw an event.
+    fn columns(&self) -> usize {
+        (self.rows.iter())
+            .filter_map(|row| row.iter().rposition(|&count| count > 0))
+            .max()
+            .map_or(1, |last| last + 1)
+    }
+}
+
+/// The smallest size in a size class.
+fn class_size(class: usize) -> u128 {
+    if class > 0 {
+        1 << (class - 1)
+    } else {
+        0
+    }
+}
+
+impl fmt::Display for Heatmap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // leave out the large classes that never saw an event
+        let columns = self.columns();
+
+        write!(f, "millis")?;
+        for class in 0..columns {
+            write!(f, ",{}", class_size(class))?;
+        }
+        writeln!(f)?;
+
+        for (i, row) in self.rows.iter().enumerate() {
+            write!(f, "{}", (self.dropped + i as u64) * self.interval_millis())?;
+            for count in &row[..columns] {
+                write!(f, ",{count}")?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+/// Serializes the same matrix as the `Display` CSV, as the `interval_millis`
+/// of each row, the `start_millis` of the first, the smallest `sizes` of the
+/// columns, and the `rows` of counts.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Heatmap {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        /// The rows cut to the columns, which are too long for serde's arrays.
+        struct Rows<'a>(&'a VecDeque<[u64; SIZE_CLASSES]>, usize);
+
+        impl serde::Serialize for Rows<'_> {
+            fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_seq(self.0.iter().map(|row| &row[..self.1]))
+            }
+        }
+
+        let columns = self.columns();
+        let sizes: Vec<u128> = (0..columns).map(class_size).collect();
+        let mut state = serializer.serialize_struct("Heatmap", 4)?;
+        state.serialize_field("interval_millis", &self.interval_millis())?;
+        state.serialize_field("start_millis", &(self.dropped * self.interval_millis()))?;
+        state.serialize_field("sizes", &sizes)?;
+        state.serialize_field("rows", &Rows(&self.rows, columns))?;
+        state.end()
+    }
+}
+
+/// A `Geiger` whose heatmap to write, erased from its allocator type.
+trait Export: Sync {
+    fn heatmap(&self) -> Heatmap;
+}
+
+impl<Alloc: Sync, const OPS: u8> Export for Geiger<Alloc, OPS> {
+    fn heatmap(&self) -> Heatmap {
+        Geiger::heatmap(self)
+    }
+}
+
+/// The `Geiger` whose heatmap to write at exit, with the path to write to.
+static EXPORT: Mutex<Option<(&'static dyn Export, String)>> = Mutex::new(None);
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Writes the [`heatmap`](Self::heatmap) to `path` as CSV as the process
+    /// exits, to diff the runs of two builds.
+    ///
+    /// This only has rows if [`Config::heatmap`](crate::Config::heatmap) is
+    /// recording, and is only written when the process exits normally.  Only
+    /// the last `Geiger` to call this is written.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use alloc_geiger::{Config, Geiger};
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: Geiger<std::alloc::System> = Geiger::with_config(
+    ///     std::alloc::System,
+    ///     Config::new().heatmap(Duration::from_millis(100)),
+    /// );
+    ///
+    /// fn main() {
+    ///     ALLOC.write_heatmap_at_exit("heatmap.csv");
+    ///     // ...
+    /// }
+    /// ```
+    pub fn write_heatmap_at_exit(&'static self, path: impl Into<String>) {
+        static REGISTER: Once = Once::new();
+        let path = quietly(|| path.into());
+        *EXPORT.lock().unwrap_or_else(PoisonError::into_inner) = Some((self, path));
+        REGISTER.call_once(|| {
+            // SAFETY: registering a plain function that doesn't unwind.
+            unsafe { atexit(exit_handler) };
+        });
+    }
+}
+
+extern "C" fn exit_handler() {
+    quietly(|| {
+        let export = EXPORT.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((geiger, path)) = &*export {
+            if let Err(e) = fs::write(path, geiger.heatmap().to_string()) {
+                eprintln!("alloc_geiger: couldn't write the heatmap to {path}: {e}");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heatmap(interval: u64) -> Heatmap {
+        Heatmap {
+            interval: Duration::from_millis(interval),
+            start: 1000,
+            dropped: 0,
+            rows: VecDeque::new(),
+        }
+    }
+
+    #[test]
+    fn writes_csv() {
+        let mut heatmap = heatmap(10);
+        assert_eq!(heatmap.to_string(), "millis,0\n");
+        heatmap.count(1000, 1);
+        heatmap.count(1009, 1);
+        heatmap.count(1025, 3);
+        assert_eq!(
+            heatmap.to_string(),
+            "millis,0,1,2,4\n\
+             0,0,2,0,0\n\
+             10,0,0,0,0\n\
+             20,0,0,0,1\n"
+        );
+    }
+
+    #[test]
+    fn counts_early_clocks_in_the_first_row() {
+        let mut heatmap = heatmap(10);
+        heatmap.count(990, 2);
+        assert_eq!(heatmap.rows.len(), 1);
+        assert_eq!(heatmap.rows[0][2], 1);
+    }
+
+    #[test]
+    fn drops_the_oldest_rows() {
+        let mut heatmap = heatmap(1);
+        heatmap.count(1000, 0);
+        heatmap.count(1000 + MAX_ROWS as u64 - 1, 0);
+        assert_eq!(heatmap.rows.len(), MAX_ROWS);
+        assert_eq!(heatmap.dropped, 0);
+
+        heatmap.count(1000 + MAX_ROWS as u64 + 9, 1);
+        assert_eq!(heatmap.rows.len(), MAX_ROWS);
+        assert_eq!(heatmap.dropped, 10);
+        assert_eq!(heatmap.rows[MAX_ROWS - 1][1], 1);
+        assert_eq!(heatmap.rows[MAX_ROWS - 11][0], 1);
+
+        // a late event from before the drop counts in the oldest row
+        heatmap.count(1001, 1);
+        assert_eq!(heatmap.rows[0][1], 1);
+
+        let csv = heatmap.to_string();
+        assert!(csv.lines().nth(1).unwrap().starts_with("10,"));
+        assert!(csv.lines().last().unwrap().starts_with("4105,"));
+
+        // far past the end, everything is dropped
+        heatmap.count(1_000_000, 0);
+        assert_eq!(heatmap.rows.len(), MAX_ROWS);
+        assert_eq!(heatmap.rows.iter().flatten().sum::<u64>(), 1);
+    }
+}
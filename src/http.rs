@@ -0,0 +1,247 @@
+//! A tiny HTTP/1.0 control server, enabled by the `http` feature.
+
+use crate::{Geiger, BUSY};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The longest request line that's read, well beyond any valid request.
+const MAX_LINE: u64 = 4096;
+
+/// How long a client may take to send its request or read the response,
+/// since one client is served at a time.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The settings that `/config` may change, which only shape the sound.
+/// Settings that name files, devices, or sockets, or that change how the
+/// program itself runs, like `cap` and `cap_enforce`, are left out.
+const HTTP_KEYS: &[&str] = &[
+    "profile",
+    "waveform",
+    "duration",
+    "attack",
+    "decay",
+    "pan",
+    "pitch",
+    "scale",
+    "bend",
+    "echo",
+    "loudness",
+    "align_timbre",
+    "voices",
+    "steal",
+    "tick",
+    "coalesce",
+    "ops",
+    "tempo",
+    "warn_above",
+    "sweep",
+    "watermark",
+    "quantile_tone",
+    "thread_cues",
+    "unwind_cue",
+    "recycle_cue",
+    "rate_window",
+    "grains",
+    "target_rate",
+    "dead_time",
+    "warmup",
+];
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread serving a plain-text HTTP control interface.
+    ///
+    /// The following paths are available:
+    ///
+    /// - `POST /mute` and `POST /unmute` toggle the sound.
+    /// - `GET /stats` reports the current [`GeigerStats`](crate::GeigerStats).
+    /// - `GET /config` reports the current settings, and `POST` with a query
+    ///   string like `/config?volume=0.25&waveform=sine` changes them.
+    ///
+    /// Only `volume`, `muted`, and the settings that shape the sound can be
+    /// changed, not those naming files, devices, or sockets.  A query with
+    /// any invalid setting changes nothing.  There's no authentication, so
+    /// only bind an address that untrusted clients can't reach.
+    ///
+    /// Clients are served one at a time, each given 5 seconds to send its
+    /// request.  The server's own allocations are not counted or sonified.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     // e.g. curl -X POST 'http://127.0.0.1:9090/config?volume=0.25'
+    ///     ALLOC.spawn_http_control("127.0.0.1:9090").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_http_control<A: ToSocketAddrs>(
+        &'static self,
+        addr: A,
+    ) -> io::Result<JoinHandle<()>> {
+        let listener = TcpListener::bind(addr)?;
+        thread::Builder::new()
+            .name("alloc_geiger-http".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                for stream in listener.incoming().flatten() {
+                    let _ = self.handle_http(stream);
+                }
+            })
+    }
+
+    fn handle_http(&self, mut stream: TcpStream) -> io::Result<()> {
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut request = String::new();
+        BufReader::new((&stream).take(MAX_LINE)).read_line(&mut request)?;
+
+        // e.g. "POST /config?volume=0.25 HTTP/1.1"
+        let mut words = request.split_whitespace();
+        let method = words.next().unwrap_or("");
+        let target = words.next().unwrap_or("");
+        let (path, query) = target.split_once('?').unwrap_or((target, ""));
+        let change = match path {
+            "/mute" | "/unmute" => true,
+            "/config" => !query.is_empty(),
+            _ => false,
+        };
+
+        let (status, body) = match path {
+            _ if !request.ends_with('\n') => ("400 Bad Request", "bad request\n".to_owned()),
+            _ if change && method != "POST" => {
+                ("405 Method Not Allowed", "changes need POST\n".to_owned())
+            }
+            "/mute" => {
+                self.mute();
+                ("200 OK", "muted\n".to_owned())
+            }
+            "/unmute" => {
+                self.unmute();
+                ("200 OK", "unmuted\n".to_owned())
+            }
+            "/stats" => ("200 OK", self.stats().to_string()),
+            "/config" => match self.apply_query(query) {
+                Ok(()) => ("200 OK", self.config_string()),
+                Err(e) => ("400 Bad Request", e + "\n"),
+            },
+            _ => ("404 Not Found", "not found\n".to_owned()),
+        };
+
+        write!(
+            stream,
+            "HTTP/1.0 {status}\r\n\
+             Content-Type: text/plain\r\n\
+             Content-Length: {}\r\n\
+             \r\n\
+             {body}",
+            body.len()
+        )
+    }
+
+    /// Changes the settings in a query, all at once or not at all.
+    fn apply_query(&self, query: &str) -> Result<(), String> {
+        if query.is_empty() {
+            return Ok(());
+        }
+        let mut volume = None;
+        let mut muted = None;
+        // checked on a copy, so a rejected query never holds the write lock
+        let mut config = self.read_config().clone();
+        for pair in query.split('&').filter(|s| !s.is_empty()) {
+            let Some((key, value)) = pair.split_once('=') else {
+                return Err(format!("invalid setting: {pair}"));
+            };
+            let key = decode(key).ok_or_else(|| format!("invalid setting: {pair}"))?;
+            let value = decode(value).ok_or_else(|| format!("invalid setting: {pair}"))?;
+            match (key.as_str(), value.as_str()) {
+                ("volume", value) => match value.parse() {
+                    Ok(v) => volume = Some(v),
+                    Err(_) => return Err(format!("invalid volume: {value}")),
+                },
+                ("muted", "true") => muted = Some(true),
+                ("muted", "false") => muted = Some(false),
+                ("muted", value) => return Err(format!("invalid muted: {value}")),
+                (key, value) if HTTP_KEYS.contains(&key) => {
+                    config.set(key, value).map_err(|e| e.to_string())?;
+                }
+                (key, _) => return Err(format!("setting {key} can't be changed over HTTP")),
+            }
+        }
+        *self.write_config() = config;
+        if let Some(volume) = volume {
+            self.set_volume(volume);
+        }
+        match muted {
+            Some(true) => self.mute(),
+            Some(false) => self.unmute(),
+            None => {}
+        }
+        Ok(())
+    }
+
+    fn config_string(&self) -> String {
+        format!(
+            "muted {}\nvolume {}\n{}",
+            self.is_muted(),
+            self.volume(),
+            self.config()
+        )
+    }
+}
+
+/// Decodes a query string component, with `%XX` escapes and `+` for spaces.
+fn decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.bytes();
+    while let Some(byte) = rest.next() {
+        bytes.push(match byte {
+            b'+' => b' ',
+            b'%' => {
+                let high = (rest.next()? as char).to_digit(16)?;
+                let low = (rest.next()? as char).to_digit(16)?;
+                (high * 16 + low) as u8
+            }
+            byte => byte,
+        });
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+    use std::sync::atomic::Ordering;
+
+    #[test]
+    fn decodes_queries() {
+        assert_eq!(decode("a%2Cb+c").as_deref(), Some("a,b c"));
+        assert_eq!(decode("%zz"), None);
+        assert_eq!(decode("%+f"), None);
+        assert_eq!(decode("%4"), None);
+    }
+
+    #[test]
+    fn applies_all_or_nothing() {
+        let geiger: Geiger<System> = Geiger::metrics_only(System);
+        let before = geiger.config().to_string();
+        let generation = geiger.config_generation.load(Ordering::Relaxed);
+        assert!(geiger.apply_query("waveform=sine&pitch=nope").is_err());
+        assert!(geiger
+            .apply_query("waveform=sine&flamegraph=%2Ftmp%2Fx")
+            .is_err());
+        assert!(geiger.apply_query("volume=0.25&muted=maybe").is_err());
+        assert!(geiger.apply_query("cap=1").is_err());
+        assert_eq!(geiger.config().to_string(), before);
+        assert_eq!(geiger.volume(), 0.5);
+        // rejected queries don't make the threads look at the config again
+        assert_eq!(geiger.config_generation.load(Ordering::Relaxed), generation);
+
+        geiger.apply_query("waveform=sine&volume=0.25").unwrap();
+        assert!(geiger.config().to_string().contains("waveform sine"));
+        assert_eq!(geiger.volume(), 0.25);
+    }
+}
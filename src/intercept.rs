@@ -0,0 +1,463 @@
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics::{self, Anomaly};
+use crate::event::{AllocEvent, AllocOp, Ops};
+use crate::overhead::{Overhead, Timer};
+use crate::quietly;
+use crate::recent::{RecentEvent, Ring};
+use crate::region::RegionStats;
+use crate::stats::{pools, Epoch, GeigerStats, Stats, ThreadList, ThreadStats};
+use crate::subscribe::{EventReceiver, Subscribers};
+use crate::{budget, count, forbid, region, BUSY};
+use std::alloc::{GlobalAlloc, Layout};
+use std::ptr;
+use std::sync::{PoisonError, RwLock};
+
+/// The allocator interception of a [`Geiger`](crate::Geiger), without any
+/// sound.
+///
+/// This counts allocator activity, with the recursion guard that keeps the
+/// crate's own allocations out of it, and calls the hooks for custom
+/// reactions to each event.  It can be a global allocator by itself.
+///
+/// ```
+/// use alloc_geiger::{AllocEvent, GeigerCore};
+///
+/// #[global_allocator]
+/// static ALLOC: GeigerCore<std::alloc::System> = GeigerCore::new(std::alloc::System);
+///
+/// fn main() {
+///     ALLOC.set_event_hook(Some(|event: &AllocEvent| {
+///         if event.new_size() > 1 << 20 {
+///             eprintln!("large allocation of {} bytes", event.new_size());
+///         }
+///     }));
+///     // ...
+/// }
+/// ```
+///
+/// The `OPS` parameter is the [`Ops::bits`] of the allocator methods to
+/// intercept at all, with the rest passed straight to `inner` without even a
+/// runtime check, for builds where every cycle counts.  Unlike
+/// [`Config::ops`](crate::Config::ops), leaving out `DEALLOC` means the live
+/// bytes and leaks aren't tracked.
+///
+/// ```
+/// use alloc_geiger::{GeigerCore, Ops};
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static ALLOC: GeigerCore<System, { Ops::ALLOC.union(Ops::REALLOC).bits() }> =
+///     GeigerCore::new(System);
+///
+/// fn main() {
+///     let vec = vec![0u8; 100];
+///     drop(vec);
+///     assert_eq!(ALLOC.stats().deallocs, 0);
+/// }
+/// ```
+pub struct GeigerCore<Alloc, const OPS: u8 = { Ops::ALL.bits() }> {
+    inner: Alloc,
+    /// `false` to pass every call straight to `inner`
+    pub(crate) enabled: bool,
+    pub(crate) stats: Stats,
+    threads: ThreadList,
+    epoch: Epoch,
+    recent: Ring,
+    subscribers: Subscribers,
+    timer: Timer,
+    /// called with each event
+    event_hook: RwLock<Option<fn(&AllocEvent)>>,
+    /// called with the layout of each failed allocation
+    failure_hook: RwLock<Option<fn(Layout)>>,
+}
+
+/// What a layer above the `GeigerCore` does with its events.
+pub(crate) trait React {
+    /// Decides whether to let the live bytes grow by `growth`, before the
+    /// inner allocator is called.
+    fn admit(&self, _live_bytes: u64, _growth: usize) -> bool {
+        true
+    }
+
+    /// Decides whether to ignore the current thread's events entirely, within
+    /// the recursion guard.
+    fn quiet(&self) -> bool {
+        false
+    }
+
+    /// Reacts to a counted event, within the recursion guard.
+    fn event(&self, _event: &AllocEvent, _region: Option<&RegionStats>) {}
+
+    /// Reacts to a failed allocation, after the failure hook.
+    fn failure(&self, _layout: Layout) {}
+
+    /// Reacts to the event that exceeded the current thread's byte budget.
+    fn over_budget(&self, _budget: usize) {}
+}
+
+/// No reaction beyond the hooks, for a bare `GeigerCore`.
+impl React for () {}
+
+impl<Alloc: Default, const OPS: u8> Default for GeigerCore<Alloc, OPS> {
+    fn default() -> Self {
+        GeigerCore::new(Alloc::default())
+    }
+}
+
+impl<Alloc, const OPS: u8> GeigerCore<Alloc, OPS> {
+    /// The allocator methods intercepted, from the `OPS` parameter.
+    pub const HOOKED: Ops = Ops::from_bits_truncate(OPS);
+
+    pub const fn new(inner: Alloc) -> Self {
+        GeigerCore {
+            inner,
+            enabled: true,
+            stats: Stats::new(),
+            threads: ThreadList::new(),
+            epoch: Epoch::new(),
+            recent: Ring::new(),
+            subscribers: Subscribers::new(),
+            timer: Timer::new(),
+            event_hook: RwLock::new(None),
+            failure_hook: RwLock::new(None),
+        }
+    }
+
+    /// Creates a `GeigerCore` that's only active in builds with
+    /// `debug_assertions`, and otherwise just passes every call to the inner
+    /// allocator.
+    pub const fn debug_only(inner: Alloc) -> Self {
+        let mut core = GeigerCore::new(inner);
+        core.enabled = cfg!(debug_assertions);
+        core
+    }
+
+    /// Returns a reference to the inner allocator.
+    pub fn inner(&self) -> &Alloc {
+        &self.inner
+    }
+
+    /// Returns the inner allocator, discarding the counts.
+    pub fn into_inner(self) -> Alloc {
+        self.inner
+    }
+
+    /// Returns a snapshot of the allocator activity counted so far.
+    ///
+    /// The crate's own allocations are not included.
+    pub fn stats(&self) -> GeigerStats {
+        self.stats.snapshot()
+    }
+
+    /// Returns a snapshot of the activity counted so far on each thread that
+    /// had any, in order of their first events.
+    ///
+    /// Threads that have exited are still included, and the crate's own
+    /// allocations are not.
+    pub fn stats_per_thread(&self) -> Vec<ThreadStats> {
+        quietly(|| self.threads.snapshot())
+    }
+
+    /// Returns a snapshot of the activity counted so far on each pool of
+    /// threads, in order of their first events, with the other threads each
+    /// on their own, so dozens of workers add up to one line.
+    ///
+    /// The pools are found by the naming conventions of thread pools: the
+    /// common name of `tokio` workers, or the name of numbered workers, like
+    /// `rayon` for `rayon-0` through `rayon-31` as named with `rayon`'s
+    /// `ThreadPoolBuilder::thread_name`.  Other threads are listed by their
+    /// name, or as `thread` and their index.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::GeigerCore<std::alloc::System> =
+    ///     alloc_geiger::GeigerCore::new(std::alloc::System);
+    ///
+    /// fn main() {
+    ///     let workers: Vec<_> = (0..4)
+    ///         .map(|i| {
+    ///             let worker = std::thread::Builder::new().name(format!("worker-{i}"));
+    ///             worker.spawn(|| vec![0u8; 100]).unwrap()
+    ///         })
+    ///         .collect();
+    ///     workers.into_iter().for_each(|worker| drop(worker.join()));
+    ///     let pools = ALLOC.stats_per_pool();
+    ///     let (_, workers) = pools.iter().find(|(name, _)| name == "worker").unwrap();
+    ///     assert!(workers.allocs >= 4);
+    /// }
+    /// ```
+    pub fn stats_per_pool(&self) -> Vec<(String, GeigerStats)> {
+        quietly(|| pools(self.threads.snapshot()))
+    }
+
+    /// Returns the reentrancy anomalies seen so far in the process, which the
+    /// recursion guard otherwise hides, as the errors of a bad interaction
+    /// with another instrumenting allocator or a hook.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let _vec = vec![0u8; 100];
+    ///     for anomaly in ALLOC.core().anomalies() {
+    ///         eprintln!("alloc_geiger: {anomaly}");
+    ///     }
+    /// }
+    /// ```
+    #[cfg(feature = "diagnostics")]
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        quietly(diagnostics::anomalies)
+    }
+
+    /// Returns the activity since the previous call, or since the start, and
+    /// starts a new epoch.
+    ///
+    /// The counters aren't reset, so [`stats`](Self::stats) still covers
+    /// everything, but concurrent callers each get a distinct span.
+    pub fn epoch(&self) -> GeigerStats {
+        self.epoch.advance(self.stats.snapshot())
+    }
+
+    /// Returns the last 64 allocator events, oldest first, as when breaking
+    /// into a debugger after a burst.
+    ///
+    /// The crate's own allocations are not included.
+    pub fn recent_events(&self) -> Vec<RecentEvent> {
+        quietly(|| self.recent.snapshot())
+    }
+
+    /// Subscribes to a live stream of allocator events, for another thread to
+    /// receive as they happen.
+    ///
+    /// The crate's own allocations are not included.
+    pub fn subscribe(&self) -> EventReceiver {
+        quietly(|| self.subscribers.subscribe(Vec::new()))
+    }
+
+    /// Subscribes to the events in regions with these names, or all events
+    /// if there are none, as for the trace recorders.
+    pub(crate) fn subscribe_regions(&self, regions: &[String]) -> EventReceiver {
+        quietly(|| self.subscribers.subscribe(regions.to_vec()))
+    }
+
+    /// Returns an estimate of the time spent counting allocator events and
+    /// reacting to them, to judge how much that perturbs the program.
+    ///
+    /// This is a rough estimate, scaled up from the time of a sample of the
+    /// events, and it doesn't include the cost of the inner allocator.
+    pub fn overhead(&self) -> Overhead {
+        self.timer.snapshot()
+    }
+
+    /// Sets a function to call with each allocator event, or `None` to clear
+    /// it.
+    ///
+    /// The hook's own allocations are not counted, and it must not unwind.
+    pub fn set_event_hook(&self, hook: Option<fn(&AllocEvent)>) {
+        *self
+            .event_hook
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = hook;
+    }
+
+    /// Sets a function to call with the `Layout` of each allocation that the
+    /// inner allocator fails, or `None` to clear it.
+    ///
+    /// The hook's own allocations are not counted, and it must not unwind.
+    pub fn set_failure_hook(&self, hook: Option<fn(Layout)>) {
+        *self
+            .failure_hook
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = hook;
+    }
+
+    /// Counts an allocator event and reacts to it, unless this thread is
+    /// already busy with our own activity.
+    fn intercept(&self, event: AllocEvent, react: &impl React) {
+        if !self.enabled {
+            return;
+        }
+        BUSY.with(|busy| {
+            if !busy.replace(true) {
+                #[cfg(feature = "diagnostics")]
+                let _busy = diagnostics::busy();
+                self.record(&event, react);
+                busy.set(false);
+            } else {
+                #[cfg(feature = "diagnostics")]
+                diagnostics::reentered();
+            }
+        });
+    }
+
+    /// Counts an allocator event and reacts to it, with this thread already
+    /// busy, or does nothing with the `off` feature.
+    pub(crate) fn record(&self, event: &AllocEvent, react: &impl React) {
+        if cfg!(feature = "off") || react.quiet() {
+            return;
+        }
+        let start = self.timer.start();
+        self.stats.count(event);
+        self.threads.count(event);
+        self.recent.record(event);
+        let region = region::count(event);
+        self.subscribers.send(event, region);
+        count::add(event);
+        forbid::check(event);
+        let over_budget = budget::check(event);
+        let hook = *self
+            .event_hook
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(hook) = hook {
+            hook(event);
+        }
+        react.event(event, region);
+        if let Some(budget) = over_budget {
+            react.over_budget(budget);
+        }
+        self.timer.finish(start);
+    }
+
+    /// Asks the reaction whether to allow an allocation, which is always
+    /// allowed for our own activity.
+    #[inline]
+    pub(crate) fn admit(&self, growth: usize, react: &impl React) -> bool {
+        !self.enabled
+            || BUSY.with(|busy| busy.get() || react.admit(self.stats.live_bytes(), growth))
+    }
+
+    /// Reacts if the inner allocator failed, passing its result through.
+    #[inline]
+    fn check_failure(&self, ptr: *mut u8, layout: Layout, react: &impl React) -> *mut u8 {
+        if ptr.is_null() {
+            self.failure(layout, react);
+        }
+        ptr
+    }
+
+    /// Calls the hook and reacts to a failed allocation, unless this thread
+    /// is already busy with our own activity.
+    #[cold]
+    fn failure(&self, layout: Layout, react: &impl React) {
+        if !self.enabled {
+            return;
+        }
+        BUSY.with(|busy| {
+            if !busy.replace(true) {
+                self.record_failure(layout, react);
+                busy.set(false);
+            }
+        });
+    }
+
+    /// Calls the hook and reacts to a failed allocation, with this thread
+    /// already busy, or does nothing with the `off` feature.
+    pub(crate) fn record_failure(&self, layout: Layout, react: &impl React) {
+        if cfg!(feature = "off") {
+            return;
+        }
+        let hook = *self
+            .failure_hook
+            .read()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(hook) = hook {
+            hook(layout);
+        }
+        react.failure(layout);
+    }
+}
+
+/// The allocator methods, with a reaction from the layer above, which pass
+/// each call straight to the inner allocator with the `off` feature.
+impl<Alloc: GlobalAlloc, const OPS: u8> GeigerCore<Alloc, OPS> {
+    #[inline]
+    pub(crate) unsafe fn alloc_with(&self, layout: Layout, react: &impl React) -> *mut u8 {
+        if cfg!(feature = "off") || !Self::HOOKED.contains(Ops::ALLOC) {
+            return self.inner.alloc(layout);
+        }
+        if !self.admit(layout.size(), react) {
+            return self.check_failure(ptr::null_mut(), layout, react);
+        }
+        let ptr = inner(|| self.inner.alloc(layout));
+        self.intercept(AllocEvent::new(AllocOp::Alloc, layout, ptr), react);
+        self.check_failure(ptr, layout, react)
+    }
+
+    #[inline]
+    pub(crate) unsafe fn alloc_zeroed_with(&self, layout: Layout, react: &impl React) -> *mut u8 {
+        if cfg!(feature = "off") || !Self::HOOKED.contains(Ops::ALLOC_ZEROED) {
+            return self.inner.alloc_zeroed(layout);
+        }
+        if !self.admit(layout.size(), react) {
+            return self.check_failure(ptr::null_mut(), layout, react);
+        }
+        let ptr = inner(|| self.inner.alloc_zeroed(layout));
+        self.intercept(AllocEvent::new(AllocOp::AllocZeroed, layout, ptr), react);
+        self.check_failure(ptr, layout, react)
+    }
+
+    #[inline]
+    pub(crate) unsafe fn dealloc_with(&self, ptr: *mut u8, layout: Layout, react: &impl React) {
+        if cfg!(feature = "off") || !Self::HOOKED.contains(Ops::DEALLOC) {
+            return self.inner.dealloc(ptr, layout);
+        }
+        self.intercept(AllocEvent::new(AllocOp::Dealloc, layout, ptr), react);
+        inner(|| self.inner.dealloc(ptr, layout))
+    }
+
+    #[inline]
+    pub(crate) unsafe fn realloc_with(
+        &self,
+        ptr: *mut u8,
+        layout: Layout,
+        new_size: usize,
+        react: &impl React,
+    ) -> *mut u8 {
+        if cfg!(feature = "off") || !Self::HOOKED.contains(Ops::REALLOC) {
+            return self.inner.realloc(ptr, layout, new_size);
+        }
+        // SAFETY: the caller guarantees that `new_size` is valid with this
+        // alignment.
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        if !self.admit(new_size.saturating_sub(layout.size()), react) {
+            // the original allocation is left as it was
+            return self.check_failure(ptr::null_mut(), new_layout, react);
+        }
+        let new_ptr = inner(|| self.inner.realloc(ptr, layout, new_size));
+        self.intercept(AllocEvent::realloc(layout, new_size, ptr, new_ptr), react);
+        self.check_failure(new_ptr, new_layout, react)
+    }
+}
+
+/// Calls the inner allocator, which aborts if it keeps reentering the global
+/// allocator with the `diagnostics` feature.
+#[inline(always)]
+fn inner<R>(call: impl FnOnce() -> R) -> R {
+    #[cfg(feature = "diagnostics")]
+    let _depth = diagnostics::enter_inner();
+    call()
+}
+
+unsafe impl<Alloc: GlobalAlloc, const OPS: u8> GlobalAlloc for GeigerCore<Alloc, OPS> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_with(layout, &())
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.alloc_zeroed_with(layout, &())
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.dealloc_with(ptr, layout, &())
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.realloc_with(ptr, layout, new_size, &())
+    }
+}
@@ -0,0 +1,115 @@
+//! A background texture for jemalloc's fragmentation, enabled by the
+//! `jemalloc` feature.
+
+use crate::output::Cue;
+use crate::pulse::Pulse;
+use crate::{Geiger, BUSY};
+use std::ffi::{c_char, c_void};
+use std::io;
+use std::mem;
+use std::ptr;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The fragmentation, as resident bytes over active bytes minus one, below
+/// which the texture is silent, and at which it's loudest.
+const QUIET: f64 = 0.05;
+const LOUDEST: f64 = 1.0;
+
+/// Reads a `size_t` statistic by name, after refreshing the cached stats.
+fn read_stat(name: &[u8]) -> io::Result<usize> {
+    let mut value: usize = 0;
+    let mut len = mem::size_of::<usize>();
+    // SAFETY: the name is a terminated string of a `size_t` control, so the
+    // value and its length match.
+    let result = unsafe {
+        jemalloc_sys::mallctl(
+            name.as_ptr().cast::<c_char>(),
+            (&mut value as *mut usize).cast::<c_void>(),
+            &mut len,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    match result {
+        0 => Ok(value),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Refreshes jemalloc's cached stats.
+fn advance_epoch() -> io::Result<()> {
+    let mut epoch: u64 = 1;
+    // SAFETY: `epoch` is a `uint64_t` control.
+    let result = unsafe {
+        jemalloc_sys::mallctl(
+            b"epoch\0".as_ptr().cast::<c_char>(),
+            ptr::null_mut(),
+            ptr::null_mut(),
+            (&mut epoch as *mut u64).cast::<c_void>(),
+            mem::size_of::<u64>(),
+        )
+    };
+    match result {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// How fragmented jemalloc's heap is, as resident bytes over active bytes
+/// minus one.
+fn fragmentation() -> io::Result<f64> {
+    advance_epoch()?;
+    let resident = read_stat(b"stats.resident\0")?;
+    let active = read_stat(b"stats.active\0")?;
+    Ok(resident as f64 / active.max(1) as f64 - 1.0)
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread sonifying jemalloc's fragmentation as a background
+    /// hiss, separately from the allocation events, when the inner allocator
+    /// is jemalloc, like `jemallocator::Jemalloc`.
+    ///
+    /// At each `interval`, this reads jemalloc's resident and active bytes,
+    /// and plays band-limited noise as loud as the resident pages exceed the
+    /// active ones: silent under 5% more, and loudest at twice as many.  Each
+    /// burst of noise lasts two intervals, overlapping the next, so steady
+    /// fragmentation is a steady texture.  This returns an error if jemalloc
+    /// wasn't built with statistics.
+    ///
+    /// ```no_run
+    /// use alloc_geiger::Geiger;
+    /// use jemallocator::Jemalloc;
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: Geiger<Jemalloc> = Geiger::new(Jemalloc);
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_jemalloc_texture(Duration::from_millis(250)).unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_jemalloc_texture(&'static self, interval: Duration) -> io::Result<JoinHandle<()>> {
+        fragmentation()?;
+        thread::Builder::new()
+            .name("alloc_geiger-jemalloc".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                loop {
+                    thread::sleep(interval);
+                    let Ok(fragmentation) = fragmentation() else {
+                        return;
+                    };
+                    if fragmentation < QUIET || self.is_muted() || self.is_paused() {
+                        continue;
+                    }
+                    let level = ((fragmentation - QUIET) / (LOUDEST - QUIET)).min(1.0) as f32;
+                    self.play(Cue::Tone, |sample_rate| {
+                        let peak = self.volume() * level;
+                        Some(Pulse::texture(sample_rate, peak, interval * 2))
+                    });
+                }
+            })
+    }
+}
@@ -0,0 +1,130 @@
+//! Single-character commands from stdin or a named pipe, enabled by the
+//! `keys` feature.
+
+use crate::{Geiger, BUSY};
+use std::io::{self, BufReader, Read};
+use std::thread::{self, JoinHandle};
+
+/// How much `+` and `-` change the volume.
+const VOLUME_STEP: f32 = 0.1;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread reading single-character commands from stdin, for
+    /// interactive control in a terminal session.
+    ///
+    /// The following commands are available, and any other characters, like
+    /// the newlines, are ignored:
+    ///
+    /// - `m` and `u` mute and unmute the sound.
+    /// - `s` writes the current [`GeigerStats`](crate::GeigerStats) to
+    ///   stderr.
+    /// - `+` and `-` turn the volume up and down by a tenth.
+    ///
+    /// A terminal sends its input a line at a time, so each command takes
+    /// effect when Enter is pressed.  Only use this when the program doesn't
+    /// read stdin itself, which would compete for its input.  The thread's
+    /// own allocations are not counted or sonified, and it ends when stdin
+    /// is closed.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_stdin_control().unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_stdin_control(&'static self) -> io::Result<JoinHandle<()>> {
+        thread::Builder::new()
+            .name("alloc_geiger-keys".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                // locked only for each read, so any reads of the program take
+                // turns with these instead of waiting for stdin to close
+                self.read_keys(io::stdin());
+            })
+    }
+
+    /// Spawns a thread reading the single-character commands of
+    /// [`spawn_stdin_control`](Self::spawn_stdin_control) from a named pipe
+    /// on Unix, made beforehand with `mkfifo`, for control from another
+    /// terminal without taking over the program's stdin.
+    ///
+    /// The pipe is opened again after each writer closes it, so commands can
+    /// be sent any number of times, as with `echo m > pipe`.  Any other kind
+    /// of file is read once.  The thread's own allocations are not counted or
+    /// sonified.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     // e.g. mkfifo /tmp/geiger && echo s > /tmp/geiger
+    ///     ALLOC.spawn_pipe_control("/tmp/geiger").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    #[cfg(unix)]
+    pub fn spawn_pipe_control(
+        &'static self,
+        path: impl AsRef<std::path::Path>,
+    ) -> io::Result<JoinHandle<()>> {
+        use std::fs::{self, File};
+        use std::os::unix::fs::FileTypeExt;
+
+        let path = path.as_ref().to_owned();
+        let fifo = fs::metadata(&path)?.file_type().is_fifo();
+        thread::Builder::new()
+            .name("alloc_geiger-keys".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                // opening a pipe waits for a writer
+                while let Ok(file) = File::open(&path) {
+                    self.read_keys(file);
+                    if !fifo {
+                        break;
+                    }
+                }
+            })
+    }
+
+    /// Runs the commands read from `input` until it ends.
+    fn read_keys(&self, input: impl Read) {
+        for key in BufReader::new(input).bytes() {
+            let Ok(key) = key else {
+                return;
+            };
+            match key {
+                b'm' => self.mute(),
+                b'u' => self.unmute(),
+                b's' => eprint!("{}", self.stats()),
+                b'+' => self.set_volume(self.volume() + VOLUME_STEP),
+                b'-' => self.set_volume(self.volume() - VOLUME_STEP),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    #[test]
+    fn runs_commands() {
+        let geiger: Geiger<System> = Geiger::metrics_only(System);
+        geiger.read_keys(&b"m+s"[..]);
+        assert!(geiger.is_muted());
+        assert!((geiger.volume() - 0.6).abs() < 1e-6);
+        geiger.read_keys(&b"-u\n"[..]);
+        assert!(!geiger.is_muted());
+        assert!((geiger.volume() - 0.5).abs() < 1e-6);
+
+        geiger.read_keys(&b"m+s-u"[..]);
+        assert!(!geiger.is_muted());
+        assert!((geiger.volume() - 0.5).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,485 @@
+//! Stackable layers of instrumentation around an inner allocator.
+
+use crate::event::{AllocEvent, AllocOp};
+use crate::intercept::GeigerCore;
+use crate::subscribe::{EventReceiver, Subscribers};
+use crate::trace::spawn_recorder;
+use crate::{quietly, Geiger, BUSY};
+use std::alloc::{GlobalAlloc, Layout};
+use std::io;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread::JoinHandle;
+
+/// Instrumentation that reacts to the events of the allocator below it,
+/// stacked into a global allocator with [`Layer`].
+///
+/// Each layer only sees the calls that reach it, so the cost is only that
+/// of the layers in the stack.  Every method is called within the recursion
+/// guard, so the allocations of any layer are not seen by the others, nor
+/// by itself.  A [`GeigerCore`] is a layer that counts the stats, a
+/// [`Geiger`] one that also makes sound, and the crate has [`CapLayer`] and
+/// [`TraceLayer`], while third parties can publish their own.
+///
+/// ```
+/// use alloc_geiger::{AllocEvent, GeigerLayer, Layer};
+/// use std::alloc::System;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// /// Counts the allocations of at least a page.
+/// struct Pages(AtomicUsize);
+///
+/// impl GeigerLayer for Pages {
+///     fn on_event(&self, event: &AllocEvent) {
+///         if event.new_size() >= 4096 {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// #[global_allocator]
+/// static ALLOC: Layer<Pages, System> = Layer::new(Pages(AtomicUsize::new(0)), System);
+///
+/// fn main() {
+///     let _page = vec![0u8; 4096];
+///     assert!(ALLOC.layer().0.load(Ordering::Relaxed) > 0);
+/// }
+/// ```
+pub trait GeigerLayer: Sync {
+    /// Decides whether to let an allocation or reallocation grow the live
+    /// bytes by `growth`, before the allocators below are called.  Refused
+    /// calls fail, and are passed to [`on_failure`](Self::on_failure).
+    fn allow(&self, _growth: usize) -> bool {
+        true
+    }
+
+    /// Gives back the `growth` of an allowed call that then failed in the
+    /// allocators below, like one refused by an inner layer, before its
+    /// event.
+    fn release(&self, _growth: usize) {}
+
+    /// Reacts to an event, after the allocators below returned.  Events of
+    /// failed calls have an [`address`](AllocEvent::address) of `0`.
+    fn on_event(&self, _event: &AllocEvent) {}
+
+    /// Reacts to a failed allocation, after its event.
+    fn on_failure(&self, _layout: Layout) {}
+}
+
+/// A [`GeigerLayer`] around an inner allocator, which is a global allocator
+/// itself, and can be the inner allocator of another layer.
+///
+/// The outermost layer allows an allocation first, and reacts to it last.
+/// Any order is possible, like a [`CapLayer`] outside a sound layer, which
+/// keeps refused allocations out of the sound, or inside it, which plays
+/// them as failures.
+///
+/// ```
+/// use alloc_geiger::{CapLayer, Layer, StatsLayer, STATS_LAYER};
+/// use std::alloc::System;
+///
+/// #[global_allocator]
+/// static ALLOC: Layer<StatsLayer, Layer<CapLayer, System>> =
+///     Layer::new(STATS_LAYER, Layer::new(CapLayer::new(1 << 30), System));
+///
+/// fn main() {
+///     let _vec = vec![0u8; 100];
+///     assert!(ALLOC.layer().stats().allocs > 0);
+///     assert!(ALLOC.inner().layer().live_bytes() >= 100);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct Layer<L, A> {
+    layer: L,
+    inner: A,
+}
+
+impl<L, A> Layer<L, A> {
+    /// Stacks `layer` on top of the `inner` allocator.
+    pub const fn new(layer: L, inner: A) -> Self {
+        Layer { layer, inner }
+    }
+
+    /// Returns a reference to this layer.
+    pub fn layer(&self) -> &L {
+        &self.layer
+    }
+
+    /// Returns a reference to the inner allocator, which may be another
+    /// layer.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Returns the layer and the inner allocator.
+    pub fn into_parts(self) -> (L, A) {
+        (self.layer, self.inner)
+    }
+}
+
+impl<L: GeigerLayer, A> Layer<L, A> {
+    /// Asks the layer whether to allow an allocation, which is always
+    /// allowed for our own activity.
+    #[inline]
+    fn allow(&self, growth: usize) -> bool {
+        cfg!(feature = "off")
+            || BUSY.with(|busy| busy.get())
+            || quietly(|| self.layer.allow(growth))
+    }
+
+    /// Gives back the growth of an allowed call that failed, unless it was
+    /// allowed as our own activity.
+    #[inline]
+    fn release(&self, growth: usize) {
+        if !cfg!(feature = "off") && !BUSY.with(|busy| busy.get()) {
+            quietly(|| self.layer.release(growth));
+        }
+    }
+
+    /// Passes an event to the layer, and the failure if it failed, unless
+    /// this thread is already busy with our own activity.
+    #[inline]
+    fn react(&self, event: AllocEvent, layout: Layout) {
+        if cfg!(feature = "off") {
+            return;
+        }
+        BUSY.with(|busy| {
+            if !busy.replace(true) {
+                self.layer.on_event(&event);
+                if event.address == 0 {
+                    self.layer.on_failure(layout);
+                }
+                busy.set(false);
+            }
+        });
+    }
+}
+
+unsafe impl<L: GeigerLayer, A: GlobalAlloc> GlobalAlloc for Layer<L, A> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let allowed = self.allow(layout.size());
+        let ptr = match allowed {
+            true => self.inner.alloc(layout),
+            false => ptr::null_mut(),
+        };
+        if allowed && ptr.is_null() {
+            self.release(layout.size());
+        }
+        self.react(AllocEvent::new(AllocOp::Alloc, layout, ptr), layout);
+        ptr
+    }
+
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let allowed = self.allow(layout.size());
+        let ptr = match allowed {
+            true => self.inner.alloc_zeroed(layout),
+            false => ptr::null_mut(),
+        };
+        if allowed && ptr.is_null() {
+            self.release(layout.size());
+        }
+        self.react(AllocEvent::new(AllocOp::AllocZeroed, layout, ptr), layout);
+        ptr
+    }
+
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.react(AllocEvent::new(AllocOp::Dealloc, layout, ptr), layout);
+        self.inner.dealloc(ptr, layout)
+    }
+
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        // SAFETY: the caller guarantees that `new_size` is valid with this
+        // alignment.
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        // the original allocation is left as it was if this is refused
+        let growth = new_size.saturating_sub(layout.size());
+        let allowed = self.allow(growth);
+        let new_ptr = match allowed {
+            true => self.inner.realloc(ptr, layout, new_size),
+            false => ptr::null_mut(),
+        };
+        if allowed && new_ptr.is_null() {
+            self.release(growth);
+        }
+        self.react(
+            AllocEvent::realloc(layout, new_size, ptr, new_ptr),
+            new_layout,
+        );
+        new_ptr
+    }
+}
+
+/// Counts the stats, as a layer, with the hooks and the rest of the core.
+impl<Alloc: Sync, const OPS: u8> GeigerLayer for GeigerCore<Alloc, OPS> {
+    fn on_event(&self, event: &AllocEvent) {
+        if self.enabled && Self::HOOKED.contains(event.op.into()) {
+            self.record(event, &());
+        }
+    }
+
+    fn on_failure(&self, layout: Layout) {
+        if self.enabled {
+            self.record_failure(layout, &());
+        }
+    }
+}
+
+/// Makes sound, as a layer, counting the stats it needs along the way.
+impl<Alloc: Sync, const OPS: u8> GeigerLayer for Geiger<Alloc, OPS> {
+    fn allow(&self, growth: usize) -> bool {
+        // our own activity is allowed, even when this is called directly
+        self.core.admit(growth, self)
+    }
+
+    fn on_event(&self, event: &AllocEvent) {
+        if self.core.enabled && GeigerCore::<Alloc, OPS>::HOOKED.contains(event.op.into()) {
+            self.core.record(event, self);
+        }
+    }
+
+    fn on_failure(&self, layout: Layout) {
+        if self.core.enabled {
+            self.core.record_failure(layout, self);
+        }
+    }
+}
+
+/// A [`GeigerCore`] as a layer, counting the stats.
+pub type StatsLayer = GeigerCore<()>;
+
+/// A new [`StatsLayer`] with nothing counted yet, to stack in a static
+/// [`Layer`].
+#[allow(clippy::declare_interior_mutable_const)]
+pub const STATS_LAYER: StatsLayer = GeigerCore::new(());
+
+/// A [`Geiger`] as a layer, making sound with the stats it needs.
+pub type SoundLayer = Geiger<()>;
+
+/// A new [`SoundLayer`] with the default settings, to stack in a static
+/// [`Layer`].
+#[allow(clippy::declare_interior_mutable_const)]
+pub const SOUND_LAYER: SoundLayer = Geiger::new(());
+
+/// A layer refusing to let the live bytes grow past a cap, as counted by
+/// this layer, like a container's memory limit.
+///
+/// The growth of each call is reserved as it's allowed, so concurrent calls
+/// can't pass the cap together, and given back if the call fails below.
+/// Unlike [`Config::cap`](crate::Config::cap), this holds whether or not
+/// there's a sound layer, and doesn't warn as the cap nears.
+#[derive(Debug)]
+pub struct CapLayer {
+    cap: u64,
+    live: AtomicU64,
+}
+
+impl CapLayer {
+    /// Creates a layer capping the live bytes at `cap`.
+    pub const fn new(cap: usize) -> Self {
+        CapLayer {
+            cap: cap as u64,
+            live: AtomicU64::new(0),
+        }
+    }
+
+    /// Returns the bytes allocated through this layer and not yet freed,
+    /// including those of calls still in progress.
+    pub fn live_bytes(&self) -> u64 {
+        self.live.load(Ordering::Relaxed)
+    }
+}
+
+impl GeigerLayer for CapLayer {
+    fn allow(&self, growth: usize) -> bool {
+        self.live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                live.checked_add(growth as u64)
+                    .filter(|&live| live <= self.cap)
+            })
+            .is_ok()
+    }
+
+    fn release(&self, growth: usize) {
+        self.sub(growth as u64);
+    }
+
+    fn on_event(&self, event: &AllocEvent) {
+        if event.address == 0 {
+            return;
+        }
+        // growth was already added by `allow`
+        let old = event.layout.size() as u64;
+        let new = event.new_size as u64;
+        match event.op {
+            AllocOp::Dealloc => self.sub(old),
+            AllocOp::Realloc if new < old => self.sub(old - new),
+            _ => {}
+        }
+    }
+}
+
+impl CapLayer {
+    /// Subtracts freed bytes, saturating at zero, since the blocks allocated
+    /// by our own activity were never added, but may be freed outside of it,
+    /// like the `Vec` of [`Geiger::recent_events`].
+    fn sub(&self, bytes: u64) {
+        let _ = self
+            .live
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |live| {
+                Some(live.saturating_sub(bytes))
+            });
+    }
+}
+
+/// A layer passing its events to subscribers, like a trace recorder, without
+/// counting anything else.
+pub struct TraceLayer {
+    subscribers: Subscribers,
+}
+
+impl Default for TraceLayer {
+    fn default() -> Self {
+        TraceLayer::new()
+    }
+}
+
+impl TraceLayer {
+    /// Creates a layer with no subscribers yet.
+    pub const fn new() -> Self {
+        TraceLayer {
+            subscribers: Subscribers::new(),
+        }
+    }
+
+    /// Subscribes to a live stream of this layer's events, as with
+    /// [`GeigerCore::subscribe`].
+    pub fn subscribe(&self) -> EventReceiver {
+        quietly(|| self.subscribers.subscribe(Vec::new()))
+    }
+
+    /// Spawns a thread recording this layer's events to a trace file, in the
+    /// format of [`Geiger::spawn_trace_recorder`].
+    ///
+    /// ```no_run
+    /// use alloc_geiger::{Layer, TraceLayer};
+    /// use std::alloc::System;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: Layer<TraceLayer, System> = Layer::new(TraceLayer::new(), System);
+    ///
+    /// fn main() {
+    ///     ALLOC.layer().spawn_recorder("alloc.trace").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_recorder<P: AsRef<Path>>(&'static self, path: P) -> io::Result<JoinHandle<()>> {
+        spawn_recorder(path.as_ref(), || self.subscribe())
+    }
+}
+
+impl GeigerLayer for TraceLayer {
+    fn on_event(&self, event: &AllocEvent) {
+        self.subscribers.send(event, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::System;
+
+    #[test]
+    fn frees_of_uncounted_blocks_saturate() {
+        let alloc = Layer::new(STATS_LAYER, Layer::new(CapLayer::new(1 << 20), System));
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let counted = alloc.alloc(layout);
+            assert_eq!(alloc.inner().layer().live_bytes(), 100);
+            // like the snapshot of `recent_events`, dropped by the caller
+            let quiet = quietly(|| alloc.alloc(layout));
+            assert_eq!(alloc.inner().layer().live_bytes(), 100);
+            alloc.dealloc(quiet, layout);
+            alloc.dealloc(counted, layout);
+            assert_eq!(alloc.inner().layer().live_bytes(), 0);
+
+            let quiet = quietly(|| alloc.alloc(layout));
+            let shrunk = alloc.realloc(quiet, layout, 10);
+            assert_eq!(alloc.inner().layer().live_bytes(), 0);
+            alloc.dealloc(shrunk, Layout::from_size_align(10, 8).unwrap());
+
+            for _ in 0..100 {
+                let ptr = alloc.alloc(layout);
+                assert!(!ptr.is_null());
+                alloc.dealloc(ptr, layout);
+            }
+            assert_eq!(alloc.inner().layer().live_bytes(), 0);
+            assert_eq!(alloc.layer().stats().allocs, 101);
+        }
+    }
+
+    #[test]
+    fn refuses_past_the_cap() {
+        let alloc = Layer::new(CapLayer::new(150), System);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            assert!(!ptr.is_null());
+            assert!(alloc.alloc(layout).is_null());
+            assert!(alloc.realloc(ptr, layout, 200).is_null());
+            let ptr = alloc.realloc(ptr, layout, 150);
+            assert!(!ptr.is_null());
+            assert_eq!(alloc.layer().live_bytes(), 150);
+            alloc.dealloc(ptr, Layout::from_size_align(150, 8).unwrap());
+            assert_eq!(alloc.layer().live_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn gives_back_growth_refused_below() {
+        let alloc = Layer::new(CapLayer::new(1000), Layer::new(CapLayer::new(150), System));
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        unsafe {
+            let ptr = alloc.alloc(layout);
+            assert!(alloc.alloc(layout).is_null());
+            assert!(alloc.alloc_zeroed(layout).is_null());
+            assert!(alloc.realloc(ptr, layout, 200).is_null());
+            assert_eq!(alloc.layer().live_bytes(), 100);
+            assert_eq!(alloc.inner().layer().live_bytes(), 100);
+            alloc.dealloc(ptr, layout);
+            assert_eq!(alloc.layer().live_bytes(), 0);
+        }
+    }
+
+    #[test]
+    fn concurrent_allocations_share_the_cap() {
+        const THREADS: usize = 8;
+        let alloc = Layer::new(CapLayer::new(1000), System);
+        let layout = Layout::from_size_align(100, 8).unwrap();
+        let barrier = std::sync::Barrier::new(THREADS);
+        let held: Vec<Vec<usize>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = (0..THREADS)
+                .map(|_| {
+                    scope.spawn(|| {
+                        barrier.wait();
+                        (0..10)
+                            .map(|_| unsafe { alloc.alloc(layout) } as usize)
+                            .filter(|&ptr| ptr != 0)
+                            .collect()
+                    })
+                })
+                .collect();
+            threads.into_iter().map(|t| t.join().unwrap()).collect()
+        });
+        assert_eq!(held.iter().map(Vec::len).sum::<usize>(), 10);
+        assert_eq!(alloc.layer().live_bytes(), 1000);
+        for ptr in held.into_iter().flatten() {
+            unsafe { alloc.dealloc(ptr as *mut u8, layout) };
+        }
+        assert_eq!(alloc.layer().live_bytes(), 0);
+    }
+}
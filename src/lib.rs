@@ -1,7 +1,10 @@
 //! A Rust allocator which makes sound when active, like a Geiger counter.
 //!
 //! The [`rodio`] crate is used to emit [sinc] pulses each time the allocator is
-//! used, excluding its own allocator activity.
+//! used, excluding its own allocator activity.  Zeroed allocations play with a
+//! softer attack, so `calloc`-heavy code is distinguishable by ear.
+//! An allocation that fails plays a long, falling alarm tone, and may call a
+//! hook set with `Geiger::set_failure_hook`.
 //!
 //! Inspired by [Malloc Geiger].
 //!
@@ -29,7 +32,20 @@
 //! }
 //! ```
 //!
-//! Alternatives like [`jemallocator`] may also be used:
+//! The `const SYSTEM_DEBUG` is the same, but only active in builds with
+//! `debug_assertions`, so it can stay in place for release builds:
+//!
+//! ```rust
+//! #[global_allocator]
+//! static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM_DEBUG;
+//!
+//! fn main() {
+//!     // ...
+//! }
+//! ```
+//!
+//! Alternatives like [`jemallocator`] may also be used, which the `jemalloc`
+//! and `mimalloc` features make ready as `JEMALLOC` and `MIMALLOC`:
 //!
 //! ```rust
 //! use alloc_geiger::Geiger;
@@ -43,208 +59,1883 @@
 //! }
 //! ```
 //!
+//! For custom reactions without any sound, [`GeigerCore`] is just the
+//! interception and counting beneath `Geiger`, with a hook for each
+//! [`AllocEvent`].  Both are also a [`GeigerLayer`], which a [`Layer`]
+//! stacks around an inner allocator with others, like a [`CapLayer`] and a
+//! [`TraceLayer`], to pay only for the instrumentation in use.
+//!
+//!
+//! ## Configuration
+//!
+//! The sound can be adjusted with a [`Config`] passed to
+//! [`Geiger::with_config`], or with environment variables that are read when
+//! the sound is first initialized:
+//!
+//! - `ALLOC_GEIGER_PROFILE`: a preset of the sound settings, one of `classic`
+//!   (the default), `geiger`, `musical`, or `minimal`, which the other
+//!   variables can still adjust.
+//! - `ALLOC_GEIGER_WAVEFORM`: one of `sinc` (the default), `sine`, `square`,
+//!   `noise`, or `authentic` for the crackle of a real Geiger counter.
+//! - `ALLOC_GEIGER_DURATION`: the length of each pulse in milliseconds.
+//! - `ALLOC_GEIGER_ATTACK` and `ALLOC_GEIGER_DECAY`: the [`Envelope`] of each
+//!   pulse in milliseconds.
+//! - `ALLOC_GEIGER_SAMPLE_RATE`: the rate in Hz for synthesizing pulses, which
+//!   otherwise matches the output device.
+//! - `ALLOC_GEIGER_PAN`: `center` (the default), `ops` to play allocations on
+//!   the left and deallocations on the right, `address` to place each event
+//!   by its address within the heap range seen so far, or `thread` to spread
+//!   the threads across the stereo field.
+//! - `ALLOC_GEIGER_PITCH`: `fixed` (the default), `size` to lower the pitch of
+//!   larger allocations, `thread` to give each thread its own pitch, `cpu` to
+//!   give each CPU core its own pitch, `class` to give each power-of-two size
+//!   class its own note, or `lifetime` to lower the pitch of deallocations of
+//!   older blocks.
+//! - `ALLOC_GEIGER_SCALE`: `continuous` (the default), `pentatonic`, or
+//!   `major`, to round varied pitches to a musical scale.
+//! - `ALLOC_GEIGER_BEND`: `off` (the default), `direction` to glide the pitch
+//!   of reallocations up when growing and down when shrinking, or `ratio` to
+//!   glide further for larger changes in size.
+//! - `ALLOC_GEIGER_ECHO`: a window in milliseconds to follow chains of
+//!   reallocations, each one resizing the last one's buffer, adding an echo to
+//!   each pulse as a chain goes on.
+//! - `ALLOC_GEIGER_LOUDNESS`: `fixed` (the default), or `size` to play larger
+//!   allocations louder.
+//! - `ALLOC_GEIGER_ALIGN_TIMBRE`: `true` to brighten the timbre of allocations
+//!   aligned above 16 bytes, more for each doubling.
+//! - `ALLOC_GEIGER_VOICES`: the most pulses that may play at once, 64 by
+//!   default.
+//! - `ALLOC_GEIGER_STEAL`: `true` (the default) to cut off the oldest pulses
+//!   for new ones over that limit, or `false` to drop the new ones.
+//! - `ALLOC_GEIGER_WARN_ABOVE`: a size in bytes above which allocations play a
+//!   loud warning clunk, even while muted.
+//! - `ALLOC_GEIGER_CAP`: a budget of live bytes, playing a rising siren as
+//!   usage reaches 90% of it, and `ALLOC_GEIGER_CAP_ENFORCE=true` to also fail
+//!   allocations beyond it.
+//! - `ALLOC_GEIGER_SWEEP`: an interval in milliseconds to play a summary
+//!   sweep, rising for net growth of the live bytes since the last one and
+//!   falling for a net shrink.
+//! - `ALLOC_GEIGER_WATERMARK`: an interval in milliseconds, playing a soft
+//!   chime at most this often when the live bytes pass their highest mark.
+//! - `ALLOC_GEIGER_QUANTILE_TONE`: an interval in milliseconds to play a
+//!   reference tone for the 99th percentile of the recent allocation sizes.
+//! - `ALLOC_GEIGER_POOL_THREADS`: `false` to give each worker of a thread
+//!   pool its own pitch and position, rather than one for the whole pool.
+//! - `ALLOC_GEIGER_THREAD_CUES`: `true` to play a rising cue when a thread
+//!   first allocates and a falling one after it exits.
+//! - `ALLOC_GEIGER_UNWIND_CUE`: `true` to play a shrill blip for each
+//!   allocation while a thread is panicking.
+//! - `ALLOC_GEIGER_RECYCLE_CUE`: `true` to play a muted, softer click for an
+//!   allocation at an address that was just freed, telling reuse from growth.
+//! - `ALLOC_GEIGER_HEATMAP`: an interval in milliseconds to record event
+//!   counts by time and size, for `Geiger::heatmap` to export as CSV, or
+//!   `Geiger::write_heatmap_at_exit`.
+//! - `ALLOC_GEIGER_STACKS`: an interval in bytes to sample the stacks of
+//!   allocations, for `write_folded_stacks` to export for a flamegraph.
+//! - `ALLOC_GEIGER_FLAMEGRAPH`: a path to write the sampled stacks to at exit,
+//!   sampling every 512 KiB unless `ALLOC_GEIGER_STACKS` is set.
+//! - `ALLOC_GEIGER_RECORD_REGIONS`: a comma-separated list of region names,
+//!   recording traces and sampling stacks only inside them, or `off`.
+//! - `ALLOC_GEIGER_SILENCE`: a comma-separated list of crate or module paths,
+//!   like `rustls,tracing_subscriber`, whose allocations are counted but
+//!   never played, capturing a backtrace for each one that would be.
+//! - `ALLOC_GEIGER_SPANS`: a comma-separated list of `tracing` span names,
+//!   like `render`, playing only the events inside them, with the `tracing`
+//!   feature, or `off`.
+//! - `ALLOC_GEIGER_TAGS`: a comma-separated list of [`tag`] numbers, playing
+//!   only the events inside them, or `off`.
+//! - `ALLOC_GEIGER_THEME`: a directory of recordings to play instead of the
+//!   synthesized sounds, any of `alloc.wav`, `dealloc.wav`, `realloc.wav`,
+//!   and `alarm.wav`, or `off`.
+//! - `ALLOC_GEIGER_BASELINE`: a trace of an earlier run, playing only the
+//!   events in size classes that allocate faster now, or `off`.
+//! - `ALLOC_GEIGER_BASELINE_CLASSES`: `true` to mute every size class that
+//!   the baseline allocated in at all, so only novel sizes play.
+//! - `ALLOC_GEIGER_QUIET_THREADS`: a comma-separated list of thread name
+//!   prefixes whose events are ignored entirely, as for the crate's own
+//!   threads, `auto` for the threads of `cpal` and PulseAudio, or `off`.
+//! - `ALLOC_GEIGER_DHAT`: a number of allocation sites to report on stderr at
+//!   exit, ranked by bytes like `dhat`, capturing a backtrace for every
+//!   allocation.
+//! - `ALLOC_GEIGER_RATE_WINDOW`: the sliding window in milliseconds for the
+//!   rates from `Geiger::rate`, 1000 by default.
+//! - `ALLOC_GEIGER_GRAINS`: the allocations per second above which to play a
+//!   granular hiss, denser for more events, instead of clicks, or `off`.
+//! - `ALLOC_GEIGER_TARGET_RATE`: the clicks per second to aim for, sampling
+//!   fewer of the events as they speed up, or `off`.
+//! - `ALLOC_GEIGER_DEAD_TIME`: the microseconds after each click when later
+//!   events on the same thread don't click, like a real tube, or `off`.
+//! - `ALLOC_GEIGER_SONIFY_FROM` and `ALLOC_GEIGER_SONIFY_UNTIL`: the time in
+//!   milliseconds after the first allocation to start and stop making sound,
+//!   to hear only part of a long run.
+//! - `ALLOC_GEIGER_WARMUP`: a number of events to keep quiet for at first,
+//!   skipping the burst from runtime initialization.
+//! - `ALLOC_GEIGER_TICK`: a period in milliseconds to cluster clicks, playing
+//!   up to 8 per tick from a single mixer however fast the allocator goes.
+//! - `ALLOC_GEIGER_TEMPO`: beats per minute to quantize clicks to sixteenth
+//!   notes instead, each as loud as the number of events in it.
+//! - `ALLOC_GEIGER_OPS`: a comma-separated list of the allocator methods that
+//!   make sound, from `alloc`, `alloc_zeroed`, `dealloc`, and `realloc`, or
+//!   `all` (the default) or `none`.
+//! - `ALLOC_GEIGER_COALESCE`: `true` to coalesce a burst of events of the same
+//!   kind and size class into one louder pulse, rather than playing every
+//!   pulse, which is the default.
+//! - `ALLOC_GEIGER_DEVICE`: the name of the audio output device to use, as
+//!   listed by `output_devices`, instead of the system default.
+//! - `ALLOC_GEIGER_TONE_DEVICE`: the name of a second device to play the
+//!   alarms and other tones on, while the clicks stay on the first, or `off`.
+//! - `ALLOC_GEIGER_BUFFER_FRAMES`: the size of the output buffer in frames,
+//!   for lower latency than the host's default.
+//! - `ALLOC_GEIGER_AUDIO_PRIORITY`: `true` to raise the priority of the thread
+//!   mixing the output, so clicks don't stutter under heavy CPU load.
+//! - `ALLOC_GEIGER_MIX_BUDGET`: the most of the audio thread's time that
+//!   mixing may take, in percent, over `0` and up to `100`, degrading the
+//!   clicks while it's over, or `off` by default.
+//! - `ALLOC_GEIGER_BACKEND`: a comma-separated list of [`Backend`]s to try in
+//!   order, `rodio,bell,silent` by default, or starting with `oboe` with the
+//!   `android` feature on Android, or with `beep` after `rodio` with the
+//!   `beep` feature on Windows.
+//! - `ALLOC_GEIGER_DETECT_HEADLESS`: `false` to try the audio backends even
+//!   when there's no display or sound server, which skips them by default.
+//! - `ALLOC_GEIGER_INIT_WARNING`: `true` to print a warning to stderr the first
+//!   time a backend fails to open, which [`Geiger::init_error`] also reports.
+//! - `ALLOC_GEIGER_INIT_TIMEOUT`: how long in milliseconds to wait for an
+//!   audio device to open and start playing before falling back to the next
+//!   backend, 3000 by default, or `off` to wait as long as it takes.
+//! - `ALLOC_GEIGER_RETRY`: an interval in milliseconds to try the backends
+//!   again after any of them failed, until the first one opens.
+//! - `ALLOC_GEIGER_SOCKET`: the path of a Unix datagram socket for the
+//!   `socket` backend to send a frame for each event to, as documented for
+//!   [`Backend::Socket`], for tools in any language to read.
+//! - `ALLOC_GEIGER_FORWARD`: the path of a Unix socket to send events to
+//!   instead of playing them, for `Geiger::spawn_aggregator` in another
+//!   process to play, with the `aggregate` feature.
+//!
+//! On Linux, PulseAudio and PipeWire list the stream under the program's own
+//! name.  To name it and give it the `event` media role, so it can be routed
+//! or turned down on its own, set their variables when starting the program,
+//! like `PULSE_PROP='application.name=alloc_geiger media.role=event'` or
+//! `PIPEWIRE_PROPS='{ application.name=alloc_geiger media.role=event }'`.
+//! These are left for the user to set, since changing the environment of a
+//! running program would race with its other threads and leak into its
+//! children.
+//!
+//!
+//! ## Features
+//!
+//! - `sound-rodio`: on by default, plays through [`rodio`] and `cpal` as
+//!   [`Backend::Rodio`], and adds `Geiger::play_source`, `output_devices`, and
+//!   the `rodio` re-export.  Turning off the default features leaves the
+//!   counting, hooks, and exports without that dependency tree, playing only
+//!   through the other backends, like `bell`.
+//! - `off`: compiles out all counting and sound, so `Geiger` just passes every
+//!   call to the inner allocator, for builds that should leave it inert.
+//! - `bench`: adds the [`bench`] module of allocation measurements for
+//!   `criterion` benchmarks.
+//! - `http`: adds `Geiger::spawn_http_control`, a plain-text HTTP server with
+//!   `/mute`, `/unmute`, `/stats`, and `/config` endpoints for remote control.
+//! - `statsd`: adds `Geiger::spawn_statsd`, which periodically flushes the
+//!   allocation counters to a statsd server over UDP.
+//! - `metrics`: adds `Geiger::spawn_metrics`, which periodically publishes the
+//!   allocation counters and rates through the `metrics` facade, for whatever
+//!   recorder the application installs.
+//! - `tui`: adds `Geiger::spawn_dashboard`, a live `ratatui` dashboard on stderr
+//!   with the allocation rate, a sparkline of its history, the live bytes, and a
+//!   pane for each of the busiest threads.
+//! - `gui`: adds `Geiger::run_window`, which opens a small `minifb` window
+//!   plotting each event by size and kind as it happens, and the live bytes,
+//!   for screen recordings and demos.
+//! - `keys`: adds `Geiger::spawn_stdin_control`, which reads single-character
+//!   commands from stdin on a background thread, `m` and `u` to mute and
+//!   unmute, `s` to write the stats, and `+` and `-` for the volume, and
+//!   `Geiger::spawn_pipe_control` on Unix, which reads them from a named pipe.
+//! - `dbus`: adds `Geiger::spawn_dbus_control` on Unix, a control object on the
+//!   session D-Bus with methods to mute, unmute, set the volume, and get the
+//!   stats, for media keys or scripts.
+//! - `config-file`: adds `Geiger::load_config_file` for settings in a TOML file,
+//!   which is also loaded from the path in `ALLOC_GEIGER_CONFIG` when the
+//!   sound is initialized, and `Geiger::watch_config_file` to reload it
+//!   whenever it changes.
+//! - `shm`: adds `Geiger::spawn_shm_export` on Unix, which publishes each
+//!   event into a ring buffer in a shared-memory file with a documented
+//!   layout, for external tools to visualize a running process.
+//! - `mimalloc`: adds the `Mimalloc` alias and `MIMALLOC` constant, a `Geiger`
+//!   around [`mimalloc`]'s allocator, like `System` and `SYSTEM`.
+//! - `jemalloc`: adds the `Jemalloc` alias and `JEMALLOC` constant, a `Geiger`
+//!   around [`jemallocator`]'s allocator, and `Geiger::spawn_jemalloc_texture`,
+//!   which plays jemalloc's fragmentation as a background hiss, when it's the
+//!   inner allocator.
+//! - `haptic`: adds `Geiger::spawn_haptic` on Linux, which rumbles a game
+//!   controller with the allocator activity through its force-feedback event
+//!   device, alongside or instead of the sound.
+//! - `aggregate`: adds `Geiger::spawn_aggregator` on Unix, which plays the
+//!   events that other processes forward to a Unix socket, as set by
+//!   `ALLOC_GEIGER_FORWARD` in their environment, so a multi-process
+//!   application makes one soundscape.
+//! - `serde`: implements `serde::Serialize` for [`GeigerStats`],
+//!   [`ThreadStats`], [`AllocRate`], [`AllocCounts`], [`Overhead`], and
+//!   [`Heatmap`], to embed them in an application's own health endpoints and
+//!   reports.
+//! - `tracing`: adds `SpanLayer`, a `tracing_subscriber` layer that names
+//!   the allocations in each span as a region, `AllocEvent::span`, and the
+//!   `spans` setting to play only inside spans with certain names.
+//! - `zstd`: adds `TraceWriter::compressed`, and compresses the files of
+//!   `Geiger::spawn_binary_trace_recorder`, with the `zstd` crate.
+//! - `runner`: builds the `alloc-geiger-run` tool, which runs any program with
+//!   the `alloc_geiger_preload` library, the workspace's `LD_PRELOAD` library
+//!   for Linux, and settings passed on as environment variables.
+//! - `alsa`: adds the [`Backend::Alsa`] output on Linux, which writes to an
+//!   ALSA PCM device from one dedicated thread instead of going through
+//!   `rodio` and `cpal`.
+//! - `android`: adds the [`Backend::Oboe`] output on Android, a low-latency
+//!   AAudio or OpenSL ES stream through Oboe, which is tried first by default.
+//! - `beep`: adds the [`Backend::Beep`] output on Windows, the legacy `Beep`
+//!   function at a fixed pitch, which is tried after `rodio` by default for
+//!   machines without a configured audio device, like CI VMs.
+//! - `windows-etw`: writes each event as an ETW TraceLogging event on Windows,
+//!   from the `AllocGeiger` provider, so Windows Performance Analyzer shows
+//!   the allocations on its timelines next to the CPU samples.
+//! - `user-events`: writes each event to a Linux `user_events` tracepoint,
+//!   `user_events:alloc_geiger`, while a `perf record` or `trace-cmd` session
+//!   has enabled it, to line the allocations up with the kernel's activity.
+//! - `diagnostics`: adds `GeigerCore::anomalies`, which reports reentrancy
+//!   anomalies that the recursion guard otherwise hides, like events on the
+//!   audio thread, and aborts with a message if the inner allocator keeps
+//!   reentering the global allocator, to debug combinations with other
+//!   instrumenting allocators.
+//! - `nightly`: adds `Geiger::install_error_hook`, which reports the stats and
+//!   lets the failure alarm finish before an out-of-memory abort.  This
+//!   requires a nightly compiler for `std::alloc::set_alloc_error_hook`.
+//!
 //! [`rodio`]: https://crates.io/crates/rodio
 //! [sinc]: https://en.wikipedia.org/wiki/Sinc_function
 //! [Malloc Geiger]: https://github.com/laserallan/malloc_geiger
 //! [`jemallocator`]: https://crates.io/crates/jemallocator
+//! [`mimalloc`]: https://crates.io/crates/mimalloc
 
-use rodio::{OutputStream, OutputStreamHandle, Source};
+#![cfg_attr(feature = "nightly", feature(alloc_error_hook))]
+
+#[cfg(feature = "sound-rodio")]
+pub use rodio;
 use std::alloc::{self, GlobalAlloc, Layout};
 use std::cell::Cell;
-use std::f32::consts::PI;
-use std::ops::Range;
-use std::sync::atomic::AtomicBool;
+use std::io;
 use std::sync::atomic::Ordering;
-use std::sync::{Arc, Barrier, OnceLock};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicU8, AtomicUsize};
+use std::sync::{PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::time::Duration;
 
+#[cfg(all(feature = "android", target_os = "android"))]
+mod aaudio;
+#[cfg(all(feature = "aggregate", unix))]
+mod aggregate;
+#[cfg(all(feature = "beep", windows))]
+mod beep;
+#[cfg(feature = "bench")]
+pub mod bench;
+mod binary;
+mod budget;
+mod clock;
+mod config;
+mod count;
+#[cfg(feature = "tui")]
+mod dashboard;
+#[cfg(all(feature = "dbus", unix))]
+mod dbus;
+mod dhat;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
+mod diff;
+mod echo;
+#[cfg(all(feature = "windows-etw", windows))]
+mod etw;
+mod event;
+#[cfg(feature = "metrics")]
+mod facade;
+#[cfg(feature = "config-file")]
+mod file;
+mod forbid;
+#[cfg(all(feature = "haptic", target_os = "linux"))]
+mod haptic;
+mod heaptrack;
+mod heatmap;
+#[cfg(feature = "http")]
+mod http;
+mod intercept;
+#[cfg(feature = "jemalloc")]
+mod jemalloc;
+#[cfg(feature = "keys")]
+mod keys;
+mod layer;
+mod mark;
+mod massif;
+// the mixer of the `rodio`, ALSA, and Oboe outputs
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+mod mixer;
+#[cfg(feature = "nightly")]
+mod oom;
+mod output;
+mod overhead;
+#[cfg(all(feature = "alsa", target_os = "linux"))]
+mod pcm;
+mod pitch;
+mod policy;
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+mod priority;
+mod pulse;
+mod quantile;
+mod rate;
+mod recent;
+mod recycle;
+mod region;
+mod sampler;
+#[cfg(all(feature = "shm", unix))]
+mod shm;
+mod socket;
+#[cfg(feature = "tracing")]
+mod spans;
+mod stacks;
+mod stats;
+#[cfg(feature = "statsd")]
+mod statsd;
+#[cfg(feature = "sound-rodio")]
+mod stream;
+mod subscribe;
+mod summary;
+mod tag;
+mod theme;
+mod threads;
+mod trace;
+#[cfg(all(feature = "user-events", target_os = "linux"))]
+mod user_events;
+mod voice;
+#[cfg(feature = "gui")]
+mod window;
+
+pub use crate::binary::{TraceMetadata, TraceReader, TraceWriter};
+pub use crate::budget::{budget, Budget};
+use crate::clock::now_millis;
+pub use crate::clock::{clock_calibration, ClockCalibration};
+pub use crate::config::{Config, ParseConfigError, Profile};
+pub use crate::count::{count, AllocCounts, Counter};
+pub use crate::dhat::write_dhat_report;
+#[cfg(feature = "diagnostics")]
+pub use crate::diagnostics::Anomaly;
+use crate::diff::Diff;
+pub use crate::event::{AllocEvent, AllocOp, Ops};
+pub use crate::forbid::{forbid, forbid_abort, Forbidden};
+pub use crate::heatmap::Heatmap;
+use crate::heatmap::Recorder;
+pub use crate::intercept::GeigerCore;
+use crate::intercept::React;
+pub use crate::layer::{
+    CapLayer, GeigerLayer, Layer, SoundLayer, StatsLayer, TraceLayer, SOUND_LAYER, STATS_LAYER,
+};
+pub use crate::mark::MarkedAlloc;
+use crate::mark::Marks;
+pub use crate::output::{Backend, GeigerError};
+use crate::output::{Cue, Output};
+pub use crate::overhead::Overhead;
+use crate::pitch::Lifetimes;
+pub use crate::pitch::{Bend, Pitch, Scale};
+pub use crate::policy::{And, Decision, GeigerPolicy, MaxRate, MaxRateBySize, MinSize, OnThread};
+use crate::pulse::AddressRange;
+pub use crate::pulse::{Envelope, Loudness, Pan, Pulse, Waveform};
+use crate::quantile::Quantiles;
+pub use crate::quantile::SizeQuantiles;
+pub use crate::rate::AllocRate;
+use crate::rate::Meter;
+pub use crate::recent::RecentEvent;
+use crate::recent::Ring;
+use crate::recycle::Recycler;
+pub use crate::region::{instrument_region, region, region_stats, InRegion, Region};
+use crate::region::{RegionStats, Tags};
+use crate::sampler::Sampler;
+#[cfg(feature = "tracing")]
+pub use crate::spans::SpanLayer;
+pub use crate::stacks::write_folded_stacks;
+pub use crate::stats::{GeigerStats, ThreadStats};
+#[cfg(feature = "sound-rodio")]
+pub use crate::stream::output_devices;
+pub use crate::subscribe::EventReceiver;
+pub use crate::tag::{tag, Tag};
+use crate::theme::{Recording, Theme};
+pub use crate::trace::read_trace;
+use crate::voice::Voice;
+
 /// Geiger counter allocator.
-#[derive(Default)]
-pub struct Geiger<Alloc> {
-    inner: Alloc,
-    stream_handle: OnceLock<Option<OutputStreamHandle>>,
-    /// non-blocking protection against recursive init
-    init: AtomicBool,
+///
+/// As with [`GeigerCore`], the `OPS` parameter chooses the allocator methods
+/// to intercept at compile time, while [`Config::ops`] only chooses which of
+/// them make sound.
+pub struct Geiger<Alloc, const OPS: u8 = { Ops::ALL.bits() }> {
+    core: GeigerCore<Alloc, OPS>,
+    /// `false` to count everything without ever opening an audio output
+    audible: bool,
+    output: RwLock<Option<Output>>,
+    /// the output of the `tone_device`, if it's configured and opened
+    tone_output: RwLock<Option<Output>>,
+    /// the backends that failed when the output was last opened
+    init_error: RwLock<Option<GeigerError>>,
+    /// when to open the output again after backends failed, in `now_millis`
+    retry_at: AtomicU64,
+    /// whether the `init_warning` was printed
+    init_warned: AtomicBool,
+    /// non-blocking protection against recursive init, one of `OutputState`
+    state: AtomicU8,
+    muted: AtomicBool,
+    paused: AtomicBool,
+    /// whether to keep the last events while paused, in `paused_events`
+    pause_buffered: AtomicBool,
+    paused_events: Ring,
+    /// the allocation size to ping for, or `usize::MAX` for none
+    watched_size: AtomicUsize,
+    marks: Marks,
+    /// `f32` bits of the pulse volume
+    volume: AtomicU32,
+    config: RwLock<Config>,
+    heatmap: Recorder,
+    heaptrack: heaptrack::Recorder,
+    massif: massif::SizeHistogram,
+    meter: Meter,
+    sampler: Sampler,
+    recycler: Recycler,
+    addresses: AddressRange,
+    lifetimes: Lifetimes,
+    tags: Tags,
+    /// consulted for each event that would make sound
+    policy: RwLock<Option<Box<dyn GeigerPolicy>>>,
+    /// whether the live bytes have reached 90% of the cap
+    near_cap: AtomicBool,
+    /// when the next summary sweep is due, in `now_millis`, and the live
+    /// bytes at the last one
+    sweep_at: AtomicU64,
+    sweep_live: AtomicU64,
+    /// the highest live bytes yet, and when the next chime for a higher mark
+    /// may play
+    peak_live: AtomicU64,
+    watermark_at: AtomicU64,
+    /// estimates of the recent allocation sizes, and when the next tone for
+    /// them is due
+    quantiles: Quantiles,
+    quantile_at: AtomicU64,
+    /// allocations while their thread was panicking
+    unwind_allocs: AtomicU64,
+    /// the recordings of `Config::theme`, loaded with the output
+    theme: RwLock<Option<Theme>>,
+    /// the rates of `Config::baseline`, loaded with the output
+    diff: Diff,
+    /// bumped by each change of the settings, to decide quiet threads again
+    config_generation: AtomicU32,
+    /// events held back for the next grains, and when they're due
+    grains_pending: AtomicU64,
+    grains_at: AtomicU64,
 }
 
 /// `Geiger` allocator based on `std::alloc::System`.
 pub type System = Geiger<alloc::System>;
 
 /// `Geiger` allocator based on `std::alloc::System`.
+#[allow(clippy::declare_interior_mutable_const)]
 pub const SYSTEM: System = Geiger::new(alloc::System);
 
+/// `Geiger` allocator based on `std::alloc::System`, only active with
+/// `debug_assertions`.
+#[allow(clippy::declare_interior_mutable_const)]
+pub const SYSTEM_DEBUG: System = Geiger::debug_only(alloc::System);
+
+/// `Geiger` allocator based on `jemallocator::Jemalloc`, with the `jemalloc`
+/// feature.
+#[cfg(feature = "jemalloc")]
+pub type Jemalloc = Geiger<jemallocator::Jemalloc>;
+
+/// `Geiger` allocator based on `jemallocator::Jemalloc`, with the `jemalloc`
+/// feature.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::Jemalloc = alloc_geiger::JEMALLOC;
+///
+/// fn main() {
+///     let _vec = vec![0u8; 100];
+///     assert!(ALLOC.stats().allocs > 0);
+/// }
+/// ```
+#[cfg(feature = "jemalloc")]
+#[allow(clippy::declare_interior_mutable_const)]
+pub const JEMALLOC: Jemalloc = Geiger::new(jemallocator::Jemalloc);
+
+/// `Geiger` allocator based on `mimalloc::MiMalloc`, with the `mimalloc`
+/// feature.
+#[cfg(feature = "mimalloc")]
+pub type Mimalloc = Geiger<mimalloc::MiMalloc>;
+
+/// `Geiger` allocator based on `mimalloc::MiMalloc`, with the `mimalloc`
+/// feature.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::Mimalloc = alloc_geiger::MIMALLOC;
+///
+/// fn main() {
+///     let _vec = vec![0u8; 100];
+///     assert!(ALLOC.stats().allocs > 0);
+/// }
+/// ```
+#[cfg(feature = "mimalloc")]
+#[allow(clippy::declare_interior_mutable_const)]
+pub const MIMALLOC: Mimalloc = Geiger::new(mimalloc::MiMalloc);
+
 thread_local! {
     /// Guard against recursion
     static BUSY: Cell<bool> = const { Cell::new(false) };
 }
 
-impl<Alloc> Geiger<Alloc> {
+/// The progress of opening a `Geiger`'s audio output.
+#[repr(u8)]
+enum OutputState {
+    Uninit,
+    Opening,
+    Open,
+    Failed,
+}
+
+/// Whether a `Geiger` is making sound, from [`Geiger::status`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum GeigerStatus {
+    /// The output hasn't been opened yet, as before the first event.
+    Uninitialized,
+    /// One of the backends is open, which may be a fallback like `bell` or
+    /// `silent`.
+    Active(Backend),
+    /// None of the backends could be opened, as in
+    /// [`Geiger::init_error`].
+    Failed,
+    /// Sound is disabled, as by [`Geiger::debug_only`] in a release build,
+    /// [`Geiger::metrics_only`], or the `off` feature.
+    Disabled,
+}
+
+/// Runs `f` as our own activity, which doesn't count or make sound.
+///
+/// This also keeps allocations from reentering the config lock.
+fn quietly<R>(f: impl FnOnce() -> R) -> R {
+    BUSY.with(|busy| {
+        let was_busy = busy.replace(true);
+        let result = f();
+        busy.set(was_busy);
+        result
+    })
+}
+
+/// A `retry_at` for no retry.
+const NEVER: u64 = u64::MAX;
+
+/// The default pulse volume, relative to full scale.
+const DEFAULT_VOLUME: f32 = 0.5;
+
+/// How often the events held back for grains are played.
+const GRAIN_WINDOW: Duration = Duration::from_millis(100);
+
+impl<Alloc: Default, const OPS: u8> Default for Geiger<Alloc, OPS> {
+    fn default() -> Self {
+        Geiger::new(Alloc::default())
+    }
+}
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
     pub const fn new(inner: Alloc) -> Self {
+        Geiger::with_config(inner, Config::new())
+    }
+
+    /// Creates a `Geiger` with the given sound settings.
+    ///
+    /// `ALLOC_GEIGER_*` environment variables still take precedence when the
+    /// sound is initialized.
+    pub const fn with_config(inner: Alloc, config: Config) -> Self {
         Geiger {
-            inner,
-            stream_handle: OnceLock::new(),
-            init: AtomicBool::new(false),
+            core: GeigerCore::new(inner),
+            audible: true,
+            output: RwLock::new(None),
+            tone_output: RwLock::new(None),
+            init_error: RwLock::new(None),
+            retry_at: AtomicU64::new(NEVER),
+            init_warned: AtomicBool::new(false),
+            state: AtomicU8::new(OutputState::Uninit as u8),
+            muted: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            pause_buffered: AtomicBool::new(false),
+            paused_events: Ring::new(),
+            watched_size: AtomicUsize::new(usize::MAX),
+            marks: Marks::new(),
+            // `f32::to_bits` isn't const until Rust 1.83
+            volume: AtomicU32::new(0x3f00_0000),
+            config: RwLock::new(config),
+            heatmap: Recorder::new(),
+            heaptrack: heaptrack::Recorder::new(),
+            massif: massif::SizeHistogram::new(),
+            meter: Meter::new(),
+            sampler: Sampler::new(),
+            recycler: Recycler::new(),
+            addresses: AddressRange::new(),
+            lifetimes: Lifetimes::new(),
+            tags: Tags::new(),
+            policy: RwLock::new(None),
+            near_cap: AtomicBool::new(false),
+            sweep_at: AtomicU64::new(0),
+            sweep_live: AtomicU64::new(0),
+            peak_live: AtomicU64::new(0),
+            watermark_at: AtomicU64::new(0),
+            quantiles: Quantiles::new(),
+            quantile_at: AtomicU64::new(0),
+            unwind_allocs: AtomicU64::new(0),
+            theme: RwLock::new(None),
+            diff: Diff::new(),
+            config_generation: AtomicU32::new(1),
+            grains_pending: AtomicU64::new(0),
+            grains_at: AtomicU64::new(0),
         }
     }
 
-    fn bell(&self) {
-        BUSY.with(|busy| {
-            if !busy.replace(true) {
-                if let Some(handle) = self.get_handle() {
-                    let _ = handle.play_raw(Pulse::new());
-                }
-                busy.set(false);
+    /// Creates a `Geiger` that's only active in builds with `debug_assertions`,
+    /// and otherwise just passes every call to the inner allocator.
+    ///
+    /// This makes a single `#[global_allocator]` safe to leave in place for
+    /// release builds.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::Geiger::debug_only(std::alloc::System);
+    ///
+    /// fn main() {
+    ///     // ...
+    /// }
+    /// ```
+    pub const fn debug_only(inner: Alloc) -> Self {
+        let mut geiger = Geiger::new(inner);
+        geiger.core.enabled = cfg!(debug_assertions);
+        geiger
+    }
+
+    /// Creates a `Geiger` that never makes sound, while still counting stats
+    /// and running hooks and exporters, as an instrumentation allocator.
+    ///
+    /// The output is always the [`Backend::Silent`] one, whatever backends
+    /// are configured, so no audio device is ever opened.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::Geiger::metrics_only(std::alloc::System);
+    ///
+    /// fn main() {
+    ///     let _vec = vec![0u8; 100];
+    ///     assert!(ALLOC.stats().allocs > 0);
+    /// }
+    /// ```
+    pub const fn metrics_only(inner: Alloc) -> Self {
+        let mut geiger = Geiger::new(inner);
+        geiger.audible = false;
+        geiger
+    }
+
+    /// Opens the audio output now, rather than lazily at the first allocation,
+    /// returning `true` if one of the configured [`Backend`]s was opened.
+    ///
+    /// Opening the output waits for the audio thread to start, so calling this
+    /// early in `main` keeps that delay out of some arbitrary allocation later.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     if !ALLOC.init() {
+    ///         eprintln!("no backend is available");
+    ///     }
+    ///     // ...
+    /// }
+    /// ```
+    pub fn init(&self) -> bool {
+        quietly(|| loop {
+            let state = self.state.load(Ordering::Acquire);
+            if state == OutputState::Uninit as u8 {
+                self.open_output(state);
+            } else if state == OutputState::Opening as u8 {
+                std::thread::yield_now();
+            } else {
+                return state == OutputState::Open as u8;
             }
+        })
+    }
+
+    /// Plays a short ascending chirp and waits for it to finish, returning
+    /// `true` if it went to a backend that makes sound and, for those with
+    /// an audio thread, that thread picked it up.
+    ///
+    /// This opens the output like [`init`](Self::init), and it plays even
+    /// while muted or paused, so calling it early in `main` checks the sound
+    /// setup at once, instead of wondering later why nothing's heard.  The
+    /// `log` and `silent` backends, and [`metrics_only`](Self::metrics_only),
+    /// fail the test.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     if !ALLOC.self_test() {
+    ///         eprintln!("no sound: {:?}", ALLOC.init_error());
+    ///     }
+    ///     // ...
+    /// }
+    /// ```
+    pub fn self_test(&self) -> bool {
+        if !self.init() {
+            return false;
+        }
+        let queued = quietly(|| {
+            let output = self.read_output();
+            let output = output.as_ref()?;
+            output.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::self_test(sample_rate, self.volume()))
+            });
+            Some(now_millis())
         });
+        let Some(queued) = queued else {
+            return false;
+        };
+        std::thread::sleep(Pulse::SELF_TEST_DURATION);
+        quietly(|| (self.read_output().as_ref()).is_some_and(|output| output.played_since(queued)))
+    }
+
+    /// Replaces the sound settings and opens the audio output now, as with
+    /// [`set_config`](Self::set_config) and [`init`](Self::init).
+    pub fn init_with(&self, config: Config) -> bool {
+        self.set_config(config);
+        self.init()
+    }
+
+    /// Stops making sound, while still counting allocator activity.
+    pub fn mute(&self) {
+        self.muted.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes making sound after [`mute`](Self::mute).
+    pub fn unmute(&self) {
+        self.muted.store(false, Ordering::Relaxed);
+    }
+
+    /// Returns `true` if sound is currently muted.
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    /// Goes off air, making no sound at all until [`resume`](Self::resume),
+    /// not even warnings or alarms, while still counting everything as
+    /// usual, so the stats have no gap.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.pause();
+    ///     // ... take a call ...
+    ///     ALLOC.resume();
+    /// }
+    /// ```
+    pub fn pause(&self) {
+        self.pause_buffered.store(false, Ordering::Relaxed);
+        self.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Pauses as with [`pause`](Self::pause), also keeping the last few
+    /// events while paused for [`resume`](Self::resume) to return.
+    pub fn pause_buffered(&self) {
+        self.paused_events.clear();
+        self.pause_buffered.store(true, Ordering::Relaxed);
+        self.paused.store(true, Ordering::Relaxed);
     }
 
-    fn get_handle(&self) -> &Option<OutputStreamHandle> {
-        if let Some(handle) = self.stream_handle.get() {
-            handle
-        } else if !self.init.swap(true, Ordering::AcqRel) {
-            self.stream_handle.get_or_init(rodio_init)
+    /// Goes back on air after a pause, playing a short rising chirp unless
+    /// muted, and returns the last events while paused if they were kept by
+    /// [`pause_buffered`](Self::pause_buffered), oldest first.
+    pub fn resume(&self) -> Vec<RecentEvent> {
+        if !self.paused.swap(false, Ordering::Relaxed) {
+            return Vec::new();
+        }
+        if !self.is_muted() {
+            quietly(|| {
+                self.play(Cue::Tone, |sample_rate| {
+                    Some(Pulse::on_air(sample_rate, self.volume()))
+                })
+            });
+        }
+        if self.pause_buffered.swap(false, Ordering::Relaxed) {
+            quietly(|| self.paused_events.snapshot())
         } else {
-            &None
+            Vec::new()
         }
     }
-}
 
-unsafe impl<Alloc: GlobalAlloc> GlobalAlloc for Geiger<Alloc> {
-    #[inline]
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        self.bell();
-        self.inner.alloc(layout)
+    /// Returns `true` if sound is paused.
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
     }
 
-    #[inline]
-    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
-        self.bell();
-        self.inner.alloc_zeroed(layout)
+    /// Watches for allocations of exactly this many bytes, playing a
+    /// distinct ping and printing the stack to stderr for each one, to find
+    /// an allocation whose size is known from an earlier report.
+    ///
+    /// Reallocations to the size count too.  The stack is only captured for
+    /// a match, so this is cheap for a size that's rare.  The ping plays even
+    /// while muted, though not while paused, and the stack is always printed.
+    /// Only one size is watched at a time, replacing any before it.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.watch_size(4096);
+    ///     let _page = vec![0u8; 4096]; // pings, with this line in the stack
+    ///     ALLOC.unwatch_size();
+    /// }
+    /// ```
+    pub fn watch_size(&self, bytes: usize) {
+        self.watched_size.store(bytes, Ordering::Relaxed);
     }
 
-    #[inline]
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        self.bell();
-        self.inner.dealloc(ptr, layout)
+    /// Stops watching for the size given to [`watch_size`](Self::watch_size).
+    pub fn unwatch_size(&self) {
+        self.watched_size.store(usize::MAX, Ordering::Relaxed);
     }
 
-    #[inline]
-    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
-        self.bell();
-        self.inner.realloc(ptr, layout, new_size)
+    /// Returns the pulse volume, relative to full scale.
+    pub fn volume(&self) -> f32 {
+        f32::from_bits(self.volume.load(Ordering::Relaxed))
+    }
+
+    /// Sets the pulse volume, clamped to the range `0.0..=1.0`.
+    pub fn set_volume(&self, volume: f32) {
+        let volume = if volume.is_nan() {
+            DEFAULT_VOLUME
+        } else {
+            volume.clamp(0.0, 1.0)
+        };
+        self.volume.store(volume.to_bits(), Ordering::Relaxed);
+    }
+
+    /// Returns which allocator methods make sound, as in [`Config::ops`].
+    pub fn ops(&self) -> Ops {
+        quietly(|| self.read_config().ops)
+    }
+
+    /// Changes which allocator methods make sound, as in [`Config::ops`].
+    ///
+    /// ```
+    /// use alloc_geiger::Ops;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     // deallocations are still counted, but quiet
+    ///     ALLOC.set_ops(Ops::ALL & !Ops::DEALLOC);
+    ///     // ...
+    /// }
+    /// ```
+    pub fn set_ops(&self, ops: Ops) {
+        quietly(|| self.write_config().ops = ops);
+    }
+
+    /// Sets a policy to decide whether and how to play each event, or `None`
+    /// to play them all as configured.
+    pub fn set_policy(&self, policy: Option<impl GeigerPolicy + 'static>) {
+        quietly(|| {
+            let policy = policy.map(|policy| Box::new(policy) as Box<dyn GeigerPolicy>);
+            *self.policy.write().unwrap_or_else(PoisonError::into_inner) = policy;
+        });
+    }
+
+    /// Returns a copy of the current sound settings.
+    pub fn config(&self) -> Config {
+        quietly(|| self.read_config().clone())
+    }
+
+    /// Replaces the sound settings.
+    pub fn set_config(&self, config: Config) {
+        quietly(|| *self.write_config() = config);
+    }
+
+    /// Changes a single setting by its `key`, as in [`Config::set`].
+    pub fn set(&self, key: &str, value: &str) -> Result<(), ParseConfigError> {
+        quietly(|| self.write_config().set(key, value))
+    }
+
+    fn read_config(&self) -> RwLockReadGuard<'_, Config> {
+        self.config.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write_config(&self) -> RwLockWriteGuard<'_, Config> {
+        let config = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        // bumped with the lock held, so no thread can decide with the old
+        // settings under the new generation
+        self.config_generation.fetch_add(1, Ordering::Relaxed);
+        config
+    }
+
+    /// Returns a snapshot of the allocator activity counted so far.
+    ///
+    /// The crate's own allocations for making sound are not included.
+    pub fn stats(&self) -> GeigerStats {
+        self.core.stats()
+    }
+
+    /// Returns a snapshot of the activity counted so far on each thread that
+    /// had any, in order of their first events, to find which one is
+    /// responsible for the churn.
+    ///
+    /// Threads that have exited are still included, and the crate's own
+    /// allocations for making sound are not.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let worker = std::thread::Builder::new().name("worker".into());
+    ///     worker.spawn(|| vec![0u8; 100]).unwrap().join().unwrap();
+    ///     for thread in ALLOC.stats_per_thread() {
+    ///         let name = thread.name.as_deref().unwrap_or("?");
+    ///         println!("{} {name}: {} allocs", thread.thread, thread.stats.allocs);
+    ///     }
+    /// }
+    /// ```
+    pub fn stats_per_thread(&self) -> Vec<ThreadStats> {
+        self.core.stats_per_thread()
+    }
+
+    /// Returns a snapshot of the activity counted so far on each pool of
+    /// threads, like the workers of `rayon` or `tokio`, in order of their
+    /// first events, as with [`GeigerCore::stats_per_pool`].
+    pub fn stats_per_pool(&self) -> Vec<(String, GeigerStats)> {
+        self.core.stats_per_pool()
+    }
+
+    /// Returns the reentrancy anomalies seen so far, as with
+    /// [`GeigerCore::anomalies`].
+    #[cfg(feature = "diagnostics")]
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        self.core.anomalies()
+    }
+
+    /// Returns the number of threads that have had an allocator event and
+    /// haven't exited yet, a gauge of the thread churn that
+    /// [`Config::thread_cues`] makes audible.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let _vec = vec![0u8; 100];
+    ///     assert!(ALLOC.live_threads() >= 1);
+    /// }
+    /// ```
+    pub fn live_threads(&self) -> usize {
+        threads::live()
+    }
+
+    /// Returns the highest live bytes seen yet, the high-water mark that
+    /// [`Config::watermark`] chimes for raising.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     drop(vec![0u8; 1 << 20]);
+    ///     assert!(ALLOC.peak_live_bytes() >= 1 << 20);
+    /// }
+    /// ```
+    pub fn peak_live_bytes(&self) -> u64 {
+        self.peak_live.load(Ordering::Relaxed)
+    }
+
+    /// Returns estimates of the median and 99th percentile of the allocation
+    /// sizes, weighted toward the last few seconds, which
+    /// [`Config::quantile_tone`] makes audible, or `None` before any
+    /// allocation.
+    ///
+    /// The sizes include the new size of each `realloc`.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let small: Vec<Box<u64>> = (0..1000).map(Box::new).collect();
+    ///     let quantiles = ALLOC.size_quantiles().unwrap();
+    ///     assert!(quantiles.p50 <= quantiles.p99);
+    ///     drop(small);
+    /// }
+    /// ```
+    pub fn size_quantiles(&self) -> Option<SizeQuantiles> {
+        self.quantiles.get()
+    }
+
+    /// Returns the number of allocations and reallocations made while their
+    /// thread was panicking, as in a `Drop` during unwinding, which
+    /// [`Config::unwind_cue`] makes audible.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// struct Noisy;
+    ///
+    /// impl Drop for Noisy {
+    ///     fn drop(&mut self) {
+    ///         let _message = format!("dropped {}", std::thread::panicking());
+    ///     }
+    /// }
+    ///
+    /// fn main() {
+    ///     let before = ALLOC.unwind_allocs();
+    ///     let _ = std::panic::catch_unwind(|| {
+    ///         let _noisy = Noisy;
+    ///         panic!("oops");
+    ///     });
+    ///     assert!(ALLOC.unwind_allocs() > before);
+    /// }
+    /// ```
+    pub fn unwind_allocs(&self) -> u64 {
+        self.unwind_allocs.load(Ordering::Relaxed)
+    }
+
+    /// Returns the recent allocation rates, averaged over the
+    /// [`Config::rate_window`].
+    ///
+    /// The crate's own allocations for making sound are not included.
+    pub fn rate(&self) -> AllocRate {
+        let window = self.read_config().rate_window;
+        self.meter.rate(window)
+    }
+
+    /// Returns the activity since the previous call, or since the start, and
+    /// starts a new epoch, to see how much one phase of a program allocates.
+    ///
+    /// The counters aren't reset, so [`stats`](Self::stats) still covers
+    /// everything, but concurrent callers each get a distinct span.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.epoch();
+    ///     let _vec = vec![0u8; 100];
+    ///     let phase = ALLOC.epoch();
+    ///     assert!(phase.bytes_allocated >= 100);
+    /// }
+    /// ```
+    pub fn epoch(&self) -> GeigerStats {
+        self.core.epoch()
+    }
+
+    /// Returns the last 64 allocator events, oldest first, as when breaking
+    /// into a debugger after hearing a burst.
+    ///
+    /// The crate's own allocations for making sound are not included.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let _vec = vec![0u8; 100];
+    ///     for event in ALLOC.recent_events() {
+    ///         eprintln!("{:?} of {} bytes on thread {}", event.op, event.size, event.thread);
+    ///     }
+    /// }
+    /// ```
+    pub fn recent_events(&self) -> Vec<RecentEvent> {
+        self.core.recent_events()
+    }
+
+    /// Subscribes to a live stream of allocator events, for another thread to
+    /// receive as they happen, like a UI or a logger.
+    ///
+    /// Each receiver has room for 1024 waiting events, and drops any more
+    /// rather than blocking the allocator.  The crate's own allocations for
+    /// making sound are not included.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let mut events = ALLOC.subscribe();
+    ///     let logger = std::thread::spawn(move || {
+    ///         for event in events.try_iter() {
+    ///             eprintln!("{:?} of {} bytes", event.op, event.size);
+    ///         }
+    ///     });
+    ///     let _vec = vec![0u8; 100];
+    ///     logger.join().unwrap();
+    /// }
+    /// ```
+    pub fn subscribe(&self) -> EventReceiver {
+        self.core.subscribe()
+    }
+
+    /// Loads a trace of an earlier run as the baseline now, as for
+    /// [`Config::baseline`], so only the allocation behavior that's new
+    /// since that calibration run makes noise.
+    ///
+    /// Unlike the setting, which is loaded when the output is opened and
+    /// only warns if it can't be read, this returns the error and keeps the
+    /// old baseline.  With [`Config::baseline_classes`], every size class
+    /// the baseline allocated in is muted, whatever its rate now.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() -> std::io::Result<()> {
+    ///     ALLOC.set("baseline_classes", "true").unwrap();
+    ///     ALLOC.load_baseline("calibration.trace")?;
+    ///     // ...
+    ///     Ok(())
+    /// }
+    /// ```
+    pub fn load_baseline(&self, path: &str) -> io::Result<()> {
+        quietly(|| {
+            self.diff.try_load(path)?;
+            self.write_config().baseline = Some(path.to_owned());
+            Ok(())
+        })
+    }
+
+    /// Plays the click of a recorded event, as from [`read_trace`], with the
+    /// current sound settings, and with its own thread for the pitch and pan.
+    ///
+    /// The event isn't counted, and the timing is up to the caller, as in
+    /// the `geiger-replay` tool.
+    pub fn replay(&self, event: &RecentEvent) {
+        quietly(|| {
+            let event = AllocEvent::replay(event);
+            self.addresses.record(event.address);
+            let config = self.read_config();
+            if !config.ops.contains(event.op.into())
+                || self.is_muted()
+                || !(self.diff).exceeds(config.rate_window, config.baseline_classes, &event)
+            {
+                return;
+            }
+            let burst = config.coalesce.then(|| event.burst());
+            let age = self.lifetimes.age(config.pitch, &event);
+            drop(config);
+            self.play(Cue::Click(burst, &event), |sample_rate| {
+                self.pulse(&event, None, age, sample_rate)
+            });
+        })
     }
-}
 
-fn rodio_init() -> Option<OutputStreamHandle> {
-    if let Ok((stream, handle)) = OutputStream::try_default() {
-        let (source, barrier) = BusySource::new();
-        if let Ok(()) = handle.play_raw(source) {
-            barrier.wait();
-            std::mem::forget(stream);
-            return Some(handle);
+    /// Returns an estimate of the time spent counting allocator events and
+    /// making sound, to judge how much that perturbs the program.
+    ///
+    /// This is a rough estimate, scaled up from the time of a sample of the
+    /// events, and it doesn't include the cost of the inner allocator.  The
+    /// audio thread's time mixing is separate, along with how far the clicks
+    /// are degraded to keep it within the [`Config::mix_budget`].
+    pub fn overhead(&self) -> Overhead {
+        let mut overhead = self.core.overhead();
+        if let Some((load, degradation)) = self.read_output().as_ref().and_then(Output::mixing) {
+            overhead.mixing = f64::from(load);
+            overhead.degradation = degradation;
         }
+        overhead
     }
-    None
-}
 
-struct BusySource {
-    busy_address: usize,
-    barrier: Option<Arc<Barrier>>,
-}
+    /// Returns a snapshot of the event counts recorded for
+    /// [`Config::heatmap`], empty if it's off.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use alloc_geiger::{Config, Geiger};
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: Geiger<std::alloc::System> = Geiger::with_config(
+    ///     std::alloc::System,
+    ///     Config::new().heatmap(Duration::from_millis(100)),
+    /// );
+    ///
+    /// fn main() {
+    ///     // ...
+    ///     std::fs::write("heatmap.csv", ALLOC.heatmap().to_string()).unwrap();
+    /// }
+    /// ```
+    pub fn heatmap(&self) -> Heatmap {
+        quietly(|| self.heatmap.snapshot())
+    }
 
-impl BusySource {
-    fn new() -> (Self, Arc<Barrier>) {
-        let barrier = Arc::new(Barrier::new(2));
-        let source = BusySource {
-            busy_address: BUSY.with(|busy| busy as *const _ as usize),
-            barrier: Some(Arc::clone(&barrier)),
-        };
-        (source, barrier)
+    /// Sets a function to call with the `Layout` of each allocation that the
+    /// inner allocator fails, or `None` to clear it.
+    ///
+    /// Every failure also plays a long, falling alarm tone, unless muted.  The
+    /// hook's own allocations are not counted, and it must not unwind.
+    ///
+    /// ```
+    /// use std::alloc::Layout;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.set_failure_hook(Some(|layout: Layout| {
+    ///         eprintln!("failed to allocate {} bytes", layout.size());
+    ///     }));
+    ///     // ...
+    /// }
+    /// ```
+    pub fn set_failure_hook(&self, hook: Option<fn(Layout)>) {
+        self.core.set_failure_hook(hook);
+    }
+
+    /// Returns a reference to the inner allocator, to reach its own API
+    /// through the global static.
+    ///
+    /// ```
+    /// use alloc_geiger::Geiger;
+    /// use jemallocator::Jemalloc;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: Geiger<Jemalloc> = Geiger::new(Jemalloc);
+    ///
+    /// fn main() {
+    ///     let _jemalloc: &Jemalloc = ALLOC.inner();
+    /// }
+    /// ```
+    pub fn inner(&self) -> &Alloc {
+        self.core.inner()
     }
-}
 
-impl Iterator for BusySource {
-    type Item = f32;
+    /// Returns the inner allocator, discarding the sound and the counts.
+    pub fn into_inner(self) -> Alloc {
+        self.core.into_inner()
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        BUSY.with(|busy| {
-            if self.busy_address == busy as *const _ as usize {
-                Some(0.0)
-            } else {
-                busy.set(true);
-                self.barrier.take()?.wait();
+    /// Returns the interception layer beneath the sound, as with
+    /// [`GeigerCore::set_event_hook`] for custom reactions to each event.
+    pub fn core(&self) -> &GeigerCore<Alloc, OPS> {
+        &self.core
+    }
+
+    /// Plays a pulse, first opening the output if needed.
+    fn play(&self, cue: Cue, pulse: impl Fn(u32) -> Option<Pulse>) {
+        let state = self.state.load(Ordering::Acquire);
+        if matches!(cue, Cue::Tone) && state == OutputState::Open as u8 {
+            let tone_output = self.tone_output.read();
+            if let Some(output) = &*tone_output.unwrap_or_else(PoisonError::into_inner) {
+                if output.is_alive() {
+                    output.play(cue, &pulse);
+                    return;
+                }
+            }
+        }
+        if state == OutputState::Open as u8 {
+            if let Some(output) = &*self.read_output() {
+                if output.is_alive() && !self.is_retry_due() {
+                    output.play(cue, &pulse);
+                    return;
+                }
+            }
+        } else if state == OutputState::Failed as u8 {
+            if !self.is_retry_due() {
+                return;
+            }
+        } else if state != OutputState::Uninit as u8 {
+            return;
+        }
+
+        // The output is missing, its device was lost, or it's time to retry
+        // the backends that failed, so (re)open it.
+        if self.open_output(state) {
+            if let Some(output) = &*self.read_output() {
+                output.play(cue, &pulse);
+            }
+        }
+    }
+
+    /// Opens the output of the `tone_device`, if there is one, or else
+    /// warns if that's configured.
+    fn open_tones(&self) -> Option<Output> {
+        let config = self.read_config();
+        let device = config.tone_device.as_deref().filter(|_| self.audible)?;
+        match Output::open_tones(&config, device) {
+            Ok(output) => Some(output),
+            Err(e) => {
+                if config.init_warning {
+                    eprintln!("alloc_geiger: failed to open the tone device: {e}");
+                }
                 None
             }
+        }
+    }
+
+    /// Returns `true` if some backends failed and it's time to try again.
+    fn is_retry_due(&self) -> bool {
+        let retry_at = self.retry_at.load(Ordering::Relaxed);
+        retry_at != NEVER && now_millis() >= retry_at
+    }
+
+    /// Opens the output if it's still in the given `state`, returning `false`
+    /// if another thread got to it first.
+    fn open_output(&self, state: u8) -> bool {
+        let opening = OutputState::Opening as u8;
+        if (self.state)
+            .compare_exchange(state, opening, Ordering::AcqRel, Ordering::Relaxed)
+            .is_err()
+        {
+            return false;
+        }
+        if state == OutputState::Uninit as u8 {
+            #[cfg(feature = "config-file")]
+            self.load_env_config_file();
+            self.write_config().apply_env();
+        }
+        let theme = (self.read_config().theme.as_deref()).map(Theme::load);
+        *self.theme.write().unwrap_or_else(PoisonError::into_inner) = theme;
+        self.diff.load(self.read_config().baseline.as_deref());
+        let (output, error) = if self.audible {
+            Output::open(&self.read_config())
+        } else {
+            (Some(Output::Silent), None)
+        };
+        if let Some(error) = &error {
+            if self.read_config().init_warning && !self.init_warned.swap(true, Ordering::Relaxed) {
+                eprintln!("alloc_geiger: failed to open audio output: {error}");
+            }
+        }
+        let retry_at = match self.read_config().retry {
+            Some(retry) if error.is_some() => now_millis().saturating_add(retry.as_millis() as u64),
+            _ => NEVER,
+        };
+        self.retry_at.store(retry_at, Ordering::Relaxed);
+        *self
+            .init_error
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = error;
+        let state = match output {
+            Some(_) => OutputState::Open,
+            None => OutputState::Failed,
+        };
+        *self.output.write().unwrap_or_else(PoisonError::into_inner) = output;
+        *self
+            .tone_output
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = self.open_tones();
+        self.state.store(state as u8, Ordering::Release);
+        true
+    }
+
+    /// Returns whether the output is open, to check that sound actually
+    /// engaged.
+    ///
+    /// ```no_run
+    /// use alloc_geiger::{Backend, GeigerStatus};
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.init();
+    ///     let status = ALLOC.status();
+    ///     assert_eq!(status, GeigerStatus::Active(Backend::Rodio), "no sound");
+    /// }
+    /// ```
+    pub fn status(&self) -> GeigerStatus {
+        if cfg!(feature = "off") || !self.core.enabled || !self.audible {
+            return GeigerStatus::Disabled;
+        }
+        let state = self.state.load(Ordering::Acquire);
+        if state == OutputState::Open as u8 {
+            match &*self.read_output() {
+                Some(output) => GeigerStatus::Active(output.backend()),
+                None => GeigerStatus::Uninitialized,
+            }
+        } else if state == OutputState::Failed as u8 {
+            GeigerStatus::Failed
+        } else {
+            GeigerStatus::Uninitialized
+        }
+    }
+
+    /// Returns why the configured backends failed to open, when the output
+    /// was last opened, or `None` if the first one worked or it hasn't been
+    /// opened yet.
+    ///
+    /// This tells a failed output from one that's quiet for lack of
+    /// allocations, even when a later backend like `silent` did open.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.init();
+    ///     if let Some(error) = ALLOC.init_error() {
+    ///         eprintln!("no sound: {error}");
+    ///     }
+    /// }
+    /// ```
+    pub fn init_error(&self) -> Option<GeigerError> {
+        quietly(|| {
+            let error = self.init_error.read();
+            error.unwrap_or_else(PoisonError::into_inner).clone()
         })
     }
+
+    fn read_output(&self) -> RwLockReadGuard<'_, Option<Output>> {
+        self.output.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Synthesizes the pulse for an event, as currently configured, unless
+    /// there's no voice free to play it.
+    fn pulse(
+        &self,
+        event: &AllocEvent,
+        region: Option<&RegionStats>,
+        age: Option<Duration>,
+        sample_rate: u32,
+    ) -> Option<Pulse> {
+        let config = self.read_config();
+        let voice = Voice::new(config.voices, config.steal)?;
+        let mut pulse = Pulse::new(&config, sample_rate, self.volume()).voice(voice);
+        if let Some(recording) = self.recorded(|theme| theme.recording(event.op)) {
+            pulse = pulse.recording(recording);
+        }
+        let voice = event.voice(config.pool_threads);
+        if let Some(ratio) = config.pitch.ratio(config.scale, event, voice, age) {
+            pulse = pulse.pitch(ratio);
+        }
+        // a tag is more specific than any region
+        if let Some(tag) = event.tag() {
+            pulse = pulse.pitch(tag::ratio(tag, config.scale));
+        } else if let Some(region) = region {
+            pulse = pulse.pitch(region.ratio(config.scale));
+        }
+        if let Some(gain) = config.loudness.gain(event) {
+            pulse = pulse.gain(gain);
+        }
+        if let Some(ratio) = config.bend.ratio(event) {
+            pulse = pulse.bend(ratio);
+        }
+
+        if event.op == AllocOp::AllocZeroed {
+            pulse = pulse.soften();
+        }
+        if config.align_timbre {
+            pulse = pulse.brighten(event.layout.align());
+        }
+        if let Some(pan) = config.pan.position(event, voice, &self.addresses) {
+            pulse = pulse.pan(pan);
+        }
+        Some(pulse)
+    }
 }
 
-impl Source for BusySource {
-    fn channels(&self) -> u16 {
-        1
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Returns a recording picked from the theme, if there is one.
+    fn recorded(&self, pick: impl FnOnce(&Theme) -> Option<&Recording>) -> Option<Recording> {
+        let theme = self.theme.read().unwrap_or_else(PoisonError::into_inner);
+        theme.as_ref().and_then(pick).cloned()
     }
 
-    fn sample_rate(&self) -> u32 {
-        1
+    /// The theme's alarm, or else the synthesized one.
+    fn alarm(&self, sample_rate: u32) -> Pulse {
+        match self.recorded(|theme| theme.alarm.as_ref()) {
+            Some(recording) => Pulse::recorded(sample_rate, self.volume(), recording),
+            None => Pulse::alarm(sample_rate, self.volume()),
+        }
     }
 
-    fn current_frame_len(&self) -> Option<usize> {
-        None
+    /// Returns `true` if there are no spans to play in, or the current thread
+    /// is inside one of them.
+    fn in_spans(&self, _names: &[String]) -> bool {
+        #[cfg(feature = "tracing")]
+        return _names.is_empty() || spans::inside(_names);
+        #[cfg(not(feature = "tracing"))]
+        true
     }
 
-    fn total_duration(&self) -> Option<Duration> {
-        None
+    /// Returns `true` when the live bytes first reach 90% of the cap, and
+    /// again only after they've dropped back under 80%.
+    fn nearing_cap(&self, cap: usize) -> bool {
+        let live_bytes = self.core.stats.live_bytes() as u128;
+        let cap = cap as u128;
+        if live_bytes * 10 >= cap * 9 {
+            !self.near_cap.swap(true, Ordering::Relaxed)
+        } else {
+            if live_bytes * 10 < cap * 8 && self.near_cap.load(Ordering::Relaxed) {
+                self.near_cap.store(false, Ordering::Relaxed);
+            }
+            false
+        }
     }
 }
 
-/// Simple pulse based on the sinc function, sin(x)/x
-struct Pulse {
-    range: Range<i16>,
-}
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Returns the events held back for grains, if it's time to play them,
+    /// claiming them for this thread.
+    fn grains_due(&self) -> Option<u64> {
+        if self.grains_pending.load(Ordering::Relaxed) == 0 {
+            return None;
+        }
+        let now = now_millis();
+        let due = self.grains_at.load(Ordering::Relaxed);
+        if now < due {
+            return None;
+        }
+        let next = now + GRAIN_WINDOW.as_millis() as u64;
+        (self.grains_at)
+            .compare_exchange(due, next, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()?;
+        Some(self.grains_pending.swap(0, Ordering::Relaxed))
+    }
+
+    /// Returns the net change of the live bytes since the last summary sweep,
+    /// if the next one is due, claiming it for this thread.
+    fn sweep_due(&self, interval: Duration) -> Option<i64> {
+        let now = now_millis();
+        let due = self.sweep_at.load(Ordering::Relaxed);
+        if now < due {
+            return None;
+        }
+        let next = now + interval.as_millis() as u64;
+        (self.sweep_at)
+            .compare_exchange(due, next, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()?;
+        let live = self.core.stats.live_bytes();
+        let last = self.sweep_live.swap(live, Ordering::Relaxed);
+        // the first sweep only starts the interval
+        (due != 0).then(|| live as i64 - last as i64)
+    }
 
-impl Pulse {
-    const PEAK: f32 = 0.5;
+    /// Returns `true` if this event raised the live bytes past their peak.
+    fn new_peak(&self, event: &AllocEvent) -> bool {
+        if event.op == AllocOp::Dealloc || event.address == 0 {
+            return false;
+        }
+        let live = self.core.stats.live_bytes();
+        self.peak_live.fetch_max(live, Ordering::Relaxed) < live
+    }
 
-    const SAMPLE_RATE: u32 = 48_000;
-    const PERIOD_MILLIS: u32 = 4;
-    const PERIOD_SAMPLES: u32 = Self::SAMPLE_RATE / (Self::PERIOD_MILLIS * 1000);
-    const SAMPLE_SCALE: f32 = 2.0 * PI / Self::PERIOD_SAMPLES as f32;
+    /// Returns `true` if it's been an interval since the last chime for a new
+    /// peak, claiming this one for this thread.
+    fn watermark_due(&self, interval: Duration) -> bool {
+        let now = now_millis();
+        let due = self.watermark_at.load(Ordering::Relaxed);
+        let next = now + interval.as_millis() as u64;
+        now >= due
+            && (self.watermark_at)
+                .compare_exchange(due, next, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+    }
 
-    const fn new() -> Self {
-        let i = Self::PERIOD_SAMPLES as i16 * 4;
-        Pulse { range: -i..i }
+    /// Returns the 99th percentile of the recent sizes if it's been an
+    /// interval since the last tone for it, claiming this one for this
+    /// thread.
+    fn quantile_due(&self, interval: Duration) -> Option<usize> {
+        let now = now_millis();
+        let due = self.quantile_at.load(Ordering::Relaxed);
+        if now < due {
+            return None;
+        }
+        let next = now + interval.as_millis() as u64;
+        (self.quantile_at)
+            .compare_exchange(due, next, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()?;
+        self.quantiles.get().map(|quantiles| quantiles.p99)
     }
 }
 
-impl Iterator for Pulse {
-    type Item = f32;
+/// Makes the sound for each event counted by the `GeigerCore`.
+impl<Alloc, const OPS: u8> React for Geiger<Alloc, OPS> {
+    /// Refuses growth beyond an enforced cap.
+    fn admit(&self, live_bytes: u64, growth: usize) -> bool {
+        let config = self.read_config();
+        match config.cap {
+            Some(cap) if config.cap_enforce => {
+                live_bytes.saturating_add(growth as u64) <= cap as u64
+            }
+            _ => true,
+        }
+    }
 
-    fn next(&mut self) -> Option<Self::Item> {
-        match self.range.next() {
-            None => None,
-            Some(0) => Some(Self::PEAK),
-            Some(i) => {
-                let x = f32::from(i) * Self::SAMPLE_SCALE;
-                Some(x.sin() / x * Self::PEAK)
+    /// Ignores the threads named by `quiet_threads`.
+    fn quiet(&self) -> bool {
+        let generation = self.config_generation.load(Ordering::Relaxed);
+        threads::is_quiet(generation, |name| self.read_config().is_quiet_thread(name))
+    }
+
+    fn event(&self, event: &AllocEvent, region: Option<&RegionStats>) {
+        self.addresses.record(event.address);
+        let config = self.read_config();
+        let warning = event.op != AllocOp::Dealloc
+            && (config.warn_above).is_some_and(|limit| event.new_size > limit);
+        let siren = config.cap.is_some_and(|cap| self.nearing_cap(cap));
+        let sweep = config.sweep.and_then(|interval| self.sweep_due(interval));
+        let chime = self.new_peak(event) && config.watermark.is_some_and(|i| self.watermark_due(i));
+        self.quantiles.record(event);
+        let p99 = (config.quantile_tone)
+            .filter(|_| event.op != AllocOp::Dealloc)
+            .and_then(|interval| self.quantile_due(interval));
+        let watched = event.op != AllocOp::Dealloc
+            && event.address != 0
+            && event.new_size == self.watched_size.load(Ordering::Relaxed);
+        let marked = self.marks.check(event);
+        let trapped = self.marks.check_trap(event);
+        let born = threads::born();
+        let died = threads::take_death();
+        let thread_cues = config.thread_cues;
+        let unwinding =
+            event.op != AllocOp::Dealloc && event.address != 0 && std::thread::panicking();
+        if unwinding {
+            self.unwind_allocs.fetch_add(1, Ordering::Relaxed);
+        }
+        let unwind_cue = unwinding && config.unwind_cue;
+        let recycled = config.recycle_cue && self.recycler.recycled(event);
+        #[cfg(all(feature = "aggregate", unix))]
+        let forwarded = !self.is_paused()
+            && (config.forward.as_deref()).is_some_and(|path| aggregate::forward(path, event));
+        // count even while muted, so the rates are right when unmuted
+        let exceeds = (self.diff).exceeds(config.rate_window, config.baseline_classes, event);
+        let audible = config.is_sonifying()
+            && config.ops.contains(event.op.into())
+            && self.core.stats.events() > config.warmup
+            && !self.is_muted()
+            && !self.is_paused()
+            && exceeds
+            && self.in_spans(&config.spans)
+            && (config.tags.is_empty() || event.tag().is_some_and(|tag| config.tags.contains(&tag)))
+            // after the others, as it captures a backtrace
+            && (config.silence.is_empty() || !stacks::called_from(&config.silence))
+            // a click starts its thread's dead time, even if it's then left
+            // out of the sample
+            && !config.dead_time.is_some_and(threads::in_dead_time)
+            // last, so it only counts the events that would otherwise play
+            && config.target_rate.map_or(true, |target| self.sampler.sample(target));
+        let burst = config.coalesce.then(|| event.burst());
+        // follow every chain, even if its pulses are coalesced
+        let echoes = config.echo.map_or(0, |window| echo::echoes(event, window));
+        let age = self.lifetimes.age(config.pitch, event);
+        // traces only see the thread's region
+        let recorded = RegionStats::matches(region, &config.record_regions);
+        // an address's tag is more specific than the thread's region
+        let region = self.tags.count(event).or(region);
+        self.meter.record(config.rate_window, event);
+        // above the limit, events only add to the density of the grains
+        let grained = audible
+            && (config.grains)
+                .is_some_and(|limit| self.meter.rate(config.rate_window).allocs > f64::from(limit));
+        if let Some(interval) = config.heatmap {
+            self.heatmap.record(interval, event);
+        }
+        if let Some(interval) = config.stack_interval().filter(|_| recorded) {
+            stacks::sample(interval, event, config.flamegraph.as_deref());
+        }
+        if let Some(top) = config.dhat {
+            dhat::record(top, event);
+        }
+        drop(config);
+        if watched {
+            stacks::print_watched(event.new_size);
+        }
+        self.heaptrack.record(event);
+        #[cfg(all(feature = "windows-etw", windows))]
+        etw::write(event);
+        #[cfg(all(feature = "user-events", target_os = "linux"))]
+        user_events::write(event);
+        self.massif.record(event);
+        if self.is_paused() {
+            if self.pause_buffered.load(Ordering::Relaxed) {
+                self.paused_events.record(event);
+            }
+            return;
+        }
+        #[cfg(all(feature = "aggregate", unix))]
+        if forwarded {
+            return;
+        }
+        if thread_cues && !self.is_muted() {
+            let cue = |born| {
+                self.play(Cue::Tone, |sample_rate| {
+                    Some(Pulse::thread(sample_rate, self.volume(), born))
+                });
+            };
+            if born {
+                cue(true);
+            }
+            if died {
+                cue(false);
+            }
+        }
+        if let Some(delta) = sweep.filter(|&delta| delta != 0 && !self.is_muted()) {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::sweep(sample_rate, self.volume(), delta))
+            });
+        }
+        if chime && !self.is_muted() {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::watermark(sample_rate, self.volume()))
+            });
+        }
+        if let Some(size) = p99.filter(|_| !self.is_muted()) {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::quantile(sample_rate, self.volume(), size))
+            });
+        }
+        if trapped && !self.is_muted() {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::trapped(sample_rate, self.volume()))
+            });
+        }
+        if marked && !self.is_muted() {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::marked(sample_rate, self.volume()))
+            });
+        }
+        if watched {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::ping(sample_rate, self.volume()))
+            });
+        }
+        if siren {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::siren(sample_rate, self.volume()))
+            });
+        } else if unwind_cue && !self.is_muted() {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::unwinding(sample_rate, self.volume()))
+            });
+        } else if warning {
+            self.play(Cue::Tone, |sample_rate| Some(Pulse::clunk(sample_rate)));
+        } else if grained {
+            self.grains_pending.fetch_add(1, Ordering::Relaxed);
+        } else if audible && !self.is_muted() {
+            let policy = self.policy.read().unwrap_or_else(PoisonError::into_inner);
+            let gain = policy
+                .as_ref()
+                .map_or(Some(1.0), |p| p.decide(event).gain());
+            drop(policy);
+            if let Some(gain) = gain {
+                self.play(Cue::Click(burst, event), |sample_rate| {
+                    let mut pulse = self.pulse(event, region, age, sample_rate)?;
+                    if recycled {
+                        pulse = pulse.recycled();
+                    }
+                    Some(pulse.gain(gain).echo(echoes))
+                });
             }
         }
+        if let Some(events) = self.grains_due() {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::grains(
+                    sample_rate,
+                    self.volume(),
+                    events,
+                    GRAIN_WINDOW,
+                ))
+            });
+        }
+    }
+
+    /// Plays the same alarm as a failure when a budget is exceeded.
+    fn over_budget(&self, _budget: usize) {
+        if !self.is_muted() && !self.is_paused() {
+            self.play(Cue::Tone, |sample_rate| Some(self.alarm(sample_rate)));
+        }
+    }
+
+    /// Plays a long, falling alarm tone.
+    fn failure(&self, _layout: Layout) {
+        if !self.is_muted() && !self.is_paused() {
+            self.play(Cue::Tone, |sample_rate| Some(self.alarm(sample_rate)));
+        }
     }
 }
 
-impl Source for Pulse {
-    fn channels(&self) -> u16 {
-        1
+unsafe impl<Alloc: GlobalAlloc, const OPS: u8> GlobalAlloc for Geiger<Alloc, OPS> {
+    #[inline]
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.core.alloc_with(layout, self)
     }
 
-    fn sample_rate(&self) -> u32 {
-        Self::SAMPLE_RATE
+    #[inline]
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.core.alloc_zeroed_with(layout, self)
     }
 
-    fn current_frame_len(&self) -> Option<usize> {
-        None
+    #[inline]
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.core.dealloc_with(ptr, layout, self)
     }
 
-    fn total_duration(&self) -> Option<Duration> {
-        None
+    #[inline]
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.core.realloc_with(ptr, layout, new_size, self)
+    }
+}
+
+#[cfg(all(test, not(feature = "off")))]
+mod tests {
+    use super::*;
+
+    /// The system allocator, failing anything over a mebibyte.
+    struct Small;
+
+    unsafe impl GlobalAlloc for Small {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            if layout.size() > 1 << 20 {
+                return std::ptr::null_mut();
+            }
+            std::alloc::System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            std::alloc::System.dealloc(ptr, layout)
+        }
+    }
+
+    #[test]
+    fn failures_are_not_live() {
+        let geiger: Geiger<Small> = Geiger::metrics_only(Small);
+        geiger.set_config(Config::new().cap((1 << 30) + 2000).cap_enforce(true));
+        unsafe {
+            let huge = Layout::from_size_align(1 << 30, 1).unwrap();
+            assert!(geiger.alloc(huge).is_null());
+            assert_eq!(geiger.core.stats.live_bytes(), 0);
+
+            let layout = Layout::from_size_align(4096, 8).unwrap();
+            let ptr = geiger.alloc(layout);
+            assert!(!ptr.is_null());
+            // a failed realloc leaves the old block allocated
+            assert!(geiger.realloc(ptr, layout, 1 << 30).is_null());
+            assert_eq!(geiger.core.stats.live_bytes(), 4096);
+            geiger.dealloc(ptr, layout);
+        }
+        assert_eq!(geiger.core.stats.live_bytes(), 0);
     }
 }
@@ -0,0 +1,169 @@
+use crate::event::{AllocEvent, AllocOp};
+use crate::{quietly, stacks, Geiger};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// The name of the mark in the report of a [`Geiger::trap_next`].
+const TRAP: &str = "trap_next";
+
+thread_local! {
+    /// Whether this thread's next allocation is trapped.
+    static TRAPPED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// The first allocation after a [`Geiger::mark`], on any thread, or after a
+/// [`Geiger::trap_next`] on its thread.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct MarkedAlloc {
+    /// The name given to the mark, or `"trap_next"` for a trap.
+    pub mark: &'static str,
+    /// The size allocated, which for a reallocation is the new size.
+    pub size: usize,
+    /// The stack of the allocating call, one `at` line for each frame from
+    /// the caller of the allocator outward.
+    pub backtrace: String,
+}
+
+/// The latest mark still waiting for an allocation, and what each one found.
+pub(crate) struct Marks {
+    armed: AtomicBool,
+    pending: Mutex<Option<&'static str>>,
+    found: Mutex<Vec<MarkedAlloc>>,
+    trapped: Mutex<Option<MarkedAlloc>>,
+}
+
+impl Marks {
+    pub(crate) const fn new() -> Self {
+        Marks {
+            armed: AtomicBool::new(false),
+            pending: Mutex::new(None),
+            found: Mutex::new(Vec::new()),
+            trapped: Mutex::new(None),
+        }
+    }
+
+    /// Records an event if it's the first allocation after a mark, returning
+    /// `true` if so.
+    pub(crate) fn check(&self, event: &AllocEvent) -> bool {
+        if event.op == AllocOp::Dealloc
+            || event.address == 0
+            || !self.armed.load(Ordering::Relaxed)
+            || !self.armed.swap(false, Ordering::Relaxed)
+        {
+            return false;
+        }
+        let pending = self.pending.lock().unwrap_or_else(PoisonError::into_inner);
+        let Some(mark) = *pending else {
+            return false;
+        };
+        drop(pending);
+        let hit = report(mark, event);
+        quietly(|| {
+            let mut found = self.found.lock().unwrap_or_else(PoisonError::into_inner);
+            found.push(hit);
+        });
+        true
+    }
+
+    /// Records an event if it's the first allocation on this thread after a
+    /// trap, returning `true` if so.
+    pub(crate) fn check_trap(&self, event: &AllocEvent) -> bool {
+        if event.op == AllocOp::Dealloc
+            || event.address == 0
+            || !TRAPPED.with(|trapped| trapped.replace(false))
+        {
+            return false;
+        }
+        let hit = report(TRAP, event);
+        *self.trapped.lock().unwrap_or_else(PoisonError::into_inner) = Some(hit);
+        true
+    }
+}
+
+/// Prints the stack of the allocation that hit a mark to stderr, and returns
+/// it.
+fn report(mark: &'static str, event: &AllocEvent) -> MarkedAlloc {
+    quietly(|| {
+        let backtrace = stacks::describe();
+        eprint!(
+            "alloc_geiger: allocated {} bytes after mark {mark:?}\n{backtrace}",
+            event.new_size
+        );
+        MarkedAlloc {
+            mark,
+            size: event.new_size,
+            backtrace,
+        }
+    })
+}
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Marks a point in the program, like the end of its initialization, so
+    /// the first allocation after it on any thread plays a distinct cue and
+    /// prints its stack to stderr, answering whether anything allocates
+    /// after that point with one sound and one stack.
+    ///
+    /// A new mark replaces one that hasn't been hit yet.  Each hit is also
+    /// kept for [`marked_allocs`](Self::marked_allocs).
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let mut buffer = Vec::with_capacity(16);
+    ///     ALLOC.mark("init done");
+    ///     buffer.extend_from_slice(&[0u8; 32]);
+    ///     let hits = ALLOC.marked_allocs();
+    ///     assert_eq!(hits[0].mark, "init done");
+    ///     assert_eq!(hits[0].size, 32);
+    /// }
+    /// ```
+    pub fn mark(&self, name: &'static str) {
+        let mut pending = (self.marks.pending.lock()).unwrap_or_else(PoisonError::into_inner);
+        *pending = Some(name);
+        self.marks.armed.store(true, Ordering::Relaxed);
+    }
+
+    /// Arms a one-shot trap on the calling thread, so its next allocation
+    /// plays a distinct cue and prints its stack to stderr, answering what
+    /// allocated right after some point, like the handling of a button press.
+    ///
+    /// Unlike a [`mark`](Self::mark), other threads don't spring the trap.
+    /// The report is kept for [`trapped_alloc`](Self::trapped_alloc).
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.trap_next();
+    ///     let _name = String::from("button");
+    ///     let hit = ALLOC.trapped_alloc().unwrap();
+    ///     assert_eq!(hit.size, 6);
+    /// }
+    /// ```
+    pub fn trap_next(&self) {
+        TRAPPED.with(|trapped| trapped.set(true));
+    }
+
+    /// Returns the allocation that sprang the latest
+    /// [`trap_next`](Self::trap_next), on any thread.
+    pub fn trapped_alloc(&self) -> Option<MarkedAlloc> {
+        quietly(|| {
+            let trapped = self.marks.trapped.lock();
+            trapped.unwrap_or_else(PoisonError::into_inner).clone()
+        })
+    }
+
+    /// Returns the first allocation after each [`mark`](Self::mark) that had
+    /// one, in order.
+    pub fn marked_allocs(&self) -> Vec<MarkedAlloc> {
+        quietly(|| {
+            let found = self.marks.found.lock();
+            found.unwrap_or_else(PoisonError::into_inner).clone()
+        })
+    }
+}
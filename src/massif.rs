@@ -0,0 +1,250 @@
+//! Heap snapshots in Valgrind massif's output format.
+
+use crate::event::{size_class, AllocEvent, AllocOp, SIZE_CLASSES};
+use crate::{quietly, Geiger, BUSY};
+use std::cmp::Reverse;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+/// Set to take a snapshot as soon as possible, by
+/// [`Geiger::massif_snapshot`] or a signal.
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// How often the snapshot thread checks for a requested snapshot.
+const POLL: Duration = Duration::from_millis(10);
+
+/// Live bytes by power-of-two size class, counted while snapshots are taken.
+pub(crate) struct SizeHistogram {
+    active: AtomicBool,
+    live: [AtomicI64; SIZE_CLASSES],
+}
+
+impl SizeHistogram {
+    pub(crate) const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicI64 = AtomicI64::new(0);
+        SizeHistogram {
+            active: AtomicBool::new(false),
+            live: [ZERO; SIZE_CLASSES],
+        }
+    }
+
+    pub(crate) fn record(&self, event: &AllocEvent) {
+        if !self.active.load(Ordering::Relaxed) || event.address == 0 {
+            return;
+        }
+        let add = |size: usize, sign: i64| {
+            self.live[size_class(size)].fetch_add(sign * size as i64, Ordering::Relaxed);
+        };
+        match event.op {
+            AllocOp::Alloc | AllocOp::AllocZeroed => add(event.new_size, 1),
+            AllocOp::Dealloc => add(event.layout.size(), -1),
+            AllocOp::Realloc => {
+                add(event.layout.size(), -1);
+                add(event.new_size, 1);
+            }
+        }
+    }
+
+    /// The live bytes of each size class that has any.
+    fn classes(&self) -> Vec<(usize, u64)> {
+        (self.live.iter().enumerate())
+            .filter_map(|(class, live)| {
+                let bytes = live.load(Ordering::Relaxed);
+                (bytes > 0).then_some((class, bytes as u64))
+            })
+            .collect()
+    }
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread writing heap snapshots to a file in Valgrind massif's
+    /// output format at every `interval`, for `ms_print` or
+    /// massif-visualizer to chart memory over time.
+    ///
+    /// Each snapshot has the live bytes as the heap size, broken down by
+    /// power-of-two size class instead of by call stack.  Only the sizes of
+    /// allocations made since the thread started are known, so any live
+    /// bytes from before then are shown as one more part of the tree.
+    ///
+    /// More snapshots can be taken on demand with
+    /// [`massif_snapshot`](Self::massif_snapshot), or on Unix by sending the
+    /// process `SIGUSR2`, unless it already handles that signal.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_massif("massif.out", Duration::from_millis(100)).unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_massif<P: AsRef<Path>>(
+        &'static self,
+        path: P,
+        interval: Duration,
+    ) -> io::Result<JoinHandle<()>> {
+        let mut file = quietly(|| -> io::Result<_> {
+            let mut file = BufWriter::new(File::create(path)?);
+            let command: Vec<String> = env::args().collect();
+            writeln!(file, "desc: alloc_geiger")?;
+            writeln!(file, "cmd: {}", command.join(" "))?;
+            writeln!(file, "time_unit: ms")?;
+            Ok(file)
+        })?;
+        self.massif.active.store(true, Ordering::Relaxed);
+        #[cfg(unix)]
+        handle_signal();
+        thread::Builder::new()
+            .name("alloc_geiger-massif".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let start = Instant::now();
+                let mut next = start;
+                for snapshot in 0.. {
+                    while Instant::now() < next && !REQUESTED.swap(false, Ordering::Relaxed) {
+                        thread::sleep(POLL);
+                    }
+                    let time = start.elapsed().as_millis();
+                    if self.write_snapshot(&mut file, snapshot, time).is_err() {
+                        return;
+                    }
+                    next = Instant::now() + interval;
+                }
+            })
+    }
+
+    /// Asks the thread from [`spawn_massif`](Self::spawn_massif) to take a
+    /// snapshot now.
+    pub fn massif_snapshot(&self) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+
+    fn write_snapshot(&self, file: &mut impl Write, snapshot: u64, time: u128) -> io::Result<()> {
+        let total = self.stats().live_bytes();
+        let mut parts: Vec<(u64, String)> = (self.massif.classes().into_iter())
+            .map(|(class, bytes)| {
+                let (low, high) = match class {
+                    0 => (0, 0),
+                    _ => (1u128 << (class - 1), (1u128 << class) - 1),
+                };
+                (bytes, format!("allocations of {low} to {high} bytes"))
+            })
+            .collect();
+        let known: u64 = parts.iter().map(|(bytes, _)| bytes).sum();
+        let before = total.saturating_sub(known);
+        if before > 0 {
+            parts.push((before, "allocations from before snapshots started".into()));
+        }
+        // massif lists the largest parts first
+        parts.sort_by_key(|&(bytes, _)| Reverse(bytes));
+        writeln!(file, "#-----------")?;
+        writeln!(file, "snapshot={snapshot}")?;
+        writeln!(file, "#-----------")?;
+        writeln!(file, "time={time}")?;
+        writeln!(file, "mem_heap_B={total}")?;
+        writeln!(file, "mem_heap_extra_B=0")?;
+        writeln!(file, "mem_stacks_B=0")?;
+        writeln!(file, "heap_tree=detailed")?;
+        writeln!(
+            file,
+            "n{}: {} (heap allocation functions) malloc/new/new[], --alloc-fns, etc.",
+            parts.len(),
+            total.max(known)
+        )?;
+        for (bytes, label) in parts {
+            writeln!(file, " n0: {bytes} 0x0: {label}")?;
+        }
+        file.flush()
+    }
+}
+
+/// Takes a snapshot on `SIGUSR2`, if nothing else handles it.
+#[cfg(unix)]
+fn handle_signal() {
+    extern "C" fn handler(_signal: libc::c_int) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+    // SAFETY: `sigaction` structs are plain data, and the handler only
+    // stores to an atomic, which is async-signal-safe.
+    unsafe {
+        let mut old: libc::sigaction = std::mem::zeroed();
+        if libc::sigaction(libc::SIGUSR2, std::ptr::null(), &mut old) == 0
+            && old.sa_sigaction == libc::SIG_DFL
+        {
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handler as *const () as libc::sighandler_t;
+            action.sa_flags = libc::SA_RESTART;
+            libc::sigaction(libc::SIGUSR2, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::{GlobalAlloc, Layout, System};
+
+    #[test]
+    fn writes_snapshots_by_size_class() {
+        let geiger: Geiger<System> = Geiger::metrics_only(System);
+        let layout = |size| Layout::from_size_align(size, 8).unwrap();
+        let mut snapshot = Vec::new();
+        unsafe {
+            let before = geiger.alloc(layout(1000));
+            geiger.massif.active.store(true, Ordering::Relaxed);
+            let small = geiger.alloc(layout(100));
+            let grown = geiger.realloc(geiger.alloc(layout(100)), layout(100), 200);
+            let large = geiger.alloc_zeroed(layout(3000));
+            let freed = geiger.alloc(layout(50));
+            geiger.dealloc(freed, layout(50));
+            geiger.write_snapshot(&mut snapshot, 3, 42).unwrap();
+            for (ptr, size) in [(before, 1000), (small, 100), (grown, 200), (large, 3000)] {
+                geiger.dealloc(ptr, layout(size));
+            }
+        }
+        let expected = "\
+#-----------
+snapshot=3
+#-----------
+time=42
+mem_heap_B=4300
+mem_heap_extra_B=0
+mem_stacks_B=0
+heap_tree=detailed
+n4: 4300 (heap allocation functions) malloc/new/new[], --alloc-fns, etc.
+ n0: 3000 0x0: allocations of 2048 to 4095 bytes
+ n0: 1000 0x0: allocations from before snapshots started
+ n0: 200 0x0: allocations of 128 to 255 bytes
+ n0: 100 0x0: allocations of 64 to 127 bytes
+";
+        assert_eq!(String::from_utf8(snapshot).unwrap(), expected);
+        assert!(geiger.massif.classes().is_empty());
+    }
+
+    #[test]
+    fn counts_only_while_active() {
+        let histogram = SizeHistogram::new();
+        let layout = Layout::from_size_align(16, 1).unwrap();
+        let event = AllocEvent::new(AllocOp::Alloc, layout, 0x1000 as *mut u8);
+        histogram.record(&event);
+        assert!(histogram.classes().is_empty());
+        histogram.active.store(true, Ordering::Relaxed);
+        histogram.record(&event);
+        // a failed allocation has no address
+        histogram.record(&AllocEvent::new(
+            AllocOp::Alloc,
+            layout,
+            std::ptr::null_mut(),
+        ));
+        assert_eq!(histogram.classes(), [(5, 16)]);
+    }
+}
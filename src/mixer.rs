@@ -0,0 +1,806 @@
+use crate::clock::now_millis;
+use crate::config::Config;
+use crate::priority;
+use crate::pulse::Pulse;
+use crate::BUSY;
+#[cfg(feature = "sound-rodio")]
+use rodio::source::UniformSourceIterator;
+#[cfg(feature = "sound-rodio")]
+use rodio::Source;
+use std::mem;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// The sample rate used when the device's rate is unknown.
+pub(crate) const DEFAULT_SAMPLE_RATE: u32 = 48_000;
+
+/// The most clicks played in a single tick, however many events there were.
+const MAX_CLICKS: usize = 8;
+
+/// With a tempo, the number of events in a step that plays at full velocity.
+const FULL_VELOCITY: u64 = 64;
+
+/// How many frames the mixer plays between checks of its queue.
+const POLL_FRAMES: u32 = 64;
+
+/// How often the mixer's own load is measured, as a fraction of a second of
+/// the frames it plays.
+const LOAD_WINDOW: u32 = 4;
+
+/// The most the clicks are degraded for the mixer's load, each level only
+/// playing half as many as the last.
+const MAX_DEGRADATION: u32 = 6;
+
+/// A pulse waiting for the mixer, standing for a burst of similar events.
+struct Queued {
+    pulse: Pulse,
+    /// what makes events similar enough to coalesce, if they may be
+    burst: Option<u32>,
+    /// the number of events coalesced into this pulse
+    events: u32,
+}
+
+/// A source from the application, converted to the mixer's stereo frames.
+#[cfg(feature = "sound-rodio")]
+type Custom = UniformSourceIterator<Box<dyn Source<Item = f32> + Send>, f32>;
+
+/// The state shared between allocating threads and a [`Mixer`].
+pub(crate) struct Shared {
+    /// pulses waiting for the mixer to pick them up
+    queue: Mutex<Vec<Queued>>,
+    /// custom sources waiting for the mixer
+    #[cfg(feature = "sound-rodio")]
+    sources: Mutex<Vec<Custom>>,
+    ticker: Option<Ticker>,
+    sample_rate: u32,
+    /// when the audio thread last polled the mixer
+    heartbeat: AtomicU64,
+    /// the most of the audio thread's time that mixing may take, from
+    /// `Config::mix_budget`
+    budget: Option<f32>,
+    /// the last measured fraction of the time spent mixing, as `f32` bits
+    load: AtomicU32,
+    /// how far the clicks are degraded to keep to the budget, and the clicks
+    /// counted toward sampling them
+    degradation: AtomicU32,
+    sampled: AtomicU32,
+}
+
+impl Shared {
+    /// Queues a pulse to be mixed in.
+    pub(crate) fn play(&self, pulse: Pulse) {
+        self.queue(pulse, None);
+    }
+
+    fn queue(&self, pulse: Pulse, burst: Option<u32>) {
+        let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+        queue.push(Queued {
+            pulse,
+            burst,
+            events: 1,
+        });
+    }
+
+    /// Counts a click for the tick, or queues it if there's no tick.
+    ///
+    /// Clicks in the same `burst` are coalesced while they wait for the
+    /// mixer, into one pulse that's louder for each of them.
+    ///
+    /// While degraded for the mixer's load, only a sample of the clicks is
+    /// played, half as many for each level.
+    pub(crate) fn click(&self, burst: Option<u32>, pulse: impl FnOnce(u32) -> Option<Pulse>) {
+        let degradation = self.degradation();
+        if degradation > 0 {
+            let sampled = self.sampled.fetch_add(1, Ordering::Relaxed);
+            if sampled & ((1 << degradation) - 1) != 0 {
+                return;
+            }
+        }
+        match &self.ticker {
+            Some(ticker) => ticker.click(|| pulse(self.sample_rate)),
+            None => {
+                if let Some(burst) = burst {
+                    let mut queue = self.queue.lock().unwrap_or_else(PoisonError::into_inner);
+                    if let Some(queued) = queue.iter_mut().find(|q| q.burst == Some(burst)) {
+                        queued.events += 1;
+                        return;
+                    }
+                }
+                if let Some(pulse) = pulse(self.sample_rate) {
+                    self.queue(pulse, burst);
+                }
+            }
+        }
+    }
+
+    /// Queues a custom source to be mixed in, at the mixer's sample rate.
+    #[cfg(feature = "sound-rodio")]
+    pub(crate) fn play_source(&self, source: Box<dyn Source<Item = f32> + Send>) {
+        let source = UniformSourceIterator::new(source, 2, self.sample_rate);
+        let mut sources = self.sources.lock().unwrap_or_else(PoisonError::into_inner);
+        sources.push(source);
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Milliseconds since the audio thread last polled the mixer.
+    pub(crate) fn silence_millis(&self) -> u64 {
+        now_millis().saturating_sub(self.heartbeat.load(Ordering::Relaxed))
+    }
+
+    /// The last measured fraction of the audio thread's time spent mixing.
+    pub(crate) fn load(&self) -> f32 {
+        f32::from_bits(self.load.load(Ordering::Relaxed))
+    }
+
+    /// How far the clicks are degraded for the mixer's load, from `0` for
+    /// not at all.
+    pub(crate) fn degradation(&self) -> u32 {
+        self.degradation.load(Ordering::Relaxed)
+    }
+
+    /// Notes a measured load, degrading the clicks another level while it's
+    /// over the budget, and recovering a level once it's well under.
+    fn measured(&self, load: f32) {
+        self.load.store(load.to_bits(), Ordering::Relaxed);
+        let Some(budget) = self.budget else {
+            return;
+        };
+        let degradation = self.degradation();
+        if load > budget {
+            let degradation = (degradation + 1).min(MAX_DEGRADATION);
+            self.degradation.store(degradation, Ordering::Relaxed);
+        } else if load < budget / 4.0 && degradation > 0 {
+            self.degradation.store(degradation - 1, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Signals the first poll of a mixer, once its audio thread is marked busy,
+/// to the thread opening the output.
+///
+/// The audio thread never waits on this, so a device that starts late only
+/// delays the opening thread, and one that never starts delays it until the
+/// timeout.
+pub(crate) struct Started {
+    started: Mutex<bool>,
+    condvar: Condvar,
+}
+
+impl Started {
+    fn signal(&self) {
+        *self.started.lock().unwrap_or_else(PoisonError::into_inner) = true;
+        self.condvar.notify_all();
+    }
+
+    /// Waits for the first poll, for at most `timeout` if there is one,
+    /// failing if the callback never ran, as with a broken sound server or a
+    /// suspended device.
+    #[cfg(any(
+        feature = "sound-rodio",
+        all(feature = "android", target_os = "android")
+    ))]
+    pub(crate) fn wait(&self, timeout: Option<Duration>) -> Result<(), String> {
+        let started = self.started.lock().unwrap_or_else(PoisonError::into_inner);
+        let not_started = |started: &mut bool| !*started;
+        let started = match timeout {
+            Some(timeout) => {
+                let waited = self
+                    .condvar
+                    .wait_timeout_while(started, timeout, not_started);
+                waited.unwrap_or_else(PoisonError::into_inner).0
+            }
+            None => {
+                let waited = self.condvar.wait_while(started, not_started);
+                waited.unwrap_or_else(PoisonError::into_inner)
+            }
+        };
+        if !*started {
+            crate::output::TIMED_OUT.store(true, Ordering::Relaxed);
+            let millis = timeout.unwrap_or_default().as_millis();
+            return Err(format!("the audio callback didn't run within {millis} ms"));
+        }
+        Ok(())
+    }
+}
+
+/// How the mixer groups clicks in time, from
+/// [`Config::tick`](crate::Config::tick) or
+/// [`Config::tempo`](crate::Config::tempo).
+#[derive(Clone, Copy)]
+pub(crate) enum Grid {
+    /// Ticks of this length, each spreading up to `MAX_CLICKS` clicks.
+    Tick(Duration),
+    /// Sixteenth notes at this many beats per minute, each playing one click
+    /// on the step as loud as the number of events in it.
+    Tempo(u32),
+}
+
+impl Grid {
+    /// How the mixer groups clicks for `config`, with the tempo taking
+    /// precedence.
+    pub(crate) fn of(config: &Config) -> Option<Grid> {
+        match (config.tempo, config.tick) {
+            (Some(bpm), _) => Some(Grid::Tempo(bpm)),
+            (None, Some(tick)) => Some(Grid::Tick(tick)),
+            (None, None) => None,
+        }
+    }
+
+    fn len(self) -> Duration {
+        match self {
+            Grid::Tick(tick) => tick,
+            Grid::Tempo(bpm) => Duration::from_secs(15) / bpm.max(1),
+        }
+    }
+}
+
+/// Clicks counted for a tick.
+struct Ticker {
+    /// events since the last tick
+    pending: AtomicU64,
+    /// a fresh click for the mixer to take at its next tick
+    click: Mutex<Option<Vec<f32>>>,
+    /// the buffer of the click the mixer replaced, to render the next one
+    spare: Mutex<Vec<f32>>,
+    /// samples per tick
+    len: usize,
+    /// whether to play one click per tick with a velocity, as for a tempo
+    quantize: bool,
+}
+
+impl Ticker {
+    /// Counts an event, and renders a fresh click if the mixer took the last
+    /// one, so there's at most one rendering per tick.
+    fn click(&self, pulse: impl FnOnce() -> Option<Pulse>) {
+        self.pending.fetch_add(1, Ordering::Relaxed);
+        let Ok(mut click) = self.click.try_lock() else {
+            return;
+        };
+        if click.is_none() {
+            *click = pulse().map(|pulse| {
+                let spare = self
+                    .spare
+                    .try_lock()
+                    .map(|mut spare| mem::take(&mut *spare));
+                render(pulse, spare.unwrap_or_default())
+            });
+        }
+    }
+}
+
+/// Renders a pulse in mono, reusing a buffer.
+fn render(mut pulse: Pulse, mut buffer: Vec<f32>) -> Vec<f32> {
+    buffer.clear();
+    let stereo = pulse.channels() == 2;
+    while let Some(left) = pulse.next() {
+        let right = if stereo { pulse.next() } else { None };
+        buffer.push(left + right.unwrap_or(0.0));
+    }
+    buffer
+}
+
+/// The mixer's side of a tick.
+struct Tick {
+    /// the latest click, which may be reused for many ticks
+    click: Vec<f32>,
+    /// the position within the current tick
+    index: usize,
+    /// offsets of the clicks still to start in this tick, latest first
+    starts: Vec<usize>,
+    /// positions within `click` of those that are playing, and their gain
+    playing: Vec<(usize, f32)>,
+    /// the gain of clicks starting in this tick
+    velocity: f32,
+}
+
+impl Tick {
+    /// Plays the next sample of the tick's clicks.
+    fn next(&mut self, ticker: &Ticker) -> f32 {
+        if self.index == 0 {
+            if let Ok(mut click) = ticker.click.try_lock() {
+                if let Some(click) = click.take() {
+                    let old = mem::replace(&mut self.click, click);
+                    if let Ok(mut spare) = ticker.spare.try_lock() {
+                        *spare = old;
+                    }
+                }
+            }
+            let pending = ticker.pending.swap(0, Ordering::Relaxed);
+            self.starts.clear();
+            if ticker.quantize {
+                if pending > 0 {
+                    self.starts.push(0);
+                }
+                self.velocity = velocity(pending);
+            } else {
+                let clicks = (pending as usize).min(MAX_CLICKS);
+                (self.starts).extend((0..clicks).rev().map(|i| i * ticker.len / clicks));
+            }
+        }
+        while self.starts.last() == Some(&self.index) {
+            self.starts.pop();
+            self.playing.push((0, self.velocity));
+        }
+        self.index = (self.index + 1) % ticker.len;
+
+        let click = &self.click;
+        let sample = (self.playing.iter())
+            .filter_map(|&(i, gain)| Some(click.get(i)? * gain))
+            .sum();
+        self.playing.retain_mut(|(i, _)| {
+            *i += 1;
+            *i < click.len()
+        });
+        sample
+    }
+}
+
+/// The gain of a pulse coalesced from a burst of events, up to triple for 16
+/// or more.
+fn burst_gain(events: u32) -> f32 {
+    (1.0 + (events as f32).log2() / 2.0).min(3.0)
+}
+
+/// The gain of a quantized click for the number of events in its step, from a
+/// quarter for one event up to full for `FULL_VELOCITY` or more.
+fn velocity(events: u64) -> f32 {
+    let density = (events.max(1) as f32).log2() / (FULL_VELOCITY as f32).log2();
+    0.25 + 0.75 * density.min(1.0)
+}
+
+/// The single long-lived source of an output, which mixes all of its pulses
+/// so they don't each need their own `play_raw`.
+///
+/// The mix goes through a [`Limiter`], so a burst of overlapping pulses is
+/// ducked rather than clipped, while isolated clicks play as they are.
+///
+/// On its first poll, it marks the audio thread as busy, so its allocations
+/// aren't counted, and raises its priority if asked, and then it keeps a
+/// heartbeat for as long as it's polled.
+///
+/// With a tick, events are played as up to `MAX_CLICKS` clicks per tick,
+/// spread evenly across it, which bounds the mixing however fast the
+/// allocator is going.
+///
+/// Pulses are queued by value rather than boxed, and the buffers of echoes
+/// and ticks are reused, so once the queues have grown to fit, playing
+/// allocates nothing that would distort the inner allocator's behavior.
+pub(crate) struct Mixer {
+    shared: Arc<Shared>,
+    busy_address: usize,
+    started: Option<Arc<Started>>,
+    /// whether to raise the audio thread's priority on the first poll
+    boost: bool,
+    pulses: Vec<Pulse>,
+    #[cfg(feature = "sound-rodio")]
+    sources: Vec<Custom>,
+    tick: Tick,
+    /// frames until the next check of the queue
+    countdown: u32,
+    /// the estimated time spent mixing, and the frames it's for, since the
+    /// load was last measured
+    mixing: Duration,
+    timed: u32,
+    /// the right sample of the current frame, still to be played
+    right: Option<f32>,
+    limiter: Limiter,
+}
+
+/// A peak limiter with a fast attack and a slow release, which leaves a mix
+/// under its threshold alone and ducks it smoothly during bursts.
+struct Limiter {
+    /// the followed peak level
+    envelope: f32,
+    /// how far the envelope moves toward a higher or lower peak each frame
+    attack: f32,
+    release: f32,
+}
+
+impl Limiter {
+    /// The highest level let through, a little under full scale.
+    const THRESHOLD: f32 = 0.7;
+    const ATTACK: Duration = Duration::from_millis(1);
+    const RELEASE: Duration = Duration::from_millis(200);
+
+    fn new(sample_rate: u32) -> Self {
+        let coefficient =
+            |time: Duration| 1.0 - (-1.0 / (time.as_secs_f32() * sample_rate as f32)).exp();
+        Limiter {
+            envelope: 0.0,
+            attack: coefficient(Self::ATTACK),
+            release: coefficient(Self::RELEASE),
+        }
+    }
+
+    /// Follows the peak of a frame, returning the gain to play it with.
+    fn gain(&mut self, peak: f32) -> f32 {
+        let rate = if peak > self.envelope {
+            self.attack
+        } else {
+            self.release
+        };
+        self.envelope += (peak - self.envelope) * rate;
+        if self.envelope > Self::THRESHOLD {
+            Self::THRESHOLD / self.envelope
+        } else {
+            1.0
+        }
+    }
+}
+
+impl Mixer {
+    pub(crate) fn new(
+        sample_rate: u32,
+        grid: Option<Grid>,
+        boost: bool,
+        budget: Option<f32>,
+    ) -> (Self, Arc<Started>, Arc<Shared>) {
+        let ticker = grid.map(|grid| Ticker {
+            pending: AtomicU64::new(0),
+            click: Mutex::new(None),
+            spare: Mutex::new(Vec::new()),
+            len: ((grid.len().as_secs_f64() * f64::from(sample_rate)) as usize).max(1),
+            quantize: matches!(grid, Grid::Tempo(_)),
+        });
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(Vec::new()),
+            #[cfg(feature = "sound-rodio")]
+            sources: Mutex::new(Vec::new()),
+            ticker,
+            sample_rate,
+            heartbeat: AtomicU64::new(now_millis()),
+            budget: budget
+                .filter(|&percent| percent > 0.0 && percent <= 100.0)
+                .map(|percent| percent / 100.0),
+            load: AtomicU32::new(0),
+            degradation: AtomicU32::new(0),
+            sampled: AtomicU32::new(0),
+        });
+        let started = Arc::new(Started {
+            started: Mutex::new(false),
+            condvar: Condvar::new(),
+        });
+        let mixer = Mixer {
+            shared: Arc::clone(&shared),
+            busy_address: BUSY.with(|busy| busy as *const _ as usize),
+            started: Some(Arc::clone(&started)),
+            boost,
+            pulses: Vec::new(),
+            #[cfg(feature = "sound-rodio")]
+            sources: Vec::new(),
+            tick: Tick {
+                click: Vec::new(),
+                index: 0,
+                starts: Vec::with_capacity(MAX_CLICKS),
+                playing: Vec::new(),
+                velocity: 1.0,
+            },
+            countdown: 0,
+            mixing: Duration::ZERO,
+            timed: 0,
+            right: None,
+            limiter: Limiter::new(sample_rate),
+        };
+        (mixer, started, shared)
+    }
+
+    /// Marks the output as lost, so it's opened again without waiting for the
+    /// heartbeat to time out.
+    #[cfg(all(feature = "android", target_os = "android"))]
+    pub(crate) fn lost(&self) {
+        self.shared.heartbeat.store(0, Ordering::Relaxed);
+    }
+
+    /// Marks the audio thread as busy, picks up queued pulses, and keeps the
+    /// heartbeat.
+    fn poll(&mut self) {
+        if let Some(started) = self.started.take() {
+            BUSY.with(|busy| {
+                if self.busy_address != busy as *const _ as usize {
+                    busy.set(true);
+                    #[cfg(feature = "diagnostics")]
+                    crate::diagnostics::mark_audio_thread();
+                }
+            });
+            started.signal();
+            if self.boost {
+                priority::boost();
+            }
+        }
+        let heartbeat = &self.shared.heartbeat;
+        heartbeat.store(now_millis(), Ordering::Relaxed);
+        // Don't block the audio thread; busy pulses can wait for next time.
+        if let Ok(mut queue) = self.shared.queue.try_lock() {
+            (self.pulses).extend(queue.drain(..).map(|queued| match queued.events {
+                1 => queued.pulse,
+                events => queued.pulse.gain(burst_gain(events)),
+            }));
+        }
+        #[cfg(feature = "sound-rodio")]
+        if let Ok(mut sources) = self.shared.sources.try_lock() {
+            self.sources.append(&mut sources);
+        }
+    }
+
+    /// Plays the next stereo frame, polling and timing one of every
+    /// `POLL_FRAMES`.
+    fn frame(&mut self) -> (f32, f32) {
+        if self.countdown > 0 {
+            self.countdown -= 1;
+            return self.mix();
+        }
+        let start = Instant::now();
+        self.poll();
+        let polled = Instant::now();
+        self.countdown = POLL_FRAMES - 1;
+        let frame = self.mix();
+        self.time(polled - start, polled.elapsed());
+        frame
+    }
+
+    /// Adds the time of a poll, once, and of mixing the frame after it,
+    /// scaled up for the frames that weren't timed, and measures the load
+    /// after each window.
+    fn time(&mut self, poll: Duration, mix: Duration) {
+        self.mixing += poll + mix * POLL_FRAMES;
+        self.timed += POLL_FRAMES;
+        let sample_rate = self.shared.sample_rate;
+        if self.timed >= sample_rate / LOAD_WINDOW {
+            let played = f64::from(self.timed) / f64::from(sample_rate);
+            self.shared
+                .measured((self.mixing.as_secs_f64() / played) as f32);
+            self.mixing = Duration::ZERO;
+            self.timed = 0;
+        }
+    }
+
+    /// Mixes the next stereo frame.
+    fn mix(&mut self) -> (f32, f32) {
+        let (mut left, mut right) = (0.0, 0.0);
+        self.pulses.retain_mut(|pulse| {
+            let stereo = pulse.channels() == 2;
+            let Some(sample) = pulse.next() else {
+                return false;
+            };
+            left += sample;
+            right += if stereo {
+                pulse.next().unwrap_or(0.0)
+            } else {
+                sample
+            };
+            true
+        });
+        #[cfg(feature = "sound-rodio")]
+        self.sources.retain_mut(|source| {
+            let Some(sample) = source.next() else {
+                return false;
+            };
+            left += sample;
+            right += source.next().unwrap_or(0.0);
+            true
+        });
+        if let Some(ticker) = &self.shared.ticker {
+            let click = self.tick.next(ticker);
+            left += click;
+            right += click;
+        }
+        let gain = self.limiter.gain(left.abs().max(right.abs()));
+        // the attack still lets the start of a burst overshoot a little
+        let (left, right) = (left * gain, right * gain);
+        (left.clamp(-1.0, 1.0), right.clamp(-1.0, 1.0))
+    }
+}
+
+impl Iterator for Mixer {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        let (left, right) = self.frame();
+        self.right = Some(right);
+        Some(left)
+    }
+}
+
+#[cfg(feature = "sound-rodio")]
+impl Source for Mixer {
+    fn channels(&self) -> u16 {
+        2
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.shared.sample_rate
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A ticker of `len` samples with a click of two full samples ready.
+    fn ticker(len: usize, quantize: bool, pending: u64) -> Ticker {
+        Ticker {
+            pending: AtomicU64::new(pending),
+            click: Mutex::new(Some(vec![1.0, 1.0])),
+            spare: Mutex::new(Vec::new()),
+            len,
+            quantize,
+        }
+    }
+
+    fn tick() -> Tick {
+        Tick {
+            click: Vec::new(),
+            index: 0,
+            starts: Vec::with_capacity(MAX_CLICKS),
+            playing: Vec::new(),
+            velocity: 1.0,
+        }
+    }
+
+    fn play(tick: &mut Tick, ticker: &Ticker) -> Vec<f32> {
+        (0..ticker.len).map(|_| tick.next(ticker)).collect()
+    }
+
+    #[test]
+    fn spreads_clicks_across_a_tick() {
+        let ticker = ticker(10, false, 3);
+        let mut tick = tick();
+        let played = play(&mut tick, &ticker);
+        assert_eq!(played, [1., 1., 0., 1., 1., 0., 1., 1., 0., 0.]);
+        // the click is kept for the next tick, and its events counted afresh
+        assert_eq!(play(&mut tick, &ticker), [0.0; 10]);
+        ticker.pending.store(100, Ordering::Relaxed);
+        let played = play(&mut tick, &ticker);
+        assert_eq!(played.iter().sum::<f32>(), 2.0 * MAX_CLICKS as f32);
+    }
+
+    #[test]
+    fn plays_one_click_per_step_with_a_tempo() {
+        let ticker = ticker(10, true, 8);
+        let mut tick = tick();
+        let played = play(&mut tick, &ticker);
+        assert_eq!(played[..3], [0.625, 0.625, 0.0]);
+        assert_eq!(played.iter().sum::<f32>(), 1.25);
+        assert_eq!(play(&mut tick, &ticker), [0.0; 10]);
+    }
+
+    #[test]
+    fn renders_a_click_per_tick() {
+        let ticker = ticker(10, false, 0);
+        let mut tick = tick();
+        tick.next(&ticker);
+        let mut rendered = 0;
+        for _ in 0..3 {
+            ticker.click(|| {
+                rendered += 1;
+                Some(Pulse::clunk(1000))
+            });
+        }
+        assert_eq!(rendered, 1);
+        assert_eq!(ticker.pending.load(Ordering::Relaxed), 3);
+        let click = ticker.click.lock().unwrap().as_ref().map(Vec::len);
+        assert_eq!(click, Some(80));
+        // the next tick takes it, leaving the old click's buffer spare
+        play(&mut tick, &ticker);
+        assert_eq!(tick.click.len(), 80);
+        assert!(ticker.click.lock().unwrap().is_none());
+        assert!(ticker.spare.lock().unwrap().capacity() >= 2);
+    }
+
+    #[test]
+    fn measures_grids() {
+        let config = Config::new().tick(Duration::from_millis(5));
+        assert!(matches!(Grid::of(&config), Some(Grid::Tick(_))));
+        let config = config.tempo(120);
+        assert!(matches!(Grid::of(&config), Some(Grid::Tempo(120))));
+        assert!(Grid::of(&Config::new()).is_none());
+        assert_eq!(Grid::Tempo(120).len(), Duration::from_millis(125));
+        assert_eq!(Grid::Tempo(0).len(), Duration::from_secs(15));
+    }
+
+    #[test]
+    fn gains_grow_with_events() {
+        assert_eq!(burst_gain(1), 1.0);
+        assert_eq!(burst_gain(4), 2.0);
+        assert_eq!(burst_gain(16), 3.0);
+        assert_eq!(burst_gain(1000), 3.0);
+        assert_eq!(velocity(0), 0.25);
+        assert_eq!(velocity(1), 0.25);
+        assert_eq!(velocity(8), 0.625);
+        assert_eq!(velocity(FULL_VELOCITY), 1.0);
+        assert_eq!(velocity(u64::MAX), 1.0);
+    }
+
+    #[test]
+    fn coalesces_bursts() {
+        let (_mixer, _started, shared) = Mixer::new(1000, None, false, None);
+        let mut rendered = 0;
+        for burst in [Some(1), Some(1), Some(2), None, None] {
+            shared.click(burst, |sample_rate| {
+                rendered += 1;
+                Some(Pulse::clunk(sample_rate))
+            });
+        }
+        assert_eq!(rendered, 4);
+        let queue = shared.queue.lock().unwrap();
+        let events: Vec<_> = queue.iter().map(|queued| queued.events).collect();
+        assert_eq!(events, [2, 1, 1, 1]);
+    }
+
+    #[test]
+    fn degrades_over_budget() {
+        let (_mixer, _started, shared) = Mixer::new(1000, None, false, Some(10.0));
+        shared.measured(0.05);
+        assert_eq!(shared.degradation(), 0);
+        for _ in 0..10 {
+            shared.measured(0.5);
+        }
+        assert_eq!(shared.load(), 0.5);
+        assert_eq!(shared.degradation(), MAX_DEGRADATION);
+        // recovering only once well under the budget
+        shared.measured(0.05);
+        assert_eq!(shared.degradation(), MAX_DEGRADATION);
+        shared.measured(0.01);
+        assert_eq!(shared.degradation(), MAX_DEGRADATION - 1);
+
+        shared.degradation.store(1, Ordering::Relaxed);
+        for _ in 0..4 {
+            shared.click(None, |sample_rate| Some(Pulse::clunk(sample_rate)));
+        }
+        assert_eq!(shared.queue.lock().unwrap().len(), 2);
+
+        // without a budget, the load is only noted
+        let (_mixer, _started, shared) = Mixer::new(1000, None, false, None);
+        shared.measured(5.0);
+        assert_eq!(shared.degradation(), 0);
+    }
+
+    #[test]
+    fn limits_bursts() {
+        let mut limiter = Limiter::new(48_000);
+        for _ in 0..48_000 {
+            assert_eq!(limiter.gain(0.5), 1.0);
+        }
+        // a loud burst is ducked to the threshold within a few milliseconds
+        let gains: Vec<_> = (0..480).map(|_| limiter.gain(2.0)).collect();
+        assert!(gains[0] == 1.0 && gains.windows(2).all(|w| w[1] <= w[0]));
+        let ducked = gains.last().unwrap() * 2.0;
+        assert!((ducked - Limiter::THRESHOLD).abs() < 0.01, "{ducked}");
+        // and released slowly
+        let gain = limiter.gain(0.0);
+        assert!(gain < 0.5, "{gain}");
+        for _ in 0..48_000 {
+            limiter.gain(0.0);
+        }
+        assert_eq!(limiter.gain(0.0), 1.0);
+    }
+
+    #[test]
+    fn mixes_queued_pulses_within_full_scale() {
+        let (mut mixer, _started, shared) = Mixer::new(1000, None, false, None);
+        for _ in 0..4 {
+            shared.play(Pulse::clunk(1000));
+        }
+        let samples: Vec<f32> = mixer.by_ref().take(2 * 100).collect();
+        assert!(samples.iter().all(|sample| sample.abs() <= 1.0));
+        assert!(samples.iter().any(|&sample| sample != 0.0));
+        // the clunks are over after 80 ms, in stereo frames
+        assert!(samples[2 * 80..].iter().all(|&sample| sample == 0.0));
+        assert!(mixer.pulses.is_empty());
+    }
+}
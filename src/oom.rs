@@ -0,0 +1,77 @@
+//! Chaining into the standard alloc error hook, enabled by the `nightly`
+//! feature.
+
+use crate::pulse::Pulse;
+use crate::{quietly, Geiger};
+use std::alloc::{self, Layout};
+use std::sync::OnceLock;
+use std::thread;
+
+static INSTALLED: OnceLock<Installed> = OnceLock::new();
+
+/// The `Geiger` that reports allocation errors, and the hook it replaced.
+struct Installed {
+    geiger: &'static (dyn Report + Sync),
+    previous: fn(Layout),
+}
+
+/// Type-erased access to the installed `Geiger`, whatever its inner allocator.
+trait Report {
+    fn report(&self, layout: Layout);
+}
+
+impl<Alloc, const OPS: u8> Report for Geiger<Alloc, OPS> {
+    fn report(&self, layout: Layout) {
+        quietly(|| {
+            eprintln!(
+                "alloc_geiger: allocation of {} bytes failed, after:\n{}",
+                layout.size(),
+                self.stats()
+            );
+            // Our allocator has already sounded the alarm, so let it finish
+            // before the process aborts.
+            if self.core.enabled && !self.is_muted() {
+                thread::sleep(Pulse::ALARM_DURATION);
+            }
+        });
+    }
+}
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Installs a [`std::alloc::set_alloc_error_hook`] that reports the stats
+    /// so far and lets the failure alarm finish playing, before chaining to
+    /// the previous hook, which normally aborts.
+    ///
+    /// Only the first call installs the hook, returning `true`.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.install_error_hook();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn install_error_hook(&'static self) -> bool {
+        let mut installed = false;
+        INSTALLED.get_or_init(|| {
+            installed = true;
+            Installed {
+                geiger: self,
+                previous: alloc::take_alloc_error_hook(),
+            }
+        });
+        if installed {
+            alloc::set_alloc_error_hook(error_hook);
+        }
+        installed
+    }
+}
+
+fn error_hook(layout: Layout) {
+    if let Some(installed) = INSTALLED.get() {
+        installed.geiger.report(layout);
+        (installed.previous)(layout);
+    }
+}
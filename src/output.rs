@@ -0,0 +1,643 @@
+use crate::clock::now_millis;
+use crate::config::{Config, ParseConfigError};
+use crate::event::AllocEvent;
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+use crate::mixer::Shared;
+use crate::pulse::Pulse;
+use crate::socket::SocketOutput;
+#[cfg(feature = "sound-rodio")]
+use rodio::Source;
+use std::env;
+use std::error::Error;
+use std::fmt;
+use std::io::{self, IsTerminal, Write};
+use std::str::FromStr;
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+use std::sync::atomic::AtomicU32;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+use std::sync::Arc;
+use std::thread::Thread;
+
+/// A way of making sound, tried in the order of [`Config::backends`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Backend {
+    /// Plays synthesized pulses with `rodio`, named `rodio`, which is only
+    /// called from a dedicated thread.  This needs the `sound-rodio` feature,
+    /// which is on by default.
+    Rodio,
+    /// Writes synthesized pulses straight to an ALSA device from a dedicated
+    /// thread, named `alsa`.  This needs the `alsa` feature on Linux, and
+    /// uses the configured device name or else `default`.
+    Alsa,
+    /// Plays synthesized pulses with a low-latency Oboe stream on Android,
+    /// named `oboe`, which is tried first by default when the `android`
+    /// feature is enabled.
+    Oboe,
+    /// Rings the terminal bell on stderr, at most 10 times a second, named
+    /// `bell`.  This is only available when stderr is a terminal.
+    Bell,
+    /// Beeps at a fixed pitch with the legacy Windows `Beep` function, at most
+    /// 10 times a second, named `beep`.  This needs the `beep` feature on
+    /// Windows, where it's tried after `rodio` by default, for machines
+    /// without a configured audio device.
+    Beep,
+    /// Sends a binary frame for each event played to the Unix datagram socket
+    /// at [`Config::socket`](crate::Config::socket), named `socket`, for
+    /// visualizers, loggers, or other sonifiers in any language, and makes no
+    /// sound itself.  This is only available on Unix, and once a reader has
+    /// bound the socket.
+    ///
+    /// Frames are dropped while the reader is behind, and it may come and go.
+    /// Each one is 64 bytes, with its fields in little endian:
+    ///
+    /// | offset | type  | field |
+    /// |--------|-------|-------|
+    /// | 0      | `u8`  | the format version, currently 1 |
+    /// | 1      | `u8`  | the method: 0 `alloc`, 1 `alloc_zeroed`, 2 `dealloc`, or 3 `realloc` |
+    /// | 2      | `u8`  | flags: bit 0 is set if the event has a tag |
+    /// | 3      | `u8`  | reserved, 0 |
+    /// | 4      | `u32` | the thread index, as in [`AllocEvent::thread`] |
+    /// | 8      | `u32` | the process ID |
+    /// | 12     | `u32` | the [`tag`](crate::tag), or 0 |
+    /// | 16     | `u64` | the time in nanoseconds, from the same zero as [`RecentEvent::time`] |
+    /// | 24     | `u64` | the new size |
+    /// | 32     | `u64` | the old size of a `realloc`, otherwise the size |
+    /// | 40     | `u64` | the address, or 0 on failure |
+    /// | 48     | `u64` | the address a `realloc` moved from, otherwise 0 |
+    /// | 56     | `u64` | the alignment |
+    ///
+    /// [`AllocEvent::thread`]: crate::AllocEvent::thread
+    /// [`RecentEvent::time`]: crate::RecentEvent::time
+    Socket,
+    /// Prints the number of events played to stderr, at most once a second,
+    /// named `log`.
+    Log,
+    /// Makes no sound at all, named `silent`, while still counting stats.
+    Silent,
+}
+
+impl Backend {
+    const ALL: [Backend; 8] = [
+        Backend::Rodio,
+        Backend::Alsa,
+        Backend::Oboe,
+        Backend::Bell,
+        Backend::Beep,
+        Backend::Socket,
+        Backend::Log,
+        Backend::Silent,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Backend::Rodio => "rodio",
+            Backend::Alsa => "alsa",
+            Backend::Oboe => "oboe",
+            Backend::Bell => "bell",
+            Backend::Beep => "beep",
+            Backend::Socket => "socket",
+            Backend::Log => "log",
+            Backend::Silent => "silent",
+        }
+    }
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Backend {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Backend::ALL
+            .into_iter()
+            .find(|backend| backend.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("backend", s))
+    }
+}
+
+/// Set when an output gave up waiting on its device or audio callback, until
+/// the backends report it in a [`GeigerError`].
+pub(crate) static TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// The backends that failed to open, from
+/// [`Geiger::init_error`](crate::Geiger::init_error).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GeigerError {
+    failures: Vec<(Backend, String)>,
+    timed_out: bool,
+}
+
+impl GeigerError {
+    /// Collects the failures, and whether any of them timed out since the
+    /// last time.
+    fn new(failures: Vec<(Backend, String)>) -> Option<Self> {
+        let timed_out = TIMED_OUT.swap(false, Ordering::Relaxed);
+        (!failures.is_empty()).then_some(GeigerError {
+            failures,
+            timed_out,
+        })
+    }
+
+    /// Returns each backend that was tried and failed, in order, with the
+    /// reason it failed.
+    pub fn failures(&self) -> &[(Backend, String)] {
+        &self.failures
+    }
+
+    /// Returns `true` if a backend gave up waiting on its device or its audio
+    /// callback, after [`Config::init_timeout`](crate::Config::init_timeout),
+    /// rather than failing outright.
+    ///
+    /// This is the sign of a sound problem that would otherwise have hung the
+    /// program, like a broken sound server or a suspended device, where the
+    /// next backend was used instead.
+    pub fn timed_out(&self) -> bool {
+        self.timed_out
+    }
+}
+
+/// Lists the failures as `backend: reason`, separated by semicolons.
+impl fmt::Display for GeigerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (backend, reason)) in self.failures.iter().enumerate() {
+            let sep = if i > 0 { "; " } else { "" };
+            write!(f, "{sep}{backend}: {reason}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Error for GeigerError {}
+
+/// What a pulse is for, which decides how it's played.
+#[derive(Clone, Copy)]
+pub(crate) enum Cue<'a> {
+    /// A click for an allocator event, which may be clustered per tick, or
+    /// coalesced with others in the same burst.
+    // only a mixer coalesces bursts
+    #[cfg_attr(
+        not(any(
+            feature = "sound-rodio",
+            all(feature = "alsa", target_os = "linux"),
+            all(feature = "android", target_os = "android")
+        )),
+        allow(dead_code)
+    )]
+    Click(Option<u32>, &'a AllocEvent),
+    /// A special tone, always played on its own.
+    Tone,
+}
+
+/// The open output of one of the backends.
+pub(crate) enum Output {
+    Rodio(MixerOutput),
+    Alsa(MixerOutput),
+    Oboe(MixerOutput),
+    Bell(BellOutput),
+    Beep(BeepOutput),
+    Socket(SocketOutput),
+    Log(LogOutput),
+    Silent,
+}
+
+impl Output {
+    /// Returns the mixer's load and how far the clicks are degraded for it,
+    /// if this backend has a mixer.
+    pub(crate) fn mixing(&self) -> Option<(f32, u32)> {
+        match self {
+            Output::Rodio(output) | Output::Alsa(output) | Output::Oboe(output) => {
+                Some(output.mixing())
+            }
+            Output::Bell(_)
+            | Output::Beep(_)
+            | Output::Socket(_)
+            | Output::Log(_)
+            | Output::Silent => None,
+        }
+    }
+
+    /// Opens the first of the configured backends that works, along with the
+    /// failures of any tried before it.
+    pub(crate) fn open(config: &Config) -> (Option<Self>, Option<GeigerError>) {
+        let headless = config.detect_headless && is_headless();
+        let mut failures = Vec::new();
+        for &backend in config.backends.iter() {
+            let output = match backend {
+                Backend::Rodio | Backend::Alsa if headless => {
+                    Err("no display or sound server, so this looks headless".into())
+                }
+                Backend::Rodio => MixerOutput::open(config).map(Output::Rodio),
+                Backend::Alsa => MixerOutput::open_alsa(config).map(Output::Alsa),
+                Backend::Oboe => MixerOutput::open_oboe(config).map(Output::Oboe),
+                Backend::Bell => BellOutput::open().map(Output::Bell),
+                Backend::Beep => BeepOutput::open().map(Output::Beep),
+                Backend::Socket => SocketOutput::open(config).map(Output::Socket),
+                Backend::Log => Ok(Output::Log(LogOutput::new())),
+                Backend::Silent => Ok(Output::Silent),
+            };
+            match output {
+                Ok(output) => return (Some(output), GeigerError::new(failures)),
+                Err(reason) => failures.push((backend, reason)),
+            }
+        }
+        (None, GeigerError::new(failures))
+    }
+
+    /// Opens a second output on a named device, for the tones, with the
+    /// first of the configured backends that plays on a named device.
+    pub(crate) fn open_tones(config: &Config, device: &str) -> Result<Self, String> {
+        let mut config = config.clone();
+        config.device = Some(device.to_owned());
+        let backends = config.backends.iter().copied();
+        let backends =
+            backends.filter(|&backend| matches!(backend, Backend::Rodio | Backend::Alsa));
+        config.backends = backends.collect::<Vec<_>>().into();
+        match Output::open(&config) {
+            (Some(output), _) => Ok(output),
+            (None, Some(error)) => Err(error.to_string()),
+            (None, None) => Err("neither the rodio nor the alsa backend is configured".into()),
+        }
+    }
+
+    /// Returns `false` if the output was lost, so it should be opened again.
+    pub(crate) fn is_alive(&self) -> bool {
+        match self {
+            Output::Rodio(output) | Output::Alsa(output) | Output::Oboe(output) => {
+                output.is_alive()
+            }
+            Output::Bell(_)
+            | Output::Beep(_)
+            | Output::Socket(_)
+            | Output::Log(_)
+            | Output::Silent => true,
+        }
+    }
+
+    /// Returns `true` if this output makes sound and, with a mixer, if the
+    /// audio thread has polled it since `millis`.
+    pub(crate) fn played_since(&self, millis: u64) -> bool {
+        match self {
+            Output::Rodio(output) | Output::Alsa(output) | Output::Oboe(output) => {
+                output.played_since(millis)
+            }
+            Output::Bell(_) | Output::Beep(_) => true,
+            Output::Socket(_) | Output::Log(_) | Output::Silent => false,
+        }
+    }
+
+    /// Returns the backend that opened this output.
+    pub(crate) fn backend(&self) -> Backend {
+        match self {
+            Output::Rodio(_) => Backend::Rodio,
+            Output::Alsa(_) => Backend::Alsa,
+            Output::Oboe(_) => Backend::Oboe,
+            Output::Bell(_) => Backend::Bell,
+            Output::Beep(_) => Backend::Beep,
+            Output::Socket(_) => Backend::Socket,
+            Output::Log(_) => Backend::Log,
+            Output::Silent => Backend::Silent,
+        }
+    }
+
+    /// Plays a pulse, synthesized at the output's sample rate if needed.
+    pub(crate) fn play(&self, cue: Cue, pulse: impl FnOnce(u32) -> Option<Pulse>) {
+        match self {
+            Output::Rodio(output) | Output::Alsa(output) | Output::Oboe(output) => {
+                output.play(cue, pulse)
+            }
+            Output::Bell(bell) => bell.ring(),
+            Output::Beep(beep) => beep.ring(),
+            Output::Socket(socket) => {
+                if let Cue::Click(_, event) = cue {
+                    socket.send(event);
+                }
+            }
+            Output::Log(log) => log.count(),
+            Output::Silent => {}
+        }
+    }
+}
+
+#[cfg(feature = "sound-rodio")]
+impl Output {
+    /// Plays a custom source through the mixer, returning `false` if this
+    /// backend has none.
+    pub(crate) fn play_source(&self, source: Box<dyn Source<Item = f32> + Send>) -> bool {
+        match self {
+            Output::Rodio(output) | Output::Alsa(output) | Output::Oboe(output) => {
+                output.play_source(source);
+                true
+            }
+            Output::Bell(_)
+            | Output::Beep(_)
+            | Output::Socket(_)
+            | Output::Log(_)
+            | Output::Silent => false,
+        }
+    }
+}
+
+/// An open `rodio` stream, ALSA device, or Oboe stream, playing everything
+/// through one `Mixer`.
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+pub(crate) struct MixerOutput {
+    mixer: Arc<Shared>,
+    /// the `forks` count when this was opened
+    forks: u32,
+}
+
+/// Stands in for a `MixerOutput` without any of the backends that have a
+/// `Mixer`, so there's never one to play through.
+#[cfg(not(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+)))]
+pub(crate) enum MixerOutput {}
+
+impl MixerOutput {
+    /// Opens the output stream on the configured device with `rodio`, if
+    /// supported.
+    fn open(_config: &Config) -> Result<Self, String> {
+        #[cfg(feature = "sound-rodio")]
+        return crate::stream::open(_config);
+        #[cfg(not(feature = "sound-rodio"))]
+        Err("the sound-rodio feature isn't enabled".into())
+    }
+
+    /// Opens the configured ALSA device without `rodio`, if supported.
+    fn open_alsa(_config: &Config) -> Result<Self, String> {
+        #[cfg(all(feature = "alsa", target_os = "linux"))]
+        return crate::pcm::open(_config).map(MixerOutput::new);
+        #[cfg(not(all(feature = "alsa", target_os = "linux")))]
+        Err("the alsa feature isn't enabled on this platform".into())
+    }
+
+    /// Opens a low-latency Oboe stream, if supported.
+    fn open_oboe(_config: &Config) -> Result<Self, String> {
+        #[cfg(all(feature = "android", target_os = "android"))]
+        return crate::aaudio::open(_config).map(MixerOutput::new);
+        #[cfg(not(all(feature = "android", target_os = "android")))]
+        Err("the android feature isn't enabled on this platform".into())
+    }
+}
+
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+impl MixerOutput {
+    /// How long the audio thread may go quiet before we assume the device is
+    /// gone.  Our `Mixer` is polled every millisecond or so.
+    const TIMEOUT_MILLIS: u64 = 3000;
+
+    pub(crate) fn new(mixer: Arc<Shared>) -> Self {
+        watch_forks();
+        MixerOutput {
+            mixer,
+            forks: forks(),
+        }
+    }
+
+    /// Returns `false` if the audio thread has stopped, as when its device was
+    /// unplugged, or if this is a forked child that doesn't have the audio
+    /// thread at all, so the output should be opened again.
+    fn is_alive(&self) -> bool {
+        self.forks == forks() && self.mixer.silence_millis() < Self::TIMEOUT_MILLIS
+    }
+
+    /// Returns `true` if the audio thread has polled the mixer since `millis`,
+    /// picking up anything queued before then.
+    fn played_since(&self, millis: u64) -> bool {
+        self.forks == forks() && now_millis().saturating_sub(self.mixer.silence_millis()) > millis
+    }
+
+    /// Returns the mixer's load and how far the clicks are degraded for it.
+    fn mixing(&self) -> (f32, u32) {
+        (self.mixer.load(), self.mixer.degradation())
+    }
+
+    fn play(&self, cue: Cue, pulse: impl FnOnce(u32) -> Option<Pulse>) {
+        match cue {
+            Cue::Click(burst, event) => {
+                // coalesce while degraded for the mixer's load, even if it's
+                // off
+                let degraded = self.mixer.degradation() > 0;
+                let burst = burst.or_else(|| degraded.then(|| event.burst()));
+                self.mixer.click(burst, pulse)
+            }
+            Cue::Tone => {
+                if let Some(pulse) = pulse(self.mixer.sample_rate()) {
+                    self.mixer.play(pulse);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "sound-rodio")]
+    fn play_source(&self, source: Box<dyn Source<Item = f32> + Send>) {
+        self.mixer.play_source(source);
+    }
+}
+
+#[cfg(not(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+)))]
+impl MixerOutput {
+    fn is_alive(&self) -> bool {
+        match *self {}
+    }
+
+    fn played_since(&self, _millis: u64) -> bool {
+        match *self {}
+    }
+
+    fn mixing(&self) -> (f32, u32) {
+        match *self {}
+    }
+
+    fn play(&self, _cue: Cue, _pulse: impl FnOnce(u32) -> Option<Pulse>) {
+        match *self {}
+    }
+}
+
+/// The terminal bell, rate-limited so it's still a distinct click.
+pub(crate) struct BellOutput {
+    last: AtomicU64,
+}
+
+impl BellOutput {
+    const INTERVAL_MILLIS: u64 = 100;
+
+    fn open() -> Result<Self, String> {
+        if !io::stderr().is_terminal() {
+            return Err("stderr is not a terminal".into());
+        }
+        Ok(BellOutput {
+            last: AtomicU64::new(0),
+        })
+    }
+
+    fn ring(&self) {
+        let now = now_millis();
+        let last = self.last.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= Self::INTERVAL_MILLIS
+            && (self.last)
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            let _ = io::stderr().write_all(b"\x07");
+        }
+    }
+}
+
+/// The Windows `Beep` function on its own thread, rate-limited like the bell.
+#[cfg_attr(not(all(feature = "beep", windows)), allow(dead_code))]
+pub(crate) struct BeepOutput {
+    last: AtomicU64,
+    thread: Thread,
+}
+
+impl BeepOutput {
+    const INTERVAL_MILLIS: u64 = 100;
+
+    fn open() -> Result<Self, String> {
+        #[cfg(all(feature = "beep", windows))]
+        return crate::beep::spawn()
+            .map(|thread| BeepOutput {
+                last: AtomicU64::new(0),
+                thread,
+            })
+            .map_err(|e| e.to_string());
+        #[cfg(not(all(feature = "beep", windows)))]
+        Err("the beep feature isn't enabled on this platform".into())
+    }
+
+    fn ring(&self) {
+        let now = now_millis();
+        let last = self.last.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= Self::INTERVAL_MILLIS
+            && (self.last)
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            self.thread.unpark();
+        }
+    }
+}
+
+/// A line on stderr with the number of events, rate-limited like the bell.
+pub(crate) struct LogOutput {
+    last: AtomicU64,
+    events: AtomicU64,
+}
+
+impl LogOutput {
+    const INTERVAL_MILLIS: u64 = 1000;
+
+    fn new() -> Self {
+        LogOutput {
+            last: AtomicU64::new(0),
+            events: AtomicU64::new(0),
+        }
+    }
+
+    fn count(&self) {
+        self.events.fetch_add(1, Ordering::Relaxed);
+        let now = now_millis();
+        let last = self.last.load(Ordering::Relaxed);
+        if now.saturating_sub(last) >= Self::INTERVAL_MILLIS
+            && (self.last)
+                .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            let events = self.events.swap(0, Ordering::Relaxed);
+            eprintln!("alloc_geiger: {events} events");
+        }
+    }
+}
+
+/// Guesses whether there's no audio to be had, on Linux and the BSDs, when
+/// there's neither a graphical session nor a sound server to reach.
+fn is_headless() -> bool {
+    if cfg!(any(
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "android",
+        not(unix)
+    )) {
+        return false;
+    }
+    [
+        "DISPLAY",
+        "WAYLAND_DISPLAY",
+        "PULSE_SERVER",
+        "XDG_RUNTIME_DIR",
+    ]
+    .iter()
+    .all(|var| env::var_os(var).is_none())
+}
+
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+/// The number of times this process is a forked child, counted by a
+/// `pthread_atfork` handler.
+static FORKS: AtomicU32 = AtomicU32::new(0);
+
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+fn forks() -> u32 {
+    FORKS.load(Ordering::Relaxed)
+}
+
+/// Registers the handler that counts `FORKS`, once.
+#[cfg(any(
+    feature = "sound-rodio",
+    all(feature = "alsa", target_os = "linux"),
+    all(feature = "android", target_os = "android")
+))]
+fn watch_forks() {
+    #[cfg(unix)]
+    {
+        extern "C" fn child() {
+            FORKS.fetch_add(1, Ordering::Relaxed);
+        }
+
+        static WATCH: std::sync::Once = std::sync::Once::new();
+        // SAFETY: the handler only touches an atomic.
+        WATCH.call_once(|| unsafe {
+            libc::pthread_atfork(None, None, Some(child));
+        });
+    }
+}
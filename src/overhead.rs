@@ -0,0 +1,104 @@
+use crate::clock::now_millis;
+use std::cell::Cell;
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// Only one in this many events on each thread is timed, to keep the clock
+/// itself from adding much overhead.
+const SAMPLE_EVERY: u32 = 64;
+
+thread_local! {
+    /// Events on this thread until the next one that's timed, skipping the
+    /// first, which may include the slow start of the output.
+    static COUNTDOWN: Cell<u32> = const { Cell::new(SAMPLE_EVERY - 1) };
+}
+
+/// An estimate of the time spent handling events, from a sample of them.
+pub(crate) struct Timer {
+    nanos: AtomicU64,
+}
+
+impl Timer {
+    pub(crate) const fn new() -> Self {
+        Timer {
+            nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Starts timing an event, if it's one of the sample.
+    pub(crate) fn start(&self) -> Option<Instant> {
+        COUNTDOWN.with(|countdown| match countdown.get() {
+            0 => {
+                countdown.set(SAMPLE_EVERY - 1);
+                Some(Instant::now())
+            }
+            n => {
+                countdown.set(n - 1);
+                None
+            }
+        })
+    }
+
+    /// Adds a sampled event's time, scaled up for those that weren't timed.
+    pub(crate) fn finish(&self, start: Option<Instant>) {
+        if let Some(start) = start {
+            let nanos = (start.elapsed().as_nanos() as u64).saturating_sub(clock_nanos());
+            let nanos = nanos * u64::from(SAMPLE_EVERY);
+            self.nanos.fetch_add(nanos, Ordering::Relaxed);
+        }
+    }
+
+    pub(crate) fn snapshot(&self) -> Overhead {
+        let time = Duration::from_nanos(self.nanos.load(Ordering::Relaxed));
+        let elapsed = Duration::from_millis(now_millis().max(1));
+        Overhead {
+            time,
+            fraction: time.as_secs_f64() / elapsed.as_secs_f64(),
+            mixing: 0.0,
+            degradation: 0,
+        }
+    }
+}
+
+/// The cost of reading the clock itself, to leave out of the estimate.
+fn clock_nanos() -> u64 {
+    static NANOS: OnceLock<u64> = OnceLock::new();
+    *NANOS.get_or_init(|| {
+        (0..16)
+            .map(|_| Instant::now().elapsed().as_nanos() as u64)
+            .min()
+            .unwrap_or(0)
+    })
+}
+
+/// An estimate of the time a `Geiger` has spent counting and making sound,
+/// from [`Geiger::overhead`](crate::Geiger::overhead).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct Overhead {
+    /// The total time spent, summed over all threads.
+    pub time: Duration,
+    /// That time as a fraction of the wall-clock time since the first event,
+    /// which may exceed 1 with many threads.
+    pub fraction: f64,
+    /// The recent fraction of the audio thread's time spent mixing the
+    /// output, which isn't included in the time above, or `0.0` without a
+    /// mixer.
+    pub mixing: f64,
+    /// How far the clicks are degraded to keep the mixing within the
+    /// [`Config::mix_budget`](crate::Config::mix_budget), from `0` for not at
+    /// all, with each level playing half as many.
+    pub degradation: u32,
+}
+
+impl fmt::Display for Overhead {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "overhead_secs {:.6}", self.time.as_secs_f64())?;
+        writeln!(f, "overhead_percent {:.3}", self.fraction * 100.0)?;
+        writeln!(f, "mixing_percent {:.3}", self.mixing * 100.0)?;
+        writeln!(f, "degradation {}", self.degradation)
+    }
+}
@@ -0,0 +1,83 @@
+//! Direct ALSA output, enabled by the `alsa` feature on Linux.
+
+use crate::config::Config;
+use crate::mixer::{Grid, Mixer, Shared, DEFAULT_SAMPLE_RATE};
+use crate::BUSY;
+use alsa::pcm::{Access, Format, HwParams, PCM};
+use alsa::{Direction, ValueOr};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+
+/// Frames written to the device at a time, about 5 ms at 48 kHz, unless
+/// [`Config::buffer_frames`] is set.
+const PERIOD_FRAMES: usize = 256;
+
+/// Opens an ALSA PCM device, returning its mixer once a dedicated thread is
+/// writing to it.
+pub(crate) fn open(config: &Config) -> Result<Arc<Shared>, String> {
+    let device = config.device.clone().unwrap_or_else(|| "default".into());
+    let sample_rate = config.sample_rate;
+    let period = config
+        .buffer_frames
+        .map_or(PERIOD_FRAMES, |frames| frames as usize);
+    let grid = Grid::of(config);
+    let boost = config.audio_priority;
+    let budget = config.mix_budget;
+    let (sender, receiver) = mpsc::sync_channel(1);
+    thread::Builder::new()
+        .name("alloc_geiger-alsa".into())
+        .spawn(move || {
+            BUSY.with(|busy| busy.set(true));
+            let (pcm, rate, period) = match open_pcm(&device, sample_rate, period) {
+                Ok(opened) => opened,
+                Err(e) => {
+                    let _ = sender.send(Err(e.to_string()));
+                    return;
+                }
+            };
+            let (mut mixer, _, shared) = Mixer::new(rate, grid, boost, budget);
+            let _ = sender.send(Ok(shared));
+            let Ok(io) = pcm.io_f32() else {
+                return;
+            };
+            let mut buf = vec![0.0; period * 2];
+            loop {
+                buf.iter_mut()
+                    .for_each(|sample| *sample = mixer.next().unwrap_or(0.0));
+                if let Err(e) = io.writei(&buf) {
+                    // recover from an underrun, or give up so the heartbeat
+                    // stops and the output is opened again
+                    if pcm.try_recover(e, true).is_err() {
+                        return;
+                    }
+                }
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    receiver
+        .recv()
+        .map_err(|_| "the ALSA thread exited".to_owned())?
+}
+
+/// Opens and configures the device for interleaved stereo floats.
+fn open_pcm(
+    device: &str,
+    sample_rate: Option<u32>,
+    period: usize,
+) -> alsa::Result<(PCM, u32, usize)> {
+    let pcm = PCM::new(device, Direction::Playback, false)?;
+    let (rate, period) = {
+        let params = HwParams::any(&pcm)?;
+        params.set_channels(2)?;
+        params.set_format(Format::float())?;
+        params.set_access(Access::RWInterleaved)?;
+        let rate = sample_rate.unwrap_or(DEFAULT_SAMPLE_RATE);
+        params.set_rate(rate, ValueOr::Nearest)?;
+        params.set_period_size_near(period as _, ValueOr::Nearest)?;
+        pcm.hw_params(&params)?;
+        (params.get_rate()?, params.get_period_size()? as usize)
+    };
+    pcm.prepare()?;
+    Ok((pcm, rate, period))
+}
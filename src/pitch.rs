@@ -0,0 +1,599 @@
+use crate::config::ParseConfigError;
+use crate::event::{AllocEvent, AllocOp};
+use crate::quietly;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{self, AtomicBool, AtomicU32};
+use std::sync::{Mutex, PoisonError};
+use std::time::{Duration, Instant};
+
+/// What varies the pitch of each pulse.
+///
+/// The `noise` waveform has no pitch, so it always sounds the same.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Pitch {
+    /// Plays every pulse at the waveform's own pitch, named `fixed`.
+    #[default]
+    Fixed,
+    /// Lowers the pitch by a whole tone each time the allocation size doubles,
+    /// named `size`, so large allocations rumble and small ones chirp.
+    Size,
+    /// Gives each thread its own pitch, named `thread`, so concurrent
+    /// activity becomes a chord with a voice for each thread, or for each
+    /// pool of threads with [`Config::pool_threads`](crate::Config::pool_threads).
+    Thread,
+    /// Gives each CPU core its own pitch, named `cpu`, so threads migrating
+    /// between cores and load piling onto a few of them can be heard.  This
+    /// needs Linux or Windows, and is fixed elsewhere.
+    Cpu,
+    /// Gives each power-of-two size class its own note of the pentatonic
+    /// scale, named `class`, falling a step for each doubling, so activity
+    /// across classes forms chords and a familiar size can be named by ear.
+    Class,
+    /// Lowers the pitch of each deallocation by a whole tone each time the
+    /// age of the freed block doubles, named `lifetime`, so short-lived
+    /// objects click high and long-lived ones low.  A storm of high clicks
+    /// suggests objects that could be reused or put in an arena.  This
+    /// tracks the address of every live block, and allocations and blocks
+    /// from before it was chosen play at the waveform's own pitch.
+    Lifetime,
+}
+
+impl Pitch {
+    const ALL: [Pitch; 6] = [
+        Pitch::Fixed,
+        Pitch::Size,
+        Pitch::Thread,
+        Pitch::Cpu,
+        Pitch::Class,
+        Pitch::Lifetime,
+    ];
+
+    /// The size class that plays at the waveform's own pitch, for 64 bytes.
+    const REFERENCE_CLASS: i32 = 7;
+
+    /// The semitones of each note of the pentatonic scale within an octave.
+    const PENTATONIC: [f32; 5] = [0.0, 2.0, 4.0, 7.0, 9.0];
+
+    /// The size that plays at the waveform's own pitch.
+    const REFERENCE_SIZE_LOG2: f32 = 6.0;
+
+    /// The age of a freed block that plays at the waveform's own pitch, in
+    /// microseconds, for a millisecond.
+    const REFERENCE_AGE_LOG2: f32 = 9.97;
+
+    /// The widest shift from the waveform's own pitch, in semitones.
+    const RANGE: f32 = 36.0;
+
+    const fn name(self) -> &'static str {
+        match self {
+            Pitch::Fixed => "fixed",
+            Pitch::Size => "size",
+            Pitch::Thread => "thread",
+            Pitch::Cpu => "cpu",
+            Pitch::Class => "class",
+            Pitch::Lifetime => "lifetime",
+        }
+    }
+
+    /// The frequency ratio of an event's pulse, or `None` to leave it be,
+    /// with the age of the block it freed from [`Lifetimes::age`].
+    /// The `voice` is the index of the event's thread, or of its pool.
+    pub(crate) fn ratio(
+        self,
+        scale: Scale,
+        event: &AllocEvent,
+        voice: u32,
+        age: Option<Duration>,
+    ) -> Option<f32> {
+        let semitones = match self {
+            Pitch::Fixed => return None,
+            Pitch::Size => {
+                let size = event.new_size.max(1) as f32;
+                (Self::REFERENCE_SIZE_LOG2 - size.log2()) * 2.0
+            }
+            // step by fifths, so the first threads are far apart and consonant
+            Pitch::Thread => fifths(voice),
+            Pitch::Cpu => fifths(current_cpu()?),
+            Pitch::Class => {
+                let step = Self::REFERENCE_CLASS - event.size_class() as i32;
+                let octave = step.div_euclid(5) as f32 * 12.0;
+                octave + Self::PENTATONIC[step.rem_euclid(5) as usize]
+            }
+            Pitch::Lifetime => {
+                let micros = age?.as_secs_f32() * 1e6;
+                (Self::REFERENCE_AGE_LOG2 - micros.max(1.0).log2()) * 2.0
+            }
+        };
+        let semitones = scale.quantize(semitones.clamp(-Self::RANGE, Self::RANGE));
+        Some((semitones / 12.0).exp2())
+    }
+}
+
+/// The birth of each live block, while [`Pitch::Lifetime`] is chosen.
+pub(crate) struct Lifetimes {
+    active: AtomicBool,
+    births: Mutex<Option<HashMap<usize, Instant>>>,
+}
+
+impl Lifetimes {
+    pub(crate) const fn new() -> Self {
+        Lifetimes {
+            active: AtomicBool::new(false),
+            births: Mutex::new(None),
+        }
+    }
+
+    /// Tracks an event's block while `pitch` is [`Pitch::Lifetime`], and
+    /// returns the age of the block it freed, if its birth was seen.
+    ///
+    /// Reallocation moves the birth along with the block, so only
+    /// deallocations have an age.
+    pub(crate) fn age(&self, pitch: Pitch, event: &AllocEvent) -> Option<Duration> {
+        if pitch != Pitch::Lifetime {
+            if self.active.swap(false, atomic::Ordering::Relaxed) {
+                *self.births.lock().unwrap_or_else(PoisonError::into_inner) = None;
+            }
+            return None;
+        }
+        self.active.store(true, atomic::Ordering::Relaxed);
+        let now = Instant::now();
+        quietly(|| {
+            let mut births = self.births.lock().unwrap_or_else(PoisonError::into_inner);
+            let births = births.get_or_insert_with(HashMap::new);
+            match event.op {
+                AllocOp::Alloc | AllocOp::AllocZeroed if event.address != 0 => {
+                    births.insert(event.address, now);
+                    None
+                }
+                AllocOp::Dealloc => births.remove(&event.address).map(|birth| now - birth),
+                AllocOp::Realloc if event.address != 0 => {
+                    let birth = births.remove(&event.old_address).unwrap_or(now);
+                    births.insert(event.address, birth);
+                    None
+                }
+                _ => None,
+            }
+        })
+    }
+}
+
+/// Steps an index by fifths within two octaves, so the first few are far
+/// apart and consonant.
+fn fifths(index: u32) -> f32 {
+    (index.wrapping_mul(7) % 25) as f32 - 12.0
+}
+
+/// The CPU core that the current thread is running on.
+#[cfg(target_os = "linux")]
+fn current_cpu() -> Option<u32> {
+    // SAFETY: `sched_getcpu` has no preconditions.
+    let cpu = unsafe { libc::sched_getcpu() };
+    u32::try_from(cpu).ok()
+}
+
+#[cfg(windows)]
+fn current_cpu() -> Option<u32> {
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentProcessorNumber() -> u32;
+    }
+    // SAFETY: `GetCurrentProcessorNumber` has no preconditions.
+    Some(unsafe { GetCurrentProcessorNumber() })
+}
+
+#[cfg(not(any(target_os = "linux", windows)))]
+fn current_cpu() -> Option<u32> {
+    None
+}
+
+/// The system's name for each thread that has one, by index, in the order
+/// they were given indices.
+static THREAD_NAMES: Mutex<Vec<(u32, String)>> = Mutex::new(Vec::new());
+
+/// A stable index for the current thread, in order of their first events.
+pub(crate) fn thread_index() -> u32 {
+    static NEXT: AtomicU32 = AtomicU32::new(1);
+    thread_local! {
+        static INDEX: Cell<u32> = const { Cell::new(0) };
+    }
+    INDEX.with(|index| {
+        if index.get() == 0 {
+            index.set(NEXT.fetch_add(1, atomic::Ordering::Relaxed));
+            if let Some(name) = os_thread_name() {
+                quietly(|| {
+                    let mut names = THREAD_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+                    names.push((index.get() - 1, name));
+                });
+            }
+        }
+        index.get() - 1
+    })
+}
+
+/// The pools of threads that share a voice, by name, with the index of the
+/// first thread in each.
+static POOLS: Mutex<Vec<(String, u32)>> = Mutex::new(Vec::new());
+
+/// The index of the current thread's pool, that of its first thread, or the
+/// thread's own index if it isn't in one, as decided by [`pool_name`].
+///
+/// This allocates the names, so it must be called within the recursion
+/// guard.
+pub(crate) fn pool_index() -> u32 {
+    thread_local! {
+        static POOL: Cell<Option<u32>> = const { Cell::new(None) };
+    }
+    POOL.with(|pool| {
+        if let Some(index) = pool.get() {
+            return index;
+        }
+        let index = thread_index();
+        let name = os_thread_name();
+        let index = match name.as_deref().and_then(pool_name) {
+            Some(name) => {
+                let mut pools = POOLS.lock().unwrap_or_else(PoisonError::into_inner);
+                match pools.iter().find(|(pool, _)| pool == name) {
+                    Some(&(_, first)) => first,
+                    None => {
+                        pools.push((name.to_owned(), index));
+                        index
+                    }
+                }
+            }
+            None => index,
+        };
+        pool.set(Some(index));
+        index
+    })
+}
+
+/// Returns the name of the pool a thread belongs to, by the naming
+/// conventions of thread pools, or `None` if it doesn't look like a worker.
+///
+/// That's the common name of `tokio` workers, or the name of a numbered
+/// worker without its number, like `rayon` for `rayon-3`.  Unnamed threads
+/// aren't pooled, even though some systems give them the program's name.
+pub(crate) fn pool_name(name: &str) -> Option<&str> {
+    // Linux truncates `tokio-runtime-worker` to 15 bytes
+    if name.starts_with("tokio-runtime-") {
+        return Some(name);
+    }
+    let unnumbered = name.trim_end_matches(|c: char| c.is_ascii_digit());
+    if unnumbered.len() == name.len() {
+        return None;
+    }
+    let prefix = unnumbered.trim_end_matches(['-', '_', '.', '#', ' ', ':']);
+    (!prefix.is_empty()).then_some(prefix)
+}
+
+/// Returns the names of threads given indices since the first `skip`.
+pub(crate) fn thread_names(skip: usize) -> Vec<(u32, String)> {
+    let names = THREAD_NAMES.lock().unwrap_or_else(PoisonError::into_inner);
+    names.get(skip..).unwrap_or_default().to_vec()
+}
+
+/// Asks the system for the current thread's name, which Rust sets from
+/// `thread::Builder::name`, truncated on some systems.
+///
+/// This doesn't use `thread::current`, which may be initializing the
+/// thread's handle when it allocates.
+#[cfg(unix)]
+pub(crate) fn os_thread_name() -> Option<String> {
+    let mut buf = [0u8; 64];
+    // SAFETY: the buffer is large enough for any system's thread names, and
+    // is written with a terminating zero.
+    let result = unsafe {
+        libc::pthread_getname_np(libc::pthread_self(), buf.as_mut_ptr().cast(), buf.len())
+    };
+    let len = buf.iter().position(|&b| b == 0)?;
+    if result != 0 || len == 0 {
+        return None;
+    }
+    Some(quietly(|| {
+        String::from_utf8_lossy(&buf[..len]).into_owned()
+    }))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn os_thread_name() -> Option<String> {
+    None
+}
+
+impl fmt::Display for Pitch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pitch {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pitch::ALL
+            .into_iter()
+            .find(|pitch| pitch.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("pitch", s))
+    }
+}
+
+/// The musical scale that varied pitches are rounded to.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Scale {
+    /// Any pitch at all, named `continuous`.
+    #[default]
+    Continuous,
+    /// The major pentatonic scale, named `pentatonic`.
+    Pentatonic,
+    /// The major scale, named `major`.
+    Major,
+}
+
+impl Scale {
+    const ALL: [Scale; 3] = [Scale::Continuous, Scale::Pentatonic, Scale::Major];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Scale::Continuous => "continuous",
+            Scale::Pentatonic => "pentatonic",
+            Scale::Major => "major",
+        }
+    }
+
+    /// The semitones of each degree within an octave.
+    const fn degrees(self) -> &'static [f32] {
+        match self {
+            Scale::Continuous => &[],
+            Scale::Pentatonic => &[0.0, 2.0, 4.0, 7.0, 9.0, 12.0],
+            Scale::Major => &[0.0, 2.0, 4.0, 5.0, 7.0, 9.0, 11.0, 12.0],
+        }
+    }
+
+    /// Rounds a number of semitones to the nearest degree of the scale.
+    pub(crate) fn quantize(self, semitones: f32) -> f32 {
+        let octave = (semitones / 12.0).floor() * 12.0;
+        self.degrees()
+            .iter()
+            .map(|&degree| octave + degree)
+            .min_by(|a, b| (a - semitones).abs().total_cmp(&(b - semitones).abs()))
+            .unwrap_or(semitones)
+    }
+}
+
+impl fmt::Display for Scale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Scale {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scale::ALL
+            .into_iter()
+            .find(|scale| scale.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("scale", s))
+    }
+}
+
+/// How the pitch of a reallocation's pulse glides.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Bend {
+    /// Holds a steady pitch, named `off`.
+    #[default]
+    Off,
+    /// Glides up a fifth when growing and down when shrinking, named
+    /// `direction`, so a `Vec` growing without enough capacity chirps upward.
+    Direction,
+    /// Glides by a major third for each doubling or halving of the size,
+    /// named `ratio`, so a `Vec` doubling makes a small sweep and a tenfold
+    /// growth a dramatic one.
+    Ratio,
+}
+
+impl Bend {
+    const ALL: [Bend; 3] = [Bend::Off, Bend::Direction, Bend::Ratio];
+
+    /// The frequency ratio of a fifth.
+    const FIFTH: f32 = 1.5;
+
+    /// The glide for each doubling of the size, in semitones, and the widest
+    /// glide in either direction.
+    const SEMITONES_PER_DOUBLING: f32 = 4.0;
+    const RANGE: f32 = 36.0;
+
+    const fn name(self) -> &'static str {
+        match self {
+            Bend::Off => "off",
+            Bend::Direction => "direction",
+            Bend::Ratio => "ratio",
+        }
+    }
+
+    /// The frequency ratio reached by the end of an event's pulse, or `None`
+    /// to hold steady.
+    pub(crate) fn ratio(self, event: &AllocEvent) -> Option<f32> {
+        if self == Bend::Off || event.op != AllocOp::Realloc {
+            return None;
+        }
+        let (old_size, new_size) = (event.layout.size(), event.new_size);
+        match self {
+            _ if new_size == old_size => None,
+            Bend::Ratio => {
+                let growth = new_size.max(1) as f32 / old_size.max(1) as f32;
+                let semitones = growth.log2() * Self::SEMITONES_PER_DOUBLING;
+                Some((semitones.clamp(-Self::RANGE, Self::RANGE) / 12.0).exp2())
+            }
+            _ if new_size > old_size => Some(Self::FIFTH),
+            _ => Some(Self::FIFTH.recip()),
+        }
+    }
+}
+
+impl fmt::Display for Bend {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Bend {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Bend::ALL
+            .into_iter()
+            .find(|bend| bend.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("bend", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+
+    fn event(op: AllocOp, size: usize, address: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(op, layout, address as *mut u8)
+    }
+
+    fn realloc(old_size: usize, new_size: usize, old: usize, new: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(old_size, 1).unwrap();
+        AllocEvent::realloc(layout, new_size, old as *mut u8, new as *mut u8)
+    }
+
+    /// The pitch of an allocation of `size` on the first voice, in semitones.
+    fn semitones(pitch: Pitch, scale: Scale, size: usize) -> f32 {
+        let ratio = pitch.ratio(scale, &event(AllocOp::Alloc, size, 0x1000), 0, None);
+        (ratio.unwrap().log2() * 12.0 * 1000.0).round() / 1000.0
+    }
+
+    #[test]
+    fn lowers_a_whole_tone_per_doubling() {
+        let size = |size| semitones(Pitch::Size, Scale::Continuous, size);
+        assert_eq!(size(64), 0.0);
+        assert_eq!(size(128), -2.0);
+        assert_eq!(size(16), 4.0);
+        assert_eq!(size(96), -1.17);
+        // zero plays as one byte, and the extremes are clamped
+        assert_eq!(size(0), 12.0);
+        assert_eq!(size(isize::MAX as usize), -Pitch::RANGE);
+        // rounded to the nearest degree of a scale
+        assert_eq!(semitones(Pitch::Size, Scale::Pentatonic, 96), 0.0);
+        assert_eq!(semitones(Pitch::Size, Scale::Pentatonic, 192), -3.0);
+        let event = event(AllocOp::Alloc, 64, 0x1000);
+        assert_eq!(Pitch::Fixed.ratio(Scale::Major, &event, 0, None), None);
+    }
+
+    #[test]
+    fn steps_size_classes_on_the_pentatonic_scale() {
+        let class = |size| semitones(Pitch::Class, Scale::Continuous, size);
+        // 64 bytes is in class 7, and each class below steps up a note
+        let up: Vec<_> = [64, 32, 16, 8, 4, 2].map(class).into();
+        assert_eq!(up, [0.0, 2.0, 4.0, 7.0, 9.0, 12.0]);
+        let down: Vec<_> = [128, 256, 512, 1024, 2048, 4096].map(class).into();
+        assert_eq!(down, [-3.0, -5.0, -8.0, -10.0, -12.0, -15.0]);
+    }
+
+    #[test]
+    fn spreads_threads_by_fifths() {
+        let event = event(AllocOp::Alloc, 8, 0x1000);
+        let thread = |voice| {
+            let ratio = Pitch::Thread.ratio(Scale::Continuous, &event, voice, None);
+            (ratio.unwrap().log2() * 12.0).round()
+        };
+        let voices: Vec<_> = (0..5).map(thread).collect();
+        assert_eq!(voices, [-12.0, -5.0, 2.0, 9.0, -9.0]);
+        // the cycle repeats after 25 voices
+        assert_eq!(thread(25), thread(0));
+    }
+
+    #[test]
+    fn tracks_the_lifetimes_of_blocks() {
+        let lifetimes = Lifetimes::new();
+        let age = |event| lifetimes.age(Pitch::Lifetime, &event);
+        assert_eq!(age(event(AllocOp::Alloc, 8, 0x1000)), None);
+        assert_eq!(age(realloc(8, 16, 0x1000, 0x2000)), None);
+        assert_eq!(age(event(AllocOp::Dealloc, 8, 0x1000)), None);
+        assert!(age(event(AllocOp::Dealloc, 16, 0x2000)).is_some());
+        // the birth was forgotten by the first deallocation
+        assert_eq!(age(event(AllocOp::Dealloc, 16, 0x2000)), None);
+
+        // choosing another pitch forgets every birth
+        assert_eq!(age(event(AllocOp::AllocZeroed, 8, 0x3000)), None);
+        let dealloc = event(AllocOp::Dealloc, 8, 0x3000);
+        assert_eq!(lifetimes.age(Pitch::Size, &dealloc), None);
+        assert_eq!(age(dealloc), None);
+
+        let young = Pitch::Lifetime.ratio(Scale::Continuous, &dealloc, 0, None);
+        assert_eq!(young, None);
+        let millisecond = Some(Duration::from_millis(1));
+        let ratio = Pitch::Lifetime.ratio(Scale::Continuous, &dealloc, 0, millisecond);
+        assert!((ratio.unwrap() - 1.0).abs() < 0.01, "{ratio:?}");
+        let second = Some(Duration::from_secs(1));
+        let ratio = Pitch::Lifetime.ratio(Scale::Continuous, &dealloc, 0, second);
+        assert!(ratio.unwrap() < 0.5, "{ratio:?}");
+    }
+
+    #[test]
+    fn quantizes_to_scales() {
+        assert_eq!(Scale::Continuous.quantize(5.3), 5.3);
+        assert_eq!(Scale::Pentatonic.quantize(5.0), 4.0);
+        assert_eq!(Scale::Pentatonic.quantize(11.0), 12.0);
+        assert_eq!(Scale::Pentatonic.quantize(-1.0), 0.0);
+        assert_eq!(Scale::Pentatonic.quantize(-14.5), -15.0);
+        assert_eq!(Scale::Major.quantize(5.4), 5.0);
+        assert_eq!(Scale::Major.quantize(-0.6), -1.0);
+    }
+
+    #[test]
+    fn bends_reallocations() {
+        let grow = realloc(64, 128, 0x1000, 0x2000);
+        let shrink = realloc(64, 16, 0x1000, 0x1000);
+        let same = realloc(64, 64, 0x1000, 0x1000);
+        assert_eq!(Bend::Direction.ratio(&grow), Some(1.5));
+        assert_eq!(Bend::Direction.ratio(&shrink), Some(1.5f32.recip()));
+        assert_eq!(Bend::Direction.ratio(&same), None);
+        assert_eq!(Bend::Off.ratio(&grow), None);
+        assert_eq!(Bend::Ratio.ratio(&event(AllocOp::Alloc, 64, 0x1000)), None);
+
+        let thirds = |event| Bend::Ratio.ratio(&event).unwrap().log2() * 3.0;
+        assert!((thirds(grow) - 1.0).abs() < 1e-5);
+        assert!((thirds(shrink) + 2.0).abs() < 1e-5);
+        // from nothing, the glide is clamped
+        assert!((thirds(realloc(0, 1 << 40, 0x1000, 0x2000)) - 9.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn names_thread_pools() {
+        assert_eq!(pool_name("rayon-3"), Some("rayon"));
+        assert_eq!(pool_name("worker #12"), Some("worker"));
+        assert_eq!(pool_name("pool_0"), Some("pool"));
+        assert_eq!(pool_name("tokio-runtime-w"), Some("tokio-runtime-w"));
+        assert_eq!(pool_name("main"), None);
+        assert_eq!(pool_name("42"), None);
+        assert_eq!(pool_name("-7"), None);
+    }
+
+    #[test]
+    fn parses_names() {
+        for pitch in Pitch::ALL {
+            assert_eq!(pitch.to_string().parse(), Ok(pitch));
+        }
+        for scale in Scale::ALL {
+            assert_eq!(scale.to_string().parse(), Ok(scale));
+        }
+        for bend in Bend::ALL {
+            assert_eq!(bend.to_string().parse(), Ok(bend));
+        }
+        assert_eq!("CLASS".parse(), Ok(Pitch::Class));
+        assert!("chromatic".parse::<Scale>().is_err());
+        assert!("".parse::<Bend>().is_err());
+    }
+}
@@ -0,0 +1,281 @@
+use crate::clock::now_millis;
+use crate::event::AllocEvent;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A decision about each event that would make sound, set with
+/// [`Geiger::set_policy`](crate::Geiger::set_policy).
+///
+/// The policy is consulted after the [`Config`](crate::Config) filters, like
+/// [`ops`](crate::Config::ops) and [`warmup`](crate::Config::warmup), and
+/// its own allocations are not counted.  Closures taking an [`AllocEvent`]
+/// are policies too, and the built-in [`MinSize`], [`MaxRate`],
+/// [`MaxRateBySize`], and [`OnThread`] combine with [`and`](Self::and).
+///
+/// ```
+/// use alloc_geiger::{AllocEvent, Decision, GeigerPolicy, MaxRate, MinSize};
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     let policy = MinSize(64).and(MaxRate::new(100)).and(|event: &AllocEvent| {
+///         // half as loud off the first thread
+///         if event.thread() == 0 { Decision::Play } else { Decision::Gain(0.5) }
+///     });
+///     ALLOC.set_policy(Some(policy));
+///     // ...
+/// }
+/// ```
+pub trait GeigerPolicy: Send + Sync {
+    /// Decides whether and how to play an event.
+    fn decide(&self, event: &AllocEvent) -> Decision;
+
+    /// Combines with another policy, playing only if both do, with the
+    /// product of their gains.
+    fn and<P: GeigerPolicy>(self, other: P) -> And<Self, P>
+    where
+        Self: Sized,
+    {
+        And(self, other)
+    }
+}
+
+/// What a [`GeigerPolicy`] decides for an event.
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[non_exhaustive]
+pub enum Decision {
+    /// Plays the event's pulse as configured.
+    Play,
+    /// Plays the event's pulse scaled by a gain.
+    Gain(f32),
+    /// Makes no sound for the event, which is still counted.
+    Quiet,
+}
+
+impl Decision {
+    /// The gain to play with, or `None` to stay quiet.
+    pub(crate) fn gain(self) -> Option<f32> {
+        match self {
+            Decision::Play => Some(1.0),
+            Decision::Gain(gain) => Some(gain),
+            Decision::Quiet => None,
+        }
+    }
+}
+
+impl<F> GeigerPolicy for F
+where
+    F: Fn(&AllocEvent) -> Decision + Send + Sync,
+{
+    fn decide(&self, event: &AllocEvent) -> Decision {
+        self(event)
+    }
+}
+
+/// Two policies combined with [`GeigerPolicy::and`].
+#[derive(Clone, Copy, Debug)]
+pub struct And<A, B>(A, B);
+
+impl<A: GeigerPolicy, B: GeigerPolicy> GeigerPolicy for And<A, B> {
+    fn decide(&self, event: &AllocEvent) -> Decision {
+        let Some(first) = self.0.decide(event).gain() else {
+            return Decision::Quiet;
+        };
+        match self.1.decide(event).gain() {
+            Some(second) if first * second == 1.0 => Decision::Play,
+            Some(second) => Decision::Gain(first * second),
+            None => Decision::Quiet,
+        }
+    }
+}
+
+/// Plays only events of at least this many bytes, by their new size.
+#[derive(Clone, Copy, Debug)]
+pub struct MinSize(pub usize);
+
+impl GeigerPolicy for MinSize {
+    fn decide(&self, event: &AllocEvent) -> Decision {
+        if event.new_size() >= self.0 {
+            Decision::Play
+        } else {
+            Decision::Quiet
+        }
+    }
+}
+
+/// Plays at most this many events in each second, staying quiet for the
+/// rest.
+#[derive(Debug)]
+pub struct MaxRate {
+    per_second: u64,
+    /// the current second, since the crate's first event
+    second: AtomicU64,
+    /// events played in the current second
+    played: AtomicU64,
+}
+
+impl MaxRate {
+    /// Creates a policy playing up to `per_second` events each second.
+    pub const fn new(per_second: u64) -> Self {
+        MaxRate {
+            per_second,
+            second: AtomicU64::new(0),
+            played: AtomicU64::new(0),
+        }
+    }
+}
+
+impl GeigerPolicy for MaxRate {
+    fn decide(&self, _event: &AllocEvent) -> Decision {
+        let second = now_millis() / 1000;
+        if self.second.swap(second, Ordering::Relaxed) != second {
+            self.played.store(0, Ordering::Relaxed);
+        }
+        if self.played.fetch_add(1, Ordering::Relaxed) < self.per_second {
+            Decision::Play
+        } else {
+            Decision::Quiet
+        }
+    }
+}
+
+/// Plays at most a separate number of events each second for each range of
+/// sizes, so a storm of small allocations can't crowd out the large ones.
+///
+/// Each limit applies to the events from its size up to the next limit's,
+/// by their new size, and events smaller than every limit always play.
+///
+/// ```
+/// use alloc_geiger::MaxRateBySize;
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     // tiny allocations barely, and a page or more always
+///     let policy = MaxRateBySize::new([(0, 10), (256, 200), (4096, u64::MAX)]);
+///     ALLOC.set_policy(Some(policy));
+///     // ...
+/// }
+/// ```
+#[derive(Debug)]
+pub struct MaxRateBySize {
+    /// the smallest size of each limit, in order
+    limits: Vec<(usize, MaxRate)>,
+}
+
+impl MaxRateBySize {
+    /// Creates a policy from pairs of the smallest size of a range and the
+    /// events it may play each second, in any order.
+    pub fn new(limits: impl IntoIterator<Item = (usize, u64)>) -> Self {
+        let limits = limits
+            .into_iter()
+            .map(|(size, per_second)| (size, MaxRate::new(per_second)));
+        let mut limits: Vec<_> = limits.collect();
+        limits.sort_by_key(|&(size, _)| size);
+        MaxRateBySize { limits }
+    }
+}
+
+impl GeigerPolicy for MaxRateBySize {
+    fn decide(&self, event: &AllocEvent) -> Decision {
+        let limit = (self.limits.iter().rev()).find(|&&(size, _)| event.new_size() >= size);
+        match limit {
+            Some((_, rate)) => rate.decide(event),
+            None => Decision::Play,
+        }
+    }
+}
+
+/// Plays only events on threads with this name.
+#[derive(Clone, Copy, Debug)]
+pub struct OnThread(pub &'static str);
+
+impl GeigerPolicy for OnThread {
+    fn decide(&self, _event: &AllocEvent) -> Decision {
+        if std::thread::current().name() == Some(self.0) {
+            Decision::Play
+        } else {
+            Decision::Quiet
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AllocOp;
+    use std::alloc::Layout;
+    use std::thread;
+
+    fn alloc(size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(AllocOp::Alloc, layout, 0x1000 as *mut u8)
+    }
+
+    /// Decides a run of events within one second of the clock, retrying if
+    /// the second changes.
+    fn decide_in_a_second(policy: &impl GeigerPolicy, sizes: &[usize]) -> Vec<Decision> {
+        loop {
+            let second = now_millis() / 1000;
+            let decisions = sizes.iter().map(|&size| policy.decide(&alloc(size)));
+            let decisions = decisions.collect();
+            if now_millis() / 1000 == second {
+                return decisions;
+            }
+        }
+    }
+
+    #[test]
+    fn combines_gains() {
+        let play = |_: &AllocEvent| Decision::Play;
+        let half = |_: &AllocEvent| Decision::Gain(0.5);
+        let double = |_: &AllocEvent| Decision::Gain(2.0);
+        let quiet = |_: &AllocEvent| Decision::Quiet;
+        let event = alloc(8);
+        assert_eq!(play.and(play).decide(&event), Decision::Play);
+        assert_eq!(play.and(half).decide(&event), Decision::Gain(0.5));
+        assert_eq!(half.and(half).decide(&event), Decision::Gain(0.25));
+        assert_eq!(half.and(double).decide(&event), Decision::Play);
+        assert_eq!(half.and(quiet).decide(&event), Decision::Quiet);
+        // the second isn't asked once the first stays quiet
+        let unasked = |_: &AllocEvent| -> Decision { unreachable!() };
+        assert_eq!(quiet.and(unasked).decide(&event), Decision::Quiet);
+    }
+
+    #[test]
+    fn plays_large_enough_events() {
+        assert_eq!(MinSize(64).decide(&alloc(63)), Decision::Quiet);
+        assert_eq!(MinSize(64).decide(&alloc(64)), Decision::Play);
+        assert_eq!(MinSize(0).decide(&alloc(0)), Decision::Play);
+    }
+
+    #[test]
+    fn limits_the_rate() {
+        use Decision::{Play, Quiet};
+        let decisions = decide_in_a_second(&MaxRate::new(3), &[8; 5]);
+        assert_eq!(decisions, [Play, Play, Play, Quiet, Quiet]);
+        assert_eq!(decide_in_a_second(&MaxRate::new(0), &[8]), [Quiet]);
+    }
+
+    #[test]
+    fn limits_the_rate_by_size() {
+        use Decision::{Play, Quiet};
+        let policy = MaxRateBySize::new([(256, 2), (0, 1)]);
+        let decisions = decide_in_a_second(&policy, &[8, 300, 100, 4096, 256]);
+        assert_eq!(decisions, [Play, Play, Quiet, Play, Quiet]);
+
+        let policy = MaxRateBySize::new([(64, 0)]);
+        let decisions = decide_in_a_second(&policy, &[8, 63, 64]);
+        assert_eq!(decisions, [Play, Play, Quiet]);
+    }
+
+    #[test]
+    fn plays_on_named_threads() {
+        let event = alloc(8);
+        let decide = move || OnThread("geiger-test").decide(&event);
+        let named = thread::Builder::new().name("geiger-test".into());
+        assert_eq!(named.spawn(decide).unwrap().join().unwrap(), Decision::Play);
+        assert_eq!(thread::spawn(decide).join().unwrap(), Decision::Quiet);
+    }
+}
@@ -0,0 +1,64 @@
+//! Raising the priority of the thread that mixes the output, for
+//! [`Config::audio_priority`](crate::Config::audio_priority).
+
+use std::io;
+
+/// Raises the priority of the calling thread as far as it's allowed, warning
+/// on stderr if it can't be raised at all.
+pub(crate) fn boost() {
+    if let Err(e) = raise() {
+        eprintln!("alloc_geiger: couldn't raise the audio thread's priority: {e}");
+    }
+}
+
+/// Asks for the lowest real-time priority, which still preempts every
+/// ordinary thread, or else the highest nice value usually allowed.
+#[cfg(unix)]
+fn raise() -> io::Result<()> {
+    // SAFETY: these only change the scheduling of the calling thread, and
+    // `sched_param` is plain data.
+    let error = unsafe {
+        let mut param: libc::sched_param = std::mem::zeroed();
+        param.sched_priority = libc::sched_get_priority_min(libc::SCHED_FIFO);
+        libc::pthread_setschedparam(libc::pthread_self(), libc::SCHED_FIFO, &param)
+    };
+    if error == 0 {
+        return Ok(());
+    }
+    // Only Linux has a nice value per thread, rather than per process.
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        // SAFETY: as above, for the nice value of this thread's ID.
+        unsafe {
+            let tid = libc::syscall(libc::SYS_gettid);
+            if libc::setpriority(libc::PRIO_PROCESS as _, tid as _, -10) == 0 {
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::from_raw_os_error(error))
+}
+
+#[cfg(windows)]
+fn raise() -> io::Result<()> {
+    use std::ffi::c_void;
+
+    const THREAD_PRIORITY_TIME_CRITICAL: i32 = 15;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetCurrentThread() -> *mut c_void;
+        fn SetThreadPriority(thread: *mut c_void, priority: i32) -> i32;
+    }
+
+    // SAFETY: the pseudo-handle of the current thread is always valid.
+    if unsafe { SetThreadPriority(GetCurrentThread(), THREAD_PRIORITY_TIME_CRITICAL) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(unix, windows)))]
+fn raise() -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
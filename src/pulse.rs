@@ -0,0 +1,1206 @@
+use crate::config::{Config, ParseConfigError};
+use crate::event::{AllocEvent, AllocOp};
+use crate::theme::Recording;
+use crate::voice::Voice;
+#[cfg(feature = "sound-rodio")]
+use rodio::Source;
+use std::f32::consts::PI;
+use std::fmt;
+use std::mem;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// The most echo buffers kept for reuse.
+const SPARE_BUFFERS: usize = 64;
+
+/// The echo buffers of finished pulses, for new ones to reuse, so echoes
+/// don't allocate once enough have played.
+static SPARE: Mutex<Vec<Vec<f32>>> = Mutex::new(Vec::new());
+
+/// The shape of the sound played for each allocator event.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Waveform {
+    /// A pulse based on the sinc function, sin(x)/x, named `sinc`.
+    #[default]
+    Sinc,
+    /// A short blip of a sine wave, named `sine`.
+    Sine,
+    /// A square wave tick, named `square`.
+    Square,
+    /// A burst of band-limited noise, named `noise`.
+    Noise,
+    /// The crackle of a real Geiger-Müller tube, named `authentic`: a sharp
+    /// noise transient with an exponential decay, varying a little with each
+    /// click.
+    Authentic,
+}
+
+impl Waveform {
+    const ALL: [Waveform; 5] = [
+        Waveform::Sinc,
+        Waveform::Sine,
+        Waveform::Square,
+        Waveform::Noise,
+        Waveform::Authentic,
+    ];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Waveform::Sinc => "sinc",
+            Waveform::Sine => "sine",
+            Waveform::Square => "square",
+            Waveform::Noise => "noise",
+            Waveform::Authentic => "authentic",
+        }
+    }
+
+    /// The fundamental frequency, in Hz.
+    const fn frequency(self) -> f32 {
+        match self {
+            Waveform::Sinc => 4000.0,
+            Waveform::Sine => 2000.0,
+            Waveform::Square => 1000.0,
+            Waveform::Noise => 0.0,
+            // the ring of the speaker cone under the transient
+            Waveform::Authentic => 3200.0,
+        }
+    }
+
+    /// The default length of the whole pulse.
+    const fn duration(self) -> Duration {
+        Duration::from_millis(match self {
+            // 8 periods at 4 kHz, centered on the peak
+            Waveform::Sinc => 2,
+            Waveform::Sine => 3,
+            Waveform::Square => 2,
+            Waveform::Noise => 4,
+            Waveform::Authentic => 6,
+        })
+    }
+}
+
+/// Where each pulse is placed in the stereo field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Pan {
+    /// Plays every pulse in mono, named `center`.
+    #[default]
+    Center,
+    /// Plays allocations on the left and deallocations on the right, named
+    /// `ops`, so an imbalance between them is audible.  Reallocations stay in
+    /// the center.
+    Ops,
+    /// Places each event by its address within the range seen so far, from
+    /// the lowest on the left to the highest on the right, named `address`.
+    /// An arena sounds like a fixed point, while fragmentation sounds wide.
+    Address,
+    /// Spreads the threads across the stereo field, named `thread`, starting
+    /// from the center and alternating sides, so concurrent threads are heard
+    /// apart, with each pool of threads in one place by default.
+    Thread,
+}
+
+impl Pan {
+    const ALL: [Pan; 4] = [Pan::Center, Pan::Ops, Pan::Address, Pan::Thread];
+
+    /// The positions of the threads, cycling after the last.
+    const THREADS: [f32; 9] = [0.0, -0.5, 0.5, -1.0, 1.0, -0.25, 0.25, -0.75, 0.75];
+
+    const fn name(self) -> &'static str {
+        match self {
+            Pan::Center => "center",
+            Pan::Ops => "ops",
+            Pan::Address => "address",
+            Pan::Thread => "thread",
+        }
+    }
+
+    /// The stereo position of an event from left `-1.0` to right `1.0`, or
+    /// `None` for mono, with the index of its thread's or pool's `voice`.
+    pub(crate) fn position(
+        self,
+        event: &AllocEvent,
+        voice: u32,
+        addresses: &AddressRange,
+    ) -> Option<f32> {
+        match (self, event.op) {
+            (Pan::Center, _) => None,
+            (Pan::Address, _) => Some(addresses.position(event.address)),
+            (Pan::Thread, _) => {
+                let threads = Self::THREADS.len() as u32;
+                Some(Self::THREADS[(voice % threads) as usize])
+            }
+            (Pan::Ops, AllocOp::Alloc | AllocOp::AllocZeroed) => Some(-1.0),
+            (Pan::Ops, AllocOp::Dealloc) => Some(1.0),
+            (Pan::Ops, AllocOp::Realloc) => Some(0.0),
+        }
+    }
+}
+
+/// The lowest and highest addresses seen, for [`Pan::Address`].
+pub(crate) struct AddressRange {
+    low: AtomicUsize,
+    high: AtomicUsize,
+}
+
+impl AddressRange {
+    pub(crate) const fn new() -> Self {
+        AddressRange {
+            low: AtomicUsize::new(usize::MAX),
+            high: AtomicUsize::new(0),
+        }
+    }
+
+    /// Widens the range to include an event's address, if it has one.
+    pub(crate) fn record(&self, address: usize) {
+        if address == 0 {
+            return;
+        }
+        // most addresses are already within range, so check before writing
+        if address < self.low.load(Ordering::Relaxed) {
+            self.low.fetch_min(address, Ordering::Relaxed);
+        }
+        if address > self.high.load(Ordering::Relaxed) {
+            self.high.fetch_max(address, Ordering::Relaxed);
+        }
+    }
+
+    /// The position of an address from `-1.0` to `1.0` within the range, or
+    /// the center until the range has any width.
+    fn position(&self, address: usize) -> f32 {
+        let low = self.low.load(Ordering::Relaxed);
+        let high = self.high.load(Ordering::Relaxed);
+        if address == 0 || high <= low {
+            return 0.0;
+        }
+        let offset = address.clamp(low, high) - low;
+        (offset as f64 / (high - low) as f64 * 2.0 - 1.0) as f32
+    }
+}
+
+impl fmt::Display for Pan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Pan {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pan::ALL
+            .into_iter()
+            .find(|pan| pan.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("pan", s))
+    }
+}
+
+/// What varies the loudness of each pulse.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Loudness {
+    /// Plays every pulse at the same volume, named `fixed`.
+    #[default]
+    Fixed,
+    /// Plays larger allocations louder, by the logarithm of their size, named
+    /// `size`.  This is easier to hear than pitch in a noisy room.
+    Size,
+}
+
+impl Loudness {
+    const ALL: [Loudness; 2] = [Loudness::Fixed, Loudness::Size];
+
+    /// The quietest level, for the smallest sizes, and the size that plays
+    /// at full volume.
+    const FLOOR: f32 = 0.2;
+    const LOUDEST_SIZE_LOG2: f32 = 24.0;
+
+    const fn name(self) -> &'static str {
+        match self {
+            Loudness::Fixed => "fixed",
+            Loudness::Size => "size",
+        }
+    }
+
+    /// The gain of an event's pulse relative to the volume, or `None` to leave
+    /// it be.
+    pub(crate) fn gain(self, event: &AllocEvent) -> Option<f32> {
+        match self {
+            Loudness::Fixed => None,
+            Loudness::Size => {
+                let size = event.new_size.max(1) as f32;
+                let level = (size.log2() / Self::LOUDEST_SIZE_LOG2).clamp(0.0, 1.0);
+                Some(Self::FLOOR + (1.0 - Self::FLOOR) * level)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Loudness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Loudness {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Loudness::ALL
+            .into_iter()
+            .find(|loudness| loudness.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("loudness", s))
+    }
+}
+
+/// The amplitude of each pulse over time, applied on top of the shape of its
+/// [`Waveform`].
+///
+/// Shorter envelopes keep dense clicks distinct, at the cost of audibility.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Envelope {
+    /// The time to ramp up linearly from silence, key `attack` in
+    /// milliseconds.
+    pub attack: Duration,
+    /// The time constant of an exponential decay after the attack, key `decay`
+    /// in milliseconds, or zero to hold the level until the pulse ends.
+    pub decay: Duration,
+}
+
+impl Envelope {
+    /// An envelope that leaves the waveform unchanged.
+    pub const FLAT: Envelope = Envelope {
+        attack: Duration::ZERO,
+        decay: Duration::ZERO,
+    };
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = ParseConfigError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Waveform::ALL
+            .into_iter()
+            .find(|waveform| waveform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| ParseConfigError::invalid("waveform", s))
+    }
+}
+
+/// A single click or tone, the sound played for each allocator event and for
+/// the other cues, as a source of `f32` samples.
+///
+/// Applications can play these for their own cues, the same as the events
+/// sound, with `rodio` when the `sound-rodio` feature is on,
+/// or take the samples as an [`Iterator`], which makes them easy to check:
+///
+/// ```
+/// use alloc_geiger::{Pulse, Waveform};
+/// use std::time::Duration;
+///
+/// let pulse = Pulse::tone(Waveform::Sine, 48_000, 440.0, 0.5, Duration::from_millis(10));
+/// assert_eq!(pulse.channels(), 1);
+/// let samples: Vec<f32> = pulse.collect();
+/// assert_eq!(samples.len(), 480);
+/// assert!(samples.iter().all(|sample| sample.abs() <= 0.5));
+/// assert!(samples.iter().any(|sample| sample.abs() > 0.4));
+/// ```
+pub struct Pulse {
+    waveform: Waveform,
+    sample_rate: u32,
+    index: u32,
+    len: u32,
+    /// length of the envelope's attack, and its decay factor per sample
+    attack: u32,
+    fade: f32,
+    gain: f32,
+    /// radians per sample at the waveform's frequency, and the oscillator's
+    /// current phase
+    step: f32,
+    phase: f32,
+    /// frequency ratio reached by the end of the pulse
+    bend: f32,
+    peak: f32,
+    rng: Rng,
+    voice: Option<Voice>,
+    /// stereo gains, and the right sample still to be played
+    stereo: Option<(f32, f32)>,
+    right: Option<f32>,
+    /// low-pass filter states for band-limiting noise
+    fast: f32,
+    slow: f32,
+    /// exponential envelope level, and its factor per sample
+    level: f32,
+    decay: f32,
+    /// delayed repeats of the pulse, the samples between them, and the dry
+    /// samples played so far for them to repeat
+    echoes: u32,
+    echo_delay: u32,
+    dry: Vec<f32>,
+    /// the chance of a grain starting at each sample, the samples in each
+    /// grain, and those left in the current one
+    grains: Option<(f32, u32, u32)>,
+    /// a recording played instead of the waveform, and its samples per
+    /// radian of the oscillator's phase
+    recording: Option<(Recording, f32)>,
+    /// how hard each sample is driven into saturation, or `0.0` for none
+    drive: f32,
+}
+
+impl Pulse {
+    /// The decay time constant of an `Authentic` click, in seconds.
+    const AUTHENTIC_DECAY: f32 = 0.0006;
+
+    /// The length of an alarm, and its starting frequency in Hz before it falls
+    /// an octave.
+    pub(crate) const ALARM_DURATION: Duration = Duration::from_millis(600);
+    const ALARM_FREQUENCY: f32 = 440.0;
+
+    /// The length of a warning clunk, and its frequency in Hz.
+    const CLUNK_DURATION: Duration = Duration::from_millis(80);
+    const CLUNK_FREQUENCY: f32 = 110.0;
+
+    /// The length of the chime for a new peak of the live bytes, and its
+    /// frequency in Hz.
+    const WATERMARK_DURATION: Duration = Duration::from_millis(150);
+    const WATERMARK_FREQUENCY: f32 = 2637.0;
+
+    /// The length of the cue for an allocation while unwinding, and its
+    /// starting frequency in Hz before it rises an octave.
+    const UNWIND_DURATION: Duration = Duration::from_millis(100);
+    const UNWIND_FREQUENCY: f32 = 1760.0;
+
+    /// The length of the cue after a pause, and its starting frequency in Hz
+    /// before it rises a fifth.
+    const ON_AIR_DURATION: Duration = Duration::from_millis(120);
+    const ON_AIR_FREQUENCY: f32 = 660.0;
+
+    /// The length of a summary sweep, its middle frequency in Hz, and the
+    /// widest it sweeps in semitones.
+    const SWEEP_DURATION: Duration = Duration::from_millis(250);
+    const SWEEP_FREQUENCY: f32 = 440.0;
+    const SWEEP_RANGE: f32 = 24.0;
+
+    /// The length of a thread's birth or death cue, and its low frequency in
+    /// Hz, an octave from the high one.
+    const THREAD_DURATION: Duration = Duration::from_millis(90);
+    const THREAD_FREQUENCY: f32 = 330.0;
+
+    /// The length of the reference tone for the sizes, its frequency in Hz
+    /// for a single byte, and the semitones it rises for each doubling.
+    const QUANTILE_DURATION: Duration = Duration::from_millis(200);
+    const QUANTILE_FREQUENCY: f32 = 110.0;
+    const QUANTILE_STEP: f32 = 2.0;
+
+    /// The length of the ping for a watched size, and its frequency in Hz.
+    const PING_DURATION: Duration = Duration::from_millis(200);
+    const PING_FREQUENCY: f32 = 1760.0;
+
+    /// The length of the cue for the first allocation after a mark, and its
+    /// starting frequency in Hz before it falls a fifth.
+    const MARKED_DURATION: Duration = Duration::from_millis(160);
+    const MARKED_FREQUENCY: f32 = 1320.0;
+
+    /// The length of the self-test chirp, and its starting frequency in Hz
+    /// before it rises two octaves.
+    pub(crate) const SELF_TEST_DURATION: Duration = Duration::from_millis(300);
+    const SELF_TEST_FREQUENCY: f32 = 440.0;
+
+    /// The length of the cue for the allocation that springs a trap, and its
+    /// starting frequency in Hz before it rises an octave.
+    const TRAPPED_DURATION: Duration = Duration::from_millis(160);
+    const TRAPPED_FREQUENCY: f32 = 880.0;
+
+    /// The length of each note of the summary jingle, the time from one to
+    /// the next, the length of its held last note, and its root frequency in
+    /// Hz.
+    const SUMMARY_DURATION: Duration = Duration::from_millis(180);
+    pub(crate) const SUMMARY_STEP: Duration = Duration::from_millis(110);
+    pub(crate) const SUMMARY_HELD: Duration = Duration::from_millis(450);
+    const SUMMARY_FREQUENCY: f32 = 523.25;
+
+    /// The length of each grain of the hiss above the grains rate, and the
+    /// fewest and most grains per second.
+    const GRAIN_DURATION: Duration = Duration::from_micros(1500);
+    const GRAIN_RATES: (f32, f32) = (20.0, 4000.0);
+
+    /// The alignment above which pulses are brightened, and the most
+    /// doublings past it that brighten them further.
+    const PLAIN_ALIGN: usize = 16;
+    const MAX_ALIGN_DOUBLINGS: u32 = 8;
+
+    /// The gain of a click at a recycled address.
+    const RECYCLED_GAIN: f32 = 0.35;
+
+    /// The time between echoes, and the gain of each relative to the last.
+    const ECHO_DELAY: Duration = Duration::from_millis(40);
+    const ECHO_FEEDBACK: f32 = 0.6;
+
+    /// A click as it's played for each event with this configuration, with
+    /// its waveform, duration, and envelope, at a peak amplitude from `0.0` to
+    /// `1.0`.
+    ///
+    /// This leaves out the pitch, loudness, and position that each event
+    /// adds, which are up to the caller with [`pitch`](Self::pitch),
+    /// [`gain`](Self::gain), and [`pan`](Self::pan).
+    pub fn new(config: &Config, sample_rate: u32, peak: f32) -> Self {
+        let waveform = config.waveform;
+        let samples = |duration: Duration| {
+            let samples = duration.as_secs_f64() * f64::from(sample_rate);
+            samples.min(f64::from(u32::MAX)) as u32
+        };
+        let len = samples(config.duration.unwrap_or(waveform.duration())).max(1);
+        let decay = samples(config.envelope.decay);
+        let fade = if decay > 0 {
+            (-1.0 / decay as f32).exp()
+        } else {
+            1.0
+        };
+        let mut rng = Rng::new();
+        let mut frequency = waveform.frequency();
+        let (mut level, mut decay) = (1.0, 1.0);
+        if waveform == Waveform::Authentic {
+            // no two discharges of a real tube sound quite the same
+            level += 0.2 * rng.next_f32();
+            frequency *= 1.0 + 0.1 * rng.next_f32();
+            let tau = Self::AUTHENTIC_DECAY * (1.0 + 0.3 * rng.next_f32());
+            decay = (-1.0 / (tau * sample_rate as f32)).exp();
+        }
+        Pulse {
+            waveform,
+            sample_rate,
+            index: 0,
+            len,
+            attack: samples(config.envelope.attack),
+            fade,
+            gain: 1.0,
+            step: 2.0 * PI * frequency / sample_rate as f32,
+            phase: 0.0,
+            bend: 1.0,
+            peak,
+            rng,
+            voice: None,
+            stereo: None,
+            right: None,
+            fast: 0.0,
+            slow: 0.0,
+            level,
+            decay,
+            echoes: 0,
+            echo_delay: 0,
+            dry: Vec::new(),
+            grains: None,
+            recording: None,
+            drive: 0.0,
+        }
+    }
+
+    /// A tone of a waveform at a frequency in Hz, for a duration, at a peak
+    /// amplitude from `0.0` to `1.0`, without any envelope.
+    ///
+    /// [`Waveform::Noise`] has no frequency, so it's ignored for that.
+    pub fn tone(
+        waveform: Waveform,
+        sample_rate: u32,
+        frequency: f32,
+        peak: f32,
+        duration: Duration,
+    ) -> Self {
+        let config = Config::new().waveform(waveform).duration(duration);
+        let mut pulse = Pulse::new(&config, sample_rate, peak);
+        pulse.step = 2.0 * PI * frequency / sample_rate as f32;
+        pulse
+    }
+
+    /// A noise click, the crackle of a real Geiger-Müller tube played for
+    /// [`Waveform::Authentic`], at a peak amplitude from `0.0` to `1.0`.
+    ///
+    /// Each one varies a little, like the clicks of the events.
+    pub fn noise_click(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new().waveform(Waveform::Authentic);
+        Pulse::new(&config, sample_rate, peak)
+    }
+
+    /// A long, falling square tone for a failed allocation, which doesn't
+    /// take a voice so it's never dropped.
+    pub(crate) fn alarm(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Self::ALARM_DURATION);
+        let ratio = Self::ALARM_FREQUENCY / Waveform::Square.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(0.5)
+    }
+
+    /// A long, rising square tone for live bytes nearing the cap, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn siren(sample_rate: u32, peak: f32) -> Self {
+        Pulse::alarm(sample_rate, peak).bend(2.0)
+    }
+
+    /// A loud, low clunk for a large allocation, at full scale regardless of
+    /// the volume, which doesn't take a voice so it's never dropped.
+    pub(crate) fn clunk(sample_rate: u32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Self::CLUNK_DURATION);
+        let ratio = Self::CLUNK_FREQUENCY / Waveform::Square.frequency();
+        Pulse::new(&config, sample_rate, 1.0).pitch(ratio)
+    }
+
+    /// A shrill square blip rising an octave for an allocation during a
+    /// panic's unwinding, which doesn't take a voice so it's never dropped.
+    pub(crate) fn unwinding(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Self::UNWIND_DURATION);
+        let ratio = Self::UNWIND_FREQUENCY / Waveform::Square.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(2.0)
+    }
+
+    /// A short, rising sine chirp when sound resumes after a pause, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn on_air(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::ON_AIR_DURATION);
+        let ratio = Self::ON_AIR_FREQUENCY / Waveform::Sine.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(1.5)
+    }
+
+    /// A sine sweep summarizing the net change of the live bytes, rising for
+    /// growth and falling for a shrink, by two semitones for each doubling
+    /// beyond a kilobyte, which doesn't take a voice so it's never dropped.
+    pub(crate) fn sweep(sample_rate: u32, peak: f32, delta: i64) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::SWEEP_DURATION);
+        let semitones = (2.0 * (1.0 + delta.unsigned_abs() as f32 / 1024.0).log2())
+            .clamp(1.0, Self::SWEEP_RANGE)
+            * delta.signum() as f32;
+        // start below the middle when rising, and above when falling
+        let ratio = Self::SWEEP_FREQUENCY / Waveform::Sine.frequency();
+        let start = (-semitones / 24.0).exp2();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio * start)
+            .bend((semitones / 12.0).exp2())
+    }
+
+    /// A sine glide up an octave when a thread is born, or down when it dies,
+    /// which doesn't take a voice so it's never dropped.
+    pub(crate) fn thread(sample_rate: u32, peak: f32, born: bool) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::THREAD_DURATION);
+        let ratio = Self::THREAD_FREQUENCY / Waveform::Sine.frequency();
+        let pulse = Pulse::new(&config, sample_rate, peak);
+        if born {
+            pulse.pitch(ratio).bend(2.0)
+        } else {
+            pulse.pitch(ratio * 2.0).bend(0.5)
+        }
+    }
+
+    /// A high, ringing sine ping for an allocation of a watched size, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn ping(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::PING_DURATION)
+            .envelope(Envelope {
+                attack: Duration::ZERO,
+                decay: Self::PING_DURATION / 4,
+            });
+        let ratio = Self::PING_FREQUENCY / Waveform::Sine.frequency();
+        Pulse::new(&config, sample_rate, peak).pitch(ratio)
+    }
+
+    /// A soft, high sine chime for a new peak of the live bytes, which doesn't
+    /// take a voice so it's never dropped.
+    pub(crate) fn watermark(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::WATERMARK_DURATION)
+            .envelope(Envelope {
+                attack: Duration::ZERO,
+                decay: Self::WATERMARK_DURATION / 3,
+            });
+        let ratio = Self::WATERMARK_FREQUENCY / Waveform::Sine.frequency();
+        Pulse::new(&config, sample_rate, peak / 3.0).pitch(ratio)
+    }
+
+    /// A soft sine tone for the 99th percentile of the sizes, higher for
+    /// larger sizes, which doesn't take a voice so it's never dropped.
+    pub(crate) fn quantile(sample_rate: u32, peak: f32, size: usize) -> Self {
+        let semitones = (size.max(1) as f32).log2() * Self::QUANTILE_STEP;
+        let frequency = Self::QUANTILE_FREQUENCY * (semitones / 12.0).exp2();
+        Pulse::tone(
+            Waveform::Sine,
+            sample_rate,
+            frequency,
+            peak / 3.0,
+            Self::QUANTILE_DURATION,
+        )
+    }
+
+    /// A falling square blip for the first allocation after a mark, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn marked(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Self::MARKED_DURATION);
+        let ratio = Self::MARKED_FREQUENCY / Waveform::Square.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(2.0 / 3.0)
+    }
+
+    /// A sine note of the summary jingle's arpeggio, `semitones` above its
+    /// root.
+    pub(crate) fn summary(sample_rate: u32, peak: f32, semitones: i32) -> Self {
+        Self::summary_note(sample_rate, peak, semitones, Self::SUMMARY_DURATION)
+    }
+
+    /// The held last note of the summary jingle, resolving two octaves above
+    /// the root if nothing leaked, or else a sour tritone that falls a
+    /// semitone.
+    pub(crate) fn summary_end(sample_rate: u32, peak: f32, leaked: bool) -> Self {
+        if !leaked {
+            return Self::summary_note(sample_rate, peak, 24, Self::SUMMARY_HELD);
+        }
+        Self::summary_note(sample_rate, peak, 6, Self::SUMMARY_HELD).bend(2f32.powf(-1.0 / 12.0))
+    }
+
+    fn summary_note(sample_rate: u32, peak: f32, semitones: i32, duration: Duration) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(duration)
+            .envelope(Envelope {
+                attack: Duration::from_millis(5),
+                decay: duration / 2,
+            });
+        let ratio = Self::SUMMARY_FREQUENCY / Waveform::Sine.frequency();
+        Pulse::new(&config, sample_rate, peak / 2.0)
+            .pitch(ratio * 2f32.powf(semitones as f32 / 12.0))
+    }
+
+    /// A sine chirp rising two octaves for [`Geiger::self_test`], which
+    /// doesn't take a voice so it's never dropped.
+    ///
+    /// [`Geiger::self_test`]: crate::Geiger::self_test
+    pub(crate) fn self_test(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Sine)
+            .duration(Self::SELF_TEST_DURATION);
+        let ratio = Self::SELF_TEST_FREQUENCY / Waveform::Sine.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(4.0)
+    }
+
+    /// A rising square blip for the allocation that springs a trap, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn trapped(sample_rate: u32, peak: f32) -> Self {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Self::TRAPPED_DURATION);
+        let ratio = Self::TRAPPED_FREQUENCY / Waveform::Square.frequency();
+        Pulse::new(&config, sample_rate, peak)
+            .pitch(ratio)
+            .bend(2.0)
+    }
+
+    /// A granular hiss for the events held back over a window, with grains
+    /// as dense as the square root of their rate, which doesn't take a voice
+    /// so it's never dropped.
+    ///
+    /// Each hiss lasts two windows, so the Hann windows of one every window
+    /// overlap to a steady level for a steady rate.
+    pub(crate) fn grains(sample_rate: u32, peak: f32, events: u64, window: Duration) -> Self {
+        let config = Config::new().waveform(Waveform::Noise).duration(2 * window);
+        let (fewest, most) = Self::GRAIN_RATES;
+        let rate = (events as f32 / window.as_secs_f32())
+            .sqrt()
+            .clamp(fewest, most);
+        let len = (Self::GRAIN_DURATION.as_secs_f32() * sample_rate as f32).max(1.0);
+        let mut pulse = Pulse::new(&config, sample_rate, peak / 2.0);
+        pulse.grains = Some((rate / sample_rate as f32, len as u32, 0));
+        pulse
+    }
+
+    /// A recording from a theme, like its alarm, at its own pitch, which
+    /// doesn't take a voice so it's never dropped.
+    pub(crate) fn recorded(sample_rate: u32, peak: f32, recording: Recording) -> Self {
+        Pulse::new(&Config::new(), sample_rate, peak).recording(recording)
+    }
+
+    /// A burst of band-limited noise for a background texture, which doesn't
+    /// take a voice so it's never dropped.
+    #[cfg(feature = "jemalloc")]
+    pub(crate) fn texture(sample_rate: u32, peak: f32, duration: Duration) -> Self {
+        let config = Config::new().waveform(Waveform::Noise).duration(duration);
+        Pulse::new(&config, sample_rate, peak)
+    }
+
+    /// Plays a recording instead of the waveform, for as long as it lasts.
+    ///
+    /// It plays at its own pitch, shifted by any later [`pitch`](Self::pitch)
+    /// and [`bend`](Self::bend) as the waveform would be.
+    pub(crate) fn recording(mut self, recording: Recording) -> Self {
+        let speed = recording.sample_rate as f32 / self.sample_rate as f32;
+        self.len = ((recording.len() as f32 / speed).ceil() as u32).max(1);
+        self.phase = 0.0;
+        self.recording = Some((recording, speed / self.step));
+        self
+    }
+
+    /// Holds a voice while playing, ending early if it's stolen.
+    pub(crate) fn voice(mut self, voice: Voice) -> Self {
+        self.voice = Some(voice);
+        self
+    }
+
+    /// Shifts the pitch by a frequency ratio.
+    pub fn pitch(mut self, ratio: f32) -> Self {
+        self.step *= ratio;
+        if self.recording.is_some() {
+            // a recording lasts as long as it takes to play at the new pitch
+            self.len = ((self.len as f32 / ratio).ceil() as u32).max(1);
+        }
+        self
+    }
+
+    /// Scales the amplitude by a gain.
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.peak *= gain;
+        self
+    }
+
+    /// Glides the pitch over the pulse, to reach a frequency ratio at the end.
+    pub fn bend(mut self, ratio: f32) -> Self {
+        self.bend = ratio;
+        self
+    }
+
+    /// Softens the attack to ramp over at least a quarter of the pulse, a
+    /// slightly duller timbre for zeroed allocations.
+    pub fn soften(mut self) -> Self {
+        self.attack = self.attack.max(self.len / 4);
+        self
+    }
+
+    /// Mutes and softens a click at an address that was just freed, so reuse
+    /// sounds quieter than growth.
+    pub(crate) fn recycled(self) -> Self {
+        self.soften().gain(Self::RECYCLED_GAIN)
+    }
+
+    /// Brightens the timbre for an alignment above 16 bytes, driving the
+    /// samples harder into saturation for each doubling, which keeps their
+    /// peak but adds harmonics.
+    pub(crate) fn brighten(mut self, align: usize) -> Self {
+        if align > Self::PLAIN_ALIGN {
+            let doublings = (align / Self::PLAIN_ALIGN).ilog2();
+            self.drive = doublings.min(Self::MAX_ALIGN_DOUBLINGS) as f32;
+        }
+        self
+    }
+
+    /// Repeats the pulse after itself, each echo quieter and a little sooner
+    /// than the last, so longer chains cascade faster.
+    pub(crate) fn echo(mut self, echoes: u32) -> Self {
+        if echoes > 0 {
+            let delay = Self::ECHO_DELAY.as_secs_f32() * self.sample_rate as f32;
+            self.echoes = echoes;
+            self.echo_delay = (delay / (1.0 + 0.15 * echoes as f32)) as u32;
+            self.dry = spare_buffer(self.len as usize);
+        }
+        self
+    }
+
+    /// The dry sample plus its echoes, at a position that may be past the
+    /// end of the dry pulse.
+    fn echoed(&mut self, n: u32) -> f32 {
+        let dry = if n < self.len {
+            let raw = self.sample(n);
+            let sample = self.saturate(raw) * self.envelope(n) * self.grain();
+            if self.echoes > 0 {
+                self.dry.push(sample);
+            }
+            sample
+        } else {
+            0.0
+        };
+        let mut gain = 1.0;
+        let mut wet = 0.0;
+        for k in 1..=self.echoes {
+            gain *= Self::ECHO_FEEDBACK;
+            let Some(i) = n.checked_sub(k * self.echo_delay) else {
+                break;
+            };
+            wet += self.dry.get(i as usize).map_or(0.0, |s| s * gain);
+        }
+        dry + wet
+    }
+
+    /// Returns 2 for a stereo pulse, with alternating left and right samples,
+    /// or else 1.
+    pub fn channels(&self) -> u16 {
+        if self.stereo.is_some() {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Returns the samples per second of each channel.
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// Plays in stereo, positioned from left `-1.0` to right `1.0`.
+    pub fn pan(mut self, pan: f32) -> Self {
+        // constant power across the stereo field
+        let angle = (pan.clamp(-1.0, 1.0) + 1.0) * PI / 4.0;
+        self.stereo = Some((angle.cos(), angle.sin()));
+        self
+    }
+
+    fn sample(&mut self, n: u32) -> f32 {
+        // progress through the pulse, from 0 to 1
+        let t = n as f32 / self.len as f32;
+        let step = self.step * self.bend.powf(t);
+        if let Some((recording, scale)) = &self.recording {
+            // the phase keeps growing, as the position in the recording
+            let position = self.phase * scale;
+            self.phase += step;
+            return recording.at(position);
+        }
+        let phase = self.phase;
+        self.phase = (phase + step) % (2.0 * PI);
+        match self.waveform {
+            Waveform::Sinc => match n as i32 - (self.len / 2) as i32 {
+                0 => 1.0,
+                i => {
+                    let x = i as f32 * step;
+                    x.sin() / x
+                }
+            },
+            Waveform::Sine => phase.sin() * hann(t),
+            Waveform::Square => {
+                let level = if phase < PI { 1.0 } else { -1.0 };
+                level * (1.0 - t)
+            }
+            Waveform::Noise => {
+                // the difference of two low-pass filters is a band-pass
+                let white = self.rng.next_f32();
+                self.fast += (white - self.fast) * 0.6;
+                self.slow += (white - self.slow) * 0.05;
+                (self.fast - self.slow) * 2.0 * hann(t)
+            }
+            Waveform::Authentic => {
+                // bright noise, with only the lowest rumble filtered out
+                let white = self.rng.next_f32();
+                self.slow += (white - self.slow) * 0.02;
+                let ring = phase.sin();
+                let level = self.level;
+                self.level *= self.decay;
+                (0.8 * (white - self.slow) + 0.4 * ring) * level
+            }
+        }
+    }
+
+    /// Drives a sample into a soft saturation, scaled so full scale stays
+    /// there.
+    fn saturate(&self, sample: f32) -> f32 {
+        if self.drive == 0.0 {
+            return sample;
+        }
+        (self.drive * sample).tanh() / self.drive.tanh()
+    }
+
+    /// The gain of the current grain, a triangle over its length, starting
+    /// new grains at random.  Without grains, this is always `1.0`.
+    fn grain(&mut self) -> f32 {
+        let Some((chance, len, left)) = &mut self.grains else {
+            return 1.0;
+        };
+        if *left == 0 {
+            let uniform = (self.rng.next_f32() + 1.0) / 2.0;
+            if uniform >= *chance {
+                return 0.0;
+            }
+            *left = *len;
+        }
+        *left -= 1;
+        1.0 - (2.0 * *left as f32 / *len as f32 - 1.0).abs()
+    }
+
+    fn envelope(&mut self, n: u32) -> f32 {
+        if n < self.attack {
+            n as f32 / self.attack as f32
+        } else {
+            let gain = self.gain;
+            self.gain *= self.fade;
+            gain
+        }
+    }
+}
+
+/// A raised-cosine window over `t` in `0..=1`.
+fn hann(t: f32) -> f32 {
+    0.5 - 0.5 * (2.0 * PI * t).cos()
+}
+
+/// Keeps the echo buffer for another pulse, without waiting for the lock, so
+/// this never blocks the audio thread.
+impl Drop for Pulse {
+    fn drop(&mut self) {
+        if self.dry.capacity() == 0 {
+            return;
+        }
+        if let Ok(mut spare) = SPARE.try_lock() {
+            if spare.len() < SPARE_BUFFERS {
+                spare.reserve_exact(SPARE_BUFFERS);
+                spare.push(mem::take(&mut self.dry));
+            }
+        }
+    }
+}
+
+/// Takes a spare buffer with room for `len` samples, only allocating if
+/// there's none big enough.
+fn spare_buffer(len: usize) -> Vec<f32> {
+    let spare = SPARE.try_lock().ok().and_then(|mut spare| spare.pop());
+    let mut buffer = spare.unwrap_or_default();
+    buffer.clear();
+    buffer.reserve(len);
+    buffer
+}
+
+impl Iterator for Pulse {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(right) = self.right.take() {
+            return Some(right);
+        }
+        if self.voice.as_ref().is_some_and(Voice::is_stolen) {
+            return None;
+        }
+        if self.index < self.len + self.echoes * self.echo_delay {
+            let n = self.index;
+            self.index += 1;
+            let sample = self.echoed(n) * self.peak;
+            match self.stereo {
+                Some((left, right)) => {
+                    self.right = Some(sample * right);
+                    Some(sample * left)
+                }
+                None => Some(sample),
+            }
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(feature = "sound-rodio")]
+impl Source for Pulse {
+    fn channels(&self) -> u16 {
+        Pulse::channels(self)
+    }
+
+    fn sample_rate(&self) -> u32 {
+        Pulse::sample_rate(self)
+    }
+
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// A tiny xorshift generator, seeded differently for each pulse.
+pub(crate) struct Rng(u32);
+
+impl Rng {
+    pub(crate) fn new() -> Self {
+        static SEED: AtomicU32 = AtomicU32::new(0x2545_f491);
+        // the golden ratio increment keeps the seeds well spread and non-zero
+        Rng(SEED.fetch_add(0x9e37_79b9, Ordering::Relaxed) | 1)
+    }
+
+    /// Returns a uniform value in `-1.0..1.0`.
+    pub(crate) fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        (self.0 as f32 / u32::MAX as f32) * 2.0 - 1.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    fn samples(pulse: Pulse) -> Vec<f32> {
+        pulse.collect()
+    }
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    /// Counts the rising zero crossings, once per period of a tone.
+    fn periods(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn plays_each_waveform_for_its_duration() {
+        for waveform in Waveform::ALL {
+            let config = Config::new().waveform(waveform);
+            let clicks = samples(Pulse::new(&config, RATE, 0.5));
+            let len = waveform.duration().as_secs_f64() * f64::from(RATE);
+            assert_eq!(clicks.len(), len as usize, "{waveform}");
+            let loudest = clicks.iter().fold(0.0f32, |max, s| max.max(s.abs()));
+            match waveform {
+                // the noise isn't normalized, but stays within a few times
+                // the peak
+                Waveform::Noise | Waveform::Authentic => {
+                    assert!(loudest > 0.05 && loudest < 1.5, "{waveform} {loudest}")
+                }
+                _ => assert!(loudest > 0.4 && loudest <= 0.5, "{waveform} {loudest}"),
+            }
+        }
+
+        let config = Config::new().duration(Duration::from_millis(10));
+        assert_eq!(samples(Pulse::new(&config, RATE, 1.0)).len(), 480);
+        // even the shortest pulse plays a sample
+        let config = Config::new().duration(Duration::ZERO);
+        assert_eq!(samples(Pulse::new(&config, RATE, 1.0)).len(), 1);
+    }
+
+    #[test]
+    fn ramps_up_for_the_attack_and_then_decays() {
+        let config = Config::new()
+            .waveform(Waveform::Square)
+            .duration(Duration::from_millis(10))
+            .envelope(Envelope {
+                attack: Duration::from_millis(1),
+                decay: Duration::from_millis(2),
+            });
+        let clicks = samples(Pulse::new(&config, RATE, 1.0));
+        // a square wave's level only fades linearly over the pulse
+        let envelope = |n: usize| clicks[n].abs() / (1.0 - n as f32 / 480.0);
+        assert_eq!(envelope(0), 0.0);
+        assert!(close(envelope(12), 0.25));
+        assert!(close(envelope(24), 0.5));
+        assert!(close(envelope(48), 1.0));
+        assert!(close(envelope(48 + 96), (-1.0f32).exp()));
+        assert!(close(envelope(48 + 192), (-2.0f32).exp()));
+    }
+
+    #[test]
+    fn pans_with_constant_power() {
+        let tone = || Pulse::tone(Waveform::Sine, RATE, 1000.0, 1.0, Duration::from_millis(5));
+        let mono = samples(tone());
+        for (pan, left, right) in [
+            (-1.0, 1.0, 0.0),
+            (0.0, 0.5f32.sqrt(), 0.5f32.sqrt()),
+            (1.0, 0.0, 1.0),
+            (2.0, 0.0, 1.0),
+        ] {
+            let pulse = tone().pan(pan);
+            assert_eq!(pulse.channels(), 2);
+            let stereo = samples(pulse);
+            assert_eq!(stereo.len(), 2 * mono.len());
+            for (frame, &sample) in stereo.chunks(2).zip(&mono) {
+                assert!(close(frame[0], sample * left), "{pan}");
+                assert!(close(frame[1], sample * right), "{pan}");
+            }
+        }
+    }
+
+    #[test]
+    fn bends_the_pitch_over_the_pulse() {
+        let tone = || {
+            Pulse::tone(
+                Waveform::Sine,
+                RATE,
+                1000.0,
+                1.0,
+                Duration::from_millis(100),
+            )
+        };
+        let steady = samples(tone());
+        let rising = samples(tone().bend(2.0));
+        let falling = samples(tone().bend(0.5));
+        assert_eq!(rising.len(), steady.len());
+        assert!(periods(&steady).abs_diff(100) <= 1);
+        // the first and last tenths are near the start and end pitches
+        let tenth = steady.len() / 10;
+        assert!(periods(&rising[..tenth]).abs_diff(10) <= 1);
+        assert!(periods(&rising[9 * tenth..]).abs_diff(19) <= 1);
+        assert!(periods(&falling[9 * tenth..]).abs_diff(5) <= 1);
+        // a glide up an octave averages 1/ln(2) of the start
+        assert!(periods(&rising).abs_diff(144) <= 2);
+    }
+
+    #[test]
+    fn echoes_after_the_pulse() {
+        let dry = samples(Pulse::new(&Config::new(), RATE, 1.0));
+        let wet = samples(Pulse::new(&Config::new(), RATE, 1.0).echo(2));
+        // 40 ms between echoes, a little sooner for longer chains
+        let delay = (0.04 * RATE as f32 / 1.3) as usize;
+        assert_eq!(wet.len(), dry.len() + 2 * delay);
+        assert_eq!(wet[..dry.len()], dry[..]);
+        let peak = dry.len() / 2;
+        assert!(close(wet[peak + delay], 0.6 * dry[peak]));
+        assert!(close(wet[peak + 2 * delay], 0.36 * dry[peak]));
+        assert_eq!(wet[dry.len() + delay / 2], 0.0);
+        assert_eq!(samples(Pulse::new(&Config::new(), RATE, 1.0).echo(0)), dry);
+    }
+
+    #[test]
+    fn softens_the_attack_over_a_quarter() {
+        let config = Config::new().waveform(Waveform::Square);
+        let sharp = samples(Pulse::new(&config, RATE, 1.0));
+        let soft = samples(Pulse::new(&config, RATE, 1.0).soften());
+        assert_eq!(soft.len(), 96);
+        assert!(close(sharp[1], 1.0 - 1.0 / 96.0));
+        assert_eq!(soft[0], 0.0);
+        assert!(close(soft[12], sharp[12] / 2.0));
+        assert_eq!(soft[24..], sharp[24..]);
+
+        // a longer attack is kept
+        let config = config.envelope(Envelope {
+            attack: Duration::from_millis(1),
+            decay: Duration::ZERO,
+        });
+        let soft = samples(Pulse::new(&config, RATE, 1.0).soften());
+        assert!(close(soft[24], sharp[24] / 2.0));
+    }
+}
@@ -0,0 +1,184 @@
+use crate::clock::now_millis;
+use crate::event::{AllocEvent, AllocOp};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The buckets between each power of two, as a power of two, which keeps the
+/// estimates within about 20% of the true size.
+const SPLIT_BITS: u32 = 2;
+const SPLIT: usize = 1 << SPLIT_BITS;
+
+/// Enough buckets for any size up to `isize::MAX`, the largest layout.
+const BUCKETS: usize = SPLIT * (usize::BITS - SPLIT_BITS) as usize;
+
+/// How often the counts are halved, so the estimates follow recent sizes.
+const HALF_LIFE_MILLIS: u64 = 10_000;
+
+/// Streaming estimates of the quantiles of the allocation sizes, counted in
+/// log-linear atomic buckets, for [`Geiger::size_quantiles`] and
+/// [`Config::quantile_tone`].
+///
+/// [`Geiger::size_quantiles`]: crate::Geiger::size_quantiles
+/// [`Config::quantile_tone`]: crate::Config::quantile_tone
+pub(crate) struct Quantiles {
+    buckets: [AtomicU64; BUCKETS],
+    /// when the counts are next halved
+    decay_at: AtomicU64,
+}
+
+impl Quantiles {
+    pub(crate) const fn new() -> Self {
+        #[allow(clippy::declare_interior_mutable_const)]
+        const ZERO: AtomicU64 = AtomicU64::new(0);
+        Quantiles {
+            buckets: [ZERO; BUCKETS],
+            decay_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts the size of an allocation, or the new size of a reallocation.
+    pub(crate) fn record(&self, event: &AllocEvent) {
+        if event.op == AllocOp::Dealloc || event.address == 0 {
+            return;
+        }
+        let now = now_millis();
+        let due = self.decay_at.load(Ordering::Relaxed);
+        if now >= due
+            && (self.decay_at)
+                .compare_exchange(
+                    due,
+                    now + HALF_LIFE_MILLIS,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_ok()
+        {
+            // Racing events may be counted before or after the halving, which
+            // only weighs them a little differently.
+            for bucket in &self.buckets {
+                let _ = bucket.fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| Some(n / 2));
+            }
+        }
+        self.buckets[bucket(event.new_size)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Estimates the median and 99th percentile of the recent sizes, or
+    /// `None` before any allocation.
+    pub(crate) fn get(&self) -> Option<SizeQuantiles> {
+        let counts: [u64; BUCKETS] =
+            std::array::from_fn(|i| self.buckets[i].load(Ordering::Relaxed));
+        let total: u64 = counts.iter().sum();
+        if total == 0 {
+            return None;
+        }
+        let quantile = |q: f64| {
+            let rank = ((q * total as f64).ceil() as u64).max(1);
+            let mut seen = 0;
+            for (index, &count) in counts.iter().enumerate() {
+                seen += count;
+                if seen >= rank {
+                    return middle(index);
+                }
+            }
+            middle(BUCKETS - 1)
+        };
+        Some(SizeQuantiles {
+            p50: quantile(0.5),
+            p99: quantile(0.99),
+        })
+    }
+}
+
+/// The bucket of a size: the exact size below `SPLIT`, and then `SPLIT`
+/// buckets for each power of two.
+fn bucket(size: usize) -> usize {
+    if size < SPLIT {
+        return size;
+    }
+    let log2 = size.ilog2();
+    let split = (size >> (log2 - SPLIT_BITS)) & (SPLIT - 1);
+    ((log2 - SPLIT_BITS + 1) as usize * SPLIT + split).min(BUCKETS - 1)
+}
+
+/// The size in the middle of a bucket.
+fn middle(index: usize) -> usize {
+    if index < SPLIT {
+        return index;
+    }
+    let shift = (index / SPLIT) as u32 - 1;
+    let lower = (SPLIT + index % SPLIT) << shift;
+    lower + (1 << shift) / 2
+}
+
+/// Estimates of the allocation sizes from
+/// [`Geiger::size_quantiles`](crate::Geiger::size_quantiles), weighted
+/// toward the last few seconds, and each within about 20% of the true size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct SizeQuantiles {
+    /// The median size, in bytes.
+    pub p50: usize,
+    /// The 99th percentile, the size that only 1% of allocations exceed.
+    pub p99: usize,
+}
+
+impl fmt::Display for SizeQuantiles {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "size_p50 {}", self.p50)?;
+        writeln!(f, "size_p99 {}", self.p99)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+
+    fn alloc(size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(AllocOp::Alloc, layout, 0x1000 as *mut u8)
+    }
+
+    #[test]
+    fn buckets_stay_close() {
+        let mut last = 0;
+        for size in (0..100_000).chain([usize::MAX / 3, isize::MAX as usize]) {
+            let index = bucket(size);
+            assert!(index >= last && index < BUCKETS, "{size}");
+            last = index;
+            let estimate = middle(index) as f64;
+            assert!(
+                (estimate - size as f64).abs() <= size as f64 * 0.2,
+                "{size}"
+            );
+        }
+    }
+
+    #[test]
+    fn estimates_quantiles() {
+        let quantiles = Quantiles::new();
+        assert_eq!(quantiles.get(), None);
+        let layout = Layout::from_size_align(1 << 20, 1).unwrap();
+        quantiles.record(&AllocEvent::new(
+            AllocOp::Dealloc,
+            layout,
+            0x1000 as *mut u8,
+        ));
+        quantiles.record(&AllocEvent::new(
+            AllocOp::Alloc,
+            layout,
+            std::ptr::null_mut(),
+        ));
+        assert_eq!(quantiles.get(), None);
+
+        for _ in 0..98 {
+            quantiles.record(&alloc(64));
+        }
+        quantiles.record(&alloc(4096));
+        quantiles.record(&alloc(4096));
+        let SizeQuantiles { p50, p99 } = quantiles.get().unwrap();
+        assert!((52..=76).contains(&p50), "{p50}");
+        assert!((3277..=4915).contains(&p99), "{p99}");
+    }
+}
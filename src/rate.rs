@@ -0,0 +1,168 @@
+use crate::clock::now_millis;
+use crate::event::{AllocEvent, AllocOp};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// The number of buckets that the sliding window is divided into.
+const BUCKETS: usize = 16;
+
+/// A sliding-window meter of allocation rates, in fixed-size atomic buckets.
+pub(crate) struct Meter {
+    buckets: [Bucket; BUCKETS],
+}
+
+struct Bucket {
+    /// the bucket's start time, in multiples of its width
+    epoch: AtomicU64,
+    allocs: AtomicU64,
+    bytes: AtomicU64,
+}
+
+impl Bucket {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Bucket = Bucket {
+        epoch: AtomicU64::new(0),
+        allocs: AtomicU64::new(0),
+        bytes: AtomicU64::new(0),
+    };
+}
+
+impl Meter {
+    pub(crate) const fn new() -> Self {
+        Meter {
+            buckets: [Bucket::EMPTY; BUCKETS],
+        }
+    }
+
+    /// Counts an allocation or reallocation in the current bucket.
+    pub(crate) fn record(&self, window: Duration, event: &AllocEvent) {
+        if event.op == AllocOp::Dealloc {
+            return;
+        }
+        let epoch = now_millis() / bucket_millis(window);
+        let bucket = &self.buckets[epoch as usize % BUCKETS];
+        let old = bucket.epoch.load(Ordering::Relaxed);
+        if old != epoch
+            && (bucket.epoch)
+                .compare_exchange(old, epoch, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            // Racing events may still land in the stale counts, which only
+            // blurs the edge of the window a little.
+            bucket.allocs.store(0, Ordering::Relaxed);
+            bucket.bytes.store(0, Ordering::Relaxed);
+        }
+        bucket.allocs.fetch_add(1, Ordering::Relaxed);
+        (bucket.bytes).fetch_add(event.new_size as u64, Ordering::Relaxed);
+    }
+
+    /// Averages the rates over the buckets still in the window.
+    pub(crate) fn rate(&self, window: Duration) -> AllocRate {
+        let width = bucket_millis(window);
+        let now = now_millis();
+        let epoch = now / width;
+        let (mut allocs, mut bytes) = (0, 0);
+        for bucket in &self.buckets {
+            let age = epoch.wrapping_sub(bucket.epoch.load(Ordering::Relaxed));
+            if age < BUCKETS as u64 {
+                allocs += bucket.allocs.load(Ordering::Relaxed);
+                bytes += bucket.bytes.load(Ordering::Relaxed);
+            }
+        }
+        // the full buckets behind the current partial one, but no further back
+        // than when we started keeping time
+        let millis = ((BUCKETS as u64 - 1) * width + now % width).min(now).max(1);
+        let secs = millis as f64 / 1000.0;
+        AllocRate {
+            allocs: allocs as f64 / secs,
+            bytes: bytes as f64 / secs,
+        }
+    }
+}
+
+fn bucket_millis(window: Duration) -> u64 {
+    (window.as_millis() as u64 / BUCKETS as u64).max(1)
+}
+
+/// Recent allocation rates from [`Geiger::rate`](crate::Geiger::rate),
+/// averaged over the [`Config::rate_window`](crate::Config::rate_window).
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct AllocRate {
+    /// `alloc`, `alloc_zeroed`, and `realloc` calls per second.
+    pub allocs: f64,
+    /// Bytes requested per second, including the new size of each `realloc`.
+    pub bytes: f64,
+}
+
+impl fmt::Display for AllocRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "allocs_per_sec {:.1}", self.allocs)?;
+        writeln!(f, "bytes_per_sec {:.1}", self.bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::alloc::Layout;
+    use std::thread;
+
+    fn event(op: AllocOp, size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(op, layout, 0x1000 as *mut u8)
+    }
+
+    #[test]
+    fn counts_allocations_in_the_window() {
+        let meter = Meter::new();
+        let window = Duration::from_secs(16);
+        for _ in 0..10 {
+            meter.record(window, &event(AllocOp::Alloc, 100));
+            meter.record(window, &event(AllocOp::Dealloc, 100));
+        }
+        let layout = Layout::from_size_align(100, 1).unwrap();
+        let realloc = AllocEvent::realloc(layout, 300, 0x1000 as *mut u8, 0x2000 as *mut u8);
+        meter.record(window, &realloc);
+        let rate = meter.rate(window);
+        assert!(rate.allocs > 0.0);
+        // both over the same time, with 1300 bytes in 11 calls
+        let mean = rate.bytes / rate.allocs;
+        assert!((mean - 1300.0 / 11.0).abs() < 1e-9, "{mean}");
+    }
+
+    #[test]
+    fn forgets_events_past_the_window() {
+        let meter = Meter::new();
+        let window = Duration::from_millis(16);
+        meter.record(window, &event(AllocOp::AllocZeroed, 8));
+        assert!(meter.rate(window).allocs > 0.0);
+        thread::sleep(window * 2);
+        assert_eq!(meter.rate(window), AllocRate::default());
+
+        let meter = Meter::new();
+        meter.record(window, &event(AllocOp::Dealloc, 8));
+        assert_eq!(meter.rate(window), AllocRate::default());
+    }
+
+    #[test]
+    fn divides_the_window_into_buckets() {
+        assert_eq!(bucket_millis(Duration::from_millis(1600)), 100);
+        assert_eq!(bucket_millis(Duration::from_millis(10)), 1);
+        assert_eq!(bucket_millis(Duration::ZERO), 1);
+    }
+
+    #[test]
+    fn writes_rates() {
+        let rate = AllocRate {
+            allocs: 12.25,
+            bytes: 4096.0,
+        };
+        assert_eq!(
+            rate.to_string(),
+            "allocs_per_sec 12.2\nbytes_per_sec 4096.0\n"
+        );
+    }
+}
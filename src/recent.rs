@@ -0,0 +1,134 @@
+use crate::clock::now;
+use crate::event::{AllocEvent, AllocOp};
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The number of recent events kept.
+const CAPACITY: usize = 64;
+
+/// A fixed ring of the most recent events, overwritten without locking.
+pub(crate) struct Ring {
+    next: AtomicU64,
+    slots: [Slot; CAPACITY],
+}
+
+/// One event in the ring, guarded by its sequence number like a seqlock.
+struct Slot {
+    /// one more than the event's position in the sequence, or `0` while it's
+    /// being written
+    seq: AtomicU64,
+    op: AtomicU8,
+    size: AtomicUsize,
+    address: AtomicUsize,
+    nanos: AtomicU64,
+    thread: AtomicU32,
+}
+
+impl Slot {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Slot = Slot {
+        seq: AtomicU64::new(0),
+        op: AtomicU8::new(0),
+        size: AtomicUsize::new(0),
+        address: AtomicUsize::new(0),
+        nanos: AtomicU64::new(0),
+        thread: AtomicU32::new(0),
+    };
+}
+
+impl Ring {
+    pub(crate) const fn new() -> Self {
+        Ring {
+            next: AtomicU64::new(0),
+            slots: [Slot::EMPTY; CAPACITY],
+        }
+    }
+
+    pub(crate) fn record(&self, event: &AllocEvent) {
+        let seq = self.next.fetch_add(1, Ordering::Relaxed);
+        let slot = &self.slots[seq as usize % CAPACITY];
+        let event = RecentEvent::new(event);
+        slot.seq.store(0, Ordering::Relaxed);
+        let op = match event.op {
+            AllocOp::Alloc => 0,
+            AllocOp::AllocZeroed => 1,
+            AllocOp::Dealloc => 2,
+            AllocOp::Realloc => 3,
+        };
+        slot.op.store(op, Ordering::Relaxed);
+        slot.size.store(event.size, Ordering::Relaxed);
+        slot.address.store(event.address, Ordering::Relaxed);
+        slot.nanos
+            .store(event.time.as_nanos() as u64, Ordering::Relaxed);
+        slot.thread.store(event.thread, Ordering::Relaxed);
+        slot.seq.store(seq + 1, Ordering::Release);
+    }
+
+    /// Forgets the events in the ring, though any being recorded right now
+    /// may still appear.
+    pub(crate) fn clear(&self) {
+        for slot in &self.slots {
+            slot.seq.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Returns the events still in the ring, oldest first, skipping any that
+    /// are being overwritten.
+    pub(crate) fn snapshot(&self) -> Vec<RecentEvent> {
+        let mut events: Vec<(u64, RecentEvent)> = (self.slots.iter())
+            .filter_map(|slot| {
+                let seq = slot.seq.load(Ordering::Acquire);
+                let op = match slot.op.load(Ordering::Relaxed) {
+                    0 => AllocOp::Alloc,
+                    1 => AllocOp::AllocZeroed,
+                    2 => AllocOp::Dealloc,
+                    _ => AllocOp::Realloc,
+                };
+                let event = RecentEvent {
+                    op,
+                    size: slot.size.load(Ordering::Relaxed),
+                    address: slot.address.load(Ordering::Relaxed),
+                    time: Duration::from_nanos(slot.nanos.load(Ordering::Relaxed)),
+                    thread: slot.thread.load(Ordering::Relaxed),
+                };
+                let unchanged = slot.seq.load(Ordering::Acquire) == seq;
+                (seq != 0 && unchanged).then_some((seq, event))
+            })
+            .collect();
+        events.sort_unstable_by_key(|&(seq, _)| seq);
+        events.into_iter().map(|(_, event)| event).collect()
+    }
+}
+
+/// One of the last allocator events, from
+/// [`Geiger::recent_events`](crate::Geiger::recent_events) or an
+/// [`EventReceiver`](crate::EventReceiver).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct RecentEvent {
+    /// The allocator method that was called.
+    pub op: AllocOp,
+    /// The size requested, which for `Realloc` is the new size.
+    pub size: usize,
+    /// The address allocated or freed, or `0` if the allocation failed.
+    pub address: usize,
+    /// The time of the event since the crate's first, roughly, which
+    /// [`clock_calibration`](crate::clock_calibration) places on the
+    /// system's other clocks.
+    pub time: Duration,
+    /// A small index of the thread, in order of their first events.
+    pub thread: u32,
+}
+
+impl RecentEvent {
+    /// Records an event as it happens, on its own thread.
+    pub(crate) fn new(event: &AllocEvent) -> Self {
+        RecentEvent {
+            op: event.op,
+            size: event.new_size,
+            address: event.address,
+            time: now(),
+            thread: event.thread(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use crate::clock::now_millis;
+use crate::event::{AllocEvent, AllocOp};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The number of recently freed addresses remembered, each in the slot its
+/// hash picks, so a newer one may push out an older one.
+const SLOTS: usize = 1024;
+
+/// How recently an address must have been freed for its reuse to count.
+const WINDOW_MILLIS: u64 = 100;
+
+/// The recently freed addresses, for
+/// [`Config::recycle_cue`](crate::Config::recycle_cue), telling an allocator
+/// that reuses memory from one that keeps growing.
+pub(crate) struct Recycler {
+    slots: [Slot; SLOTS],
+}
+
+struct Slot {
+    address: AtomicUsize,
+    freed: AtomicU64,
+}
+
+impl Slot {
+    #[allow(clippy::declare_interior_mutable_const)]
+    const EMPTY: Slot = Slot {
+        address: AtomicUsize::new(0),
+        freed: AtomicU64::new(0),
+    };
+}
+
+impl Recycler {
+    pub(crate) const fn new() -> Self {
+        Recycler {
+            slots: [Slot::EMPTY; SLOTS],
+        }
+    }
+
+    /// Remembers the address of a deallocation, or returns `true` for an
+    /// allocation at an address that was freed very recently.
+    ///
+    /// Racing events may mix up a slot's address and time, which only
+    /// mistakes the odd event either way.
+    pub(crate) fn recycled(&self, event: &AllocEvent) -> bool {
+        if event.address == 0 {
+            return false;
+        }
+        let slot = &self.slots[slot(event.address)];
+        match event.op {
+            AllocOp::Dealloc => {
+                slot.address.store(event.address, Ordering::Relaxed);
+                slot.freed.store(now_millis(), Ordering::Relaxed);
+                false
+            }
+            AllocOp::Alloc | AllocOp::AllocZeroed => {
+                slot.address.load(Ordering::Relaxed) == event.address
+                    && now_millis().saturating_sub(slot.freed.load(Ordering::Relaxed))
+                        < WINDOW_MILLIS
+            }
+            AllocOp::Realloc => false,
+        }
+    }
+}
+
+/// The slot of an address, mixing in the bits above the usual alignment.
+fn slot(address: usize) -> usize {
+    let hash = (address as u64 >> 4).wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    (hash >> (64 - SLOTS.ilog2())) as usize
+}
@@ -0,0 +1,318 @@
+use crate::event::AllocEvent;
+use crate::pitch::Scale;
+use crate::stats::Stats;
+use crate::{quietly, Geiger, GeigerStats};
+use std::cell::Cell;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, PoisonError, RwLock};
+use std::task::{Context, Poll};
+
+/// Every region that has been entered, in order, leaked so that threads can
+/// count into them without a lock.
+static REGIONS: Mutex<Vec<&'static RegionStats>> = Mutex::new(Vec::new());
+
+thread_local! {
+    /// The innermost region on this thread.
+    static CURRENT: Cell<Option<&'static RegionStats>> = const { Cell::new(None) };
+}
+
+/// The running counters of one named region.
+pub(crate) struct RegionStats {
+    name: &'static str,
+    stats: Stats,
+}
+
+impl RegionStats {
+    /// Finds or adds the region with this name.
+    fn get(name: &'static str) -> &'static RegionStats {
+        quietly(|| {
+            let mut regions = REGIONS.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(region) = regions.iter().find(|region| region.name == name) {
+                return *region;
+            }
+            let region = RegionStats::leak(name);
+            regions.push(region);
+            region
+        })
+    }
+
+    /// Adds a region that isn't listed by [`region_stats`].
+    fn leak(name: &'static str) -> &'static RegionStats {
+        quietly(|| {
+            Box::leak(Box::new(RegionStats {
+                name,
+                stats: Stats::new(),
+            }))
+        })
+    }
+
+    /// Returns `true` if there are no names to match, or this is the region
+    /// of one of them.
+    pub(crate) fn matches(region: Option<&RegionStats>, names: &[String]) -> bool {
+        names.is_empty() || region.is_some_and(|region| names.iter().any(|n| n == region.name))
+    }
+
+    /// The frequency ratio of the region's pulses, a shift of up to an octave
+    /// either way that's derived from its name.
+    pub(crate) fn ratio(&self, scale: Scale) -> f32 {
+        // FNV-1a
+        let hash = (self.name.bytes()).fold(0x811c_9dc5_u32, |hash, byte| {
+            (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+        });
+        let semitones = scale.quantize((hash % 25) as f32 - 12.0);
+        (semitones / 12.0).exp2()
+    }
+}
+
+/// Names the allocator activity on the current thread until the returned
+/// guard is dropped.
+///
+/// Pulses within a region play at a pitch derived from its name, so each
+/// phase of a program sounds a little different, and each region's activity
+/// is counted for [`region_stats`].  Regions may be nested, in which case the
+/// innermost one applies.
+///
+/// This only sees allocations through a [`Geiger`](crate::Geiger) that is the
+/// global allocator.
+///
+/// ```
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     {
+///         let _parse = alloc_geiger::region("parse");
+///         let _words: Vec<String> = "a b c".split(' ').map(String::from).collect();
+///     }
+///     for (name, stats) in alloc_geiger::region_stats() {
+///         println!("{name}: {} allocs", stats.allocs);
+///     }
+/// }
+/// ```
+pub fn region(name: &'static str) -> Region {
+    Region::enter(RegionStats::get(name))
+}
+
+/// Names the allocator activity of a future, entering the region each time
+/// it's polled and leaving it before returning, as [`region`] does for a
+/// thread.
+///
+/// A [`Region`] guard held across an `.await` would name whatever else runs
+/// on the thread while the task is suspended, and can't follow the task to
+/// another thread of a multi-threaded runtime, so use this for async code
+/// instead.  Regions entered this way nest like any others.
+///
+/// ```
+/// # use std::future::Future;
+/// # use std::sync::Arc;
+/// # use std::task::{Context, Wake, Waker};
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// async fn handle(words: &str) -> Vec<String> {
+///     words.split(' ').map(String::from).collect()
+/// }
+///
+/// # struct Noop;
+/// # impl Wake for Noop {
+/// #     fn wake(self: Arc<Self>) {}
+/// # }
+/// fn main() {
+///     let request = alloc_geiger::instrument_region("request", handle("a b c"));
+///     // ... spawn `request` on any runtime, or poll it directly:
+///     let mut request = Box::pin(request);
+/// #   let waker = Waker::from(Arc::new(Noop));
+/// #   let mut cx = Context::from_waker(&waker);
+///     assert!(request.as_mut().poll(&mut cx).is_ready());
+///
+///     let stats = alloc_geiger::region_stats();
+///     assert!(stats.iter().any(|(name, stats)| *name == "request" && stats.allocs > 0));
+/// }
+/// ```
+pub fn instrument_region<F: Future>(name: &'static str, future: F) -> InRegion<F> {
+    InRegion {
+        region: RegionStats::get(name),
+        future,
+    }
+}
+
+/// Returns a snapshot of the activity counted in each region so far, in the
+/// order they were first entered.
+pub fn region_stats() -> Vec<(&'static str, GeigerStats)> {
+    quietly(|| {
+        let regions = REGIONS.lock().unwrap_or_else(PoisonError::into_inner);
+        (regions.iter())
+            .map(|region| (region.name, region.stats.snapshot()))
+            .collect()
+    })
+}
+
+/// A named region of a thread's activity, from [`region`].
+#[must_use = "the region only lasts until the guard is dropped"]
+pub struct Region {
+    outer: Option<&'static RegionStats>,
+    /// `CURRENT` is thread-local, so the guard must stay on its thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Region {
+    fn enter(region: &'static RegionStats) -> Self {
+        Region {
+            outer: CURRENT.with(|cell| cell.replace(Some(region))),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Enters the region with this name, as for a `tracing` span.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn named(name: &'static str) -> Self {
+        Region::enter(RegionStats::get(name))
+    }
+
+    /// Takes over the region an `outer` guard would restore, as when it's
+    /// left before this one, so this restores that instead.
+    #[cfg(feature = "tracing")]
+    pub(crate) fn replace_outer(&mut self, outer: Region) {
+        self.outer = outer.outer;
+        std::mem::forget(outer);
+    }
+}
+
+impl Drop for Region {
+    fn drop(&mut self) {
+        CURRENT.with(|cell| cell.set(self.outer));
+    }
+}
+
+/// A future in a named region, from [`instrument_region`].
+#[must_use = "futures do nothing unless polled"]
+pub struct InRegion<F> {
+    region: &'static RegionStats,
+    future: F,
+}
+
+impl<F: Future> Future for InRegion<F> {
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<F::Output> {
+        let region = self.region;
+        // SAFETY: the future is pinned along with `self`, and never moved.
+        let future = unsafe { self.map_unchecked_mut(|this| &mut this.future) };
+        // The guard leaves the region when polling returns or unwinds.
+        let _region = Region::enter(region);
+        future.poll(cx)
+    }
+}
+
+/// The tagged address ranges of a `Geiger`, from [`Geiger::tag_region`].
+pub(crate) struct Tags {
+    any: AtomicBool,
+    tags: RwLock<Vec<(Range<usize>, &'static RegionStats)>>,
+}
+
+impl Tags {
+    pub(crate) const fn new() -> Self {
+        Tags {
+            any: AtomicBool::new(false),
+            tags: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Counts an event in the tag of the range holding its address, if any,
+    /// and returns it.
+    pub(crate) fn count(&self, event: &AllocEvent) -> Option<&'static RegionStats> {
+        if !self.any.load(Ordering::Relaxed) || event.address == 0 {
+            return None;
+        }
+        let tags = self.tags.read().unwrap_or_else(PoisonError::into_inner);
+        let (_, tag) = tags
+            .iter()
+            .find(|(range, _)| range.contains(&event.address))?;
+        tag.stats.count(event);
+        Some(tag)
+    }
+}
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Tags a range of addresses with a name, so events whose pointers fall
+    /// within it play at a pitch derived from the name, instead of their
+    /// [`region`]'s, and are counted for [`tag_stats`](Self::tag_stats).
+    ///
+    /// This tells an arena's own allocations, or those of a custom allocator
+    /// carving up a big block, apart from the general heap noise.  Ranges
+    /// may share a name to count together, and the first one tagged wins
+    /// where they overlap.  Only events through this `Geiger` are seen, so
+    /// the range must be handed out by its inner allocator.
+    ///
+    /// ```
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let arena = vec![0u8; 4096];
+    ///     let start = arena.as_ptr() as usize;
+    ///     ALLOC.tag_region(start..start + arena.len(), "arena-A");
+    ///     drop(arena);
+    ///     let stats = ALLOC.tag_stats();
+    ///     assert_eq!(stats[0].0, "arena-A");
+    ///     assert_eq!(stats[0].1.deallocs, 1);
+    /// }
+    /// ```
+    pub fn tag_region(&self, range: Range<usize>, name: &'static str) {
+        quietly(|| {
+            let mut tags = self
+                .tags
+                .tags
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+            let tag = match tags.iter().find(|(_, tag)| tag.name == name) {
+                Some(&(_, tag)) => tag,
+                None => RegionStats::leak(name),
+            };
+            tags.push((range, tag));
+            self.tags.any.store(true, Ordering::Relaxed);
+        })
+    }
+
+    /// Removes every range tagged with this name, along with its counters,
+    /// as when its arena is freed.
+    pub fn untag_region(&self, name: &str) {
+        let mut tags = self
+            .tags
+            .tags
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        tags.retain(|(_, tag)| tag.name != name);
+        self.tags.any.store(!tags.is_empty(), Ordering::Relaxed);
+    }
+
+    /// Returns a snapshot of the activity counted in each tag of
+    /// [`tag_region`](Self::tag_region), in the order they were tagged.
+    pub fn tag_stats(&self) -> Vec<(&'static str, GeigerStats)> {
+        quietly(|| {
+            let tags = self
+                .tags
+                .tags
+                .read()
+                .unwrap_or_else(PoisonError::into_inner);
+            let mut stats: Vec<(&'static str, GeigerStats)> = Vec::new();
+            for (_, tag) in tags.iter() {
+                if !stats.iter().any(|(name, _)| *name == tag.name) {
+                    stats.push((tag.name, tag.stats.snapshot()));
+                }
+            }
+            stats
+        })
+    }
+}
+
+/// Counts an event in the current region, if any, and returns it.
+pub(crate) fn count(event: &AllocEvent) -> Option<&'static RegionStats> {
+    let region = CURRENT.with(Cell::get)?;
+    region.stats.count(event);
+    Some(region)
+}
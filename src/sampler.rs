@@ -0,0 +1,63 @@
+use crate::clock::now;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// A ratio or credit of a whole event.
+const ONE: u64 = 1 << 32;
+
+/// How often the rate of candidates is measured again, unless there are
+/// already more than a second's worth of them.
+const INTERVAL: Duration = Duration::from_millis(250);
+
+/// Samples the events that would click down to a target rate, for
+/// [`Config::target_rate`](crate::Config::target_rate), adjusting the ratio
+/// as the rate of candidates changes.
+pub(crate) struct Sampler {
+    /// the start of the current interval, in microseconds
+    start: AtomicU64,
+    /// the candidates seen in the current interval
+    seen: AtomicU64,
+    /// the fraction of the candidates to play, in units of `1 / ONE`
+    ratio: AtomicU64,
+    /// the running sum of the ratio, playing each time it passes a whole
+    credit: AtomicU64,
+}
+
+impl Sampler {
+    pub(crate) const fn new() -> Self {
+        Sampler {
+            start: AtomicU64::new(0),
+            seen: AtomicU64::new(0),
+            ratio: AtomicU64::new(ONE),
+            credit: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts a candidate, returning `true` if it should play to keep to
+    /// about `per_second` of them.
+    pub(crate) fn sample(&self, per_second: u32) -> bool {
+        let seen = self.seen.fetch_add(1, Ordering::Relaxed) + 1;
+        let now = now().as_micros() as u64;
+        let start = self.start.load(Ordering::Relaxed);
+        let elapsed = now.saturating_sub(start).max(1);
+        if (elapsed >= INTERVAL.as_micros() as u64 || seen > u64::from(per_second))
+            && (self.start)
+                .compare_exchange(start, now, Ordering::Relaxed, Ordering::Relaxed)
+                .is_ok()
+        {
+            // Racing candidates may be lost from the count, which only
+            // underestimates the rate a little.
+            self.seen.store(0, Ordering::Relaxed);
+            let rate = seen as f64 * 1e6 / elapsed as f64;
+            let wanted = (f64::from(per_second) / rate).min(1.0);
+            // down at once in a burst, but only halfway back up each
+            // interval, so a lull doesn't let the next burst through
+            let old = self.ratio.load(Ordering::Relaxed) as f64 / ONE as f64;
+            let ratio = wanted.min((old + wanted) / 2.0);
+            (self.ratio).store((ratio * ONE as f64) as u64, Ordering::Relaxed);
+        }
+        let ratio = self.ratio.load(Ordering::Relaxed).max(1);
+        let credit = self.credit.fetch_add(ratio, Ordering::Relaxed);
+        credit.wrapping_add(ratio) / ONE != credit / ONE
+    }
+}
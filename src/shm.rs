@@ -0,0 +1,235 @@
+//! A shared-memory event export, enabled by the `shm` feature on Unix.
+
+use crate::{AllocOp, Geiger, RecentEvent, BUSY};
+use std::fs::OpenOptions;
+use std::io;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::ptr;
+use std::sync::atomic::{fence, AtomicU32, AtomicU64, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// The first 8 bytes of the segment.
+const MAGIC: [u8; 8] = *b"ALLOCGGR";
+/// The version of the layout.
+const VERSION: u32 = 1;
+/// The number of event slots in the segment.
+const CAPACITY: usize = 4096;
+/// The size of the header, before the first slot.
+const HEADER: usize = 64;
+/// The size of each slot.
+const SLOT: usize = 40;
+/// How long the exporter sleeps when there are no new events.
+const IDLE: Duration = Duration::from_millis(1);
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread publishing each allocator event into a ring buffer in
+    /// a shared-memory file, for external tools to visualize a running
+    /// process.
+    ///
+    /// The file at `path`, like `/dev/shm/alloc_geiger.1234`, is created or
+    /// truncated and mapped into memory.  All values are in native byte
+    /// order, and naturally aligned.  The 64-byte header has:
+    ///
+    /// | offset | type     | contents                                      |
+    /// |--------|----------|-----------------------------------------------|
+    /// | 0      | `[u8;8]` | the magic bytes `ALLOCGGR`                    |
+    /// | 8      | `u32`    | the layout version, `1`                       |
+    /// | 12     | `u32`    | the number of slots, `4096`                   |
+    /// | 16     | `u64`    | the number of events written so far           |
+    /// | 24     | `u64`    | the number of events dropped by the exporter  |
+    /// | 32     | `u32`    | the process ID                                |
+    ///
+    /// Event `n` is written to the 40-byte slot `n % 4096` after the header:
+    ///
+    /// | offset | type  | contents                                                          |
+    /// |--------|-------|-------------------------------------------------------------------|
+    /// | 0      | `u64` | `n + 1`, or `0` while the slot is being written                   |
+    /// | 8      | `u64` | the time of the event in nanoseconds, roughly                     |
+    /// | 16     | `u64` | the size, which for `realloc` is the new size                     |
+    /// | 24     | `u64` | the address, or `0` if the allocation failed                      |
+    /// | 32     | `u32` | the method: 0 `alloc`, 1 `alloc_zeroed`, 2 `dealloc`, 3 `realloc` |
+    /// | 36     | `u32` | a small index of the thread                                       |
+    ///
+    /// Readers should check that a slot's sequence number is the same before
+    /// and after reading the rest, like a seqlock.  The exporter's own
+    /// allocations are not counted.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     let path = format!("/dev/shm/alloc_geiger.{}", std::process::id());
+    ///     ALLOC.spawn_shm_export(path).unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_shm_export<P: AsRef<Path>>(&'static self, path: P) -> io::Result<JoinHandle<()>> {
+        let mut segment = Segment::create(path.as_ref())?;
+        let mut events = self.subscribe();
+        thread::Builder::new()
+            .name("alloc_geiger-shm".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                loop {
+                    let mut idle = true;
+                    for event in events.try_iter() {
+                        segment.write(&event);
+                        idle = false;
+                    }
+                    segment.set_dropped(events.dropped());
+                    if idle {
+                        thread::sleep(IDLE);
+                    }
+                }
+            })
+    }
+}
+
+/// The mapped file, which is never unmapped while its thread runs.
+struct Segment {
+    base: *mut u8,
+    next: u64,
+}
+
+// SAFETY: the mapping is only written by the thread that owns the `Segment`.
+unsafe impl Send for Segment {}
+
+impl Segment {
+    fn create(path: &Path) -> io::Result<Self> {
+        let len = HEADER + CAPACITY * SLOT;
+        let file = (OpenOptions::new())
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)?;
+        file.set_len(len as u64)?;
+        // SAFETY: mapping a fresh file of `len` bytes, which stays mapped
+        // after the file is closed.
+        let base = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                file.as_raw_fd(),
+                0,
+            )
+        };
+        if base == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        let segment = Segment {
+            base: base.cast(),
+            next: 0,
+        };
+        // SAFETY: the header is within the mapping.
+        unsafe { ptr::copy_nonoverlapping(MAGIC.as_ptr(), segment.base, MAGIC.len()) };
+        segment.u32_at(8).store(VERSION, Ordering::Relaxed);
+        segment.u32_at(12).store(CAPACITY as u32, Ordering::Relaxed);
+        segment
+            .u32_at(32)
+            .store(std::process::id(), Ordering::Relaxed);
+        Ok(segment)
+    }
+
+    fn u32_at(&self, offset: usize) -> &AtomicU32 {
+        // SAFETY: every offset used is aligned and within the mapping.
+        unsafe { &*self.base.add(offset).cast::<AtomicU32>() }
+    }
+
+    fn u64_at(&self, offset: usize) -> &AtomicU64 {
+        // SAFETY: every offset used is aligned and within the mapping.
+        unsafe { &*self.base.add(offset).cast::<AtomicU64>() }
+    }
+
+    fn write(&mut self, event: &RecentEvent) {
+        let slot = HEADER + (self.next as usize % CAPACITY) * SLOT;
+        let op = match event.op {
+            AllocOp::Alloc => 0,
+            AllocOp::AllocZeroed => 1,
+            AllocOp::Dealloc => 2,
+            AllocOp::Realloc => 3,
+        };
+        self.u64_at(slot).store(0, Ordering::Relaxed);
+        fence(Ordering::Release);
+        let nanos = event.time.as_nanos() as u64;
+        self.u64_at(slot + 8).store(nanos, Ordering::Relaxed);
+        self.u64_at(slot + 16)
+            .store(event.size as u64, Ordering::Relaxed);
+        self.u64_at(slot + 24)
+            .store(event.address as u64, Ordering::Relaxed);
+        self.u32_at(slot + 32).store(op, Ordering::Relaxed);
+        self.u32_at(slot + 36)
+            .store(event.thread, Ordering::Relaxed);
+        self.next += 1;
+        self.u64_at(slot).store(self.next, Ordering::Release);
+        self.u64_at(16).store(self.next, Ordering::Release);
+    }
+
+    fn set_dropped(&self, dropped: u64) {
+        self.u64_at(24).store(dropped, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_ne_bytes(bytes[offset..][..4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_ne_bytes(bytes[offset..][..8].try_into().unwrap())
+    }
+
+    #[test]
+    fn writes_the_documented_layout() {
+        let path = std::env::temp_dir().join(format!("alloc_geiger-shm.{}", std::process::id()));
+        let mut segment = Segment::create(&path).unwrap();
+        let event = |op, size| RecentEvent {
+            op,
+            size,
+            address: 0x1000 + size,
+            time: Duration::from_micros(size as u64),
+            thread: 3,
+        };
+        segment.write(&event(AllocOp::Realloc, 24));
+        segment.set_dropped(5);
+
+        // the file sees the mapping's writes
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(bytes.len(), HEADER + CAPACITY * SLOT);
+        assert_eq!(bytes[..8], MAGIC);
+        assert_eq!(u32_at(&bytes, 8), VERSION);
+        assert_eq!(u32_at(&bytes, 12), CAPACITY as u32);
+        assert_eq!(u64_at(&bytes, 16), 1);
+        assert_eq!(u64_at(&bytes, 24), 5);
+        assert_eq!(u32_at(&bytes, 32), std::process::id());
+        let slot = HEADER;
+        assert_eq!(u64_at(&bytes, slot), 1);
+        assert_eq!(u64_at(&bytes, slot + 8), 24_000);
+        assert_eq!(u64_at(&bytes, slot + 16), 24);
+        assert_eq!(u64_at(&bytes, slot + 24), 0x1018);
+        assert_eq!(u32_at(&bytes, slot + 32), 3);
+        assert_eq!(u32_at(&bytes, slot + 36), 3);
+
+        // events wrap around the ring
+        for size in 1..=CAPACITY {
+            segment.write(&event(AllocOp::Dealloc, size));
+        }
+        let bytes = fs::read(&path).unwrap();
+        assert_eq!(u64_at(&bytes, 16), CAPACITY as u64 + 1);
+        assert_eq!(u64_at(&bytes, HEADER), CAPACITY as u64 + 1);
+        assert_eq!(u64_at(&bytes, HEADER + 16), CAPACITY as u64);
+        assert_eq!(u32_at(&bytes, HEADER + 32), 2);
+        let last = HEADER + (CAPACITY - 1) * SLOT;
+        assert_eq!(u64_at(&bytes, last), CAPACITY as u64);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+//! Frames of the events played, sent to a Unix datagram socket for the
+//! [`Backend::Socket`](crate::Backend::Socket) output.
+#![cfg_attr(not(unix), allow(dead_code))]
+
+use crate::clock::{now, now_millis};
+use crate::config::Config;
+use crate::event::{AllocEvent, AllocOp};
+#[cfg(unix)]
+use std::io;
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// The size of each frame.
+const FRAME: usize = 64;
+
+/// The version of the frame format, its first byte.
+const VERSION: u8 = 1;
+
+/// Flags in the third byte of a frame.
+const HAS_TAG: u8 = 1;
+
+/// How long to wait before connecting again when the reader went away.
+const RETRY_MILLIS: u64 = 1000;
+
+/// A Unix datagram socket receiving a frame for each event played.
+pub(crate) struct SocketOutput {
+    #[cfg(unix)]
+    datagram: UnixDatagram,
+    #[cfg(unix)]
+    path: String,
+    /// when to connect again after the reader went away, or `0`
+    retry_at: AtomicU64,
+}
+
+impl SocketOutput {
+    /// Connects to the configured socket, which must already be bound by a
+    /// reader.
+    pub(crate) fn open(config: &Config) -> Result<Self, String> {
+        let path = config
+            .socket
+            .as_deref()
+            .ok_or("no socket path is configured")?;
+        #[cfg(unix)]
+        return {
+            let connect = || {
+                let datagram = UnixDatagram::unbound()?;
+                datagram.connect(path)?;
+                datagram.set_nonblocking(true)?;
+                Ok::<_, io::Error>(datagram)
+            };
+            Ok(SocketOutput {
+                datagram: connect().map_err(|e| format!("{path}: {e}"))?,
+                path: path.to_owned(),
+                retry_at: AtomicU64::new(0),
+            })
+        };
+        #[cfg(not(unix))]
+        Err(format!(
+            "{path}: Unix sockets aren't available on this platform"
+        ))
+    }
+
+    /// Sends the frame of an event, dropping it if the reader is behind or
+    /// gone, and reconnecting now and then in case it's back.
+    #[cfg(unix)]
+    pub(crate) fn send(&self, event: &AllocEvent) {
+        let retry_at = self.retry_at.load(Ordering::Relaxed);
+        if retry_at != 0 {
+            if now_millis() < retry_at || self.datagram.connect(&self.path).is_err() {
+                self.retry_later(retry_at);
+                return;
+            }
+            self.retry_at.store(0, Ordering::Relaxed);
+        }
+        if let Err(e) = self.datagram.send(&encode(event)) {
+            if e.kind() != io::ErrorKind::WouldBlock {
+                self.retry_later(0);
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    pub(crate) fn send(&self, _event: &AllocEvent) {}
+
+    /// Puts off connecting again, unless it was already put off further.
+    fn retry_later(&self, retry_at: u64) {
+        let now = now_millis();
+        if now >= retry_at {
+            let next = now + RETRY_MILLIS;
+            let _ = (self.retry_at).compare_exchange(
+                retry_at,
+                next,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+        }
+    }
+}
+
+/// Encodes an event as a frame, in the format documented for
+/// [`Backend::Socket`](crate::Backend::Socket).
+fn encode(event: &AllocEvent) -> [u8; FRAME] {
+    let op = match event.op {
+        AllocOp::Alloc => 0,
+        AllocOp::AllocZeroed => 1,
+        AllocOp::Dealloc => 2,
+        AllocOp::Realloc => 3,
+    };
+    let tag = event.tag();
+    let mut frame = [0; FRAME];
+    frame[0] = VERSION;
+    frame[1] = op;
+    frame[2] = if tag.is_some() { HAS_TAG } else { 0 };
+    frame[4..8].copy_from_slice(&event.thread().to_le_bytes());
+    frame[8..12].copy_from_slice(&std::process::id().to_le_bytes());
+    frame[12..16].copy_from_slice(&tag.unwrap_or(0).to_le_bytes());
+    frame[16..24].copy_from_slice(&(now().as_nanos() as u64).to_le_bytes());
+    frame[24..32].copy_from_slice(&(event.new_size as u64).to_le_bytes());
+    frame[32..40].copy_from_slice(&(event.layout.size() as u64).to_le_bytes());
+    frame[40..48].copy_from_slice(&(event.address as u64).to_le_bytes());
+    frame[48..56].copy_from_slice(&(event.old_address as u64).to_le_bytes());
+    frame[56..64].copy_from_slice(&(event.layout.align() as u64).to_le_bytes());
+    frame
+}
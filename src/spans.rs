@@ -0,0 +1,97 @@
+//! The `tracing` layer that names allocator activity after the entered spans,
+//! enabled by the `tracing` feature.
+
+use crate::quietly;
+use crate::region::Region;
+use std::cell::RefCell;
+use tracing_core::span::Id;
+use tracing_core::Subscriber;
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+thread_local! {
+    /// The spans entered on this thread, innermost last, each with the
+    /// region of its name.
+    static ENTERED: RefCell<Vec<(Id, &'static str, Region)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A `tracing_subscriber` layer that enters a [`region`](crate::region)
+/// named after each span while it's entered, so the allocations in spans
+/// are counted by [`region_stats`](crate::region_stats) and play at their
+/// pitch.
+///
+/// Events also know their span, as [`AllocEvent::span`](crate::AllocEvent::span),
+/// and [`Config::spans`](crate::Config::spans) plays only the events inside
+/// spans with certain names.  This needs the `tracing` feature.
+///
+/// ```
+/// use tracing_subscriber::layer::SubscriberExt;
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// fn main() {
+///     let subscriber = tracing_subscriber::registry().with(alloc_geiger::SpanLayer);
+///     tracing::subscriber::with_default(subscriber, || {
+///         let _render = tracing::info_span!("render").entered();
+///         let _frame = vec![0u8; 4096];
+///     });
+///     let stats = alloc_geiger::region_stats();
+///     assert!(stats.iter().any(|(name, stats)| *name == "render" && stats.allocs > 0));
+/// }
+/// ```
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SpanLayer;
+
+impl<S> Layer<S> for SpanLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_enter(&self, id: &Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let name = span.name();
+        quietly(|| {
+            let region = Region::named(name);
+            ENTERED.with(|entered| entered.borrow_mut().push((id.clone(), name, region)));
+        });
+    }
+
+    fn on_exit(&self, id: &Id, _ctx: Context<'_, S>) {
+        quietly(|| {
+            ENTERED.with(|entered| {
+                let mut entered = entered.borrow_mut();
+                let Some(i) = entered.iter().rposition(|(entered, ..)| entered == id) else {
+                    return;
+                };
+                let (_, _, region) = entered.remove(i);
+                match entered.get_mut(i) {
+                    // a span exited out of order hands its outer region on
+                    Some((_, _, inner)) => inner.replace_outer(region),
+                    None => drop(region),
+                }
+            })
+        });
+    }
+}
+
+/// The innermost span entered on this thread.
+pub(crate) fn current() -> Option<Id> {
+    let entered = ENTERED.try_with(|entered| {
+        let entered = entered.try_borrow().ok()?;
+        entered.last().map(|(id, ..)| id.clone())
+    });
+    entered.ok().flatten()
+}
+
+/// Returns `true` if this thread is inside a span with one of these names.
+pub(crate) fn inside(names: &[String]) -> bool {
+    let inside = ENTERED.try_with(|entered| {
+        let Ok(entered) = entered.try_borrow() else {
+            return false;
+        };
+        (entered.iter()).any(|(_, name, _)| names.iter().any(|n| n == name))
+    });
+    inside.unwrap_or(false)
+}
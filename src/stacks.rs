@@ -0,0 +1,257 @@
+use crate::event::{AllocEvent, AllocOp};
+use crate::quietly;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::collections::BTreeMap;
+use std::ffi::c_int;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::{Mutex, Once, PoisonError};
+
+/// The sampled bytes of each folded stack, from the root to the allocation.
+static STACKS: Mutex<BTreeMap<String, u64>> = Mutex::new(BTreeMap::new());
+
+/// Where to write the folded stacks at exit, from [`Config::flamegraph`].
+///
+/// [`Config::flamegraph`]: crate::Config::flamegraph
+static FLAMEGRAPH: Mutex<Option<String>> = Mutex::new(None);
+
+thread_local! {
+    /// The bytes left to allocate on this thread before the next sample, or
+    /// `0` before the first allocation.
+    static COUNTDOWN: Cell<usize> = const { Cell::new(0) };
+}
+
+extern "C" {
+    pub(crate) fn atexit(callback: extern "C" fn()) -> c_int;
+}
+
+/// Counts an allocation towards the next sample, capturing the stack each
+/// time another `interval` bytes have been allocated on this thread.
+///
+/// Each sample is weighted by the bytes it stands for, so large allocations
+/// are always sampled and small ones in proportion to their total.
+pub(crate) fn sample(interval: usize, event: &AllocEvent, flamegraph: Option<&str>) {
+    if event.op == AllocOp::Dealloc || event.address == 0 || interval == 0 {
+        return;
+    }
+    let size = event.new_size;
+    let samples = COUNTDOWN.with(|countdown| {
+        let left = match countdown.get() {
+            0 => interval,
+            left => left,
+        };
+        if size < left {
+            countdown.set(left - size);
+            return 0;
+        }
+        let over = size - left;
+        countdown.set(interval - over % interval);
+        1 + over / interval
+    });
+    if samples == 0 {
+        return;
+    }
+    let weight = (samples as u64).saturating_mul(interval as u64);
+    quietly(|| {
+        let stack = fold(&capture());
+        let mut stacks = STACKS.lock().unwrap_or_else(PoisonError::into_inner);
+        *stacks.entry(stack).or_default() += weight;
+        drop(stacks);
+        if let Some(path) = flamegraph {
+            write_at_exit(path);
+        }
+    });
+}
+
+/// One function in a captured stack.
+pub(crate) struct Frame<'a> {
+    /// The function name, without its symbol hash.
+    pub(crate) name: &'a str,
+    /// The source file and line, if there's debug info.
+    pub(crate) location: Option<(&'a str, u32)>,
+}
+
+/// Captures and resolves the current stack, for [`caller_frames`].
+pub(crate) fn capture() -> String {
+    Backtrace::force_capture().to_string()
+}
+
+/// Parses a displayed backtrace into frames from the caller of the allocator
+/// outward, leaving out the allocator's own frames.
+pub(crate) fn caller_frames(backtrace: &str) -> Vec<Frame<'_>> {
+    let mut frames: Vec<Frame<'_>> = Vec::new();
+    for line in backtrace.lines() {
+        let line = line.trim_start();
+        if let Some(location) = line.strip_prefix("at ") {
+            let mut parts = location.rsplitn(3, ':');
+            let (_column, line, file) = (parts.next(), parts.next(), parts.next());
+            if let (Some(frame), Some(line), Some(file)) = (frames.last_mut(), line, file) {
+                if frame.location.is_none() {
+                    frame.location = line.parse().ok().map(|line| (file, line));
+                }
+            }
+        } else if let Some((index, name)) = line.split_once(": ") {
+            if index.bytes().all(|b| b.is_ascii_digit()) {
+                let name = strip_hash(name);
+                frames.push(Frame {
+                    name,
+                    location: None,
+                });
+            }
+        }
+    }
+    // Everything up to the last frame of the allocator itself is the capture.
+    let mut start = frames
+        .iter()
+        .rposition(|frame| is_allocator(frame.name))
+        .map_or(0, |i| i + 1);
+    start += frames[start..]
+        .iter()
+        .take_while(|frame| (frame.name.trim_start_matches('<')).starts_with("alloc::alloc::"))
+        .count();
+    frames.drain(..start);
+    frames
+}
+
+/// Returns `true` if any frame of the current stack, outside the allocator,
+/// is in one of these crates or modules.
+pub(crate) fn called_from(paths: &[String]) -> bool {
+    quietly(|| {
+        let backtrace = capture();
+        let frames = caller_frames(&backtrace);
+        frames.iter().any(|frame| {
+            let name = frame.name.trim_start_matches('<');
+            paths.iter().any(|path| {
+                name.strip_prefix(path.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+            })
+        })
+    })
+}
+
+/// Prints the current stack to stderr, for an allocation of the size given
+/// to `Geiger::watch_size`.
+pub(crate) fn print_watched(size: usize) {
+    quietly(|| {
+        let backtrace = describe();
+        eprint!("alloc_geiger: allocated {size} bytes, the watched size\n{backtrace}");
+    })
+}
+
+/// Describes the current stack outside the allocator, with an indented `at`
+/// line for each frame.
+pub(crate) fn describe() -> String {
+    let backtrace = capture();
+    let mut described = String::new();
+    for frame in caller_frames(&backtrace) {
+        let _ = match frame.location {
+            Some((file, line)) => writeln!(described, "    at {} ({file}:{line})", frame.name),
+            None => writeln!(described, "    at {}", frame.name),
+        };
+    }
+    described
+}
+
+/// Folds a displayed backtrace into `root;...;caller` frames, leaving out the
+/// allocator's own frames and symbol hashes.
+fn fold(backtrace: &str) -> String {
+    let mut folded = String::new();
+    for frame in caller_frames(backtrace).iter().rev() {
+        if !folded.is_empty() {
+            folded.push(';');
+        }
+        folded.extend(frame.name.chars().map(|c| if c == ';' { ',' } else { c }));
+    }
+    if folded.is_empty() {
+        folded.push_str("[unknown]");
+    }
+    folded
+}
+
+/// Returns `true` for frames of the capture and the allocator hooks.
+fn is_allocator(name: &str) -> bool {
+    const PREFIXES: [&str; 7] = [
+        "alloc_geiger::",
+        "<alloc_geiger::",
+        "std::backtrace",
+        "<std::backtrace",
+        "__rust_",
+        "__rustc::",
+        "__rdl_",
+    ];
+    PREFIXES.iter().any(|prefix| name.starts_with(prefix))
+}
+
+/// Removes a trailing `::h0123456789abcdef` symbol hash.
+fn strip_hash(name: &str) -> &str {
+    match name.rsplit_once("::h") {
+        Some((name, hash)) if hash.len() == 16 && hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            name
+        }
+        _ => name,
+    }
+}
+
+/// Sets the path to write at exit, registering the exit handler once.
+fn write_at_exit(path: &str) {
+    static REGISTER: Once = Once::new();
+    let mut flamegraph = FLAMEGRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+    if flamegraph.as_deref() != Some(path) {
+        *flamegraph = Some(path.into());
+    }
+    drop(flamegraph);
+    REGISTER.call_once(|| {
+        // SAFETY: registering a plain function that doesn't unwind.
+        unsafe { atexit(exit_handler) };
+    });
+}
+
+extern "C" fn exit_handler() {
+    quietly(|| {
+        let path = FLAMEGRAPH.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some(path) = path.as_deref() {
+            let result = File::create(path).and_then(write_folded_stacks);
+            if let Err(e) = result {
+                eprintln!("alloc_geiger: couldn't write flamegraph stacks to {path}: {e}");
+            }
+        }
+    });
+}
+
+/// Writes the allocation stacks sampled as configured by
+/// [`Config::stacks`](crate::Config::stacks), in the folded format of
+/// `flamegraph.pl` and `inferno-flamegraph`.
+///
+/// Each line is a stack of function names from the root to the allocating
+/// call, separated by semicolons, then a space and the bytes allocated there.
+/// The allocator's own frames are left out.
+///
+/// ```
+/// use alloc_geiger::Config;
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System =
+///     alloc_geiger::Geiger::with_config(std::alloc::System, Config::new().stacks(4096));
+///
+/// fn main() {
+///     let _vec = vec![0u8; 10000];
+///     let mut folded = Vec::new();
+///     alloc_geiger::write_folded_stacks(&mut folded).unwrap();
+///     assert!(!folded.is_empty());
+/// }
+/// ```
+pub fn write_folded_stacks<W: Write>(writer: W) -> io::Result<()> {
+    quietly(|| {
+        let stacks = STACKS
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        let mut writer = BufWriter::new(writer);
+        for (stack, bytes) in &stacks {
+            writeln!(writer, "{stack} {bytes}")?;
+        }
+        writer.flush()
+    })
+}
@@ -0,0 +1,296 @@
+use crate::event::{AllocEvent, AllocOp};
+use crate::pitch::{self, pool_name};
+use std::cell::Cell;
+use std::fmt;
+use std::mem;
+use std::ptr;
+use std::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
+use std::sync::{Mutex, PoisonError};
+
+/// Running allocator counters, updated outside of the crate's own activity.
+pub(crate) struct Stats {
+    allocs: AtomicU64,
+    deallocs: AtomicU64,
+    reallocs: AtomicU64,
+    bytes_allocated: AtomicU64,
+    bytes_freed: AtomicU64,
+}
+
+impl Stats {
+    pub(crate) const fn new() -> Self {
+        Stats {
+            allocs: AtomicU64::new(0),
+            deallocs: AtomicU64::new(0),
+            reallocs: AtomicU64::new(0),
+            bytes_allocated: AtomicU64::new(0),
+            bytes_freed: AtomicU64::new(0),
+        }
+    }
+
+    /// Counts an event, except a failed one, which leaves the memory as it
+    /// was.
+    pub(crate) fn count(&self, event: &AllocEvent) {
+        if event.address == 0 {
+            return;
+        }
+        let size = event.layout.size();
+        match event.op {
+            AllocOp::Alloc | AllocOp::AllocZeroed => self.alloc(size),
+            AllocOp::Dealloc => self.dealloc(size),
+            AllocOp::Realloc => self.realloc(size, event.new_size),
+        }
+    }
+
+    fn alloc(&self, size: usize) {
+        self.allocs.fetch_add(1, Ordering::Relaxed);
+        self.bytes_allocated
+            .fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn dealloc(&self, size: usize) {
+        self.deallocs.fetch_add(1, Ordering::Relaxed);
+        self.bytes_freed.fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    fn realloc(&self, old_size: usize, new_size: usize) {
+        self.reallocs.fetch_add(1, Ordering::Relaxed);
+        self.bytes_allocated
+            .fetch_add(new_size as u64, Ordering::Relaxed);
+        self.bytes_freed
+            .fetch_add(old_size as u64, Ordering::Relaxed);
+    }
+
+    /// The number of events counted so far.
+    pub(crate) fn events(&self) -> u64 {
+        self.allocs.load(Ordering::Relaxed)
+            + self.deallocs.load(Ordering::Relaxed)
+            + self.reallocs.load(Ordering::Relaxed)
+    }
+
+    /// Bytes currently allocated, as far as the counters can tell.
+    pub(crate) fn live_bytes(&self) -> u64 {
+        let freed = self.bytes_freed.load(Ordering::Relaxed);
+        let allocated = self.bytes_allocated.load(Ordering::Relaxed);
+        allocated.saturating_sub(freed)
+    }
+
+    pub(crate) fn snapshot(&self) -> GeigerStats {
+        GeigerStats {
+            allocs: self.allocs.load(Ordering::Relaxed),
+            deallocs: self.deallocs.load(Ordering::Relaxed),
+            reallocs: self.reallocs.load(Ordering::Relaxed),
+            bytes_allocated: self.bytes_allocated.load(Ordering::Relaxed),
+            bytes_freed: self.bytes_freed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+thread_local! {
+    /// The node of this thread in the last `ThreadList` it counted into, by
+    /// the list's address.
+    static NODE: Cell<(usize, Option<&'static ThreadNode>)> = const { Cell::new((0, None)) };
+}
+
+/// The counters of each thread, in nodes that are leaked so threads can count
+/// into them without a lock, and linked into a list that's only prepended.
+pub(crate) struct ThreadList {
+    head: AtomicPtr<ThreadNode>,
+}
+
+struct ThreadNode {
+    thread: u32,
+    stats: Stats,
+    next: Option<&'static ThreadNode>,
+}
+
+impl ThreadList {
+    pub(crate) const fn new() -> Self {
+        ThreadList {
+            head: AtomicPtr::new(ptr::null_mut()),
+        }
+    }
+
+    /// Counts an event in its thread's node, adding one if it's new.  This
+    /// must be called within the recursion guard, since it may allocate.
+    pub(crate) fn count(&self, event: &AllocEvent) {
+        if event.address == 0 {
+            return;
+        }
+        let list = self as *const ThreadList as usize;
+        let node = NODE.with(|cell| match cell.get() {
+            (owner, Some(node)) if owner == list => node,
+            _ => {
+                let node = self.node(event.thread());
+                cell.set((list, Some(node)));
+                node
+            }
+        });
+        node.stats.count(event);
+    }
+
+    fn nodes(&self) -> impl Iterator<Item = &'static ThreadNode> {
+        // SAFETY: nodes are leaked, so any published pointer stays valid.
+        let mut next = unsafe { self.head.load(Ordering::Acquire).as_ref() };
+        std::iter::from_fn(move || {
+            let node = next?;
+            next = node.next;
+            Some(node)
+        })
+    }
+
+    /// Finds the node of a thread, or adds one.
+    fn node(&self, thread: u32) -> &'static ThreadNode {
+        if let Some(node) = self.nodes().find(|node| node.thread == thread) {
+            return node;
+        }
+        let mut node = Box::new(ThreadNode {
+            thread,
+            stats: Stats::new(),
+            next: None,
+        });
+        let mut head = self.head.load(Ordering::Acquire);
+        loop {
+            // SAFETY: as in `nodes`.
+            node.next = unsafe { head.as_ref() };
+            let new = &*node as *const ThreadNode as *mut ThreadNode;
+            match (self.head).compare_exchange_weak(head, new, Ordering::AcqRel, Ordering::Acquire)
+            {
+                Ok(_) => return Box::leak(node),
+                Err(current) => head = current,
+            }
+        }
+    }
+
+    /// Snapshots of each thread's counters, in the order of their indices.
+    pub(crate) fn snapshot(&self) -> Vec<ThreadStats> {
+        let names = pitch::thread_names(0);
+        let mut threads: Vec<ThreadStats> = (self.nodes())
+            .map(|node| ThreadStats {
+                thread: node.thread,
+                name: (names.iter())
+                    .find(|(thread, _)| *thread == node.thread)
+                    .map(|(_, name)| name.clone()),
+                stats: node.stats.snapshot(),
+            })
+            .collect();
+        threads.sort_unstable_by_key(|thread| thread.thread);
+        threads
+    }
+}
+
+/// The allocator activity of one thread, from
+/// [`Geiger::stats_per_thread`](crate::Geiger::stats_per_thread).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct ThreadStats {
+    /// A small index of the thread, in order of their first events, as in
+    /// [`RecentEvent::thread`](crate::RecentEvent::thread).
+    pub thread: u32,
+    /// The thread's name, if it had one when it first allocated.
+    pub name: Option<String>,
+    /// The thread's activity, including any after it exited.
+    pub stats: GeigerStats,
+}
+
+/// Adds up the activity of the threads in each pool, as named by
+/// `pool_name`, keeping the others on their own.
+pub(crate) fn pools(threads: Vec<ThreadStats>) -> Vec<(String, GeigerStats)> {
+    let mut pools: Vec<(String, GeigerStats)> = Vec::new();
+    for thread in threads {
+        let name = match thread.name.as_deref().and_then(pool_name) {
+            Some(pool) => match pools.iter_mut().find(|(name, _)| name == pool) {
+                Some((_, stats)) => {
+                    stats.add(&thread.stats);
+                    continue;
+                }
+                None => pool.to_owned(),
+            },
+            None => (thread.name).unwrap_or_else(|| format!("thread {}", thread.thread)),
+        };
+        pools.push((name, thread.stats));
+    }
+    pools
+}
+
+/// The start of the current epoch, for [`Geiger::epoch`](crate::Geiger::epoch).
+pub(crate) struct Epoch {
+    start: Mutex<GeigerStats>,
+}
+
+impl Epoch {
+    pub(crate) const fn new() -> Self {
+        Epoch {
+            start: Mutex::new(GeigerStats::ZERO),
+        }
+    }
+
+    /// Starts a new epoch at `now`, returning the activity in the last one.
+    pub(crate) fn advance(&self, now: GeigerStats) -> GeigerStats {
+        let mut start = self.start.lock().unwrap_or_else(PoisonError::into_inner);
+        now.since(&mem::replace(&mut start, now))
+    }
+}
+
+/// A snapshot of the allocator activity seen by a `Geiger`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[non_exhaustive]
+pub struct GeigerStats {
+    /// Number of `alloc` and `alloc_zeroed` calls.
+    pub allocs: u64,
+    /// Number of `dealloc` calls.
+    pub deallocs: u64,
+    /// Number of `realloc` calls.
+    pub reallocs: u64,
+    /// Total bytes requested, including the new size of each `realloc`.
+    pub bytes_allocated: u64,
+    /// Total bytes released, including the old size of each `realloc`.
+    pub bytes_freed: u64,
+}
+
+impl GeigerStats {
+    const ZERO: GeigerStats = GeigerStats {
+        allocs: 0,
+        deallocs: 0,
+        reallocs: 0,
+        bytes_allocated: 0,
+        bytes_freed: 0,
+    };
+
+    /// The activity between an earlier snapshot and this one.
+    fn since(&self, earlier: &GeigerStats) -> GeigerStats {
+        GeigerStats {
+            allocs: self.allocs.saturating_sub(earlier.allocs),
+            deallocs: self.deallocs.saturating_sub(earlier.deallocs),
+            reallocs: self.reallocs.saturating_sub(earlier.reallocs),
+            bytes_allocated: self.bytes_allocated.saturating_sub(earlier.bytes_allocated),
+            bytes_freed: self.bytes_freed.saturating_sub(earlier.bytes_freed),
+        }
+    }
+
+    /// Adds the activity of another snapshot, as of another thread.
+    fn add(&mut self, other: &GeigerStats) {
+        self.allocs += other.allocs;
+        self.deallocs += other.deallocs;
+        self.reallocs += other.reallocs;
+        self.bytes_allocated += other.bytes_allocated;
+        self.bytes_freed += other.bytes_freed;
+    }
+
+    /// Bytes currently allocated, as far as this snapshot can tell.
+    pub fn live_bytes(&self) -> u64 {
+        self.bytes_allocated.saturating_sub(self.bytes_freed)
+    }
+}
+
+impl fmt::Display for GeigerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "allocs {}", self.allocs)?;
+        writeln!(f, "deallocs {}", self.deallocs)?;
+        writeln!(f, "reallocs {}", self.reallocs)?;
+        writeln!(f, "bytes_allocated {}", self.bytes_allocated)?;
+        writeln!(f, "bytes_freed {}", self.bytes_freed)?;
+        writeln!(f, "live_bytes {}", self.live_bytes())
+    }
+}
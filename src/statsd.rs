@@ -0,0 +1,135 @@
+//! A statsd exporter, enabled by the `statsd` feature.
+
+use crate::{Geiger, GeigerStats, BUSY};
+use std::io::{self, Cursor, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread flushing the allocation counters to a statsd server
+    /// over UDP at every `interval`.
+    ///
+    /// The counters `alloc_geiger.allocs`, `deallocs`, `reallocs`,
+    /// `bytes_allocated`, and `bytes_freed` are sent as deltas since the last
+    /// flush, and `alloc_geiger.live_bytes` as a gauge, all in one packet
+    /// written to a fixed buffer without allocating.
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_statsd("127.0.0.1:8125", Duration::from_secs(10)).unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_statsd<A: ToSocketAddrs>(
+        &'static self,
+        addr: A,
+        interval: Duration,
+    ) -> io::Result<JoinHandle<()>> {
+        let socket = connect(addr)?;
+        thread::Builder::new()
+            .name("alloc_geiger-statsd".into())
+            .spawn(move || {
+                BUSY.with(|busy| busy.set(true));
+                let mut last = GeigerStats::default();
+                let mut buf = [0; 512];
+                loop {
+                    thread::sleep(interval);
+                    let stats = self.stats();
+                    let len = packet(&mut buf, &stats, &last);
+                    // statsd is fire-and-forget, so a missing server isn't fatal
+                    let _ = socket.send(&buf[..len]);
+                    last = stats;
+                }
+            })
+    }
+}
+
+/// Connects a socket to the first of the addresses that works, bound to the
+/// unspecified address of the same family.
+fn connect<A: ToSocketAddrs>(addr: A) -> io::Result<UdpSocket> {
+    let mut error = io::Error::new(io::ErrorKind::InvalidInput, "no addresses to send to");
+    for addr in addr.to_socket_addrs()? {
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        match UdpSocket::bind(local).and_then(|socket| socket.connect(addr).map(|()| socket)) {
+            Ok(socket) => return Ok(socket),
+            Err(e) => error = e,
+        }
+    }
+    Err(error)
+}
+
+/// Writes the statsd lines for the change since `last`, returning the length.
+fn packet(buf: &mut [u8], stats: &GeigerStats, last: &GeigerStats) -> usize {
+    let mut cursor = Cursor::new(buf);
+    let counters = [
+        ("allocs", stats.allocs - last.allocs),
+        ("deallocs", stats.deallocs - last.deallocs),
+        ("reallocs", stats.reallocs - last.reallocs),
+        (
+            "bytes_allocated",
+            stats.bytes_allocated - last.bytes_allocated,
+        ),
+        ("bytes_freed", stats.bytes_freed - last.bytes_freed),
+    ];
+    for (name, delta) in counters {
+        let _ = writeln!(cursor, "alloc_geiger.{name}:{delta}|c");
+    }
+    let _ = write!(cursor, "alloc_geiger.live_bytes:{}|g", stats.live_bytes());
+    cursor.position() as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(allocs: u64, bytes_allocated: u64, bytes_freed: u64) -> GeigerStats {
+        GeigerStats {
+            allocs,
+            bytes_allocated,
+            bytes_freed,
+            ..GeigerStats::default()
+        }
+    }
+
+    #[test]
+    fn writes_deltas_and_a_gauge() {
+        let mut buf = [0; 512];
+        let len = packet(&mut buf, &stats(10, 1000, 400), &stats(4, 300, 100));
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            "alloc_geiger.allocs:6|c\n\
+             alloc_geiger.deallocs:0|c\n\
+             alloc_geiger.reallocs:0|c\n\
+             alloc_geiger.bytes_allocated:700|c\n\
+             alloc_geiger.bytes_freed:300|c\n\
+             alloc_geiger.live_bytes:600|g"
+        );
+    }
+
+    #[test]
+    fn sends_to_loopback() {
+        for local in ["127.0.0.1:0", "[::1]:0"] {
+            // IPv6 may be disabled where this runs
+            let Ok(server) = UdpSocket::bind(local) else {
+                assert!(local.starts_with('['));
+                continue;
+            };
+            let socket = connect(server.local_addr().unwrap()).unwrap();
+            let mut buf = [0; 512];
+            let len = packet(&mut buf, &stats(1, 8, 0), &GeigerStats::default());
+            socket.send(&buf[..len]).unwrap();
+            let mut received = [0; 512];
+            let n = server.recv(&mut received).unwrap();
+            assert_eq!(received[..n], buf[..len], "{local}");
+        }
+    }
+}
@@ -0,0 +1,259 @@
+//! The `rodio` output and custom sources, enabled by the `sound-rodio`
+//! feature, which is on by default.
+
+use crate::config::Config;
+use crate::mixer::{Grid, Mixer, Shared, DEFAULT_SAMPLE_RATE};
+use crate::output::{MixerOutput, TIMED_OUT};
+use crate::{quietly, Geiger, BUSY};
+use rodio::cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
+use rodio::source::UniformSourceIterator;
+use rodio::{cpal, OutputStream, Source};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::mpsc::{self, RecvError, RecvTimeoutError, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+/// The result of opening a stream, sent back from the audio thread.
+type Opened = Result<Arc<Shared>, String>;
+
+/// Whether an audio thread is still opening its device after the wait for it
+/// timed out, so the device isn't tried again in the meantime.
+static STALLED: AtomicBool = AtomicBool::new(false);
+
+/// The progress of an audio thread, agreed with the thread waiting for it.
+const WAITING: u8 = 0;
+const OPENED: u8 = 1;
+const ABANDONED: u8 = 2;
+
+/// Opens the output stream on the configured device, from a dedicated audio
+/// thread that owns it from then on.
+///
+/// All of `rodio` and `cpal` is called from that thread, so the allocating
+/// threads only ever queue pulses for the mixer, and never wait on the
+/// device, except for this one while it opens, up to the `init_timeout`.
+pub(crate) fn open(config: &Config) -> Result<MixerOutput, String> {
+    if STALLED.load(Ordering::Relaxed) {
+        return Err("the device is still opening since it timed out".into());
+    }
+    let config = config.clone();
+    let timeout = config.init_timeout;
+    let (sender, receiver) = mpsc::sync_channel(1);
+    let progress = Arc::new(AtomicU8::new(WAITING));
+    let audio_progress = Arc::clone(&progress);
+    thread::Builder::new()
+        .name("alloc_geiger-audio".into())
+        .spawn(move || {
+            BUSY.with(|busy| busy.set(true));
+            match config.buffer_frames {
+                Some(frames) => keep(&sender, &audio_progress, open_buffered(&config, frames)),
+                None => keep(&sender, &audio_progress, open_default(&config)),
+            }
+        })
+        .map_err(|e| e.to_string())?;
+    let opened = match timeout {
+        Some(timeout) => match receiver.recv_timeout(timeout) {
+            Err(RecvTimeoutError::Timeout) => {
+                // Flagged before giving up, so the audio thread either sees
+                // that it was abandoned and clears this, or it already sent.
+                STALLED.store(true, Ordering::Relaxed);
+                if progress.swap(ABANDONED, Ordering::AcqRel) != OPENED {
+                    TIMED_OUT.store(true, Ordering::Relaxed);
+                    let millis = timeout.as_millis();
+                    return Err(format!("timed out after {millis} ms opening the device"));
+                }
+                STALLED.store(false, Ordering::Relaxed);
+                receiver.recv()
+            }
+            opened => opened.map_err(|_| RecvError),
+        },
+        None => receiver.recv(),
+    };
+    let mixer = opened.map_err(|_| "the audio thread exited".to_owned())??;
+    Ok(MixerOutput::new(mixer))
+}
+
+/// Sends back the mixer of an opened stream, and then keeps the stream
+/// playing for as long as the process runs.
+///
+/// The stream isn't `Send`, so it can't be kept anywhere else, but it also
+/// mustn't be dropped or playback will end.  If its device is lost, the
+/// output is opened again on a new thread, and this one stays parked.
+///
+/// If the opening thread stopped waiting, the stream is dropped instead, and
+/// the device can be tried again.
+fn keep<S>(
+    sender: &SyncSender<Opened>,
+    progress: &AtomicU8,
+    opened: Result<(S, Arc<Shared>), String>,
+) {
+    if progress.swap(OPENED, Ordering::AcqRel) == ABANDONED {
+        STALLED.store(false, Ordering::Relaxed);
+        return;
+    }
+    match opened {
+        Ok((_stream, mixer)) => {
+            let _ = sender.send(Ok(mixer));
+            loop {
+                thread::park();
+            }
+        }
+        Err(e) => {
+            let _ = sender.send(Err(e));
+        }
+    }
+}
+
+/// Opens the configured device with `rodio`'s stream.
+fn open_default(config: &Config) -> Result<(OutputStream, Arc<Shared>), String> {
+    let (stream, handle, sample_rate) = match config.device.as_deref() {
+        Some(name) => {
+            let device = find_device(name)?;
+            let config = device.default_output_config().map_err(|e| e.to_string())?;
+            let sample_rate = config.sample_rate().0;
+            let (stream, handle) =
+                OutputStream::try_from_device_config(&device, config).map_err(|e| e.to_string())?;
+            (stream, handle, sample_rate)
+        }
+        None => {
+            let (stream, handle) = OutputStream::try_default().map_err(|e| e.to_string())?;
+            (stream, handle, default_sample_rate())
+        }
+    };
+    let sample_rate = config.sample_rate.unwrap_or(sample_rate);
+    let (source, started, mixer) = Mixer::new(
+        sample_rate,
+        Grid::of(config),
+        config.audio_priority,
+        config.mix_budget,
+    );
+    handle.play_raw(source).map_err(|e| e.to_string())?;
+    started.wait(config.init_timeout)?;
+    Ok((stream, mixer))
+}
+
+/// Opens the configured device with a fixed buffer size, through `cpal`
+/// without `rodio`'s stream, which always leaves the size to the host.
+fn open_buffered(config: &Config, frames: u32) -> Result<(cpal::Stream, Arc<Shared>), String> {
+    let device = match config.device.as_deref() {
+        Some(name) => find_device(name)?,
+        None => cpal::default_host()
+            .default_output_device()
+            .ok_or("no default output device")?,
+    };
+    let supported = device.default_output_config().map_err(|e| e.to_string())?;
+    let frames = match *supported.buffer_size() {
+        cpal::SupportedBufferSize::Range { min, max } => frames.clamp(min, max),
+        cpal::SupportedBufferSize::Unknown => frames,
+    };
+    let format = supported.sample_format();
+    let mut stream_config = supported.config();
+    stream_config.buffer_size = cpal::BufferSize::Fixed(frames);
+    let sample_rate = config.sample_rate.unwrap_or(stream_config.sample_rate.0);
+    let (mixer, started, shared) = Mixer::new(
+        sample_rate,
+        Grid::of(config),
+        config.audio_priority,
+        config.mix_budget,
+    );
+    let source =
+        UniformSourceIterator::new(mixer, stream_config.channels, stream_config.sample_rate.0);
+    let stream = match format {
+        cpal::SampleFormat::F32 => build_stream::<f32>(&device, &stream_config, source),
+        cpal::SampleFormat::I16 => build_stream::<i16>(&device, &stream_config, source),
+        cpal::SampleFormat::U16 => build_stream::<u16>(&device, &stream_config, source),
+        format => return Err(format!("unsupported sample format {format}")),
+    }?;
+    stream.play().map_err(|e| e.to_string())?;
+    started.wait(config.init_timeout)?;
+    Ok((stream, shared))
+}
+
+/// Finds an output device by name.
+fn find_device(name: &str) -> Result<cpal::Device, String> {
+    cpal::default_host()
+        .output_devices()
+        .map_err(|e| e.to_string())?
+        .find(|device| device.name().is_ok_and(|n| n == name))
+        .ok_or_else(|| format!("no output device named {name:?}"))
+}
+
+/// Builds a `cpal` stream playing the mixed samples in the device's format.
+fn build_stream<T>(
+    device: &cpal::Device,
+    config: &cpal::StreamConfig,
+    mut source: impl Iterator<Item = f32> + Send + 'static,
+) -> Result<cpal::Stream, String>
+where
+    T: cpal::SizedSample + cpal::FromSample<f32>,
+{
+    let fill = move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+        for sample in data {
+            *sample = T::from_sample(source.next().unwrap_or(0.0));
+        }
+    };
+    // errors stop the heartbeat, so the output is opened again
+    device
+        .build_output_stream(config, fill, |_| {}, None)
+        .map_err(|e| e.to_string())
+}
+
+/// Queries the native sample rate of the default output device.
+fn default_sample_rate() -> u32 {
+    cpal::default_host()
+        .default_output_device()
+        .and_then(|device| device.default_output_config().ok())
+        .map_or(DEFAULT_SAMPLE_RATE, |config| config.sample_rate().0)
+}
+
+/// Returns the names of the available audio output devices, for
+/// [`Config::device`].
+///
+/// This needs the `sound-rodio` feature, which is on by default.
+pub fn output_devices() -> Vec<String> {
+    quietly(|| {
+        let devices = cpal::default_host().output_devices();
+        devices
+            .map(|devices| devices.filter_map(|device| device.name().ok()).collect())
+            .unwrap_or_default()
+    })
+}
+
+impl<Alloc, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Plays a custom sound through the same output and mix as the pulses,
+    /// like a marker at the start of a phase, returning `false` if it can't.
+    ///
+    /// The source is converted to the output's sample rate and mixed in
+    /// as-is, without the volume.  It isn't played while muted or paused, or
+    /// by backends without a mixer, like `bell` and `silent`, and this opens
+    /// the output if it isn't already.  The source is moved to the audio
+    /// thread, so its allocations there aren't counted.
+    ///
+    /// This needs the `sound-rodio` feature, which is on by default.
+    ///
+    /// ```no_run
+    /// use alloc_geiger::rodio::source::{SineWave, Source};
+    /// use std::time::Duration;
+    ///
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     // ... phase 1 ...
+    ///     let marker = SineWave::new(880.0).take_duration(Duration::from_millis(200));
+    ///     ALLOC.play_source(marker.amplify(0.3));
+    ///     // ... phase 2 ...
+    /// }
+    /// ```
+    pub fn play_source<S>(&self, source: S) -> bool
+    where
+        S: Source<Item = f32> + Send + 'static,
+    {
+        if self.is_muted() || self.is_paused() || !self.init() {
+            return false;
+        }
+        quietly(|| match &*self.read_output() {
+            Some(output) => output.play_source(Box::new(source)),
+            None => false,
+        })
+    }
+}
@@ -0,0 +1,301 @@
+use crate::event::AllocEvent;
+use crate::quietly;
+use crate::recent::RecentEvent;
+use crate::region::RegionStats;
+use std::cell::UnsafeCell;
+use std::fmt;
+use std::mem::{ManuallyDrop, MaybeUninit};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// The number of events each subscriber may have waiting.
+const CAPACITY: usize = 1024;
+
+/// The channels of all the current subscribers.
+pub(crate) struct Subscribers {
+    /// the length of `channels`, to skip the lock when there are none
+    count: AtomicUsize,
+    channels: RwLock<Vec<Arc<Channel>>>,
+}
+
+impl Subscribers {
+    pub(crate) const fn new() -> Self {
+        Subscribers {
+            count: AtomicUsize::new(0),
+            channels: RwLock::new(Vec::new()),
+        }
+    }
+
+    /// Adds a channel for the events in regions with these names, or all
+    /// events if there are none, which the caller must allocate quietly.
+    pub(crate) fn subscribe(&self, regions: Vec<String>) -> EventReceiver {
+        let channel = Arc::new(Channel::new(regions));
+        let mut channels = self.write();
+        channels.push(Arc::clone(&channel));
+        self.count.store(channels.len(), Ordering::Relaxed);
+        EventReceiver {
+            channel: ManuallyDrop::new(channel),
+        }
+    }
+
+    /// Sends an event to every subscriber, dropping it for those that are
+    /// full, and forgets those that were dropped themselves.
+    pub(crate) fn send(&self, event: &AllocEvent, region: Option<&RegionStats>) {
+        if self.count.load(Ordering::Relaxed) == 0 {
+            return;
+        }
+        let event = RecentEvent::new(event);
+        let mut closed = false;
+        for channel in self.read().iter() {
+            if channel.closed.load(Ordering::Relaxed) {
+                closed = true;
+            } else if !RegionStats::matches(region, &channel.regions) {
+                continue;
+            } else if !channel.send(event) {
+                channel.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        if closed {
+            let mut channels = self.write();
+            channels.retain(|channel| !channel.closed.load(Ordering::Relaxed));
+            self.count.store(channels.len(), Ordering::Relaxed);
+        }
+    }
+
+    fn read(&self) -> RwLockReadGuard<'_, Vec<Arc<Channel>>> {
+        self.channels.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Vec<Arc<Channel>>> {
+        self.channels
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A bounded queue of events, lock-free for any number of senders and a
+/// single receiver, after Dmitry Vyukov's bounded MPMC queue.
+struct Channel {
+    /// the next position to send to
+    tail: AtomicUsize,
+    /// the next position to receive from, only moved by the receiver
+    head: AtomicUsize,
+    slots: Box<[Slot]>,
+    /// events that found the channel full
+    dropped: AtomicU64,
+    /// whether the receiver was dropped
+    closed: AtomicBool,
+    /// the names of the regions whose events are sent, or none for all
+    regions: Vec<String>,
+}
+
+/// One event in a channel, guarded by its sequence number.
+struct Slot {
+    /// the position that may be sent here next, or one more than the
+    /// position of the event waiting here
+    seq: AtomicUsize,
+    event: UnsafeCell<MaybeUninit<RecentEvent>>,
+}
+
+// SAFETY: each slot's event is only written by the sender that claimed its
+// position, and only read by the receiver once its `seq` is published.
+unsafe impl Sync for Channel {}
+
+impl Channel {
+    fn new(regions: Vec<String>) -> Self {
+        Channel {
+            tail: AtomicUsize::new(0),
+            head: AtomicUsize::new(0),
+            slots: (0..CAPACITY)
+                .map(|seq| Slot {
+                    seq: AtomicUsize::new(seq),
+                    event: UnsafeCell::new(MaybeUninit::uninit()),
+                })
+                .collect(),
+            dropped: AtomicU64::new(0),
+            closed: AtomicBool::new(false),
+            regions,
+        }
+    }
+
+    /// Sends an event, returning `false` if the channel is full.
+    fn send(&self, event: RecentEvent) -> bool {
+        let mut pos = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[pos % CAPACITY];
+            let seq = slot.seq.load(Ordering::Acquire);
+            match (seq.wrapping_sub(pos) as isize).signum() {
+                0 => match (self.tail).compare_exchange_weak(
+                    pos,
+                    pos.wrapping_add(1),
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                ) {
+                    Ok(_) => {
+                        // SAFETY: claiming `pos` gives us this slot until
+                        // its `seq` is published.
+                        unsafe { (*slot.event.get()).write(event) };
+                        slot.seq.store(pos.wrapping_add(1), Ordering::Release);
+                        return true;
+                    }
+                    Err(tail) => pos = tail,
+                },
+                // the slot still holds an event from a lap ago
+                -1 => return false,
+                _ => pos = self.tail.load(Ordering::Relaxed),
+            }
+        }
+    }
+
+    /// Receives the oldest event, if any.  Only the one receiver calls this.
+    fn recv(&self) -> Option<RecentEvent> {
+        let pos = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[pos % CAPACITY];
+        if slot.seq.load(Ordering::Acquire) != pos.wrapping_add(1) {
+            return None;
+        }
+        // SAFETY: the sender published this event with its `seq`.
+        let event = unsafe { (*slot.event.get()).assume_init() };
+        slot.seq
+            .store(pos.wrapping_add(CAPACITY), Ordering::Release);
+        self.head.store(pos.wrapping_add(1), Ordering::Relaxed);
+        Some(event)
+    }
+}
+
+/// A live stream of allocator events, from
+/// [`Geiger::subscribe`](crate::Geiger::subscribe).
+///
+/// Up to 1024 events wait to be received, and any more are dropped rather
+/// than ever blocking the allocator.  Dropping the receiver unsubscribes.
+pub struct EventReceiver {
+    /// dropped quietly, as it was allocated
+    channel: ManuallyDrop<Arc<Channel>>,
+}
+
+impl EventReceiver {
+    /// Returns the oldest waiting event, or `None` if there are none yet.
+    pub fn try_recv(&mut self) -> Option<RecentEvent> {
+        self.channel.recv()
+    }
+
+    /// Returns an iterator over the events waiting now, oldest first.
+    pub fn try_iter(&mut self) -> impl Iterator<Item = RecentEvent> + '_ {
+        std::iter::from_fn(move || self.try_recv())
+    }
+
+    /// Returns the number of events dropped so far because too many were
+    /// waiting.
+    pub fn dropped(&self) -> u64 {
+        self.channel.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl fmt::Debug for EventReceiver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EventReceiver")
+            .field("dropped", &self.dropped())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for EventReceiver {
+    fn drop(&mut self) {
+        self.channel.closed.store(true, Ordering::Relaxed);
+        // SAFETY: the channel isn't used again.
+        quietly(|| unsafe { ManuallyDrop::drop(&mut self.channel) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AllocOp;
+    use std::alloc::Layout;
+    use std::thread;
+
+    fn alloc(size: usize) -> AllocEvent {
+        let layout = Layout::from_size_align(size, 1).unwrap();
+        AllocEvent::new(AllocOp::Alloc, layout, 0x1000 as *mut u8)
+    }
+
+    fn sizes(receiver: &mut EventReceiver) -> Vec<usize> {
+        receiver.try_iter().map(|event| event.size).collect()
+    }
+
+    #[test]
+    fn delivers_in_order_and_drops_when_full() {
+        let subscribers = Subscribers::new();
+        let mut receiver = subscribers.subscribe(Vec::new());
+        assert_eq!(receiver.try_recv(), None);
+        for size in 0..CAPACITY + 5 {
+            subscribers.send(&alloc(size), None);
+        }
+        assert_eq!(receiver.dropped(), 5);
+        assert_eq!(sizes(&mut receiver), (0..CAPACITY).collect::<Vec<_>>());
+
+        // the slots are reused on the next laps
+        for lap in 0..3 {
+            for size in 0..CAPACITY / 2 {
+                subscribers.send(&alloc(lap * CAPACITY + size), None);
+            }
+            let expected: Vec<_> = (0..CAPACITY / 2)
+                .map(|size| lap * CAPACITY + size)
+                .collect();
+            assert_eq!(sizes(&mut receiver), expected);
+        }
+        assert_eq!(receiver.dropped(), 5);
+    }
+
+    #[test]
+    fn forgets_dropped_receivers() {
+        let subscribers = Subscribers::new();
+        let mut kept = subscribers.subscribe(Vec::new());
+        let dropped = subscribers.subscribe(Vec::new());
+        assert_eq!(subscribers.count.load(Ordering::Relaxed), 2);
+        drop(dropped);
+        subscribers.send(&alloc(8), None);
+        assert_eq!(subscribers.count.load(Ordering::Relaxed), 1);
+        assert_eq!(sizes(&mut kept), [8]);
+    }
+
+    #[test]
+    fn filters_by_region() {
+        let subscribers = Subscribers::new();
+        let mut all = subscribers.subscribe(Vec::new());
+        let mut parsing = subscribers.subscribe(vec!["parse".into()]);
+        subscribers.send(&alloc(8), None);
+        assert_eq!(sizes(&mut all), [8]);
+        assert!(sizes(&mut parsing).is_empty());
+        assert_eq!(parsing.dropped(), 0);
+    }
+
+    #[test]
+    fn receives_from_many_threads() {
+        const THREADS: usize = 4;
+        const EVENTS: usize = CAPACITY / THREADS;
+        let subscribers = Subscribers::new();
+        let mut receiver = subscribers.subscribe(Vec::new());
+        thread::scope(|scope| {
+            for thread in 0..THREADS {
+                let subscribers = &subscribers;
+                scope.spawn(move || {
+                    for i in 0..EVENTS {
+                        subscribers.send(&alloc(thread * EVENTS + i), None);
+                    }
+                });
+            }
+        });
+        let mut received = sizes(&mut receiver);
+        assert_eq!(receiver.dropped(), 0);
+        assert_eq!(received.len(), CAPACITY);
+        // each thread's events are in the order it sent them
+        for thread in 0..THREADS {
+            let range = thread * EVENTS..(thread + 1) * EVENTS;
+            let sent = received.iter().filter(|size| range.contains(size));
+            assert!(sent.clone().zip(sent.skip(1)).all(|(a, b)| a < b));
+        }
+        received.sort_unstable();
+        assert_eq!(received, (0..CAPACITY).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,85 @@
+//! The summary of a run at exit, printed and played as a short jingle.
+
+use crate::output::Cue;
+use crate::pulse::Pulse;
+use crate::stacks::atexit;
+use crate::{quietly, Geiger};
+use std::sync::{Mutex, Once, PoisonError};
+use std::thread;
+
+/// The semitones above the root of each note of the jingle's arpeggio.
+const ARPEGGIO: [i32; 6] = [0, 4, 7, 12, 16, 19];
+
+/// A `Geiger` to summarize, erased from its allocator type.
+trait Summarize: Sync {
+    fn summarize(&self, live_before: u64);
+}
+
+/// The `Geiger` to summarize at exit, with its live bytes at the time.
+static SUMMARY: Mutex<Option<(&'static dyn Summarize, u64)>> = Mutex::new(None);
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Prints a summary of the allocator activity to stderr as the process
+    /// exits, and plays a short jingle for it, as an audible punctuation mark
+    /// for test runs and demos.
+    ///
+    /// The jingle rises by a note for each power of ten allocations, up to
+    /// six, and then resolves with a high note if the live bytes are no more
+    /// than when this was called, or else ends on a sour, falling note for
+    /// the leak.  It doesn't play while muted or paused, and otherwise the
+    /// exit waits a second or so for it to finish.  Only the last `Geiger`
+    /// to call this is summarized.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.summarize_at_exit();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn summarize_at_exit(&'static self) {
+        static REGISTER: Once = Once::new();
+        let live = self.core.stats.live_bytes();
+        *SUMMARY.lock().unwrap_or_else(PoisonError::into_inner) = Some((self, live));
+        REGISTER.call_once(|| {
+            // SAFETY: registering a plain function that doesn't unwind.
+            unsafe { atexit(exit_handler) };
+        });
+    }
+}
+
+impl<Alloc: Sync, const OPS: u8> Summarize for Geiger<Alloc, OPS> {
+    fn summarize(&self, live_before: u64) {
+        let stats = self.stats();
+        let leaked = stats.live_bytes().saturating_sub(live_before);
+        eprintln!(
+            "alloc_geiger: {} allocs, {} deallocs, {} reallocs, {leaked} bytes leaked",
+            stats.allocs, stats.deallocs, stats.reallocs,
+        );
+        if self.is_muted() || self.is_paused() {
+            return;
+        }
+        let notes = (1 + stats.allocs.max(1).ilog10() as usize).min(ARPEGGIO.len());
+        for &semitones in &ARPEGGIO[..notes] {
+            self.play(Cue::Tone, |sample_rate| {
+                Some(Pulse::summary(sample_rate, self.volume(), semitones))
+            });
+            thread::sleep(Pulse::SUMMARY_STEP);
+        }
+        self.play(Cue::Tone, |sample_rate| {
+            Some(Pulse::summary_end(sample_rate, self.volume(), leaked > 0))
+        });
+        thread::sleep(Pulse::SUMMARY_HELD);
+    }
+}
+
+extern "C" fn exit_handler() {
+    quietly(|| {
+        let summary = *SUMMARY.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((geiger, live_before)) = summary {
+            geiger.summarize(live_before);
+        }
+    });
+}
@@ -0,0 +1,82 @@
+//! Small integer tags for the events of a scope, a cheaper kind of region.
+
+use crate::pitch::Scale;
+use std::cell::Cell;
+use std::marker::PhantomData;
+
+thread_local! {
+    /// The innermost tag on this thread.
+    static CURRENT: Cell<Option<u32>> = const { Cell::new(None) };
+}
+
+/// Tags the allocator events on the current thread with a number until the
+/// returned guard is dropped.
+///
+/// The tag is available from [`AllocEvent::tag`](crate::AllocEvent::tag),
+/// shifts the pitch of the pulses within it, instead of their
+/// [`region`](crate::region)'s, and can pick the events to play with
+/// [`Config::tags`](crate::Config::tags).  Tags nest, in which case the
+/// innermost one applies.
+///
+/// Unlike a region, entering a tag only swaps a thread-local number, without
+/// looking up or counting anything, so it's cheap enough for hot paths, and
+/// the numbers can stand for anything, like a request ID or an enum.
+///
+/// ```
+/// use alloc_geiger::AllocEvent;
+/// use std::sync::atomic::{AtomicUsize, Ordering};
+///
+/// #[global_allocator]
+/// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+///
+/// static TAGGED: AtomicUsize = AtomicUsize::new(0);
+///
+/// fn main() {
+///     ALLOC.core().set_event_hook(Some(|event: &AllocEvent| {
+///         if event.tag() == Some(7) {
+///             TAGGED.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }));
+///     {
+///         let _tag = alloc_geiger::tag(7);
+///         let _words: Vec<String> = "a b c".split(' ').map(String::from).collect();
+///     }
+///     assert!(TAGGED.load(Ordering::Relaxed) > 0);
+/// }
+/// ```
+pub fn tag(tag: u32) -> Tag {
+    Tag {
+        outer: CURRENT.with(|cell| cell.replace(Some(tag))),
+        _marker: PhantomData,
+    }
+}
+
+/// A tagged scope of a thread's activity, from [`tag`].
+#[must_use = "the tag only lasts until the guard is dropped"]
+pub struct Tag {
+    outer: Option<u32>,
+    /// `CURRENT` is thread-local, so the guard must stay on its thread.
+    _marker: PhantomData<*const ()>,
+}
+
+impl Drop for Tag {
+    fn drop(&mut self) {
+        CURRENT.with(|cell| cell.set(self.outer));
+    }
+}
+
+/// Returns the innermost tag on the current thread.
+pub(crate) fn current() -> Option<u32> {
+    CURRENT.with(Cell::get)
+}
+
+/// The frequency ratio of a tag's pulses, a shift of up to an octave either
+/// way that's mixed from its number, so neighboring tags sound apart.
+pub(crate) fn ratio(tag: u32, scale: Scale) -> f32 {
+    // the finalizer of MurmurHash3
+    let mut hash = tag ^ tag >> 16;
+    hash = hash.wrapping_mul(0x85eb_ca6b);
+    hash ^= hash >> 13;
+    let semitones = scale.quantize((hash % 25) as f32 - 12.0);
+    (semitones / 12.0).exp2()
+}
@@ -0,0 +1,144 @@
+//! Recorded sounds loaded from a theme directory, played instead of the
+//! synthesized pulses.
+
+use crate::event::AllocOp;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A recorded sound in mono, shared by the pulses playing it.
+#[derive(Clone)]
+pub(crate) struct Recording {
+    pub(crate) sample_rate: u32,
+    samples: Arc<[f32]>,
+}
+
+impl Recording {
+    /// The number of samples at the recording's own rate.
+    pub(crate) fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    /// The sound at a fractional sample position, interpolated linearly, or
+    /// silence past the end.
+    pub(crate) fn at(&self, position: f32) -> f32 {
+        let i = position as usize;
+        let frac = position - i as f32;
+        match (self.samples.get(i), self.samples.get(i + 1)) {
+            (Some(&a), Some(&b)) => a + (b - a) * frac,
+            (Some(&a), None) => a * (1.0 - frac),
+            _ => 0.0,
+        }
+    }
+}
+
+/// The recordings of a theme, each falling back to the synthesized sound if
+/// it's missing.
+#[derive(Clone, Default)]
+pub(crate) struct Theme {
+    alloc: Option<Recording>,
+    dealloc: Option<Recording>,
+    realloc: Option<Recording>,
+    pub(crate) alarm: Option<Recording>,
+}
+
+impl Theme {
+    /// Loads `alloc.wav`, `dealloc.wav`, `realloc.wav`, and `alarm.wav` from
+    /// a directory, warning on stderr about any that can't be read.
+    pub(crate) fn load(dir: &str) -> Self {
+        let load = |name: &str| {
+            let path = Path::new(dir).join(name);
+            match read_wav(&path) {
+                Ok(recording) => Some(recording),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    eprintln!("alloc_geiger: couldn't load {}: {e}", path.display());
+                    None
+                }
+            }
+        };
+        Theme {
+            alloc: load("alloc.wav"),
+            dealloc: load("dealloc.wav"),
+            realloc: load("realloc.wav"),
+            alarm: load("alarm.wav"),
+        }
+    }
+
+    /// The recording for an event, with zeroed allocations using `alloc`.
+    pub(crate) fn recording(&self, op: AllocOp) -> Option<&Recording> {
+        match op {
+            AllocOp::Alloc | AllocOp::AllocZeroed => self.alloc.as_ref(),
+            AllocOp::Dealloc => self.dealloc.as_ref(),
+            AllocOp::Realloc => self.realloc.as_ref(),
+        }
+    }
+}
+
+/// Reads a WAV file of integer or float PCM, mixing its channels to mono.
+fn read_wav(path: &Path) -> io::Result<Recording> {
+    let bytes = fs::read(path)?;
+    if bytes.len() < 12 || &bytes[..4] != b"RIFF" || &bytes[8..12] != b"WAVE" {
+        return Err(invalid("not a WAV file"));
+    }
+    let u16_at = |chunk: &[u8], i: usize| u16::from_le_bytes([chunk[i], chunk[i + 1]]);
+    let u32_at = |chunk: &[u8], i: usize| {
+        u32::from_le_bytes([chunk[i], chunk[i + 1], chunk[i + 2], chunk[i + 3]])
+    };
+
+    let mut format = None;
+    let mut data = None;
+    let mut rest = &bytes[12..];
+    while rest.len() >= 8 {
+        let len = u32_at(rest, 4) as usize;
+        let body = rest.get(8..8 + len).unwrap_or(&rest[8..]);
+        match &rest[..4] {
+            b"fmt " if body.len() >= 16 => {
+                let mut tag = u16_at(body, 0);
+                if tag == 0xfffe && body.len() >= 26 {
+                    // `WAVE_FORMAT_EXTENSIBLE` keeps the tag in its subformat
+                    tag = u16_at(body, 24);
+                }
+                format = Some((tag, u16_at(body, 2), u32_at(body, 4), u16_at(body, 14)));
+            }
+            b"data" => data = Some(body),
+            _ => {}
+        }
+        // chunks are padded to an even length
+        rest = rest.get(8 + len + len % 2..).unwrap_or_default();
+    }
+    let (tag, channels, sample_rate, bits) = format.ok_or_else(|| invalid("no format chunk"))?;
+    let data = data.ok_or_else(|| invalid("no data chunk"))?;
+    if channels == 0 || sample_rate == 0 {
+        return Err(invalid("no channels"));
+    }
+
+    let decode: fn(&[u8]) -> f32 = match (tag, bits) {
+        (1, 8) => |b| (f32::from(b[0]) - 128.0) / 128.0,
+        (1, 16) => |b| f32::from(i16::from_le_bytes([b[0], b[1]])) / 32768.0,
+        (1, 24) => |b| i32::from_le_bytes([0, b[0], b[1], b[2]]) as f32 / 2_147_483_648.0,
+        (1, 32) => |b| i32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f32 / 2_147_483_648.0,
+        (3, 32) => |b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]),
+        _ => {
+            return Err(invalid(&format!(
+                "unsupported format {tag} with {bits} bits"
+            )))
+        }
+    };
+    let frame = usize::from(bits / 8) * usize::from(channels);
+    let samples = (data.chunks_exact(frame))
+        .map(|frame| {
+            let sum: f32 = frame.chunks_exact(usize::from(bits / 8)).map(decode).sum();
+            sum / f32::from(channels)
+        })
+        .collect();
+    Ok(Recording {
+        sample_rate,
+        samples,
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
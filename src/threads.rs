@@ -0,0 +1,102 @@
+//! The births and deaths of threads, seen by their first event and their
+//! thread-local destructors.
+
+use crate::clock::now;
+use crate::pitch::os_thread_name;
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The threads that have had an event and haven't exited.
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+
+/// The threads that have exited and haven't been taken by [`take_death`].
+static DEATHS: AtomicUsize = AtomicUsize::new(0);
+
+/// Whether the current thread has had an event, counting it as exited when
+/// its thread-locals are destroyed.
+struct Seen(Cell<bool>);
+
+impl Drop for Seen {
+    fn drop(&mut self) {
+        if self.0.get() {
+            LIVE.fetch_sub(1, Ordering::Relaxed);
+            DEATHS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+thread_local! {
+    static SEEN: Seen = const { Seen(Cell::new(false)) };
+
+    /// Whether the current thread is quiet, and the generation of the
+    /// settings that decided it, or `0` if it hasn't been decided.
+    static QUIET: Cell<(u32, bool)> = const { Cell::new((0, false)) };
+
+    /// When the current thread last clicked, in microseconds of `now`.
+    static LAST_CLICK: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Notes an event on the current thread, returning `true` for its first.
+///
+/// Events while the thread is exiting, after its thread-locals are gone,
+/// aren't counted.
+pub(crate) fn born() -> bool {
+    let first = SEEN.try_with(|seen| !seen.0.replace(true)).unwrap_or(false);
+    if first {
+        LIVE.fetch_add(1, Ordering::Relaxed);
+    }
+    first
+}
+
+/// Takes one of the threads that have exited, returning `false` if none
+/// are left.
+///
+/// A thread can't play its own death, which happens after its last event, so
+/// the next events on any thread play them instead, one at a time.
+pub(crate) fn take_death() -> bool {
+    DEATHS.load(Ordering::Relaxed) > 0
+        && DEATHS
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| n.checked_sub(1))
+            .is_ok()
+}
+
+/// Returns `true` if the current thread's events should be ignored, asking
+/// `is_quiet` about its name once for each `generation` of the settings.
+///
+/// This allocates the name, so it must be called within the recursion guard.
+pub(crate) fn is_quiet(generation: u32, is_quiet: impl FnOnce(&str) -> bool) -> bool {
+    QUIET
+        .try_with(|quiet| {
+            let (decided, quiet_now) = quiet.get();
+            if decided == generation {
+                return quiet_now;
+            }
+            let quiet_now = os_thread_name().is_some_and(|name| is_quiet(&name));
+            quiet.set((generation, quiet_now));
+            quiet_now
+        })
+        .unwrap_or(false)
+}
+
+/// Returns `true` if the current thread clicked within the `dead_time`,
+/// or else notes a click now.
+pub(crate) fn in_dead_time(dead_time: Duration) -> bool {
+    let now = now().as_micros() as u64;
+    LAST_CLICK
+        .try_with(|last| {
+            let dead = last
+                .get()
+                .is_some_and(|last| now.saturating_sub(last) < dead_time.as_micros() as u64);
+            if !dead {
+                last.set(Some(now));
+            }
+            dead
+        })
+        .unwrap_or(false)
+}
+
+/// The threads that have had an event and haven't exited.
+pub(crate) fn live() -> usize {
+    LIVE.load(Ordering::Relaxed)
+}
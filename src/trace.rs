@@ -0,0 +1,159 @@
+//! A plain-text trace of allocator events, for replaying them later.
+
+use crate::binary::{TraceReader, MAGIC};
+use crate::clock::clock_calibration;
+use crate::{quietly, AllocOp, EventReceiver, Geiger, RecentEvent, BUSY};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Write};
+use std::path::Path;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, UNIX_EPOCH};
+
+/// The first line of a trace.
+const HEADER: &str = "# alloc_geiger trace v1";
+/// How long the recorder sleeps when there are no new events.
+const IDLE: Duration = Duration::from_millis(1);
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Spawns a thread recording each allocator event to a trace file, for
+    /// `geiger-replay` or [`read_trace`] to play back later.
+    ///
+    /// Each line of the trace has the time in nanoseconds, the method, the
+    /// size, the address, and the thread index of an event, separated by
+    /// spaces, like `1204853 alloc 64 94827362387552 0`.  Lines starting with
+    /// `#` are comments, which include the count of any events dropped when
+    /// the recorder fell behind.  The second line is a comment with the
+    /// [`clock_calibration`] of the times, in nanoseconds, for merging the
+    /// trace with other timelines.  The recorder's own allocations are not
+    /// counted.
+    ///
+    /// [`clock_calibration`]: crate::clock_calibration
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     ALLOC.spawn_trace_recorder("alloc.trace").unwrap();
+    ///     // ...
+    /// }
+    /// ```
+    pub fn spawn_trace_recorder<P: AsRef<Path>>(
+        &'static self,
+        path: P,
+    ) -> io::Result<JoinHandle<()>> {
+        spawn_recorder(path.as_ref(), || {
+            (self.core).subscribe_regions(&self.read_config().record_regions)
+        })
+    }
+}
+
+/// Creates a trace file, and spawns a thread recording the events of a new
+/// subscription to it.
+pub(crate) fn spawn_recorder(
+    path: &Path,
+    subscribe: impl FnOnce() -> EventReceiver,
+) -> io::Result<JoinHandle<()>> {
+    let mut file = quietly(|| File::create(path).map(BufWriter::new))?;
+    writeln!(file, "{HEADER}")?;
+    write_calibration(&mut file)?;
+    let mut events = subscribe();
+    thread::Builder::new()
+        .name("alloc_geiger-trace".into())
+        .spawn(move || {
+            BUSY.with(|busy| busy.set(true));
+            let mut dropped = 0;
+            loop {
+                let mut idle = true;
+                for event in events.try_iter() {
+                    if write_event(&mut file, &event).is_err() {
+                        return;
+                    }
+                    idle = false;
+                }
+                if events.dropped() != dropped {
+                    dropped = events.dropped();
+                    if writeln!(file, "# {dropped} events dropped").is_err() {
+                        return;
+                    }
+                }
+                if idle {
+                    if file.flush().is_err() {
+                        return;
+                    }
+                    thread::sleep(IDLE);
+                }
+            }
+        })
+}
+
+/// Writes the calibration as a comment, like `# time zero: wall
+/// 1700000000000000000, monotonic 123456789, uncertainty 80`.
+fn write_calibration(file: &mut impl Write) -> io::Result<()> {
+    let calibration = clock_calibration();
+    let wall = calibration
+        .wall
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    write!(file, "# time zero: wall {}", wall.as_nanos())?;
+    if let Some(monotonic) = calibration.monotonic {
+        write!(file, ", monotonic {}", monotonic.as_nanos())?;
+    }
+    writeln!(file, ", uncertainty {}", calibration.uncertainty.as_nanos())
+}
+
+fn write_event(file: &mut impl Write, event: &RecentEvent) -> io::Result<()> {
+    writeln!(
+        file,
+        "{} {} {} {} {}",
+        event.time.as_nanos(),
+        event.op,
+        event.size,
+        event.address,
+        event.thread
+    )
+}
+
+/// Reads the events of a trace from
+/// [`Geiger::spawn_trace_recorder`], in order.
+///
+/// A binary trace from [`Geiger::spawn_binary_trace_recorder`] is read with
+/// a [`TraceReader`] instead, recognized by its first bytes.
+///
+/// ```
+/// let trace = "# alloc_geiger trace v1\n1000 alloc 64 4096 0\n2500 dealloc 64 4096 0\n";
+/// let events = alloc_geiger::read_trace(trace.as_bytes()).unwrap();
+/// assert_eq!(events.len(), 2);
+/// assert_eq!(events[1].op, alloc_geiger::AllocOp::Dealloc);
+/// ```
+pub fn read_trace<R: BufRead>(mut reader: R) -> io::Result<Vec<RecentEvent>> {
+    if reader.fill_buf()?.starts_with(MAGIC) {
+        return TraceReader::new(reader)?.collect();
+    }
+    let mut events = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.starts_with('#') || line.trim().is_empty() {
+            continue;
+        }
+        let event = parse_event(&line).ok_or_else(|| {
+            let message = format!("line {}: invalid event: {line}", i + 1);
+            io::Error::new(io::ErrorKind::InvalidData, message)
+        })?;
+        events.push(event);
+    }
+    Ok(events)
+}
+
+fn parse_event(line: &str) -> Option<RecentEvent> {
+    let mut fields = line.split_whitespace();
+    let mut next = || fields.next();
+    let event = RecentEvent {
+        time: Duration::from_nanos(next()?.parse().ok()?),
+        op: next()?.parse::<AllocOp>().ok()?,
+        size: next()?.parse().ok()?,
+        address: next()?.parse().ok()?,
+        thread: next()?.parse().ok()?,
+    };
+    fields.next().is_none().then_some(event)
+}
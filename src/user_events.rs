@@ -0,0 +1,142 @@
+//! Linux `user_events` tracepoints, enabled by the `user-events` feature, so
+//! `perf record` and `trace-cmd` sessions capture the allocator events next
+//! to the kernel's own, like page faults and `mmap` calls.
+//!
+//! The tracepoint is registered with the first event, through
+//! `user_events_data` in tracefs, which needs Linux 6.4 and permission to
+//! write it, usually root.  After that, each event is written from the
+//! allocating thread as it happens, but only while a session has enabled the
+//! tracepoint, which the kernel flags in a bit of our memory.  For example:
+//!
+//! ```text
+//! perf record -e user_events:alloc_geiger -- <program>
+//! perf script
+//! ```
+//!
+//! The fields are `size`, the new size, `old_size`, the old size of a
+//! reallocation, `address`, `old_address`, the address a reallocation moved
+//! from, and `op`, the method as in a trace.
+
+use crate::event::AllocEvent;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+
+/// The tracepoint's name and fields, in the order they're written.
+const FORMAT: &[u8] =
+    b"alloc_geiger u64 size;u64 old_size;u64 address;u64 old_address;__rel_loc char[] op\0";
+
+/// Where tracefs is usually mounted, then where it was before Linux 4.1.
+const PATHS: [&str; 2] = [
+    "/sys/kernel/tracing/user_events_data",
+    "/sys/kernel/debug/tracing/user_events_data",
+];
+
+/// `struct user_reg` from `linux/user_events.h`.
+#[repr(C, packed)]
+struct UserReg {
+    size: u32,
+    enable_bit: u8,
+    enable_size: u8,
+    flags: u16,
+    enable_addr: u64,
+    name_args: u64,
+    write_index: u32,
+}
+
+/// `DIAG_IOCSREG`, to register a tracepoint, from `linux/user_events.h`.
+const DIAG_IOCSREG: u32 =
+    (3 << 30) | ((mem::size_of::<*mut UserReg>() as u32) << 16) | (b'*' as u32) << 8;
+
+/// The fields before the `op` string.
+#[repr(C, packed)]
+struct Fields {
+    size: u64,
+    old_size: u64,
+    address: u64,
+    old_address: u64,
+    /// the length of `op` in the high half, and its offset after this field
+    op: u32,
+}
+
+/// Bit 0 is set by the kernel while a session has enabled the tracepoint.
+static ENABLED: AtomicU32 = AtomicU32::new(0);
+
+/// The registered tracepoint, or `None` if it couldn't be registered.
+static TRACEPOINT: OnceLock<Option<Tracepoint>> = OnceLock::new();
+
+struct Tracepoint {
+    data: File,
+    write_index: u32,
+}
+
+impl Tracepoint {
+    fn register() -> io::Result<Self> {
+        let data = match OpenOptions::new().write(true).open(PATHS[0]) {
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                OpenOptions::new().write(true).open(PATHS[1])?
+            }
+            data => data?,
+        };
+        let mut reg = UserReg {
+            size: mem::size_of::<UserReg>() as u32,
+            enable_bit: 0,
+            enable_size: mem::size_of::<AtomicU32>() as u8,
+            flags: 0,
+            enable_addr: ENABLED.as_ptr() as u64,
+            name_args: FORMAT.as_ptr() as u64,
+            write_index: 0,
+        };
+        // SAFETY: `reg` is the kernel's struct, and the enable bit is in a
+        // static that lives as long as the registration.
+        if unsafe { libc::ioctl(data.as_raw_fd(), DIAG_IOCSREG as _, &mut reg) } < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let write_index = reg.write_index;
+        Ok(Tracepoint { data, write_index })
+    }
+
+    fn write(&self, event: &AllocEvent) {
+        let op = event.op.name();
+        let fields = Fields {
+            size: event.new_size as u64,
+            old_size: event.layout.size() as u64,
+            address: event.address as u64,
+            old_address: event.old_address as u64,
+            op: (op.len() as u32 + 1) << 16,
+        };
+        let iov = |base: *const u8, len: usize| libc::iovec {
+            iov_base: base as *mut libc::c_void,
+            iov_len: len,
+        };
+        let iovs = [
+            iov((&self.write_index as *const u32).cast(), 4),
+            iov((&fields as *const Fields).cast(), mem::size_of::<Fields>()),
+            iov(op.as_ptr(), op.len()),
+            iov(b"\0".as_ptr(), 1),
+        ];
+        // SAFETY: each iovec points to that many bytes, which outlive the call.
+        // A failed write loses the event, like a full perf buffer.
+        unsafe { libc::writev(self.data.as_raw_fd(), iovs.as_ptr(), iovs.len() as _) };
+    }
+}
+
+/// Writes an event if a session is listening, registering the tracepoint
+/// with the first one, or warning on stderr if it can't be.
+pub(crate) fn write(event: &AllocEvent) {
+    let tracepoint = TRACEPOINT.get_or_init(|| match Tracepoint::register() {
+        Ok(tracepoint) => Some(tracepoint),
+        Err(e) => {
+            eprintln!("alloc_geiger: couldn't register the user_events tracepoint: {e}");
+            None
+        }
+    });
+    if let Some(tracepoint) = tracepoint {
+        if ENABLED.load(Ordering::Relaxed) & 1 != 0 {
+            tracepoint.write(event);
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+
+/// The voices of every pulse.
+static VOICES: Voices = Voices::new();
+
+/// Counts the voices claimed from it.
+struct Voices {
+    /// The sequence number of the next voice.
+    next: AtomicU64,
+    /// The number of voices still playing.
+    active: AtomicUsize,
+}
+
+impl Voices {
+    const fn new() -> Self {
+        Voices {
+            next: AtomicU64::new(0),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Claims a voice, unless `max` are already playing and may not be stolen.
+    fn claim(&'static self, max: usize, steal: bool) -> Option<Voice> {
+        if !steal && self.active.load(Ordering::Relaxed) >= max {
+            return None;
+        }
+        self.active.fetch_add(1, Ordering::Relaxed);
+        Some(Voice {
+            voices: self,
+            seq: self.next.fetch_add(1, Ordering::Relaxed),
+            max,
+        })
+    }
+}
+
+/// A slot for one playing pulse, bounding the total polyphony.
+pub(crate) struct Voice {
+    voices: &'static Voices,
+    seq: u64,
+    max: usize,
+}
+
+impl Voice {
+    /// Claims a voice, unless `max` are already playing and may not be stolen.
+    pub(crate) fn new(max: usize, steal: bool) -> Option<Self> {
+        VOICES.claim(max, steal)
+    }
+
+    /// Returns `true` if `max` newer voices have started since this one,
+    /// which should then stop playing.
+    pub(crate) fn is_stolen(&self) -> bool {
+        let newer = self.voices.next.load(Ordering::Relaxed) - self.seq - 1;
+        newer >= self.max as u64
+    }
+}
+
+impl Drop for Voice {
+    fn drop(&mut self) {
+        self.voices.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refuses_voices_past_the_max() {
+        static VOICES: Voices = Voices::new();
+        let first = VOICES.claim(2, false).unwrap();
+        let second = VOICES.claim(2, false).unwrap();
+        assert!(VOICES.claim(2, false).is_none());
+        drop(first);
+        let third = VOICES.claim(2, false).unwrap();
+        assert!(!second.is_stolen() && !third.is_stolen());
+        assert_eq!(VOICES.active.load(Ordering::Relaxed), 2);
+        drop((second, third));
+        assert_eq!(VOICES.active.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn steals_the_oldest_voices() {
+        static VOICES: Voices = Voices::new();
+        let voices: Vec<_> = (0..3).map(|_| VOICES.claim(2, true).unwrap()).collect();
+        let stolen: Vec<_> = voices.iter().map(Voice::is_stolen).collect();
+        assert_eq!(stolen, [true, false, false]);
+        let newest = VOICES.claim(2, true).unwrap();
+        let stolen: Vec<_> = voices.iter().map(Voice::is_stolen).collect();
+        assert_eq!(stolen, [true, true, false]);
+        assert!(!newest.is_stolen());
+        assert_eq!(VOICES.active.load(Ordering::Relaxed), 4);
+    }
+}
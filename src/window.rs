@@ -0,0 +1,235 @@
+//! A window plotting events and live bytes, enabled by the `gui` feature.
+
+use crate::event::{size_class, SIZE_CLASSES};
+use crate::{quietly, AllocOp, Geiger, RecentEvent};
+use minifb::{Key, Window, WindowOptions};
+use std::collections::VecDeque;
+use std::io;
+
+/// The width of the window, one column per frame.
+const WIDTH: usize = 320;
+
+/// The height of each size class in the event plot.
+const ROW: usize = 2;
+
+/// The height of the event plot, with the largest sizes at the top.
+const EVENTS: usize = SIZE_CLASSES * ROW;
+
+/// The height of the line between the plots.
+const GAP: usize = 2;
+
+/// The height of the live bytes plot.
+const LIVE: usize = 64;
+
+/// The height of the window.
+const HEIGHT: usize = EVENTS + GAP + LIVE;
+
+const BACKGROUND: u32 = 0x10_10_10;
+const DIVIDER: u32 = 0x40_40_40;
+const ALLOC: u32 = 0x40_e0_40;
+const DEALLOC: u32 = 0xe0_40_40;
+const REALLOC: u32 = 0xe0_e0_40;
+/// The color of a size class with more than one kind of event in a frame.
+const MIXED: u32 = 0xf0_f0_f0;
+const LIVE_BYTES: u32 = 0x40_80_e0;
+
+impl<Alloc: Sync + 'static, const OPS: u8> Geiger<Alloc, OPS> {
+    /// Opens a small window plotting the allocator events as they happen,
+    /// and the live bytes below them, until it's closed or Escape is
+    /// pressed.
+    ///
+    /// Each frame is a column, scrolling to the left, with a dot in the row
+    /// of each event's power-of-two size class: green for allocations, red
+    /// for deallocations, yellow for reallocations, and white for a mix.
+    /// The events come from [`subscribe`](crate::GeigerCore::subscribe), the
+    /// same ones the sound is made from.
+    ///
+    /// This blocks the calling thread, which on macOS must be the main
+    /// thread, so the work to watch goes on another.  The window's own
+    /// allocations are not counted or sonified.
+    ///
+    /// ```no_run
+    /// #[global_allocator]
+    /// static ALLOC: alloc_geiger::System = alloc_geiger::SYSTEM;
+    ///
+    /// fn main() {
+    ///     std::thread::spawn(|| {
+    ///         // ...
+    ///     });
+    ///     ALLOC.run_window().unwrap();
+    /// }
+    /// ```
+    pub fn run_window(&self) -> io::Result<()> {
+        quietly(|| {
+            let error = |e: minifb::Error| io::Error::new(io::ErrorKind::Other, e.to_string());
+            let mut window = Window::new("alloc_geiger", WIDTH, HEIGHT, WindowOptions::default())
+                .map_err(error)?;
+            window.set_target_fps(60);
+            let mut events = self.subscribe();
+            let mut plot = Plot::new();
+            let mut pixels = vec![BACKGROUND; WIDTH * HEIGHT];
+            while window.is_open() && !window.is_key_down(Key::Escape) {
+                plot.frame(events.try_iter(), self.stats().live_bytes());
+                plot.draw(&mut pixels);
+                window
+                    .update_with_buffer(&pixels, WIDTH, HEIGHT)
+                    .map_err(error)?;
+            }
+            Ok(())
+        })
+    }
+}
+
+/// The recent frames to plot, oldest first.
+struct Plot {
+    /// for each frame, the kinds of events in each size class, as bits of
+    /// [`kind`]
+    events: VecDeque<[u8; SIZE_CLASSES]>,
+    /// for each frame, the live bytes at its end
+    live: VecDeque<u64>,
+}
+
+/// The bit of a kind of event.
+fn kind(op: AllocOp) -> u8 {
+    match op {
+        AllocOp::Alloc | AllocOp::AllocZeroed => 1,
+        AllocOp::Dealloc => 2,
+        AllocOp::Realloc => 4,
+    }
+}
+
+/// The color of a size class with these kinds of events.
+fn color(kinds: u8) -> u32 {
+    match kinds {
+        0 => BACKGROUND,
+        1 => ALLOC,
+        2 => DEALLOC,
+        4 => REALLOC,
+        _ => MIXED,
+    }
+}
+
+impl Plot {
+    fn new() -> Self {
+        Plot {
+            events: VecDeque::with_capacity(WIDTH),
+            live: VecDeque::with_capacity(WIDTH),
+        }
+    }
+
+    /// Adds a frame with these events, dropping the oldest past [`WIDTH`].
+    fn frame(&mut self, events: impl IntoIterator<Item = RecentEvent>, live: u64) {
+        if self.events.len() == WIDTH {
+            self.events.pop_front();
+            self.live.pop_front();
+        }
+        let mut column = [0; SIZE_CLASSES];
+        for event in events {
+            column[size_class(event.size)] |= kind(event.op);
+        }
+        self.events.push_back(column);
+        self.live.push_back(live);
+    }
+
+    /// Draws the frames into a `WIDTH` by `HEIGHT` buffer, with the newest
+    /// at the right edge, and the live bytes scaled to their largest.
+    fn draw(&self, pixels: &mut [u32]) {
+        pixels.fill(BACKGROUND);
+        pixels[EVENTS * WIDTH..][..GAP * WIDTH].fill(DIVIDER);
+        let max = self.live.iter().copied().max().unwrap_or(0).max(1);
+        let left = WIDTH - self.events.len();
+        let frames = self.events.iter().zip(&self.live);
+        for (x, (column, &live)) in (left..).zip(frames) {
+            for (class, &kinds) in column.iter().enumerate() {
+                let top = (SIZE_CLASSES - 1 - class) * ROW;
+                for y in top..top + ROW {
+                    pixels[y * WIDTH + x] = color(kinds);
+                }
+            }
+            let height = (live as u128 * LIVE as u128 / max as u128) as usize;
+            for y in HEIGHT - height..HEIGHT {
+                pixels[y * WIDTH + x] = LIVE_BYTES;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn event(op: AllocOp, size: usize) -> RecentEvent {
+        RecentEvent {
+            op,
+            size,
+            address: 0x1000,
+            time: Duration::ZERO,
+            thread: 0,
+        }
+    }
+
+    /// Returns the color at a column and row of the drawn plot.
+    fn pixel(plot: &Plot, x: usize, y: usize) -> u32 {
+        let mut pixels = vec![0; WIDTH * HEIGHT];
+        plot.draw(&mut pixels);
+        pixels[y * WIDTH + x]
+    }
+
+    /// The top row of a size class.
+    fn row(class: usize) -> usize {
+        (SIZE_CLASSES - 1 - class) * ROW
+    }
+
+    #[test]
+    fn plots_events_by_size_and_kind() {
+        let mut plot = Plot::new();
+        let events = [
+            event(AllocOp::Alloc, 8),
+            event(AllocOp::Dealloc, 100),
+            event(AllocOp::Realloc, 4096),
+            event(AllocOp::AllocZeroed, 0),
+            event(AllocOp::Dealloc, 0),
+        ];
+        plot.frame(events, 0);
+        let x = WIDTH - 1;
+        assert_eq!(pixel(&plot, x, row(4)), ALLOC);
+        assert_eq!(pixel(&plot, x, row(4) + 1), ALLOC);
+        assert_eq!(pixel(&plot, x, row(7)), DEALLOC);
+        assert_eq!(pixel(&plot, x, row(13)), REALLOC);
+        assert_eq!(pixel(&plot, x, row(0)), MIXED);
+        assert_eq!(pixel(&plot, x, row(5)), BACKGROUND);
+        assert_eq!(pixel(&plot, x - 1, row(4)), BACKGROUND);
+        assert_eq!(pixel(&plot, x, EVENTS), DIVIDER);
+    }
+
+    #[test]
+    fn scrolls_to_the_left() {
+        let mut plot = Plot::new();
+        plot.frame([event(AllocOp::Alloc, 8)], 0);
+        for _ in 1..WIDTH {
+            plot.frame([], 0);
+        }
+        assert_eq!(pixel(&plot, 0, row(4)), ALLOC);
+        plot.frame([], 0);
+        assert_eq!(plot.events.len(), WIDTH);
+        assert_eq!(pixel(&plot, 0, row(4)), BACKGROUND);
+    }
+
+    #[test]
+    fn scales_the_live_bytes() {
+        let mut plot = Plot::new();
+        plot.frame([], 1 << 20);
+        plot.frame([], 1 << 19);
+        plot.frame([], 0);
+        let (full, half, empty) = (WIDTH - 3, WIDTH - 2, WIDTH - 1);
+        assert_eq!(pixel(&plot, full, HEIGHT - LIVE), LIVE_BYTES);
+        assert_eq!(pixel(&plot, half, HEIGHT - LIVE / 2 - 1), BACKGROUND);
+        assert_eq!(pixel(&plot, half, HEIGHT - LIVE / 2), LIVE_BYTES);
+        assert_eq!(pixel(&plot, empty, HEIGHT - 1), BACKGROUND);
+
+        // huge heaps don't overflow the scale
+        plot.frame([], u64::MAX);
+        assert_eq!(pixel(&plot, WIDTH - 1, HEIGHT - LIVE), LIVE_BYTES);
+    }
+}
@@ -0,0 +1,27 @@
+//! Checks that a `CapLayer` below a stats layer keeps allowing allocations
+//! after blocks allocated by the crate's own activity are freed by the
+//! program, which the cap never counted.
+
+#![cfg(not(feature = "off"))]
+
+use alloc_geiger::{CapLayer, Layer, StatsLayer, STATS_LAYER};
+use std::alloc::System;
+
+#[global_allocator]
+static ALLOC: Layer<StatsLayer, Layer<CapLayer, System>> =
+    Layer::new(STATS_LAYER, Layer::new(CapLayer::new(1 << 30), System));
+
+#[test]
+fn cap_survives_uncounted_frees() {
+    for _ in 0..100 {
+        drop(Box::new([0u8; 100]));
+    }
+    // each snapshot is allocated quietly, and freed here, enough times to
+    // outweigh the harness's own live bytes
+    for _ in 0..10_000 {
+        drop(ALLOC.layer().recent_events());
+    }
+    let live = ALLOC.inner().layer().live_bytes();
+    assert!(live < 1 << 30, "{live}");
+    drop(Box::new([0u8; 100]));
+}
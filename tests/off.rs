@@ -0,0 +1,69 @@
+//! Checks that the `off` feature leaves a `Geiger` as a plain passthrough,
+//! by compiling the `passthrough` example to assembly and comparing each
+//! allocator method with the inner allocator's.
+
+// The assembly is parsed as ELF for x86-64.
+#![cfg(all(target_arch = "x86_64", target_os = "linux"))]
+
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+const METHODS: [&str; 4] = ["alloc", "alloc_zeroed", "dealloc", "realloc"];
+
+#[test]
+fn off_is_passthrough() {
+    let target = Path::new(env!("CARGO_TARGET_TMPDIR")).join("off");
+    let status = Command::new(env!("CARGO"))
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("CARGO_TARGET_DIR", &target)
+        .args(["rustc", "--release", "--example", "passthrough"])
+        .args(["--no-default-features", "--features", "off"])
+        .args(["--", "--emit=asm"])
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let asm = fs::read_dir(target.join("release/examples"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "s"))
+        .max_by_key(|path| fs::metadata(path).unwrap().modified().unwrap())
+        .map(fs::read_to_string)
+        .unwrap()
+        .unwrap();
+    for method in METHODS {
+        let geiger = format!("geiger_{method}");
+        let system = format!("system_{method}");
+        // identical functions may be merged, with one an alias of the other
+        let alias = |a: &str, b: &str| asm.lines().any(|line| line == format!("{a} = {b}"));
+        if alias(&geiger, &system) || alias(&system, &geiger) {
+            continue;
+        }
+        // The `&System` passed along points into the `Geiger` rather than
+        // to `INNER`, so only the instructions and what they call must match.
+        assert_eq!(shape(&asm, &geiger), shape(&asm, &system), "{method}");
+    }
+}
+
+/// The instructions of a function, and the functions it calls or jumps to.
+fn shape<'a>(asm: &'a str, name: &str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let start = format!("{name}:");
+    let instructions: Vec<(&str, &str)> = asm
+        .lines()
+        .skip_while(|line| *line != start)
+        .skip(1)
+        .take_while(|line| !line.starts_with(".Lfunc_end"))
+        .filter(|line| line.starts_with('\t') && !line.trim().starts_with('.'))
+        .map(|line| line.trim().split_once('\t').unwrap_or((line.trim(), "")))
+        .collect();
+    assert!(!instructions.is_empty(), "{name} not found");
+    let calls = instructions
+        .iter()
+        .filter(|(op, arg)| {
+            (op.starts_with("call") || op.starts_with('j')) && !arg.starts_with(".L")
+        })
+        .map(|&(_, arg)| arg)
+        .collect();
+    (instructions.iter().map(|&(op, _)| op).collect(), calls)
+}